@@ -23,7 +23,7 @@ fn walk_outline(r: &impl Resolve, mut node: RcRef<OutlineItem>, map: &impl Fn(&s
         if let Some(ref title) = node.title {
             println!("{}title: {:?}", indent, title.as_str().unwrap());
         }
-        if let Some(ref dest) = node.dest {
+        if let Some(MaybeNamedDest::Named(ref dest)) = node.dest {
             let name = dest.as_str().unwrap();
             let page_nr = map(&name);
             println!("{}dest: {:?} -> page nr. {:?}", indent, name, page_nr);
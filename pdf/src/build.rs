@@ -1,41 +1,114 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
 use crate::object::*;
 use crate::content::*;
-use crate::error::Result;
+use crate::object::Rect;
+use crate::error::*;
+use crate::font::{self, *};
+use crate::encoding::{Encoding, BaseEncoding};
+use crate::cmap::CMap;
+use crate::primitive::{Primitive, Dictionary, PdfString};
+use crate::enc::StreamFilter;
+use crate::file::{PromisedRef, SaveOptions, Storage};
+use crate::standard_fonts::StandardFont;
+use crate::xmp;
 
 #[derive(Default)]
 pub struct PageBuilder {
     content: Option<Content>,
     media_box: Option<Rect>,
     crop_box: Option<Rect>,
+    bleed_box: Option<Rect>,
     trim_box: Option<Rect>,
+    art_box: Option<Rect>,
+    rotate: Option<i32>,
     resources: Option<Resources>,
 }
 impl PageBuilder {
+    pub fn new() -> PageBuilder {
+        PageBuilder::default()
+    }
     pub fn from_content(content: Content) -> PageBuilder {
         PageBuilder {
             content: Some(content),
             .. PageBuilder::default()
         }
     }
+    /// Media box from (0, 0) to (`width`, `height`)
     pub fn size(&mut self, width: f32, height: f32) {
         self.media_box = Some(Rect {
-            top: 0.,
+            top: height,
             left: 0.,
-            bottom: height,
+            bottom: 0.,
             right: width,
         });
     }
+    pub fn media_box(&mut self, media_box: Rect) {
+        self.media_box = Some(media_box);
+    }
+    pub fn crop_box(&mut self, crop_box: Rect) {
+        self.crop_box = Some(crop_box);
+    }
+    pub fn bleed_box(&mut self, bleed_box: Rect) {
+        self.bleed_box = Some(bleed_box);
+    }
+    pub fn trim_box(&mut self, trim_box: Rect) {
+        self.trim_box = Some(trim_box);
+    }
+    pub fn art_box(&mut self, art_box: Rect) {
+        self.art_box = Some(art_box);
+    }
+    /// Clockwise rotation in degrees when displayed, a multiple of 90
+    pub fn rotate(&mut self, rotate: i32) {
+        self.rotate = Some(rotate);
+    }
+    /// Resources of this page, instead of the inherited ones
+    pub fn resources(&mut self, resources: Resources) {
+        self.resources = Some(resources);
+    }
+    pub fn content(&mut self, content: Content) {
+        self.content = Some(content);
+    }
+    /// The page, as a child of `parent`
+    pub fn build(self, parent: PagesRc) -> Page {
+        Page {
+            parent,
+            contents: self.content,
+            media_box: self.media_box,
+            crop_box: self.crop_box,
+            bleed_box: self.bleed_box,
+            trim_box: self.trim_box,
+            art_box: self.art_box,
+            rotate: self.rotate,
+            resources: self.resources.map(|r| MaybeRef::Direct(Rc::new(r))),
+            annotations: None,
+            struct_parents: None,
+            associated_files: None,
+            transition: None,
+            duration: None,
+        }
+    }
 }
 
 pub struct CatalogBuilder {
-    pages: Vec<PageBuilder>
+    pages: Vec<PageBuilder>,
+    resources: Option<Resources>,
 }
 impl CatalogBuilder {
     pub fn from_pages(pages: Vec<PageBuilder>) -> CatalogBuilder {
         CatalogBuilder {
-            pages
+            pages,
+            resources: None,
         }
     }
+    /// Resources inherited by all pages that don't have their own
+    pub fn with_resources(mut self, resources: Resources) -> CatalogBuilder {
+        self.resources = Some(resources);
+        self
+    }
     pub fn build(self, update: &mut impl Updater) -> Result<Catalog> {
         let kids_promise: Vec<_> = self.pages.iter()
             .map(|_page| update.promise::<PagesNode>())
@@ -48,30 +121,628 @@ impl CatalogBuilder {
             parent: None,
             count: kids.len() as _,
             kids,
-            resources: None,
+            resources: self.resources.map(|r| MaybeRef::Direct(Rc::new(r))),
             media_box: None,
-            crop_box: None
+            crop_box: None,
+            rotate: None,
         }, update)?;
 
         for (page, promise) in self.pages.into_iter().zip(kids_promise) {
-            let page = Page {
-                parent: tree.clone(),
-                contents: page.content,
-                media_box: page.media_box,
-                crop_box: page.crop_box,
-                trim_box: page.trim_box,
-                resources: None,
-            };
-            update.fulfill(promise, PagesNode::Leaf(page))?;
+            update.fulfill(promise, PagesNode::Leaf(page.build(tree.clone())))?;
         }
 
         Ok(Catalog {
             pages: tree,
+            page_labels: None,
             names: None,
             dests: None,
+            viewer_preferences: None,
+            page_layout: None,
+            page_mode: None,
             metadata: None,
             outlines: None,
-            struct_tree_root: None
+            threads: None,
+            open_action: None,
+            struct_tree_root: None,
+            associated_files: None,
+            output_intents: None,
+            collection: None,
+        })
+    }
+}
+/// Creates a new document: the catalog, the page tree and the Info dictionary.
+///
+/// Fonts and images are added to resources shared by all pages (unless a page has its own),
+/// under the names returned by `add_font` and `add_image`.
+///
+/// ```
+/// # use pdf::build::{ContentBuilder, DocumentBuilder, PageBuilder};
+/// # use pdf::standard_fonts::StandardFont;
+/// let mut doc = DocumentBuilder::new();
+/// let font = doc.add_font(DocumentBuilder::standard_font(StandardFont::Helvetica)).unwrap();
+/// let content = ContentBuilder::new()
+///     .font(font, 12.)
+///     .text_position(72., 720.)
+///     .show("Hello")
+///     .build();
+/// doc.add_page(PageBuilder::from_content(content));
+/// doc.info("Title", "Hello");
+/// let data = doc.to_bytes().unwrap();
+/// assert!(data.starts_with(b"%PDF-"));
+/// ```
+pub struct DocumentBuilder {
+    storage: Storage<Vec<u8>>,
+    pages: Vec<PageBuilder>,
+    resources: Resources,
+    info: Dictionary,
+}
+impl DocumentBuilder {
+    pub fn new() -> DocumentBuilder {
+        DocumentBuilder {
+            storage: Storage::empty(),
+            pages: vec![],
+            resources: Resources::default(),
+            info: Dictionary::new(),
+        }
+    }
+    /// A Type1 font dictionary for one of the standard fonts, with `WinAnsiEncoding`
+    /// (except for `Symbol` and `ZapfDingbats`, which use their built-in encoding)
+    pub fn standard_font(font: StandardFont) -> Font {
+        let encoding = match font {
+            StandardFont::Symbol | StandardFont::ZapfDingbats => None,
+            _ => Some(Encoding { base: BaseEncoding::WinAnsiEncoding, differences: HashMap::new() })
+        };
+        Font {
+            subtype: FontType::Type1,
+            name: font.name().into(),
+            data: Ok(FontData::None),
+            encoding,
+            cmap: None,
+            to_unicode: None,
+            _other: Dictionary::new(),
+        }
+    }
+    /// Add `font` to the shared resources and return its name (`F1`, `F2`, ...)
+    pub fn add_font(&mut self, font: Font) -> Result<String> {
+        let font = self.storage.create(font)?;
+        let name = format!("F{}", self.resources.fonts.len() + 1);
+        self.resources.fonts.insert(name.clone(), font.get_ref());
+        Ok(name)
+    }
+    /// Add `image` to the shared resources and return its name (`Im1`, `Im2`, ...)
+    pub fn add_image(&mut self, image: ImageXObject) -> Result<String> {
+        let image = self.storage.create(XObject::Image(image))?;
+        let name = format!("Im{}", self.resources.xobjects.len() + 1);
+        self.resources.xobjects.insert(name.clone(), image.get_ref());
+        Ok(name)
+    }
+    pub fn add_page(&mut self, page: PageBuilder) -> &mut Self {
+        self.pages.push(page);
+        self
+    }
+    /// Set the entry `key` of the Info dictionary (`Title`, `Author`, `Subject`, `Keywords`, `Creator`, ...)
+    pub fn info(&mut self, key: &str, value: &str) -> &mut Self {
+        self.info.insert(key, Primitive::String(text_string(value)));
+        self
+    }
+    /// The complete file
+    pub fn to_bytes(mut self) -> Result<Vec<u8>> {
+        let pages = std::mem::take(&mut self.pages);
+        let resources = std::mem::take(&mut self.resources);
+        let mut catalog = CatalogBuilder::from_pages(pages)
+            .with_resources(resources)
+            .build(&mut self.storage)?;
+        if !self.info.is_empty() {
+            let metadata = self.storage.create(xmp::metadata_stream(xmp::from_info(&self.info)))?;
+            catalog.metadata = Some(Ref::new(metadata.get_ref().get_inner()));
+        }
+        let catalog = self.storage.create(catalog)?;
+
+        let mut trailer = Dictionary::new();
+        trailer.insert("Root", Primitive::Reference(catalog.get_ref().get_inner()));
+        if !self.info.is_empty() {
+            let info = std::mem::take(&mut self.info);
+            let info = self.storage.create(info)?;
+            trailer.insert("Info", Primitive::Reference(info.get_ref().get_inner()));
+        }
+        self.storage.rewrite(trailer, &SaveOptions::default())
+    }
+    /// Write the file to `path`
+    pub fn save(self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+}
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        DocumentBuilder::new()
+    }
+}
+impl Updater for DocumentBuilder {
+    fn create<T: ObjectWrite>(&mut self, obj: T) -> Result<RcRef<T>> {
+        self.storage.create(obj)
+    }
+    fn update<T: ObjectWrite>(&mut self, old: PlainRef, obj: T) -> Result<RcRef<T>> {
+        self.storage.update(old, obj)
+    }
+    fn promise<T: Object>(&mut self) -> PromisedRef<T> {
+        self.storage.promise()
+    }
+    fn fulfill<T: ObjectWrite>(&mut self, promise: PromisedRef<T>, obj: T) -> Result<RcRef<T>> {
+        self.storage.fulfill(promise, obj)
+    }
+}
+
+/// A text string: ASCII as is, anything else in UTF-16BE with a byte order mark
+pub(crate) fn text_string(s: &str) -> PdfString {
+    if s.is_ascii() {
+        return PdfString::new(s.as_bytes().to_vec());
+    }
+    let mut data = vec![0xfe, 0xff];
+    for unit in s.encode_utf16() {
+        data.extend_from_slice(&unit.to_be_bytes());
+    }
+    PdfString::new(data)
+}
+
+/// Builds a `Type0` font with a `CIDFontType2` descendant from a TrueType font program.
+///
+/// Text has to be encoded with `encode` (`Identity-H`, glyph ids as CIDs).
+pub struct FontBuilder {
+    data: Vec<u8>,
+    name: String,
+    /// advance of each glyph, in 1/1000 em
+    widths: Vec<f32>,
+    glyph_ids: HashMap<char, u16>,
+    bbox: Rect,
+    italic_angle: f32,
+    ascent: f32,
+    descent: f32,
+    cap_height: f32,
+    x_height: f32,
+    weight: f32,
+    stretch: FontStretch,
+    flags: u32,
+}
+impl FontBuilder {
+    pub fn from_ttf(data: Vec<u8>) -> Result<FontBuilder> {
+        let face = match ttf_parser::Face::from_slice(&data, 0) {
+            Ok(face) => face,
+            Err(e) => bail!("can't parse TrueType font: {}", e)
+        };
+        let scale = 1000. / face.units_per_em().unwrap_or(1000) as f32;
+
+        let name = face.names()
+            .filter(|name| name.name_id() == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .map(|name| name.chars().filter(|c| c.is_ascii_graphic()).collect::<String>())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "EmbeddedFont".into());
+
+        let widths = (0 .. face.number_of_glyphs())
+            .map(|gid| face.glyph_hor_advance(ttf_parser::GlyphId(gid)).unwrap_or(0) as f32 * scale)
+            .map(f32::round)
+            .collect();
+
+        let mut glyph_ids = HashMap::new();
+        for subtable in face.character_mapping_subtables().filter(|s| s.is_unicode()) {
+            subtable.codepoints(|cp| {
+                if let (Some(c), Some(gid)) = (std::char::from_u32(cp), subtable.glyph_index(cp)) {
+                    glyph_ids.entry(c).or_insert(gid.0);
+                }
+            });
+        }
+
+        let bbox = face.global_bounding_box();
+        let bbox = Rect {
+            left: bbox.x_min as f32 * scale,
+            bottom: bbox.y_min as f32 * scale,
+            right: bbox.x_max as f32 * scale,
+            top: bbox.y_max as f32 * scale,
+        };
+
+        let mut flags = font::flags::Symbolic;
+        if face.is_monospaced() {
+            flags |= font::flags::FixedPitch;
+        }
+        if face.is_italic() {
+            flags |= font::flags::Italic;
+        }
+
+        let stretch = match face.width() {
+            ttf_parser::Width::UltraCondensed => FontStretch::UltraCondensed,
+            ttf_parser::Width::ExtraCondensed => FontStretch::ExtraCondensed,
+            ttf_parser::Width::Condensed => FontStretch::Condensed,
+            ttf_parser::Width::SemiCondensed => FontStretch::SemiCondensed,
+            ttf_parser::Width::Normal => FontStretch::Normal,
+            ttf_parser::Width::SemiExpanded => FontStretch::SemiExpanded,
+            ttf_parser::Width::Expanded => FontStretch::Expanded,
+            ttf_parser::Width::ExtraExpanded => FontStretch::ExtraExpanded,
+            ttf_parser::Width::UltraExpanded => FontStretch::UltraExpanded,
+        };
+        let ascent = face.ascender() as f32 * scale;
+        let descent = face.descender() as f32 * scale;
+
+        Ok(FontBuilder {
+            name,
+            widths,
+            glyph_ids,
+            bbox,
+            italic_angle: face.italic_angle().unwrap_or(0.),
+            ascent,
+            descent,
+            cap_height: face.capital_height().map(|h| h as f32 * scale).unwrap_or(ascent),
+            x_height: face.x_height().map(|h| h as f32 * scale).unwrap_or(0.),
+            weight: face.weight().to_number() as f32,
+            stretch,
+            flags,
+            data,
         })
     }
-}
\ No newline at end of file
+
+    /// The PostScript name of the font, used as `BaseFont`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Glyph id for `c`, if the font has a glyph for it.
+    pub fn glyph_id(&self, c: char) -> Option<u16> {
+        self.glyph_ids.get(&c).cloned()
+    }
+
+    /// Encode `text` for use with `TextDraw`. Missing glyphs are replaced by `.notdef`.
+    pub fn encode(&self, text: &str) -> PdfString {
+        let data = text.chars()
+            .flat_map(|c| self.glyph_id(c).unwrap_or(0).to_be_bytes())
+            .collect();
+        PdfString::new(data)
+    }
+
+    pub fn build(&self, update: &mut impl Updater) -> Result<Font> {
+        let font_file = Stream::new(
+            FontFileInfo { length1: Some(self.data.len() as u32), .. FontFileInfo::default() },
+            self.data.clone()
+        ).with_filter(StreamFilter::FlateDecode(Default::default()))?;
+        let font_descriptor = FontDescriptor {
+            font_name: self.name.clone(),
+            font_family: None,
+            font_stretch: Some(self.stretch.clone()),
+            font_weight: Some(self.weight),
+            flags: self.flags,
+            font_bbox: self.bbox,
+            italic_angle: self.italic_angle,
+            ascent: Some(self.ascent),
+            descent: Some(self.descent),
+            leading: 0.,
+            cap_height: Some(self.cap_height),
+            xheight: self.x_height,
+            // TrueType fonts do not record a stem width, estimate it from the weight
+            stem_v: 10. + 220. * (self.weight - 50.) / 900.,
+            stem_h: 0.,
+            avg_width: 0.,
+            max_width: 0.,
+            missing_width: 0.,
+            font_file: None,
+            font_file2: Some(font_file),
+            font_file3: None,
+            char_set: None,
+        };
+
+        let mut system_info = Dictionary::new();
+        system_info.insert("Registry", PdfString::new(b"Adobe".to_vec()).into());
+        system_info.insert("Ordering", PdfString::new(b"Identity".to_vec()).into());
+        system_info.insert("Supplement", Primitive::Integer(0));
+
+        let widths = vec![
+            Primitive::Integer(0),
+            Primitive::Array(self.widths.iter().map(|&w| Primitive::Number(w)).collect())
+        ];
+        let cid_font = CIDFont {
+            system_info,
+            font_descriptor,
+            default_width: 1000.,
+            widths,
+            _other: Dictionary::new(),
+        };
+        let descendant = update.create(Font {
+            subtype: FontType::CIDFontType2,
+            name: self.name.clone(),
+            data: Ok(FontData::CIDFontType2(cid_font, None)),
+            encoding: None,
+            cmap: None,
+            to_unicode: None,
+            _other: Dictionary::new(),
+        })?;
+
+        Ok(Font {
+            subtype: FontType::Type0,
+            name: self.name.clone(),
+            data: Ok(FontData::Type0(Type0Font {
+                descendant_fonts: vec![descendant],
+                to_unicode: None,
+            })),
+            encoding: Some(Encoding {
+                base: BaseEncoding::IdentityH,
+                differences: HashMap::new(),
+            }),
+            cmap: Some(CMap::identity_h()),
+            to_unicode: Some(self.to_unicode()?),
+            _other: Dictionary::new(),
+        })
+    }
+
+    /// The `ToUnicode` CMap, so text set with this font can be extracted.
+    ///
+    /// Glyphs shared by several characters map to the smallest one.
+    fn to_unicode(&self) -> Result<Stream> {
+        let mut chars: HashMap<u16, char> = HashMap::new();
+        for (&c, &gid) in &self.glyph_ids {
+            if gid != 0 {
+                let entry = chars.entry(gid).or_insert(c);
+                *entry = (*entry).min(c);
+            }
+        }
+        let map = ToUnicodeMap::create(chars.into_iter().map(|(gid, c)| (gid, c.to_string())));
+        Stream::new((), map.to_cmap()).with_filter(StreamFilter::FlateDecode(Default::default()))
+    }
+}
+
+/// Builds a content stream operation by operation.
+///
+/// Text showing operators start a text object (`BT`) if needed, and operators
+/// that are not allowed in a text object end it (`ET`) first.
+/// `build` closes an open text object and restores unbalanced saves.
+///
+/// ```
+/// # use pdf::build::ContentBuilder;
+/// let content = ContentBuilder::new()
+///     .font("F1", 12.)
+///     .text_position(72., 720.)
+///     .show("Hello")
+///     .rect(72., 700., 100., 10.)
+///     .fill()
+///     .build();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ContentBuilder {
+    ops: Vec<Op>,
+    in_text: bool,
+    saved: usize,
+}
+impl ContentBuilder {
+    pub fn new() -> ContentBuilder {
+        ContentBuilder::default()
+    }
+
+    /// Append `op` as it is
+    pub fn op(mut self, op: Op) -> Self {
+        self.ops.push(op);
+        self
+    }
+    fn text_op(self, op: Op) -> Self {
+        self.begin_text().op(op)
+    }
+    fn page_op(mut self, op: Op) -> Self {
+        if self.in_text {
+            self.ops.push(Op::EndText);
+            self.in_text = false;
+        }
+        self.op(op)
+    }
+
+    /// `q`
+    pub fn save(mut self) -> Self {
+        self.saved += 1;
+        self.page_op(Op::Save)
+    }
+    /// `Q`, ignored if there is no matching `save`
+    pub fn restore(mut self) -> Self {
+        if self.saved == 0 {
+            return self;
+        }
+        self.saved -= 1;
+        self.page_op(Op::Restore)
+    }
+    /// `cm`
+    pub fn transform(self, matrix: Matrix) -> Self {
+        self.page_op(Op::Transform { matrix })
+    }
+    /// `w`
+    pub fn line_width(self, width: f32) -> Self {
+        self.op(Op::LineWidth { width })
+    }
+    /// `g`, `rg`, `k` or `scn`
+    pub fn fill_color(self, color: Color) -> Self {
+        self.op(Op::FillColor { color })
+    }
+    /// `G`, `RG`, `K` or `SCN`
+    pub fn stroke_color(self, color: Color) -> Self {
+        self.op(Op::StrokeColor { color })
+    }
+    /// `gs`
+    pub fn graphics_state(self, name: impl Into<String>) -> Self {
+        self.op(Op::GraphicsState { name: name.into() })
+    }
+
+    /// `m`
+    pub fn move_to(self, x: f32, y: f32) -> Self {
+        self.page_op(Op::MoveTo { p: Point { x, y } })
+    }
+    /// `l`
+    pub fn line_to(self, x: f32, y: f32) -> Self {
+        self.page_op(Op::LineTo { p: Point { x, y } })
+    }
+    /// `c`
+    pub fn curve_to(self, c1: Point, c2: Point, p: Point) -> Self {
+        self.page_op(Op::CurveTo { c1, c2, p })
+    }
+    /// `re`
+    pub fn rect(self, x: f32, y: f32, width: f32, height: f32) -> Self {
+        self.page_op(Op::Rect { rect: crate::content::Rect { x, y, width, height } })
+    }
+    /// `h`
+    pub fn close(self) -> Self {
+        self.page_op(Op::Close)
+    }
+    /// `S`
+    pub fn stroke(self) -> Self {
+        self.page_op(Op::Stroke)
+    }
+    /// `f`
+    pub fn fill(self) -> Self {
+        self.page_op(Op::Fill { winding: Winding::NonZero })
+    }
+    /// `B`
+    pub fn fill_and_stroke(self) -> Self {
+        self.page_op(Op::FillAndStroke { winding: Winding::NonZero })
+    }
+    /// `W n`: intersect the clipping path with the current path
+    pub fn clip(self) -> Self {
+        self.page_op(Op::Clip { winding: Winding::NonZero }).page_op(Op::EndPath)
+    }
+    /// `Do`
+    pub fn xobject(self, name: impl Into<String>) -> Self {
+        self.page_op(Op::XObject { name: name.into() })
+    }
+
+    /// `BT`; text showing operators start a text object themselves
+    pub fn begin_text(mut self) -> Self {
+        if !self.in_text {
+            self.ops.push(Op::BeginText);
+            self.in_text = true;
+        }
+        self
+    }
+    /// `ET`
+    pub fn end_text(mut self) -> Self {
+        if self.in_text {
+            self.ops.push(Op::EndText);
+            self.in_text = false;
+        }
+        self
+    }
+    /// `Tf`: `name` is the name of the font in the page resources
+    pub fn font(self, name: impl Into<String>, size: f32) -> Self {
+        self.op(Op::TextFont { name: name.into(), size })
+    }
+    /// `TL`
+    pub fn leading(self, leading: f32) -> Self {
+        self.op(Op::Leading { leading })
+    }
+    /// `Td`: start a new line, offset from the start of the current one
+    pub fn text_position(self, x: f32, y: f32) -> Self {
+        self.text_op(Op::MoveTextPosition { translation: Point { x, y } })
+    }
+    /// `Tm`
+    pub fn text_matrix(self, matrix: Matrix) -> Self {
+        self.text_op(Op::SetTextMatrix { matrix })
+    }
+    /// `T*`
+    pub fn next_line(self) -> Self {
+        self.text_op(Op::TextNewline)
+    }
+    /// `Tj` with `text` in `WinAnsiEncoding`, the encoding usually used with the standard fonts.
+    ///
+    /// Characters that can't be encoded are replaced by `?`.
+    pub fn show(self, text: &str) -> Self {
+        let data = text.chars()
+            .map(|c| BaseEncoding::WinAnsiEncoding.encode(c).unwrap_or(b'?'))
+            .collect();
+        self.show_encoded(PdfString::new(data))
+    }
+    /// `Tj` with text that is already encoded for the font, for example with `FontBuilder::encode`
+    pub fn show_encoded(self, text: PdfString) -> Self {
+        self.text_op(Op::TextDraw { text })
+    }
+
+    /// The operations so far
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Close the open text object, restore unbalanced saves and serialize the operations
+    pub fn build(self) -> Content {
+        let mut builder = self.end_text();
+        for _ in 0 .. builder.saved {
+            builder.ops.push(Op::Restore);
+        }
+        Content::from_ops(builder.ops)
+    }
+
+    /// Build the operations as a form XObject covering `bbox` in its own space,
+    /// for example a stamp to put on several pages.
+    pub fn build_form(self, bbox: Rect, resources: Option<Resources>) -> Result<FormXObject> {
+        let content = self.build();
+        let dict = FormDict {
+            form_type: 1,
+            name: None,
+            last_modified: None,
+            bbox,
+            matrix: None,
+            resources: resources.map(|r| MaybeRef::Direct(Rc::new(r))),
+            group: None,
+            reference: None,
+            metadata: None,
+            piece_info: None,
+            struct_parent: None,
+            struct_parents: None,
+            opi: None,
+            associated_files: None,
+            other: Dictionary::new(),
+        };
+        let data = t!(content.parts[0].data()).to_vec();
+        Ok(FormXObject { operations: content.operations, stream: Stream::new(dict, data) })
+    }
+}
+
+/// Draw `xobject` on top of the content of `page`.
+///
+/// `transform` maps the form space (or the unit square of an image) to the user space of the page.
+/// The XObject is added to the page resources as `Stamp1`, `Stamp2`, ... and the name is returned;
+/// inherited resources are copied into the page first.
+///
+/// ```no_run
+/// # use pdf::build::{stamp, ContentBuilder, DocumentBuilder};
+/// # use pdf::content::Matrix;
+/// # use pdf::file::File;
+/// # use pdf::object::*;
+/// # use pdf::standard_fonts::StandardFont;
+/// # fn main() -> pdf::error::Result<()> {
+/// let mut file = File::open("in.pdf")?;
+/// let font = file.create(DocumentBuilder::standard_font(StandardFont::HelveticaBold))?;
+/// let mut resources = Resources::default();
+/// resources.fonts.insert("F1".into(), font.get_ref());
+/// let form = ContentBuilder::new()
+///     .font("F1", 72.)
+///     .text_position(0., 0.)
+///     .show("DRAFT")
+///     .build_form(Rect { left: 0., bottom: 0., right: 250., top: 72. }, Some(resources))?;
+/// let draft = file.create(XObject::Form(form))?.get_ref();
+/// for i in 0 .. file.num_pages() {
+///     let page = file.get_page(i)?;
+///     let mut stamped = (*page).clone();
+///     stamp(&mut stamped, draft, Matrix::translate(100., 400.))?;
+///     file.update(page.get_ref().get_inner(), PagesNode::Leaf(stamped))?;
+/// }
+/// file.save_to("out.pdf")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn stamp(page: &mut Page, xobject: Ref<XObject>, transform: Matrix) -> Result<String> {
+    let mut resources = match page.resources() {
+        Ok(resources) => (**resources).clone(),
+        Err(_) => Resources::default()
+    };
+    let name = (1 ..).map(|i| format!("Stamp{}", i))
+        .find(|name| !resources.xobjects.contains_key(name))
+        .unwrap();
+    resources.xobjects.insert(name.clone(), xobject);
+    page.resources = Some(MaybeRef::Direct(Rc::new(resources)));
+    page.append_content(vec![
+        Op::Save,
+        Op::Transform { matrix: transform },
+        Op::XObject { name: name.clone() },
+        Op::Restore,
+    ])?;
+    Ok(name)
+}
@@ -0,0 +1,311 @@
+//! CMaps, mapping character codes to CIDs (`Encoding` of Type0 fonts)
+use std::io::Write;
+
+use crate::object::*;
+use crate::primitive::*;
+use crate::error::*;
+use crate::parser::{Lexer, parse_with_lexer};
+
+/// Code space range. A code of `low.len()` bytes is in the range when every byte lies
+/// between the corresponding bytes of `low` and `high`.
+#[derive(Debug, Clone)]
+pub struct CodespaceRange {
+    pub low: Vec<u8>,
+    pub high: Vec<u8>,
+}
+impl CodespaceRange {
+    fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.low.len() && data.iter()
+            .zip(self.low.iter().zip(self.high.iter()))
+            .all(|(b, (lo, hi))| lo <= b && b <= hi)
+    }
+}
+
+/// Maps codes `low ..= high` to CIDs `cid ..`
+#[derive(Debug, Clone, Copy)]
+pub struct CidRange {
+    pub low: u32,
+    pub high: u32,
+    pub cid: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CMap {
+    pub name: Option<String>,
+    /// 0 = horizontal, 1 = vertical
+    pub wmode: i32,
+    codespace: Vec<CodespaceRange>,
+    /// later entries take precedence
+    ranges: Vec<CidRange>,
+}
+impl CMap {
+    fn identity(name: &str, wmode: i32) -> CMap {
+        CMap {
+            name: Some(name.into()),
+            wmode,
+            codespace: vec![CodespaceRange { low: vec![0, 0], high: vec![0xff, 0xff] }],
+            ranges: vec![CidRange { low: 0, high: 0xffff, cid: 0 }],
+        }
+    }
+    pub fn identity_h() -> CMap {
+        CMap::identity("Identity-H", 0)
+    }
+    pub fn identity_v() -> CMap {
+        CMap::identity("Identity-V", 1)
+    }
+
+    /// Look up one of the predefined CMaps.
+    ///
+    /// Only `Identity-H` and `Identity-V` are known.
+    pub fn predefined(name: &str) -> Option<CMap> {
+        match name {
+            "Identity-H" => Some(CMap::identity_h()),
+            "Identity-V" => Some(CMap::identity_v()),
+            _ => None
+        }
+    }
+
+    /// Parse the CMap program in `data`. `parent` is used for `usecmap` if given.
+    pub fn parse(data: &[u8], parent: Option<CMap>) -> Result<CMap> {
+        let mut cmap = parent.unwrap_or(CMap {
+            name: None,
+            wmode: 0,
+            codespace: vec![],
+            ranges: vec![],
+        });
+        let mut lexer = Lexer::new(data);
+        let mut last_name = None;
+        while let Ok(token) = lexer.next() {
+            match token.as_slice() {
+                b"begincodespacerange" => while !t!(lexer.peek()).equals("endcodespacerange") {
+                    let low = t!(parse_with_lexer(&mut lexer, &NoResolve)).into_string()?.into_bytes();
+                    let high = t!(parse_with_lexer(&mut lexer, &NoResolve)).into_string()?.into_bytes();
+                    if low.len() != high.len() || low.is_empty() || low.len() > 4 {
+                        bail!("invalid codespace range {:?} .. {:?}", low, high);
+                    }
+                    cmap.codespace.push(CodespaceRange { low, high });
+                },
+                b"begincidrange" => while !t!(lexer.peek()).equals("endcidrange") {
+                    let low = code(t!(parse_with_lexer(&mut lexer, &NoResolve)))?;
+                    let high = code(t!(parse_with_lexer(&mut lexer, &NoResolve)))?;
+                    let cid = t!(parse_with_lexer(&mut lexer, &NoResolve)).as_u32()?;
+                    cmap.ranges.push(CidRange { low, high, cid });
+                },
+                b"begincidchar" => while !t!(lexer.peek()).equals("endcidchar") {
+                    let code = code(t!(parse_with_lexer(&mut lexer, &NoResolve)))?;
+                    let cid = t!(parse_with_lexer(&mut lexer, &NoResolve)).as_u32()?;
+                    cmap.ranges.push(CidRange { low: code, high: code, cid });
+                },
+                b"usecmap" => {
+                    let name: Option<String> = last_name.take();
+                    match name.as_deref().and_then(CMap::predefined) {
+                        Some(used) => {
+                            // the used cmap comes first, so definitions in this one take precedence
+                            cmap.codespace.splice(0 .. 0, used.codespace);
+                            cmap.ranges.splice(0 .. 0, used.ranges);
+                        }
+                        None => warn!("usecmap: unknown CMap {:?}", name)
+                    }
+                }
+                b"/CMapName" => {
+                    let name = t!(lexer.next());
+                    if name.starts_with(b"/") {
+                        cmap.name = Some(name.reslice(1 ..).to_string());
+                    }
+                }
+                b"/WMode" => {
+                    cmap.wmode = t!(lexer.next()).to::<i32>()?;
+                }
+                b"endcmap" => break,
+                name if name.starts_with(b"/") => {
+                    last_name = Some(token.reslice(1 ..).to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(cmap)
+    }
+
+    /// Length in bytes of the code at the start of `data`.
+    fn code_len(&self, data: &[u8]) -> usize {
+        if let Some(range) = self.codespace.iter().find(|r| r.matches(data)) {
+            return range.low.len();
+        }
+        // Invalid code: use the shortest range whose first byte matches (9.7.6.3)
+        self.codespace.iter()
+            .filter(|r| r.low[0] <= data[0] && data[0] <= r.high[0])
+            .map(|r| r.low.len())
+            .min()
+            .unwrap_or(1)
+            .min(data.len())
+    }
+
+    /// CID of `code`, if mapped
+    pub fn cid(&self, code: u32) -> Option<u32> {
+        self.ranges.iter().rev()
+            .find(|r| r.low <= code && code <= r.high)
+            .map(|r| r.cid + (code - r.low))
+    }
+
+    /// Split `data` into the bytes of each code
+    pub fn split<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item=&'a [u8]> + 'a {
+        let mut data = data;
+        std::iter::from_fn(move || {
+            if data.is_empty() {
+                return None;
+            }
+            let (code, rest) = data.split_at(self.code_len(data));
+            data = rest;
+            Some(code)
+        })
+    }
+
+    /// Split `data` into codes and map them to CIDs. Unmapped codes map to CID 0.
+    pub fn decode<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item=(u32, u32)> + 'a {
+        self.split(data).map(move |code| {
+            let code = code.iter().fold(0, |c, &b| c << 8 | b as u32);
+            (code, self.cid(code).unwrap_or(0))
+        })
+    }
+
+    /// The CMap program
+    pub fn program(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_program(&mut out).unwrap();
+        out
+    }
+    fn write_program(&self, out: &mut impl Write) -> Result<()> {
+        let name = self.name.as_deref().unwrap_or("Custom");
+        writeln!(out, "/CIDInit /ProcSet findresource begin")?;
+        writeln!(out, "12 dict begin")?;
+        writeln!(out, "begincmap")?;
+        writeln!(out, "/CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> def")?;
+        writeln!(out, "/CMapName /{} def", name)?;
+        writeln!(out, "/CMapType 1 def")?;
+        writeln!(out, "/WMode {} def", self.wmode)?;
+        for chunk in self.codespace.chunks(100) {
+            writeln!(out, "{} begincodespacerange", chunk.len())?;
+            for range in chunk {
+                writeln!(out, "<{}> <{}>", hex(&range.low), hex(&range.high))?;
+            }
+            writeln!(out, "endcodespacerange")?;
+        }
+        for chunk in self.ranges.chunks(100) {
+            writeln!(out, "{} begincidrange", chunk.len())?;
+            for range in chunk {
+                let len = self.codespace.iter()
+                    .map(|r| r.low.len())
+                    .filter(|&n| (range.high as u64) < 1 << (8 * n))
+                    .min()
+                    .unwrap_or(2);
+                writeln!(out, "<{:0w$x}> <{:0w$x}> {}", range.low, range.high, range.cid, w = 2 * len)?;
+            }
+            writeln!(out, "endcidrange")?;
+        }
+        writeln!(out, "endcmap")?;
+        writeln!(out, "CMapName currentdict /CMap defineresource pop")?;
+        writeln!(out, "end")?;
+        writeln!(out, "end")?;
+        Ok(())
+    }
+}
+
+fn code(p: Primitive) -> Result<u32> {
+    let data = p.into_string()?.into_bytes();
+    if data.len() > 4 {
+        bail!("code has more than 4 bytes: {:?}", data);
+    }
+    Ok(data.iter().fold(0, |c, &b| c << 8 | b as u32))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Object for CMap {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        CMap::load(p, resolve, 0)
+    }
+}
+impl CMap {
+    /// Load the CMap `p`, `depth` references and `UseCMap` entries away from the one of the font
+    fn load(p: Primitive, resolve: &impl Resolve, depth: usize) -> Result<CMap> {
+        // a cycle would never end
+        if depth > 16 {
+            bail!("UseCMap is nested too deeply");
+        }
+        match p {
+            Primitive::Name(name) => match CMap::predefined(&name) {
+                Some(cmap) => Ok(cmap),
+                None => bail!("unsupported predefined CMap {}", name)
+            },
+            Primitive::Reference(r) => CMap::load(resolve.resolve(r)?, resolve, depth + 1),
+            p => {
+                let mut stream: Stream<Dictionary> = Stream::from_primitive(p, resolve)?;
+                let parent = match stream.info.info.remove("UseCMap") {
+                    Some(p) => Some(t!(CMap::load(p, resolve, depth + 1))),
+                    None => None
+                };
+                CMap::parse(t!(stream.data()), parent)
+            }
+        }
+    }
+}
+impl ObjectWrite for CMap {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        match self.name.as_deref() {
+            Some(name) if CMap::predefined(name).is_some() => return Ok(Primitive::name(name)),
+            _ => {}
+        }
+        let mut info = Dictionary::new();
+        info.insert("Type", Primitive::name("CMap"));
+        info.insert("CMapName", Primitive::name(self.name.as_deref().unwrap_or("Custom")));
+        let mut system_info = Dictionary::new();
+        system_info.insert("Registry", PdfString::new(b"Adobe".to_vec()).into());
+        system_info.insert("Ordering", PdfString::new(b"Identity".to_vec()).into());
+        system_info.insert("Supplement", Primitive::Integer(0));
+        info.insert("CIDSystemInfo", system_info.into());
+        info.insert("WMode", Primitive::Integer(self.wmode));
+        Stream::new(info, self.program()).to_primitive(update)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CMAP: &[u8] = b"
+/CIDInit /ProcSet findresource begin
+12 dict begin
+begincmap
+/CMapName /Test-H def
+2 begincodespacerange
+<00> <80>
+<8140> <9ffc>
+endcodespacerange
+1 begincidrange
+<8140> <817e> 633
+endcidrange
+2 begincidchar
+<20> 1
+<8143> 1000
+endcidchar
+endcmap
+";
+
+    #[test]
+    fn mixed_byte_codes() {
+        let cmap = CMap::parse(CMAP, None).unwrap();
+        assert_eq!(cmap.name.as_deref(), Some("Test-H"));
+        let codes: Vec<_> = cmap.decode(b"\x20\x81\x41\x81\x43\x41").collect();
+        assert_eq!(codes, [(0x20, 1), (0x8141, 634), (0x8143, 1000), (0x41, 0)]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let cmap = CMap::parse(CMAP, None).unwrap();
+        let cmap = CMap::parse(&cmap.program(), None).unwrap();
+        let codes: Vec<_> = cmap.decode(b"\x81\x41\x20").collect();
+        assert_eq!(codes, [(0x8141, 634), (0x20, 1)]);
+    }
+}
@@ -1,6 +1,8 @@
 /// PDF content streams.
+use std::convert::TryFrom;
 use std::fmt::{self, Display};
-use std::cmp::Ordering;
+use std::io;
+use std::ops::Mul;
 use itertools::Itertools;
 
 use crate::error::*;
@@ -8,6 +10,7 @@ use crate::object::*;
 use crate::parser::{Lexer, parse_with_lexer};
 use crate::primitive::*;
 use crate::enc::StreamFilter;
+use crate::object::Rect as ViewRect;
 
 /// Represents a PDF content stream - a `Vec` of `Operator`s
 #[derive(Debug, Clone)]
@@ -84,6 +87,24 @@ fn matrix(args: &mut impl Iterator<Item=Primitive>) -> Result<Matrix> {
         f: number(args)?,
     })
 }
+/// Operands of `sc`, `scn`, `SC` and `SCN`: numbers, optionally followed by a pattern name
+fn color_args(args: impl Iterator<Item=Primitive>) -> Result<Color> {
+    let mut components = vec![];
+    let mut pattern = None;
+    for p in args {
+        if pattern.is_some() {
+            bail!("operand after the pattern name");
+        }
+        match p {
+            Primitive::Name(name) => pattern = Some(name),
+            p => components.push(p.as_number()?),
+        }
+    }
+    Ok(match pattern {
+        Some(name) => Color::Pattern { name, components },
+        None => Color::Components(components),
+    })
+}
 fn array(args: &mut impl Iterator<Item=Primitive>) -> Result<Vec<Primitive>> {
     match args.next() {
         Some(Primitive::Array(arr)) => Ok(arr),
@@ -108,6 +129,50 @@ fn expand_abbr(p: Primitive, alt: &[(&str, &str)]) -> Primitive {
     }
 }
 
+fn is_pdf_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\r' | b'\n' | b'\t' | b'\x0c' | 0)
+}
+
+/// Number of color components of an (unabbreviated) inline image color space, if known
+fn color_components(color_space: &Primitive) -> Option<usize> {
+    match color_space {
+        Primitive::Name(name) => match name.as_str() {
+            "DeviceGray" | "CalGray" => Some(1),
+            "DeviceRGB" | "CalRGB" | "Lab" => Some(3),
+            "DeviceCMYK" => Some(4),
+            _ => None
+        },
+        Primitive::Array(parts) => match parts.first() {
+            Some(Primitive::Name(name)) if name == "Indexed" => Some(1),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Find the `EI` that ends the image data in `data`, and return its position.
+///
+/// `EI` has to be surrounded by whitespace (or the end of the stream),
+/// and be followed by something that looks like content, not binary data.
+fn find_end_of_image(data: &[u8], from: usize) -> Option<usize> {
+    let mut pos = from;
+    while pos + 2 <= data.len() {
+        let i = pos + data[pos ..].windows(2).position(|w| w == b"EI")?;
+        pos = i + 1;
+        if i > from && !is_pdf_whitespace(data[i - 1]) {
+            continue;
+        }
+        let rest = &data[i + 2 ..];
+        if !rest.first().copied().is_none_or(is_pdf_whitespace) {
+            continue;
+        }
+        if rest.iter().take(16).all(|&b| is_pdf_whitespace(b) || (0x20 .. 0x7f).contains(&b)) {
+            return Some(i);
+        }
+    }
+    None
+}
+
 fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Stream<ImageDict>> {
     let mut dict = Dictionary::new();
     loop {
@@ -131,29 +196,38 @@ fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Stream<Imag
             ("H", "Height"),
             ("IM", "ImageMask"),
             ("I", "Interpolate"),
+            ("L", "Length"),
             ("W", "Width"),
         ]);
         let val = parse_with_lexer(lexer, &NoResolve)?;
         dict.insert(key, val);
     }
     lexer.next_expect("ID")?;
+    // a single whitespace character separates `ID` from the data
     let data_start = lexer.get_pos() + 1;
 
-    // ugh
-    let bits_per_component = dict.require("InlineImage", "BitsPerComponent")?.as_integer()?;
-    let color_space = expand_abbr(
-        dict.require("InlineImage", "ColorSpace")?,
+    let width = dict.require("InlineImage", "Width")?.as_integer()?;
+    let height = dict.require("InlineImage", "Height")?.as_integer()?;
+    let image_mask = dict.remove("ImageMask").map(|p| p.as_bool()).transpose()?.unwrap_or(false);
+    let bits_per_component = match dict.remove("BitsPerComponent") {
+        Some(p) => p.as_integer()?,
+        None if image_mask => 1,
+        None => bail!("inline image without BitsPerComponent")
+    };
+    let color_space = dict.remove("ColorSpace").map(|cs| expand_abbr(cs,
         &[
             ("G", "DeviceGray"),
             ("RGB", "DeviceRGB"),
             ("CMYK", "DeviceCMYK"),
             ("I", "Indexed")
         ]
-    );
-    let decode = Object::from_primitive(dict.require("InlineImage", "Decode")?, resolve)?;
-    let decode_parms = dict.require("InlineImage", "DecodeParms")?.into_dictionary(resolve)?;
-    let filter = expand_abbr(
-        dict.require("InlineImage", "Filter")?,
+    ));
+    let decode = dict.remove("Decode").map(|p| Object::from_primitive(p, resolve)).transpose()?;
+    let intent = dict.remove("Intent").map(|p| RenderingIntent::from_primitive(p, &NoResolve)).transpose()?;
+    let interpolate = dict.remove("Interpolate").map(|p| p.as_bool()).transpose()?.unwrap_or(false);
+    let length = dict.remove("Length").map(|p| p.as_u32()).transpose()?.map(|n| n as usize);
+
+    let filter = dict.remove("Filter").map(|f| expand_abbr(f,
         &[
             ("AHx", "ASCIIHexDecode"),
             ("A85", "ASCII85Decode"),
@@ -163,25 +237,76 @@ fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Stream<Imag
             ("CCF", "CCITTFaxDecode"),
             ("DCT", "DCTDecode"),
         ]
-    );
+    ));
+    // one dictionary, or one per filter
+    let params = |p: Option<Primitive>| -> Result<Dictionary> {
+        match p {
+            None | Some(Primitive::Null) => Ok(Dictionary::new()),
+            Some(p) => p.into_dictionary(resolve)
+        }
+    };
+    let decode_parms = dict.remove("DecodeParms");
     let filters = match filter {
-        Primitive::Array(parts) => parts.into_iter()
-            .map(|p| p.as_name().and_then(|kind| StreamFilter::from_kind_and_params(kind, decode_parms.clone(), resolve)))
-            .collect::<Result<_>>()?,
-        Primitive::Name(kind) => vec![StreamFilter::from_kind_and_params(&kind, decode_parms, resolve)?],
-        _ => bail!("invalid filter")
+        None => vec![],
+        Some(Primitive::Name(kind)) => {
+            let parms = match decode_parms {
+                Some(Primitive::Array(parts)) => params(parts.into_iter().next())?,
+                p => params(p)?
+            };
+            vec![StreamFilter::from_kind_and_params(&kind, parms, resolve)?]
+        }
+        Some(Primitive::Array(kinds)) => {
+            let mut parms = match decode_parms {
+                Some(Primitive::Array(parts)) => parts,
+                Some(p) => vec![p],
+                None => vec![]
+            }.into_iter();
+            kinds.into_iter()
+                .map(|kind| StreamFilter::from_kind_and_params(kind.as_name()?, params(parms.next())?, resolve))
+                .collect::<Result<_>>()?
+        }
+        Some(_) => bail!("invalid filter")
     };
-    
-    let height = dict.require("InlineImage", "Height")?.as_integer()?;
-    let image_mask = dict.get("ImageMask").map(|p| p.as_bool()).transpose()?.unwrap_or(false);
-    let intent = dict.remove("Intent").map(|p| RenderingIntent::from_primitive(p, &NoResolve)).transpose()?;
-    let interpolate = dict.get("Interpolate").map(|p| p.as_bool()).transpose()?.unwrap_or(false);
-    let width = dict.require("InlineImage", "Width")?.as_integer()?;
+
+    // the rest of the content stream, starting at `offset`
+    let offset = lexer.get_pos();
+    let rest = lexer.get_remaining_slice();
+    let ends_here = |end: usize| {
+        let rest = end.checked_sub(offset).and_then(|i| rest.get(i ..)).unwrap_or(&[]);
+        let rest = &rest[rest.iter().take_while(|&&b| is_pdf_whitespace(b)).count() ..];
+        rest.starts_with(b"EI") && rest.get(2).copied().is_none_or(is_pdf_whitespace)
+    };
+
+    // If the size of the data is known, trust it as long as it is followed by `EI`.
+    // Otherwise (including for sizes that are not positive or too large) look for `EI`.
+    let samples = match (&filters[..], image_mask, color_space.as_ref().and_then(color_components)) {
+        ([], true, _) => Some(1),
+        ([], false, Some(n)) => Some(n),
+        _ => None
+    }.and_then(|n| {
+        let width = usize::try_from(width).ok().filter(|&w| w > 0)?;
+        let height = usize::try_from(height).ok().filter(|&h| h > 0)?;
+        let bits = usize::try_from(bits_per_component).ok().filter(|&b| b > 0)?;
+        width.checked_mul(n)?.checked_mul(bits)?.div_ceil(8).checked_mul(height)
+    });
+    let data_end = match length.or(samples).and_then(|len| data_start.checked_add(len)) {
+        Some(end) if ends_here(end) => end,
+        _ => {
+            let ei = find_end_of_image(rest, (data_start - offset).min(rest.len()))
+                .ok_or(PdfError::EOF)?;
+            // the whitespace before `EI` is not part of the data
+            (offset + ei).saturating_sub(1).max(data_start)
+        }
+    };
+
+    let data = lexer.new_substr(data_start .. data_end).to_vec();
+    lexer.set_pos(data_end);
+    lexer.next_expect("EI")?;
 
     let image_dict = ImageDict {
         width,
         height,
-        color_space: Some(color_space),
+        color_space,
         bits_per_component,
         intent,
         image_mask,
@@ -191,14 +316,12 @@ fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Stream<Imag
         struct_parent: None,
         id: None,
         smask: None,
+        matte: None,
+        smask_in_data: None,
+        associated_files: None,
         other: dict,
     };
 
-    lexer.seek_substr("\nEI").expect("BUGZ");
-    let data_end = lexer.get_pos() - 3;
-
-    let data = lexer.new_substr(data_start .. data_end).to_vec();
-
     Ok(Stream::new_with_filters(image_dict, data, filters))
 }
 struct OpBuilder {
@@ -217,10 +340,15 @@ impl OpBuilder {
     fn parse(&mut self, data: &[u8], resolve: &impl Resolve) -> Result<()> {
         let mut lexer = Lexer::new(data);
         let mut buffer = Vec::with_capacity(5);
-
-        loop {
+        while self.next_operator(&mut lexer, data.len(), &mut buffer, resolve)? {}
+        Ok(())
+    }
+    /// Read operands up to and including the next operator.
+    /// Returns `false` when the end of the data is reached.
+    fn next_operator(&mut self, lexer: &mut Lexer, end: usize, buffer: &mut Vec<Primitive>, resolve: &impl Resolve) -> Result<bool> {
+        while lexer.get_pos() < end {
             let backup_pos = lexer.get_pos();
-            let obj = parse_with_lexer(&mut lexer, resolve);
+            let obj = parse_with_lexer(lexer, resolve);
             match obj {
                 Ok(obj) => {
                     // Operand
@@ -228,22 +356,24 @@ impl OpBuilder {
                 }
                 Err(e) => {
                     if e.is_eof() {
-                        break;
+                        return Ok(false);
                     }
                     // It's not an object/operand - treat it as an operator.
                     lexer.set_pos(backup_pos);
                     let op = t!(lexer.next());
                     let operator = t!(op.as_str());
-                    t!(self.add(operator, buffer.drain(..), &mut lexer, resolve), op.as_str());
+                    t!(self.add(operator, buffer.drain(..), lexer, resolve), op.as_str());
+                    if lexer.get_pos() > end {
+                        err!(PdfError::ContentReadPastBoundary);
+                    }
+                    return Ok(true);
                 }
             }
-            match lexer.get_pos().cmp(&data.len()) {
-                Ordering::Greater => err!(PdfError::ContentReadPastBoundary),
-                Ordering::Less => (),
-                Ordering::Equal => break
-            }
         }
-        Ok(())
+        if lexer.get_pos() > end {
+            err!(PdfError::ContentReadPastBoundary);
+        }
+        Ok(false)
     }
     fn add(&mut self, op: &str, mut args: impl Iterator<Item=Primitive>, lexer: &mut Lexer, resolve: &impl Resolve) -> Result<()> {
         use Winding::*;
@@ -272,7 +402,10 @@ impl OpBuilder {
                 properties: None
             }),
             "BT"  => push(Op::BeginText),
-            "BX"  => self.compability_section = true,
+            "BX"  => {
+                self.compability_section = true;
+                push(Op::BeginCompatibility);
+            }
             "c"   => {
                 points!(args, c1, c2, p);
                 push(Op::CurveTo { c1, c2, p });
@@ -296,8 +429,14 @@ impl OpBuilder {
                 let phase = args.next().ok_or(PdfError::NoOpArg)?.as_number()?;
                 push(Op::Dash { pattern, phase });
             }
-            "d0"  => {}
-            "d1"  => {}
+            "d0"  => {
+                numbers!(args, wx, wy);
+                push(Op::CharWidth { wx, wy });
+            }
+            "d1"  => {
+                numbers!(args, wx, wy, left, bottom, right, top);
+                push(Op::CharBoundingBox { wx, wy, bbox: ViewRect { left, bottom, right, top } });
+            }
             "Do"  => {
                 names!(args, name);
                 push(Op::XObject { name });
@@ -309,7 +448,10 @@ impl OpBuilder {
             "EI"  => bail!("Parse Error. Unexpected 'EI'"),
             "EMC" => push(Op::EndMarkedContent),
             "ET"  => push(Op::EndText),
-            "EX"  => self.compability_section = false,
+            "EX"  => {
+                self.compability_section = false;
+                push(Op::EndCompatibility);
+            }
             "f" |
             "F"   => push(Op::Fill { winding: NonZero }),
             "f*"  => push(Op::Fill { winding: EvenOdd }),
@@ -376,15 +518,9 @@ impl OpBuilder {
                 push(Op::Stroke);
             }
             "S"   => push(Op::Stroke),
-            "SC" | "SCN" => {
-                push(Op::StrokeColor { color: Color::Other(args.collect()) });
-            }
-            "sc" | "scn" => {
-                push(Op::FillColor { color: Color::Other(args.collect()) });
-            }
-            "sh"  => {
-
-            }
+            "SC" | "SCN" => push(Op::StrokeColor { color: color_args(args)? }),
+            "sc" | "scn" => push(Op::FillColor { color: color_args(args)? }),
+            "sh"  => push(Op::Shade { name: name(&mut args)? }),
             "T*"  => push(Op::TextNewline),
             "Tc"  => push(Op::CharSpacing { char_space: number(&mut args)? }),
             "Td"  => push(Op::MoveTextPosition { translation: point(&mut args)? }),
@@ -474,12 +610,15 @@ impl Object for Content {
 
         match p {
             Primitive::Array(arr) => {
+                // an operator may be split between the parts, so they are parsed as one stream
+                let mut data = Vec::new();
                 for p in arr {
                     let part = t!(ContentStream::from_primitive(p, resolve));
-                    let data = t!(part.data());
-                    ops.parse(&data, resolve)?;
+                    data.extend_from_slice(t!(part.data()));
+                    data.push(b'\n');
                     parts.push(part);
                 }
+                ops.parse(&data, resolve)?;
             }
             Primitive::Reference(r) => return Self::from_primitive(t!(resolve.resolve(r)), resolve),
             p => {
@@ -494,6 +633,73 @@ impl Object for Content {
     }
 }
 
+/// Iterator over the operations of a content stream, parsing them as they are needed.
+///
+/// Created by `Content::operations_lazy` or `Ops::new`.
+pub struct Ops<'a> {
+    parts: std::slice::Iter<'a, Stream<()>>,
+    // lexer for the current part and its length
+    current: Option<(Lexer<'a>, usize)>,
+    builder: OpBuilder,
+    buffer: Vec<Primitive>,
+    // operations of the last operator not returned yet
+    pending: std::vec::IntoIter<Op>,
+}
+impl<'a> Ops<'a> {
+    /// Parse the (decoded) content stream `data`.
+    pub fn new(data: &'a [u8]) -> Ops<'a> {
+        Ops {
+            parts: [].iter(),
+            current: Some((Lexer::new(data), data.len())),
+            builder: OpBuilder::new(),
+            buffer: Vec::with_capacity(5),
+            pending: Vec::new().into_iter(),
+        }
+    }
+    fn next_part(&mut self) -> Result<bool> {
+        match self.parts.next() {
+            Some(part) => {
+                let data = t!(part.data());
+                self.current = Some((Lexer::new(data), data.len()));
+                Ok(true)
+            }
+            None => Ok(false)
+        }
+    }
+}
+impl<'a> Iterator for Ops<'a> {
+    type Item = Result<Op>;
+    fn next(&mut self) -> Option<Result<Op>> {
+        loop {
+            if let Some(op) = self.pending.next() {
+                return Some(Ok(op));
+            }
+            let result = match self.current {
+                Some((ref mut lexer, end)) => self.builder.next_operator(lexer, end, &mut self.buffer, &NoResolve),
+                None => Ok(false)
+            };
+            match result {
+                Ok(true) => self.pending = std::mem::take(&mut self.builder.ops).into_iter(),
+                Ok(false) => {
+                    // operands at the end of a part belong to an operator in the next one
+                    self.current = None;
+                    match self.next_part() {
+                        Ok(true) => {}
+                        Ok(false) => return None,
+                        Err(e) => return Some(Err(e))
+                    }
+                }
+                Err(e) => {
+                    // don't continue after an error
+                    self.current = None;
+                    self.parts = [].iter();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct FormXObject {
     pub operations: Vec<Op>,
@@ -516,73 +722,138 @@ impl Object for FormXObject {
         })
     }
 }
+impl ObjectWrite for FormXObject {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        self.stream.to_primitive(update)
+    }
+}
 
 
+/// Combines operations that were split when parsing (`b`, `s`, `TD`, `'`, `"`)
 fn serialize_ops(mut ops: &[Op]) -> Result<Vec<u8>> {
     use Op::*;
     use std::io::Write;
 
     let mut data = Vec::new();
-    let mut current_point = None;
     let f = &mut data;
 
-    while ops.len() > 0 {
-        let mut advance = 1;
-        match ops[0] {
-            BeginMarkedContent { ref tag, properties: Some(ref name) } => {
-                serialize_name(&tag, f)?;
+    while !ops.is_empty() {
+        let advance = match *ops {
+            [Close, Stroke, ..] => {
+                writeln!(f, "s")?;
+                2
+            }
+            [Close, FillAndStroke { winding: Winding::NonZero }, ..] => {
+                writeln!(f, "b")?;
+                2
+            }
+            [Close, FillAndStroke { winding: Winding::EvenOdd }, ..] => {
+                writeln!(f, "b*")?;
+                2
+            }
+            [Leading { leading }, MoveTextPosition { translation }, ..] if leading == -translation.y => {
+                writeln!(f, "{} TD", translation)?;
+                2
+            }
+            [WordSpacing { word_space }, CharSpacing { char_space }, TextNewline, TextDraw { ref text }, ..] => {
+                write!(f, "{} {} ", word_space, char_space)?;
+                text.serialize(f)?;
+                writeln!(f, " \"")?;
+                4
+            }
+            [TextNewline, TextDraw { ref text }, ..] => {
+                text.serialize(f)?;
+                writeln!(f, " '")?;
+                2
+            }
+            [ref op, ..] => {
+                op.serialize(f)?;
+                1
+            }
+            [] => unreachable!()
+        };
+        ops = &ops[advance..];
+    }
+    Ok(data)
+}
+
+/// Abbreviations of the inline image keys
+const INLINE_IMAGE_KEYS: &[(&str, &str)] = &[
+    ("BitsPerComponent", "BPC"),
+    ("ColorSpace", "CS"),
+    ("Decode", "D"),
+    ("DecodeParms", "DP"),
+    ("Filter", "F"),
+    ("Height", "H"),
+    ("ImageMask", "IM"),
+    ("Interpolate", "I"),
+    ("Width", "W"),
+];
+
+fn serialize_inline_image(image: &Stream<ImageDict>, f: &mut impl io::Write) -> Result<()> {
+    let stream = image.to_pdf_stream(&mut NoUpdate)?;
+    writeln!(f, "BI")?;
+    for (key, val) in stream.info.iter() {
+        if matches!(key.as_str(), "Type" | "Subtype" | "Length") {
+            continue;
+        }
+        serialize_name(&expand_abbr_name(key.clone(), INLINE_IMAGE_KEYS), f)?;
+        write!(f, " ")?;
+        val.serialize(f, 0)?;
+        writeln!(f)?;
+    }
+    write!(f, "ID ")?;
+    f.write_all(&stream.data)?;
+    writeln!(f, "\nEI")?;
+    Ok(())
+}
+
+impl Op {
+    /// Write the operator with its operands, followed by a newline.
+    ///
+    /// Each `Op` is written on its own, so `TD` becomes `TL` and `Td` for example.
+    /// `Content::to_bytes` combines them again.
+    pub fn serialize(&self, f: &mut impl io::Write) -> Result<()> {
+        use Op::*;
+
+        match *self {
+            BeginMarkedContent { ref tag, properties: Some(ref properties) } => {
+                serialize_name(tag, f)?;
                 write!(f, " ")?;
-                name.serialize(f, 0)?;
+                properties.serialize(f, 0)?;
                 writeln!(f, " BDC")?;
             }
             BeginMarkedContent { ref tag, properties: None } => {
-                serialize_name(&tag, f)?;
+                serialize_name(tag, f)?;
                 writeln!(f, " BMC")?;
             }
-            MarkedContentPoint { ref tag, properties: Some(ref name) } => {
-                serialize_name(&tag, f)?;
+            MarkedContentPoint { ref tag, properties: Some(ref properties) } => {
+                serialize_name(tag, f)?;
                 write!(f, " ")?;
-                name.serialize(f, 0)?;
+                properties.serialize(f, 0)?;
                 writeln!(f, " DP")?;
             }
             MarkedContentPoint { ref tag, properties: None } => {
-                serialize_name(&tag, f)?;
+                serialize_name(tag, f)?;
                 writeln!(f, " MP")?;
             }
             EndMarkedContent => writeln!(f, "EMC")?,
-            Close => match ops.get(1) {
-                Some(Stroke) => {
-                    writeln!(f, "s")?;
-                    advance += 1;
-                }
-                Some(FillAndStroke { winding: Winding::NonZero }) => {
-                    writeln!(f, "b")?;
-                    advance += 1;
-                }
-                Some(FillAndStroke { winding: Winding::EvenOdd }) => {
-                    writeln!(f, "b*")?;
-                    advance += 1;
-                }
-                _ => writeln!(f, "h")?,
-            }
-            MoveTo { p } => {
-                writeln!(f, "{} m", p)?;
-                current_point = Some(p);
+            BeginCompatibility => writeln!(f, "BX")?,
+            EndCompatibility => writeln!(f, "EX")?,
+            CharWidth { wx, wy } => writeln!(f, "{} {} d0", wx, wy)?,
+            CharBoundingBox { wx, wy, bbox } => {
+                writeln!(f, "{} {} {} {} {} {} d1", wx, wy, bbox.left, bbox.bottom, bbox.right, bbox.top)?;
             }
-            LineTo { p } => {
-                writeln!(f, "{} l", p)?;
-                current_point = Some(p);
-            },
+            Close => writeln!(f, "h")?,
+            MoveTo { p } => writeln!(f, "{} m", p)?,
+            LineTo { p } => writeln!(f, "{} l", p)?,
             CurveTo { c1, c2, p } => {
-                if Some(c1) == current_point {
-                    writeln!(f, "{} {} v", c2, p)?;
-                } else if c2 == p {
+                if c2 == p {
                     writeln!(f, "{} {} y", c1, p)?;
                 } else {
-                    writeln!(f, "{} {} {} y", c1, c2, p)?;
+                    writeln!(f, "{} {} {} c", c1, c2, p)?;
                 }
-                current_point = Some(p);
-            },
+            }
             Rect { rect } => writeln!(f, "{} re", rect)?,
             EndPath => writeln!(f, "n")?,
             Stroke => writeln!(f, "S")?,
@@ -593,14 +864,14 @@ fn serialize_ops(mut ops: &[Op]) -> Result<Vec<u8>> {
             Shade { ref name } => {
                 serialize_name(name, f)?;
                 writeln!(f, " sh")?;
-            },
+            }
             Clip { winding: Winding::NonZero } => writeln!(f, "W")?,
             Clip { winding: Winding::EvenOdd } => writeln!(f, "W*")?,
             Save => writeln!(f, "q")?,
             Restore => writeln!(f, "Q")?,
             Transform { matrix } => writeln!(f, "{} cm", matrix)?,
             LineWidth { width } => writeln!(f, "{} w", width)?,
-            Dash { ref pattern, phase } => write!(f, "[{}] {} d", pattern.iter().format(" "), phase)?,
+            Dash { ref pattern, phase } => writeln!(f, "[{}] {} d", pattern.iter().format(" "), phase)?,
             LineJoin { join } => writeln!(f, "{} j", join as u8)?,
             LineCap { cap } => writeln!(f, "{} J", cap as u8)?,
             MiterLimit { limit } => writeln!(f, "{} M", limit)?,
@@ -608,98 +879,255 @@ fn serialize_ops(mut ops: &[Op]) -> Result<Vec<u8>> {
             GraphicsState { ref name } => {
                 serialize_name(name, f)?;
                 writeln!(f, " gs")?;
-            },
-            StrokeColor { color: Color::Gray(g) } => writeln!(f, "{} G", g)?,
-            StrokeColor { color: Color::Rgb(rgb) } => writeln!(f, "{} RG", rgb)?,
-            StrokeColor { color: Color::Cmyk(cmyk) } => writeln!(f, "{} K", cmyk)?,
-            StrokeColor { color: Color::Other(ref args) } =>  {
-                for p in args {
-                    p.serialize(f, 0)?;
-                    write!(f, " ")?;
-                }
-                writeln!(f, "SCN")?;
-            }
-            FillColor { color: Color::Gray(g) } => writeln!(f, "{} g", g)?,
-            FillColor { color: Color::Rgb(rgb) } => writeln!(f, "{} rg", rgb)?,
-            FillColor { color: Color::Cmyk(cmyk) } => writeln!(f, "{} k", cmyk)?,
-            FillColor { color: Color::Other(ref args) } => {
-                for p in args {
-                    p.serialize(f, 0)?;
-                    write!(f, " ")?;
-                }
-                writeln!(f, "scn")?;
             }
+            StrokeColor { ref color } => color.serialize(f, "G", "RG", "K", "SCN")?,
+            FillColor { ref color } => color.serialize(f, "g", "rg", "k", "scn")?,
             FillColorSpace { ref name } => {
                 serialize_name(name, f)?;
                 writeln!(f, " cs")?;
-            },
+            }
             StrokeColorSpace { ref name } => {
                 serialize_name(name, f)?;
                 writeln!(f, " CS")?;
-            },
-
-            RenderingIntent { intent } => writeln!(f, "{} ri", intent.to_str())?,
-            Op::BeginText => writeln!(f, "BT")?,
-            Op::EndText => writeln!(f, "ET")?,
-            CharSpacing { char_space } => writeln!(f, "{} Tc", char_space)?,
-            WordSpacing { word_space } => {
-                if let [
-                    Op::CharSpacing { char_space },
-                    Op::TextNewline,
-                    Op::TextDraw { ref text },
-                    ..
-                ] = ops[1..] {
-                    write!(f, "{} {} ", word_space, char_space)?;
-                    text.serialize(f)?;
-                    writeln!(f, " \"")?;
-                    advance += 3;
-                } else {
-                    writeln!(f, "{} Tw", word_space)?;
-                }
             }
+            RenderingIntent { intent } => writeln!(f, "/{} ri", intent.to_str())?,
+            BeginText => writeln!(f, "BT")?,
+            EndText => writeln!(f, "ET")?,
+            CharSpacing { char_space } => writeln!(f, "{} Tc", char_space)?,
+            WordSpacing { word_space } => writeln!(f, "{} Tw", word_space)?,
             TextScaling { horiz_scale } => writeln!(f, "{} Tz", horiz_scale)?,
-            Leading { leading } => match ops[1..] {
-                [Op::MoveTextPosition { translation }, ..] if leading == -translation.x => {
-                    writeln!(f, "{} {} TD", translation.x, translation.y)?;
-                    advance += 1;
-                }
-                _ => {
-                    writeln!(f, "{} TL", leading)?;
-                }
-            }
-            TextFont { ref name, ref size } => {
+            Leading { leading } => writeln!(f, "{} TL", leading)?,
+            TextFont { ref name, size } => {
                 serialize_name(name, f)?;
                 writeln!(f, " {} Tf", size)?;
-            },
+            }
             TextRenderMode { mode } => writeln!(f, "{} Tr", mode as u8)?,
             TextRise { rise } => writeln!(f, "{} Ts", rise)?,
-            MoveTextPosition { translation } => writeln!(f, "{} {} Td", translation.x, translation.y)?,
+            MoveTextPosition { translation } => writeln!(f, "{} Td", translation)?,
             SetTextMatrix { matrix } => writeln!(f, "{} Tm", matrix)?,
-            TextNewline => {
-                if let [Op::TextDraw { ref text }, ..] = ops[1..] {
-                    text.serialize(f)?;
-                    writeln!(f, " '")?;
-                    advance += 1;
-                } else {
-                    writeln!(f, "T*")?;
-                }
-            },
+            TextNewline => writeln!(f, "T*")?,
             TextDraw { ref text } => {
                 text.serialize(f)?;
                 writeln!(f, " Tj")?;
-            },
+            }
             TextDrawAdjusted { ref array } => {
-                writeln!(f, "[{}] TJ", array.iter().format(" "))?;
-            },
-            InlineImage { image: _ } => unimplemented!(),
+                write!(f, "[")?;
+                for (i, part) in array.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    match *part {
+                        self::TextDrawAdjusted::Text(ref text) => text.serialize(f)?,
+                        self::TextDrawAdjusted::Spacing(n) => write!(f, "{}", n)?,
+                    }
+                }
+                writeln!(f, "] TJ")?;
+            }
+            InlineImage { ref image } => serialize_inline_image(image, f)?,
             XObject { ref name } => {
                 serialize_name(name, f)?;
                 writeln!(f, " Do")?;
-            },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Color {
+    /// Write the color with the operator for gray, rgb, cmyk and other colors.
+    fn serialize(&self, f: &mut impl io::Write, gray: &str, rgb: &str, cmyk: &str, other: &str) -> Result<()> {
+        match *self {
+            Color::Gray(g) => writeln!(f, "{} {}", g, gray)?,
+            Color::Rgb(c) => writeln!(f, "{} {}", c, rgb)?,
+            Color::Cmyk(c) => writeln!(f, "{} {}", c, cmyk)?,
+            Color::Components(ref c) => writeln!(f, "{} {}", c.iter().format(" "), other)?,
+            Color::Pattern { ref name, ref components } => {
+                for c in components {
+                    write!(f, "{} ", c)?;
+                }
+                serialize_name(name, f)?;
+                writeln!(f, " {}", other)?;
+            }
+            Color::Other(ref args) => {
+                for p in args {
+                    p.serialize(f, 0)?;
+                    write!(f, " ")?;
+                }
+                writeln!(f, "{}", other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rewrite the (decoded) content stream `data`, see `Content::rewrite`.
+/// The operations of the result are appended to `ops`.
+fn rewrite_part(builder: &mut OpBuilder, data: &[u8], f: &mut impl FnMut(Op) -> Option<Op>, ops: &mut Vec<Op>) -> Result<Vec<u8>> {
+    let mut lexer = Lexer::new(data);
+    let mut buffer = Vec::with_capacity(5);
+    let mut out = Vec::with_capacity(data.len());
+    // end of the last operator
+    let mut start = 0;
+    while builder.next_operator(&mut lexer, data.len(), &mut buffer, &NoResolve)? {
+        let end = lexer.get_pos();
+        let old = std::mem::take(&mut builder.ops);
+        let new: Vec<Op> = old.iter().cloned().filter_map(&mut *f).collect();
+        if new.len() == old.len() && serialize_ops(&new)? == serialize_ops(&old)? {
+            // unchanged, including the whitespace and comments before it
+            out.extend_from_slice(&data[start .. end]);
+        } else if !new.is_empty() {
+            if out.last().is_some_and(|&b| !is_pdf_whitespace(b)) {
+                out.push(b'\n');
+            }
+            out.extend(serialize_ops(&new)?);
+        }
+        ops.extend(new);
+        start = end;
+    }
+    out.extend_from_slice(&data[start ..]);
+    Ok(out)
+}
+
+/// Number of operands of each operator, `None` if it varies
+const OPERATORS: &[(&str, Option<usize>)] = &[
+    ("b", Some(0)), ("B", Some(0)), ("b*", Some(0)), ("B*", Some(0)), ("BDC", Some(2)), ("BI", Some(0)),
+    ("BMC", Some(1)), ("BT", Some(0)), ("BX", Some(0)), ("c", Some(6)), ("cm", Some(6)), ("CS", Some(1)),
+    ("cs", Some(1)), ("d", Some(2)), ("d0", Some(2)), ("d1", Some(6)), ("Do", Some(1)), ("DP", Some(2)),
+    ("EI", Some(0)), ("EMC", Some(0)), ("ET", Some(0)), ("EX", Some(0)), ("f", Some(0)), ("F", Some(0)),
+    ("f*", Some(0)), ("G", Some(1)), ("g", Some(1)), ("gs", Some(1)), ("h", Some(0)), ("i", Some(1)),
+    ("ID", Some(0)), ("j", Some(1)), ("J", Some(1)), ("K", Some(4)), ("k", Some(4)), ("l", Some(2)),
+    ("m", Some(2)), ("M", Some(1)), ("MP", Some(1)), ("n", Some(0)), ("q", Some(0)), ("Q", Some(0)),
+    ("re", Some(4)), ("RG", Some(3)), ("rg", Some(3)), ("ri", Some(1)), ("s", Some(0)), ("S", Some(0)),
+    ("SC", None), ("SCN", None), ("sc", None), ("scn", None), ("sh", Some(1)), ("T*", Some(0)),
+    ("Tc", Some(1)), ("Td", Some(2)), ("TD", Some(2)), ("Tf", Some(2)), ("Tj", Some(1)), ("TJ", Some(1)),
+    ("TL", Some(1)), ("Tm", Some(6)), ("Tr", Some(1)), ("Ts", Some(1)), ("Tw", Some(1)), ("Tz", Some(1)),
+    ("v", Some(4)), ("w", Some(1)), ("W", Some(0)), ("W*", Some(0)), ("y", Some(4)), ("'", Some(1)),
+    ("\"", Some(3)),
+];
+
+/// A problem found by `Content::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Index of the content stream part
+    pub part: usize,
+    /// Offset of the operator in the decoded part
+    pub pos: usize,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// Not a PDF operator, outside of a `BX` / `EX` section
+    UnknownOperator(String),
+    /// The operator has `found` operands instead of `expected`
+    OperandCount { operator: String, expected: usize, found: usize },
+    /// The operands have the wrong type or value
+    InvalidOperands { operator: String, msg: String },
+    /// `Q` without `q`
+    UnbalancedRestore,
+    /// `q` still open at the end of the content
+    UnclosedSave,
+    /// `BT` inside a text object
+    NestedText,
+    /// `ET` outside of a text object
+    UnbalancedEndText,
+    /// text object still open at the end of the content
+    UnclosedText,
+    /// `EMC` without `BMC` / `BDC`
+    UnbalancedEndMarkedContent,
+    /// marked content still open at the end of the content
+    UnclosedMarkedContent,
+    /// The rest of the part could not be read
+    Syntax(String),
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "part {} at {}: ", self.part, self.pos)?;
+        match self.kind {
+            DiagnosticKind::UnknownOperator(ref op) => write!(f, "unknown operator {}", op),
+            DiagnosticKind::OperandCount { ref operator, expected, found } => write!(f, "{} takes {} operands, found {}", operator, expected, found),
+            DiagnosticKind::InvalidOperands { ref operator, ref msg } => write!(f, "invalid operands for {}: {}", operator, msg),
+            DiagnosticKind::UnbalancedRestore => write!(f, "Q without q"),
+            DiagnosticKind::UnclosedSave => write!(f, "q without Q"),
+            DiagnosticKind::NestedText => write!(f, "BT inside a text object"),
+            DiagnosticKind::UnbalancedEndText => write!(f, "ET without BT"),
+            DiagnosticKind::UnclosedText => write!(f, "BT without ET"),
+            DiagnosticKind::UnbalancedEndMarkedContent => write!(f, "EMC without BMC or BDC"),
+            DiagnosticKind::UnclosedMarkedContent => write!(f, "BMC or BDC without EMC"),
+            DiagnosticKind::Syntax(ref msg) => write!(f, "syntax error: {}", msg),
+        }
+    }
+}
+
+/// Nesting of `q`, `BT` and marked content while validating
+#[derive(Default)]
+struct Nesting {
+    saves: usize,
+    text: bool,
+    marked: usize,
+}
+
+fn validate_part(builder: &mut OpBuilder, data: &[u8], part: usize, buffer: &mut Vec<Primitive>, nesting: &mut Nesting, out: &mut Vec<Diagnostic>) {
+    let mut lexer = Lexer::new(data);
+    let mut report = |pos, kind| out.push(Diagnostic { part, pos, kind });
+
+    while lexer.get_pos() < data.len() {
+        let backup_pos = lexer.get_pos();
+        match parse_with_lexer(&mut lexer, &NoResolve) {
+            Ok(obj) => buffer.push(obj),
+            Err(e) if e.is_eof() => break,
+            Err(_) => {
+                lexer.set_pos(backup_pos);
+                let word = match lexer.next() {
+                    Ok(word) => word,
+                    Err(e) => {
+                        report(backup_pos, DiagnosticKind::Syntax(e.to_string()));
+                        break;
+                    }
+                };
+                let pos = lexer.get_pos() - word.as_slice().len();
+                let operator = word.to_string();
+                let found = buffer.len();
+                let count = OPERATORS.iter().find(|&&(name, _)| name == operator).map(|&(_, count)| count);
+                let mut checked = true;
+                match count {
+                    None if !builder.compability_section => {
+                        report(pos, DiagnosticKind::UnknownOperator(operator.clone()));
+                        checked = false;
+                    }
+                    Some(Some(expected)) if expected != found => {
+                        report(pos, DiagnosticKind::OperandCount { operator: operator.clone(), expected, found });
+                        checked = false;
+                    }
+                    _ => {}
+                }
+                let result = builder.add(&operator, buffer.drain(..), &mut lexer, &NoResolve);
+                builder.ops.clear();
+                if let Err(e) = result {
+                    if operator == "BI" {
+                        report(pos, DiagnosticKind::Syntax(format!("invalid inline image: {}", e)));
+                        break;
+                    }
+                    if checked {
+                        report(pos, DiagnosticKind::InvalidOperands { operator: operator.clone(), msg: e.to_string() });
+                    }
+                }
+                match operator.as_str() {
+                    "q" => nesting.saves += 1,
+                    "Q" if nesting.saves == 0 => report(pos, DiagnosticKind::UnbalancedRestore),
+                    "Q" => nesting.saves -= 1,
+                    "BT" if nesting.text => report(pos, DiagnosticKind::NestedText),
+                    "BT" => nesting.text = true,
+                    "ET" if !nesting.text => report(pos, DiagnosticKind::UnbalancedEndText),
+                    "ET" => nesting.text = false,
+                    "BMC" | "BDC" => nesting.marked += 1,
+                    "EMC" if nesting.marked == 0 => report(pos, DiagnosticKind::UnbalancedEndMarkedContent),
+                    "EMC" => nesting.marked -= 1,
+                    _ => {}
+                }
+            }
         }
-        ops = &ops[advance..];
     }
-    Ok(data)
 }
 
 impl Content {
@@ -710,6 +1138,112 @@ impl Content {
             parts: vec![Stream::new((), data)]
         }
     }
+
+    /// Parse the operations from `parts` again, one operator at a time.
+    ///
+    /// Loading a `Content` already parses all of `operations`, so this saves no memory by itself.
+    /// To go through a huge content stream without holding all of its operations, load it as
+    /// a plain `Stream<()>` and use `Ops::new` on its data. Iteration stops after the first error.
+    pub fn operations_lazy(&self) -> Ops<'_> {
+        Ops {
+            parts: self.parts.iter(),
+            current: None,
+            builder: OpBuilder::new(),
+            buffer: Vec::with_capacity(5),
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+    /// Apply `f` to every operation, and remove those it returns `None` for.
+    ///
+    /// Operators that `f` leaves as they are (as far as their serialization goes)
+    /// are copied byte for byte from the original streams, only the others are serialized again.
+    /// The result has a single unfiltered part, as operators may be split between the parts of `self`.
+    pub fn rewrite(&self, mut f: impl FnMut(Op) -> Option<Op>) -> Result<Content> {
+        let data = match *self.parts {
+            [ref part] => t!(part.data()).to_vec(),
+            ref parts => {
+                let mut data = Vec::new();
+                for part in parts {
+                    data.extend_from_slice(t!(part.data()));
+                    data.push(b'\n');
+                }
+                data
+            }
+        };
+        let mut operations = vec![];
+        let data = rewrite_part(&mut OpBuilder::new(), &data, &mut f, &mut operations)?;
+        Ok(Content { parts: vec![Stream::new((), data)], operations })
+    }
+
+    /// Insert `ops` before the existing operations, as a new part.
+    ///
+    /// They are wrapped in `q` / `Q`, so they don't change the graphics state of the original content.
+    pub fn prepend(&mut self, ops: Vec<Op>) -> Result<()> {
+        let mut wrapped = Vec::with_capacity(ops.len() + 2);
+        wrapped.push(Op::Save);
+        wrapped.extend(ops);
+        wrapped.push(Op::Restore);
+        self.parts.insert(0, Stream::new((), serialize_ops(&wrapped)?));
+        self.operations.splice(0 .. 0, wrapped);
+        Ok(())
+    }
+
+    /// Add `ops` after the existing operations, as a new part.
+    ///
+    /// The existing content is wrapped in `q` / `Q` (closing any saves it leaves open),
+    /// so `ops` start with the initial graphics state of the page.
+    /// The original parts are kept as they are.
+    pub fn append(&mut self, ops: Vec<Op>) -> Result<()> {
+        let open = self.operations.iter().fold(0usize, |depth, op| match *op {
+            Op::Save => depth + 1,
+            Op::Restore => depth.saturating_sub(1),
+            _ => depth
+        });
+        let mut tail = vec![Op::Restore; open + 1];
+        tail.extend(ops);
+        self.parts.insert(0, Stream::new((), serialize_ops(&[Op::Save])?));
+        self.parts.push(Stream::new((), serialize_ops(&tail)?));
+        self.operations.insert(0, Op::Save);
+        self.operations.extend(tail);
+        Ok(())
+    }
+
+    /// Check the content streams in `parts` thoroughly.
+    ///
+    /// Reports unknown operators, wrong numbers or types of operands and unbalanced
+    /// `q` / `Q`, `BT` / `ET` and marked content, where parsing would ignore them or fail.
+    pub fn validate(&self) -> Result<Vec<Diagnostic>> {
+        let mut builder = OpBuilder::new();
+        let mut nesting = Nesting::default();
+        let mut diagnostics = vec![];
+        // operands may continue in the next part
+        let mut buffer = Vec::with_capacity(5);
+        for (i, part) in self.parts.iter().enumerate() {
+            let data = t!(part.data());
+            validate_part(&mut builder, data, i, &mut buffer, &mut nesting, &mut diagnostics);
+        }
+        let end = self.parts.len().saturating_sub(1);
+        let pos = self.parts.last().and_then(|part| part.data().ok()).map_or(0, |data| data.len());
+        let mut report = |kind| diagnostics.push(Diagnostic { part: end, pos, kind });
+        if nesting.saves > 0 {
+            report(DiagnosticKind::UnclosedSave);
+        }
+        if nesting.text {
+            report(DiagnosticKind::UnclosedText);
+        }
+        if nesting.marked > 0 {
+            report(DiagnosticKind::UnclosedMarkedContent);
+        }
+        Ok(diagnostics)
+    }
+
+    /// Serialize the operations into a single content stream.
+    ///
+    /// Parsing the result gives the same operations again.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serialize_ops(&self.operations)
+    }
 }
 
 impl ObjectWrite for Content {
@@ -854,6 +1388,47 @@ impl Default for Matrix {
         }
     }
 }
+impl Matrix {
+    pub fn translate(x: f32, y: f32) -> Matrix {
+        Matrix { e: x, f: y, ..Matrix::default() }
+    }
+    /// Apply the matrix to the point `p`
+    pub fn transform(&self, p: Point) -> Point {
+        Point {
+            x: self.a * p.x + self.c * p.y + self.e,
+            y: self.b * p.x + self.d * p.y + self.f,
+        }
+    }
+}
+impl Object for Matrix {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let arr = p.into_array(resolve)?;
+        if arr.len() != 6 {
+            bail!("matrix needs 6 numbers, found {}", arr.len());
+        }
+        matrix(&mut arr.into_iter())
+    }
+}
+impl ObjectWrite for Matrix {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        let Matrix { a, b, c, d, e, f } = *self;
+        Primitive::array::<f32, _, _, _>([a, b, c, d, e, f].iter(), update)
+    }
+}
+/// `a * b` first applies `a`, then `b` (the order used in the spec: `Tm × CTM`)
+impl Mul for Matrix {
+    type Output = Matrix;
+    fn mul(self, m: Matrix) -> Matrix {
+        Matrix {
+            a: self.a * m.a + self.b * m.c,
+            b: self.a * m.b + self.b * m.d,
+            c: self.c * m.a + self.d * m.c,
+            d: self.c * m.b + self.d * m.d,
+            e: self.e * m.a + self.f * m.c + m.e,
+            f: self.e * m.b + self.f * m.d + m.f,
+        }
+    }
+}
 #[cfg(feature = "euclid")]
 impl Into<euclid::Transform2D<f32, PdfSpace, PdfSpace>> for Matrix {
     fn into(self) -> euclid::Transform2D<f32, PdfSpace, PdfSpace> {
@@ -878,8 +1453,24 @@ pub enum Color {
     Gray(f32),
     Rgb(Rgb),
     Cmyk(Cmyk),
+    /// Components in the current color space (`sc`, `scn`)
+    Components(Vec<f32>),
+    /// A pattern (`scn`), with the components of the underlying color space for uncolored patterns
+    Pattern { name: String, components: Vec<f32> },
+    /// Any other operands, written as they are
     Other(Vec<Primitive>),
 }
+impl Color {
+    /// The color as RGB if it is a device color, with CMYK converted naively (see `Cmyk::to_rgb`)
+    pub fn to_rgb(&self) -> Option<Rgb> {
+        match *self {
+            Color::Gray(g) => Some(Rgb { red: g, green: g, blue: g }),
+            Color::Rgb(rgb) => Some(rgb),
+            Color::Cmyk(cmyk) => Some(cmyk.to_rgb()),
+            _ => None
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TextMode {
@@ -915,6 +1506,19 @@ impl Display for Cmyk {
         write!(f, "{} {} {} {}", self.cyan, self.magenta, self.yellow, self.key)
     }
 }
+impl Cmyk {
+    /// Naive conversion without color management, `(1 - c) * (1 - k)` for red.
+    /// For the colors a printer would produce, use the profile of the output intent with
+    /// `icc::Profile` (`icc` feature).
+    pub fn to_rgb(self) -> Rgb {
+        let Cmyk { cyan, magenta, yellow, key } = self;
+        Rgb {
+            red: (1. - cyan) * (1. - key),
+            green: (1. - magenta) * (1. - key),
+            blue: (1. - yellow) * (1. - key),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum TextDrawAdjusted {
@@ -955,6 +1559,26 @@ pub enum Op {
     /// generated by operators `MP` and `DP`.
     MarkedContentPoint { tag: String, properties: Option<Primitive> },
 
+    /// Begin a compatibility section, in which unknown operators are ignored.
+    ///
+    /// generated by operator `BX`
+    BeginCompatibility,
+
+    /// End a compatibility section.
+    ///
+    /// generated by operator `EX`
+    EndCompatibility,
+
+    /// Set the glyph width in a Type 3 glyph description.
+    ///
+    /// generated by operator `d0`
+    CharWidth { wx: f32, wy: f32 },
+
+    /// Set the glyph width and bounding box in a Type 3 glyph description.
+    ///
+    /// generated by operator `d1`
+    CharBoundingBox { wx: f32, wy: f32, bbox: ViewRect },
+
 
     Close,
     MoveTo { p: Point },
@@ -1032,5 +1656,154 @@ pub enum Op {
 
     XObject { name: String },
 
+    /// An inline image (`BI` … `ID` … `EI`), as an image XObject.
+    ///
+    /// Abbreviated keys and names are expanded.
     InlineImage { image: Stream::<ImageDict> },
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &[u8]) -> Vec<Op> {
+        let mut ops = OpBuilder::new();
+        ops.parse(data, &NoResolve).unwrap();
+        ops.ops
+    }
+    #[test]
+    fn operators() {
+        let ops = parse(b"BX /Sh0 sh 0.5 1 0 sc /P0 scn 0.2 /P1 SCN 500 0 d0 500 0 0 -10 450 700 d1 foo EX");
+        assert!(matches!(ops[0], Op::BeginCompatibility));
+        assert!(matches!(ops[1], Op::Shade { ref name } if name == "Sh0"));
+        assert!(matches!(ops[2], Op::FillColor { color: Color::Components(ref c) } if c == &[0.5, 1., 0.]));
+        assert!(matches!(ops[3], Op::FillColor { color: Color::Pattern { ref name, ref components } } if name == "P0" && components.is_empty()));
+        assert!(matches!(ops[4], Op::StrokeColor { color: Color::Pattern { ref name, ref components } } if name == "P1" && components == &[0.2]));
+        assert!(matches!(ops[5], Op::CharWidth { wx, wy } if wx == 500. && wy == 0.));
+        assert!(matches!(ops[6], Op::CharBoundingBox { bbox, .. } if bbox.bottom == -10. && bbox.top == 700.));
+        // unknown operators are ignored in compatibility sections
+        assert!(matches!(ops[7], Op::EndCompatibility));
+        assert_eq!(ops.len(), 8);
+    }
+
+    #[test]
+    fn rewrite() {
+        let data: &[u8] = b"q 1 0 0 RG\n/F1 12 Tf BT 72 700 Td (secret) Tj ET % keep\n0 0 10 10 re b Q";
+        let content = Content { parts: vec![Stream::new((), data.to_vec())], operations: parse(data) };
+
+        let redacted = content.rewrite(|op| match op {
+            Op::TextDraw { ref text } if text.as_bytes() == b"secret" => None,
+            Op::StrokeColor { .. } => Some(Op::StrokeColor { color: Color::Gray(0.) }),
+            op => Some(op)
+        }).unwrap();
+        let out = redacted.parts[0].data().unwrap();
+        assert_eq!(out, &b"q\n0 G\n\n/F1 12 Tf BT 72 700 Td ET % keep\n0 0 10 10 re b Q"[..]);
+        assert_eq!(format!("{:?}", redacted.operations), format!("{:?}", parse(out)));
+
+        // nothing changed
+        let same = content.rewrite(Some).unwrap();
+        assert_eq!(same.parts[0].data().unwrap(), data);
+    }
+
+    #[test]
+    fn split_parts() {
+        let part = |data: &[u8]| {
+            let mut info = Dictionary::new();
+            info.insert("Length", Primitive::Integer(data.len() as i32));
+            Primitive::Stream(PdfStream { info, data: data.to_vec() })
+        };
+        let contents = Primitive::Array(vec![part(b"q 1 0 0"), part(b"1 0 0 cm BT /F1"), part(b"12 Tf ET Q")]);
+        let content = Content::from_primitive(contents, &NoResolve).unwrap();
+        assert_eq!(content.parts.len(), 3);
+        let ops = format!("{:?}", content.operations);
+        assert_eq!(ops, format!("{:?}", parse(b"q 1 0 0 1 0 0 cm BT /F1 12 Tf ET Q")));
+
+        let lazy: Vec<Op> = content.operations_lazy().collect::<Result<_>>().unwrap();
+        assert_eq!(format!("{:?}", lazy), ops);
+        assert_eq!(content.validate().unwrap(), []);
+        let rewritten = content.rewrite(Some).unwrap();
+        assert_eq!(rewritten.parts[0].data().unwrap(), &b"q 1 0 0\n1 0 0 cm BT /F1\n12 Tf ET Q\n"[..]);
+    }
+
+    #[test]
+    fn validate() {
+        use DiagnosticKind::*;
+
+        let data: &[u8] = b"q Q Q BT BT 1 0 0 rg 1 2 re foo /F1 Tf 1 Tj ET ET q /Span BMC";
+        let content = Content { parts: vec![Stream::new((), data.to_vec())], operations: vec![] };
+        let kinds: Vec<_> = content.validate().unwrap().into_iter().map(|d| match d.kind {
+            // don't depend on the wording of the error
+            InvalidOperands { operator, .. } => (d.pos, InvalidOperands { operator, msg: String::new() }),
+            kind => (d.pos, kind)
+        }).collect();
+        assert_eq!(kinds, [
+            (4, UnbalancedRestore),
+            (9, NestedText),
+            (25, OperandCount { operator: "re".into(), expected: 4, found: 2 }),
+            (28, UnknownOperator("foo".into())),
+            (36, OperandCount { operator: "Tf".into(), expected: 2, found: 1 }),
+            (41, InvalidOperands { operator: "Tj".into(), msg: String::new() }),
+            (47, UnbalancedEndText),
+            (61, UnclosedSave),
+            (61, UnclosedMarkedContent),
+        ][..]);
+    }
+
+    #[test]
+    fn serialize() {
+        let data: &[u8] = b"/OC /MC0 BDC q 1 0 0 1 10 20 cm 0.5 g 1 0 0 RG [2 1] 0 d\n\
+            0 0 m 10 0 l 1 2 3 4 5 6 c 1 2 5 6 v 1 2 5 6 y 0 0 10 10 re b W* n Q EMC\n\
+            BT /F1 12 Tf 0 -14 TD (a\\(b) Tj [(x) -120 (y)] TJ 1 2 (z) \" (w) ' ET\n\
+            BI /W 1 /H 1 /BPC 8 /CS /G ID \x7f\nEI /Im0 Do /Perceptual ri";
+        let ops = parse(data);
+        let content = Content { parts: vec![], operations: ops.clone() };
+        let bytes = content.to_bytes().unwrap();
+        assert_eq!(format!("{:?}", parse(&bytes)), format!("{:?}", ops), "{}", String::from_utf8_lossy(&bytes));
+    }
+
+    fn image(op: &Op) -> &Stream<ImageDict> {
+        match op {
+            Op::InlineImage { image } => image,
+            op => panic!("expected an inline image, got {:?}", op)
+        }
+    }
+
+    #[test]
+    fn inline_image() {
+        // the size is known, so `EI` in the data doesn't end it
+        let ops = parse(b"q BI /W 2 /H 2 /BPC 8 /CS /G ID EI x\n EI Q");
+        assert_eq!(ops.len(), 3);
+        let img = image(&ops[1]);
+        assert_eq!((img.width, img.height), (2, 2));
+        assert_eq!(img.color_space.as_ref().unwrap().as_name().unwrap(), "DeviceGray");
+        assert_eq!(img.data().unwrap(), b"EI x");
+        assert!(matches!(ops[2], Op::Restore));
+
+        // image masks don't need BitsPerComponent and ColorSpace
+        let ops = parse(b"BI /IM true /W 8 /H 1 /F /AHx ID ff> EI");
+        let img = image(&ops[0]);
+        assert!(img.image_mask);
+        assert_eq!(img.bits_per_component, 1);
+        assert_eq!(img.data().unwrap(), [0xff]);
+
+        // unknown size: skip `EI` followed by binary data
+        let ops = parse(b"BI /W 1 /H 1 /BPC 8 /CS /Cs0 ID a EI\x01\x02 b\nEI Q\n");
+        assert_eq!(ops.len(), 2);
+        assert_eq!(image(&ops[0]).data().unwrap(), b"a EI\x01\x02 b");
+
+        // sizes that are negative or overflow are unknown, too
+        let ops = parse(b"BI /W -1 /H 2 /BPC 8 /CS /G ID ab EI Q");
+        assert_eq!(image(&ops[0]).data().unwrap(), b"ab");
+        let ops = parse(b"BI /W 2147483647 /H 2147483647 /BPC 8 /CS /CMYK ID ab EI Q");
+        assert_eq!(ops.len(), 2);
+        assert_eq!(image(&ops[0]).data().unwrap(), b"ab");
+    }
+
+    #[test]
+    fn color_to_rgb() {
+        let rgb = |c: Color| c.to_rgb().map(|c| [c.red, c.green, c.blue]);
+        assert_eq!(rgb(Color::Gray(0.5)), Some([0.5; 3]));
+        assert_eq!(rgb(Color::Cmyk(Cmyk { cyan: 1., magenta: 0., yellow: 0.5, key: 0. })), Some([0., 1., 0.5]));
+        assert_eq!(rgb(Color::Cmyk(Cmyk { cyan: 0., magenta: 0., yellow: 0., key: 0.5 })), Some([0.5; 3]));
+        assert_eq!(rgb(Color::Components(vec![0.5])), None);
+    }
+}
@@ -9,7 +9,7 @@ use sha2::{Digest, Sha256, Sha384, Sha512};
 use std::fmt;
 use std::collections::HashMap;
 use crate::object::PlainRef;
-use crate::primitive::{Dictionary, PdfString};
+use crate::primitive::{Dictionary, PdfStream, PdfString, Primitive};
 use crate::error::{PdfError, Result};
 
 const PADDING: [u8; 32] = [
@@ -57,18 +57,21 @@ impl Rc4 {
 }
 
 /// 7.6.1 Table 20 + 7.6.3.2 Table 21
-#[derive(Object, Debug, Clone)]
+///
+/// `O`, `U`, `R` and `P` belong to the standard security handler,
+/// dictionaries of the public-key handler (7.6.4) don't have them.
+#[derive(Object, ObjectWrite, Debug, Clone)]
 pub struct CryptDict {
-    #[pdf(key="O")]
+    #[pdf(key="O", default="PdfString::new(vec![])")]
     o: PdfString,
 
-    #[pdf(key="U")]
+    #[pdf(key="U", default="PdfString::new(vec![])")]
     u: PdfString,
 
-    #[pdf(key="R")]
+    #[pdf(key="R", default="0")]
     r: u32,
 
-    #[pdf(key="P")]
+    #[pdf(key="P", default="-1")]
     p: i32,
 
     #[pdf(key="V")]
@@ -83,6 +86,12 @@ pub struct CryptDict {
     #[pdf(key="StmF")]
     default_crypt_filter: Option<String>,
 
+    #[pdf(key="StrF")]
+    string_crypt_filter: Option<String>,
+
+    #[pdf(key="EFF")]
+    embedded_file_crypt_filter: Option<String>,
+
     #[pdf(key="EncryptMetadata", default="true")]
     encrypt_metadata: bool,
 
@@ -96,7 +105,59 @@ pub struct CryptDict {
     _other: Dictionary
 }
 
-#[derive(Object, Debug, Clone, Copy)]
+impl CryptDict {
+    /// The permissions of the user password
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_p(self.p)
+    }
+
+    /// The security handler (`Filter`), `Standard` for passwords and `Adobe.PubSec` for certificates
+    pub fn filter(&self) -> &str {
+        match self._other.get("Filter") {
+            Some(Primitive::Name(ref name)) => name.as_str(),
+            _ => "Standard"
+        }
+    }
+
+    /// The CMS envelopes of the recipients of a document encrypted with the public-key handler.
+    ///
+    /// They are in the crypt filter of streams from version 4 on and in the dictionary before.
+    fn recipients(&self) -> Result<Vec<&[u8]>> {
+        let recipients = match self.default_crypt_filter {
+            Some(ref name) if self.v >= 4 => self.crypt_filters.get(name).and_then(|f| f._other.get("Recipients")),
+            _ => self._other.get("Recipients")
+        };
+        match recipients {
+            Some(Primitive::String(ref s)) => Ok(vec![s.as_bytes()]),
+            Some(Primitive::Array(ref array)) => array.iter().map(|p| Ok(p.as_string()?.as_bytes())).collect(),
+            Some(p) => bail!("Recipients should be an array of strings, found {:?}", p),
+            None => err!(PdfError::MissingEntry { typ: "Encrypt", field: "Recipients".into() })
+        }
+    }
+
+    /// `EncryptMetadata` of the crypt filter of streams, or of the dictionary
+    fn metadata_encrypted(&self) -> bool {
+        let filter = self.default_crypt_filter.as_ref().and_then(|name| self.crypt_filters.get(name));
+        match filter.and_then(|f| f._other.get("EncryptMetadata")) {
+            Some(&Primitive::Boolean(b)) => b,
+            _ => self.encrypt_metadata
+        }
+    }
+
+    /// Method and key length (in bits) of the crypt filter `name`, `Identity` if there is none
+    fn crypt_filter(&self, name: Option<&str>) -> Result<(CryptMethod, Option<u32>)> {
+        match name {
+            None | Some("Identity") => Ok((CryptMethod::None, None)),
+            Some(name) => match self.crypt_filters.get(name) {
+                // the length of a crypt filter is given in bytes, but some writers use bits
+                Some(filter) => Ok((filter.method, filter.length.map(|n| if n <= 32 { 8 * n } else { n }))),
+                None => bail!("crypt filter {} not found", name)
+            }
+        }
+    }
+}
+
+#[derive(Object, ObjectWrite, Debug, Clone, Copy)]
 pub enum CryptMethod {
     None,
     V2,
@@ -104,13 +165,13 @@ pub enum CryptMethod {
     AESV3,
 }
 
-#[derive(Object, Debug, Clone, Copy)]
+#[derive(Object, ObjectWrite, Debug, Clone, Copy)]
 pub enum AuthEvent {
     DocOpen,
     EFOpen
 }
 
-#[derive(Object, Debug, Clone)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 #[pdf(Type="CryptFilter?")]
 pub struct CryptFilter {
     #[pdf(key="CFM", default="CryptMethod::None")]
@@ -126,10 +187,117 @@ pub struct CryptFilter {
     _other: Dictionary
 }
 
+fn compute_u_rev_3_4(id: &[u8], key: &[u8]) -> Vec<u8> {
+    // algorithm 5
+    // a) we derived the key already.
+
+    // b)
+    let mut hash = md5::Context::new();
+    hash.consume(PADDING);
+
+    // c)
+    hash.consume(id);
+
+    // d)
+    let mut data = *hash.compute();
+    Rc4::encrypt(key, &mut data);
+
+    // e)
+    for i in 1u8..=19 {
+        let mut key = key.to_owned();
+        for b in &mut key {
+            *b ^= i;
+        }
+        Rc4::encrypt(&key, &mut data);
+    }
+
+    // f)
+    data.to_vec()
+}
+
+fn key_derivation_user_password_rc4(
+    revision: u32,
+    key_size: usize,
+    dict: &CryptDict,
+    id: &[u8],
+    pass: &[u8],
+) -> [u8; 32] {
+    let o = dict.o.as_bytes();
+    let p = dict.p;
+    // 7.6.3.3 - Algorithm 2
+    // a) and b)
+    let mut hash = md5::Context::new();
+    if pass.len() < 32 {
+        hash.consume(pass);
+        hash.consume(&PADDING[..32 - pass.len()]);
+    } else {
+        hash.consume(&pass[..32]);
+    }
+
+    // c)
+    hash.consume(o);
+
+    // d)
+    hash.consume(p.to_le_bytes());
+
+    // e)
+    hash.consume(id);
+
+    // f)
+    if revision >= 4 && !dict.encrypt_metadata {
+        hash.consume([0xff, 0xff, 0xff, 0xff]);
+    }
+
+    // g)
+    let mut data = *hash.compute();
+
+    // h)
+    if revision >= 3 {
+        for _ in 0..50 {
+            data = *md5::compute(&data[..key_size]);
+        }
+    }
+
+    let mut key = [0u8; 32];
+    key[..16].copy_from_slice(&data);
+    key
+}
+
+fn key_derivation_owner_password_rc4(
+    revision: u32,
+    key_size: usize,
+    pass: &[u8],
+) -> Vec<u8> {
+    let mut hash = md5::Context::new();
+    if pass.len() < 32 {
+        hash.consume(pass);
+        hash.consume(&PADDING[..32 - pass.len()]);
+    } else {
+        hash.consume(&pass[..32]);
+    }
+
+    if revision >= 3 {
+        for _ in 0..50 {
+            let digest = *std::mem::replace(&mut hash, md5::Context::new()).compute();
+            hash.consume(digest);
+        }
+    }
+
+    let digest = &hash.compute()[..key_size];
+    digest.to_vec()
+}
+
 pub struct Decoder {
     key_size: usize,
     key: [u8; 32], // maximum length
+    /// method of streams (`StmF`)
     method: CryptMethod,
+    /// method of strings (`StrF`)
+    string_method: CryptMethod,
+    /// method of embedded file streams (`EFF`)
+    embedded_file_method: CryptMethod,
+    /// methods of the crypt filters, for streams with a `Crypt` filter
+    filters: HashMap<String, CryptMethod>,
     /// A reference to the /Encrypt dictionary, if it is in an indirect
     /// object. The strings in this dictionary are not encrypted, so
     /// decryption must be skipped when accessing them.
@@ -142,6 +310,10 @@ pub struct Decoder {
     /// Whether the metadata is encrypted, as indicated by /EncryptMetadata
     /// in the /Encrypt dictionary.
     encrypt_metadata: bool,
+    /// The owner password was given, which grants all permissions
+    owner: bool,
+    /// Permissions of the recipient, for documents encrypted with the public-key handler
+    recipient_permissions: Option<Permissions>,
 }
 impl Decoder {
     pub fn default(dict: &CryptDict, id: &[u8]) -> Result<Decoder> {
@@ -157,13 +329,125 @@ impl Decoder {
             key_size,
             key,
             method,
+            string_method: method,
+            embedded_file_method: method,
+            filters: HashMap::new(),
             encrypt_indirect_object: None,
             metadata_indirect_object: None,
             encrypt_metadata,
+            owner: false,
+            recipient_permissions: None,
         }
     }
 
+    /// Whether the document was opened with the owner password, not the user password
+    pub fn is_owner(&self) -> bool {
+        self.owner
+    }
+
+    /// The permissions granted to the recipient that opened a document encrypted for certificates
+    pub fn recipient_permissions(&self) -> Option<Permissions> {
+        self.recipient_permissions
+    }
+
     pub fn from_password(dict: &CryptDict, id: &[u8], pass: &[u8]) -> Result<Decoder> {
+        if dict.filter() != "Standard" {
+            bail!("the document is encrypted with the {} security handler, not with a password", dict.filter());
+        }
+        Decoder::from_dict(dict, |key_bits, method| Decoder::authenticate(dict, id, pass, key_bits, method))
+    }
+
+    /// Open a document encrypted with the public-key security handler (`Adobe.PubSec`).
+    ///
+    /// `decrypt` gets the CMS (PKCS#7) enveloped data of each recipient and returns its
+    /// content if the envelope was encrypted for the private key of the caller:
+    /// a 20 byte seed, followed by 4 bytes of permissions.
+    /// It returns `None` for envelopes of other recipients.
+    pub fn from_recipients(dict: &CryptDict, mut decrypt: impl FnMut(&[u8]) -> Option<Vec<u8>>) -> Result<Decoder> {
+        if dict.filter() != "Adobe.PubSec" {
+            bail!("the document is encrypted with the {} security handler, not for certificates", dict.filter());
+        }
+        let recipients = t!(dict.recipients());
+        let content = match recipients.iter().find_map(|&r| decrypt(r)) {
+            Some(content) if content.len() >= 20 => content,
+            Some(content) => bail!("the decrypted envelope has {} bytes, expected at least 20", content.len()),
+            None => err!(PdfError::InvalidPassword)
+        };
+        let encrypt_metadata = dict.metadata_encrypted();
+        Decoder::from_dict(dict, |key_bits, method| {
+            // 7.6.4.3.3: a hash of the seed and all recipients
+            let mut data = content[.. 20].to_vec();
+            for r in &recipients {
+                data.extend_from_slice(r);
+            }
+            if !encrypt_metadata && dict.v >= 4 {
+                data.extend_from_slice(&[0xff; 4]);
+            }
+            // the length is read from the dictionary, the hashes below give at most 20 (SHA-1) or 32 bytes
+            let valid = match method {
+                CryptMethod::AESV3 => key_bits == 256,
+                _ => key_bits.is_multiple_of(8) && (40 ..= 128).contains(&key_bits)
+            };
+            if !valid {
+                err!(format!("unsupported key length of {} bits for {:?}", key_bits, method).into())
+            }
+            let mut key = [0u8; 32];
+            match method {
+                CryptMethod::AESV3 => key.copy_from_slice(&Sha256::digest(&data)),
+                _ => key[.. 20].copy_from_slice(&sha1(&data)),
+            }
+            let key_size = key_bits as usize / 8;
+            let mut decoder = Decoder::new(key, key_size, method, encrypt_metadata);
+            if let Some(p) = content.get(20 .. 24) {
+                decoder.recipient_permissions = Some(Permissions::from_p(i32::from_be_bytes([p[0], p[1], p[2], p[3]])));
+            }
+            Ok(decoder)
+        })
+    }
+
+    /// Find the crypt methods and key length of `dict` and create the decoder with
+    /// the key from `derive_key`, which gets the key length in bits and the method
+    fn from_dict(dict: &CryptDict, derive_key: impl FnOnce(u32, CryptMethod) -> Result<Decoder>) -> Result<Decoder> {
+        let (key_bits, method) = match dict.v {
+            1 => (40, CryptMethod::V2),
+            2 => (dict.bits, CryptMethod::V2),
+            4 ..= 6 => {
+                let (stream_method, stream_bits) = t!(dict.crypt_filter(dict.default_crypt_filter.as_deref()));
+                let (string_method, string_bits) = t!(dict.crypt_filter(dict.string_crypt_filter.as_deref()));
+                let embedded_file = match dict.embedded_file_crypt_filter {
+                    Some(ref name) => t!(dict.crypt_filter(Some(name))).0,
+                    None => stream_method
+                };
+
+                // all crypt filters share the file key, its length is given by the ones in use
+                let used = [stream_method, string_method, embedded_file];
+                if used.iter().any(|m| matches!(m, CryptMethod::AESV3)) && used.iter().any(|m| matches!(m, CryptMethod::V2 | CryptMethod::AESV2)) {
+                    bail!("crypt filters {:?} need file keys of different lengths", used);
+                }
+                let (method, bits) = match (stream_method, string_method) {
+                    (CryptMethod::None, m) => (m, string_bits),
+                    (m, _) => (m, stream_bits)
+                };
+                let key_bits = match method {
+                    CryptMethod::V2 | CryptMethod::None => bits.unwrap_or(dict.bits),
+                    CryptMethod::AESV2 => 128,
+                    CryptMethod::AESV3 if dict.v == 5 => 256,
+                    m => err!(format!("unimplemented crypt method {:?}", m).into()),
+                };
+                let mut decoder = t!(derive_key(key_bits, method));
+                decoder.method = stream_method;
+                decoder.string_method = string_method;
+                decoder.embedded_file_method = embedded_file;
+                decoder.filters = dict.crypt_filters.iter().map(|(name, filter)| (name.clone(), filter.method)).collect();
+                return Ok(decoder);
+            }
+            v => err!(format!("unsupported V value {}", v).into()),
+        };
+        derive_key(key_bits, method)
+    }
+
+    /// Check the password `pass` and derive the file key of `key_bits` bits for `method`
+    fn authenticate(dict: &CryptDict, id: &[u8], pass: &[u8], key_bits: u32, method: CryptMethod) -> Result<Decoder> {
         fn compute_u_rev_2(key: &[u8]) -> Vec<u8> {
             // algorithm 4
             let mut data = PADDING.to_vec();
@@ -175,36 +459,8 @@ impl Decoder {
             compute_u_rev_2(key) == document_u
         }
 
-        fn compute_u_rev_3_4(id: &[u8], key: &[u8]) -> Vec<u8> {
-            // algorithm 5
-            // a) we derived the key already.
-
-            // b)
-            let mut hash = md5::Context::new();
-            hash.consume(&PADDING);
-
-            // c)
-            hash.consume(id);
-
-            // d)
-            let mut data = *hash.compute();
-            Rc4::encrypt(key, &mut data);
-
-            // e)
-            for i in 1u8..=19 {
-                let mut key = key.to_owned();
-                for b in &mut key {
-                    *b ^= i;
-                }
-                Rc4::encrypt(&key, &mut data);
-            }
-
-            // f)
-            data.to_vec()
-        }
-
         fn check_password_rev_3_4(document_u: &[u8], id: &[u8], key: &[u8]) -> bool {
-            compute_u_rev_3_4(id, key) == &document_u[..16]
+            document_u.get(..16) == Some(&compute_u_rev_3_4(id, key)[..])
         }
 
         fn check_password_rc4(revision: u32, document_u: &[u8], id: &[u8], key: &[u8]) -> bool {
@@ -215,105 +471,15 @@ impl Decoder {
             }
         }
 
-        fn key_derivation_user_password_rc4(
-            revision: u32,
-            key_size: usize,
-            dict: &CryptDict,
-            id: &[u8],
-            pass: &[u8],
-        ) -> [u8; 32] {
-            let o = dict.o.as_bytes();
-            let p = dict.p;
-            // 7.6.3.3 - Algorithm 2
-            // a) and b)
-            let mut hash = md5::Context::new();
-            if pass.len() < 32 {
-                hash.consume(pass);
-                hash.consume(&PADDING[..32 - pass.len()]);
-            } else {
-                hash.consume(&pass[..32]);
-            }
-
-            // c)
-            hash.consume(o);
-
-            // d)
-            hash.consume(p.to_le_bytes());
-
-            // e)
-            hash.consume(id);
-
-            // f)
-            if revision >= 4 && !dict.encrypt_metadata {
-                hash.consume([0xff, 0xff, 0xff, 0xff]);
-            }
-
-            // g)
-            let mut data = *hash.compute();
-
-            // h)
-            if revision >= 3 {
-                for _ in 0..50 {
-                    data = *md5::compute(&data[..key_size]);
-                }
-            }
-
-            let mut key = [0u8; 32];
-            (&mut key[..16]).copy_from_slice(&data);
-            key
-        }
-
-        fn key_derivation_owner_password_rc4(
-            revision: u32,
-            key_size: usize,
-            pass: &[u8],
-        ) -> Vec<u8> {
-            let mut hash = md5::Context::new();
-            if pass.len() < 32 {
-                hash.consume(pass);
-                hash.consume(&PADDING[..32 - pass.len()]);
-            } else {
-                hash.consume(&pass[..32]);
-            }
-
-            if revision >= 3 {
-                for _ in 0..50 {
-                    let digest = *std::mem::replace(&mut hash, md5::Context::new()).compute();
-                    hash.consume(digest);
-                }
-            }
-
-            let digest = &hash.compute()[..key_size];
-            digest.to_vec()
-        }
-
-        let (key_bits, method) = match dict.v {
-            1 => (40, CryptMethod::V2),
-            2 => (dict.bits, CryptMethod::V2),
-            4 | 5 | 6 => {
-                let default = dict
-                    .crypt_filters
-                    .get(dict.default_crypt_filter.as_ref().unwrap().as_str())
-                    .unwrap();
-                match default.method {
-                    CryptMethod::V2 | CryptMethod::AESV2 => (
-                        default.length.map(|n| 8 * n).unwrap_or(dict.bits),
-                        default.method,
-                    ),
-                    CryptMethod::AESV3 if dict.v == 5 => (
-                        default.length.map(|n| 8 * n).unwrap_or(dict.bits),
-                        default.method,
-                    ),
-                    m => err!(format!("unimplemented crypt method {:?}", m).into()),
-                }
-            }
-            v => err!(format!("unsupported V value {}", v).into()),
-        };
         let level = dict.r;
         if level < 2 || level > 6 {
             err!(format!("unsupported standard security handler revision {}", level).into())
         };
         if level <= 4 {
+            // RC4 keys are 40 to 128 bits long, AES-128 keys 128 bits
+            if !key_bits.is_multiple_of(8) || !(40 ..= 128).contains(&key_bits) {
+                err!(format!("unsupported key length of {} bits", key_bits).into())
+            }
             let key_size = key_bits as usize / 8;
             let key = key_derivation_user_password_rc4(level, key_size, dict, id, pass);
 
@@ -342,7 +508,8 @@ impl Decoder {
                 );
 
                 if check_password_rc4(level, dict.u.as_bytes(), id, &key[..key_size]) {
-                    let decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata);
+                    let mut decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata);
+                    decoder.owner = true;
                     Ok(decoder)
                 } else {
                     Err(PdfError::InvalidPassword)
@@ -396,13 +563,14 @@ impl Decoder {
             .as_bytes()
             .to_vec();
 
-            let (intermediate_key, mut wrapped_key) = if level == 6 {
+            let (intermediate_key, mut wrapped_key, owner) = if level == 6 {
                 let user_hash_computed =
                     Self::revision_6_kdf(password_encoded, user_validation_salt, b"");
                 if user_hash_computed == user_hash {
                     (
                         Self::revision_6_kdf(password_encoded, user_key_salt, b"").into(),
                         ue,
+                        false,
                     )
                 } else {
                     let owner_hash_computed =
@@ -411,6 +579,7 @@ impl Decoder {
                         (
                             Self::revision_6_kdf(password_encoded, owner_key_salt, u).into(),
                             oe,
+                            true,
                         )
                     } else {
                         err!(PdfError::InvalidPassword);
@@ -427,7 +596,7 @@ impl Decoder {
                     let mut intermediate_kdf_hash = Sha256::new();
                     intermediate_kdf_hash.update(password_encoded);
                     intermediate_kdf_hash.update(user_key_salt);
-                    (intermediate_kdf_hash.finalize(), ue)
+                    (intermediate_kdf_hash.finalize(), ue, false)
                 } else {
                     let mut owner_check_hash = Sha256::new();
                     owner_check_hash.update(password_encoded);
@@ -439,7 +608,7 @@ impl Decoder {
                         intermediate_kdf_hash.update(password_encoded);
                         intermediate_kdf_hash.update(owner_key_salt);
                         intermediate_kdf_hash.update(u);
-                        (intermediate_kdf_hash.finalize(), oe)
+                        (intermediate_kdf_hash.finalize(), oe, true)
                     } else {
                         err!(PdfError::InvalidPassword);
                     }
@@ -455,7 +624,12 @@ impl Decoder {
             let mut key = [0u8; 32];
             key.copy_from_slice(key_slice);
 
-            let decoder = Decoder::new(key,  32, method, dict.encrypt_metadata);
+            if level == 6 {
+                t!(check_perms(dict, &key));
+            }
+
+            let mut decoder = Decoder::new(key,  32, method, dict.encrypt_metadata);
+            decoder.owner = owner;
             Ok(decoder)
         } else {
             err!(format!("unsupported V value {}", level).into())
@@ -527,7 +701,37 @@ impl Decoder {
         hash
     }
 
+    /// Decrypt the string `data` of object `id`
+    pub fn decrypt_string<'buf>(&self, id: u64, gen: u16, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
+        self.decrypt_with(self.string_method, id, gen, data)
+    }
+
+    /// Decrypt the `data` of the stream `id` with the dictionary `info`.
+    ///
+    /// Streams with a `Crypt` filter use the crypt filter it names (`Identity` by default),
+    /// embedded files the one of `EFF`, other streams that of `StmF`.
+    pub fn decrypt_stream<'buf>(&self, id: u64, gen: u16, info: &Dictionary, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
+        let method = match crypt_filter_name(info) {
+            Some("Identity") => CryptMethod::None,
+            Some(name) => match self.filters.get(name) {
+                Some(&method) => method,
+                None => bail!("crypt filter {} not found", name)
+            },
+            None => match info.get("Type") {
+                Some(Primitive::Name(ref t)) if t == "EmbeddedFile" => self.embedded_file_method,
+                Some(Primitive::Name(ref t)) if t == "Metadata" && !self.encrypt_metadata => CryptMethod::None,
+                _ => self.method
+            }
+        };
+        self.decrypt_with(method, id, gen, data)
+    }
+
+    /// Decrypt `data` of object `id` as a stream without crypt filter
     pub fn decrypt<'buf>(&self, id: u64, gen: u16, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
+        self.decrypt_with(self.method, id, gen, data)
+    }
+
+    fn decrypt_with<'buf>(&self, method: CryptMethod, id: u64, gen: u16, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
         if self.encrypt_indirect_object == Some(PlainRef { id, gen }) {
             // Strings inside the /Encrypt dictionary are not encrypted
             return Ok(data);
@@ -546,8 +750,12 @@ impl Decoder {
         // Algorithm 1
         // a) we have those already
 
-        match self.method {
-            CryptMethod::None => unreachable!(),
+        // the file key is shared by all crypt filters, RC4 and AES-128 take at most 16 bytes of it
+        if matches!(method, CryptMethod::V2 | CryptMethod::AESV2) && self.key_size > 16 {
+            bail!("{:?} can't be used with a file key of {} bytes", method, self.key_size);
+        }
+        match method {
+            CryptMethod::None => Ok(data),
             CryptMethod::V2 => {
                 // b)
                 let mut key = [0; 16 + 5];
@@ -582,6 +790,10 @@ impl Decoder {
                     return Err(PdfError::DecryptionFailure);
                 }
                 let (iv, ciphertext) = data.split_at_mut(16);
+                // just the initialization vector: the data was empty
+                if ciphertext.is_empty() {
+                    return Ok(ciphertext);
+                }
                 let cipher =
                     t!(Aes128Cbc::new_var(key, iv).map_err(|_| PdfError::DecryptionFailure));
                 Ok(t!(cipher
@@ -594,6 +806,9 @@ impl Decoder {
                     return Err(PdfError::DecryptionFailure);
                 }
                 let (iv, ciphertext) = data.split_at_mut(16);
+                if ciphertext.is_empty() {
+                    return Ok(ciphertext);
+                }
                 let cipher =
                     t!(Aes256Cbc::new_var(self.key(), iv).map_err(|_| PdfError::DecryptionFailure));
                 Ok(t!(cipher
@@ -603,6 +818,367 @@ impl Decoder {
         }
     }
 }
+impl Decoder {
+    /// The inverse of `decrypt`: encrypt the string or stream `data` of object `id`.
+    ///
+    /// AES encryption uses a random initialization vector, which is prepended to the result.
+    pub fn encrypt(&self, id: u64, gen: u16, data: &[u8]) -> Result<Vec<u8>> {
+        match self.method {
+            CryptMethod::None => Ok(data.to_vec()),
+            CryptMethod::V2 => {
+                let mut data = data.to_vec();
+                Rc4::encrypt(&self.object_key(id, gen, false), &mut data);
+                Ok(data)
+            }
+            CryptMethod::AESV2 | CryptMethod::AESV3 => {
+                let mut out = random_bytes::<16>()?.to_vec();
+                let encrypted = match self.method {
+                    CryptMethod::AESV2 => {
+                        let key = self.object_key(id, gen, true);
+                        t!(Cbc::<Aes128, Pkcs7>::new_var(&key, &out).map_err(|_| PdfError::DecryptionFailure)).encrypt_vec(data)
+                    }
+                    _ => t!(Cbc::<Aes256, Pkcs7>::new_var(self.key(), &out).map_err(|_| PdfError::DecryptionFailure)).encrypt_vec(data)
+                };
+                out.extend_from_slice(&encrypted);
+                Ok(out)
+            }
+        }
+    }
+
+    /// Encrypt the strings and the stream data in `p`, the object `id`
+    pub(crate) fn encrypt_primitive(&self, id: u64, gen: u16, p: Primitive) -> Result<Primitive> {
+        Ok(match p {
+            Primitive::String(s) => Primitive::String(PdfString::new(self.encrypt(id, gen, s.as_bytes())?)),
+            Primitive::Array(arr) => Primitive::Array(
+                arr.into_iter().map(|p| self.encrypt_primitive(id, gen, p)).collect::<Result<_>>()?
+            ),
+            Primitive::Dictionary(dict) => Primitive::Dictionary(self.encrypt_dict(id, gen, dict)?),
+            Primitive::Stream(stream) => Primitive::Stream(PdfStream {
+                data: match stream.info.get("Type") {
+                    Some(Primitive::Name(ref t)) if t == "Metadata" && !self.encrypt_metadata => stream.data,
+                    _ => self.encrypt(id, gen, &stream.data)?
+                },
+                info: self.encrypt_dict(id, gen, stream.info)?,
+            }),
+            p => p
+        })
+    }
+    fn encrypt_dict(&self, id: u64, gen: u16, dict: Dictionary) -> Result<Dictionary> {
+        let signature = is_signature(&dict);
+        let mut out = Dictionary::new();
+        for (key, val) in dict {
+            let val = match (key.as_str(), val) {
+                ("Contents", val @ Primitive::String(_)) if signature => val,
+                (_, val) => self.encrypt_primitive(id, gen, val)?
+            };
+            out.insert(key, val);
+        }
+        Ok(out)
+    }
+
+    /// Algorithm 1: the key for the object `id` (RC4 and AES-128)
+    fn object_key(&self, id: u64, gen: u16, aes: bool) -> Vec<u8> {
+        let n = self.key_size;
+        let mut key = Vec::with_capacity(n + 9);
+        key.extend_from_slice(self.key());
+        key.extend_from_slice(&id.to_le_bytes()[..3]);
+        key.extend_from_slice(&gen.to_le_bytes());
+        if aes {
+            key.extend_from_slice(b"sAlT");
+        }
+        md5::compute(&key)[..(n + 5).min(16)].to_vec()
+    }
+}
+
+pub(crate) fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut data = [0; N];
+    t!(getrandom::fill(&mut data).map_err(|e| PdfError::Other { msg: format!("no random numbers: {}", e) }));
+    Ok(data)
+}
+
+/// Access permissions of the user password (`P` in the encryption dictionary, PDF32000 Table 22)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions(pub u32);
+impl Permissions {
+    pub const PRINT: Permissions = Permissions(1 << 2);
+    pub const MODIFY: Permissions = Permissions(1 << 3);
+    pub const COPY: Permissions = Permissions(1 << 4);
+    pub const ANNOTATE: Permissions = Permissions(1 << 5);
+    pub const FILL_FORMS: Permissions = Permissions(1 << 8);
+    pub const EXTRACT_FOR_ACCESSIBILITY: Permissions = Permissions(1 << 9);
+    pub const ASSEMBLE: Permissions = Permissions(1 << 10);
+    pub const PRINT_HIGH_QUALITY: Permissions = Permissions(1 << 11);
+
+    pub fn all() -> Permissions {
+        Permissions(0xf3c)
+    }
+    pub fn none() -> Permissions {
+        Permissions(0)
+    }
+    /// The permissions granted by the value `P` of the encryption dictionary
+    pub fn from_p(p: i32) -> Permissions {
+        Permissions(p as u32 & 0xf3c)
+    }
+    pub fn contains(self, other: Permissions) -> bool {
+        self.0 & other.0 == other.0
+    }
+    /// The value of `P`, with the reserved bits set as required
+    fn to_p(self) -> i32 {
+        ((self.0 & 0xf3c) | 0xffff_f0c0) as i32
+    }
+}
+impl std::ops::BitOr for Permissions {
+    type Output = Permissions;
+    fn bitor(self, rhs: Permissions) -> Permissions {
+        Permissions(self.0 | rhs.0)
+    }
+}
+
+/// How to encrypt a document with the standard security handler, see `SaveOptions::encrypt`
+#[derive(Debug, Clone)]
+pub struct EncryptOptions {
+    /// Password needed to open the document, may be empty
+    pub user_password: String,
+    /// Password that grants all permissions. The user password is used if it is empty.
+    pub owner_password: String,
+    pub permissions: Permissions,
+    /// `AESV2` (AES-128, revision 4), `AESV3` (AES-256, revision 6) or `V2` (128 bit RC4, revision 3)
+    pub method: CryptMethod,
+    /// Encrypt the XMP metadata stream as well, so it can't be read without the password.
+    /// Only AES supports leaving it unencrypted.
+    pub encrypt_metadata: bool,
+}
+impl EncryptOptions {
+    /// AES-256 encryption with the given passwords and all permissions
+    pub fn aes256(user_password: &str, owner_password: &str) -> EncryptOptions {
+        EncryptOptions {
+            user_password: user_password.into(),
+            owner_password: owner_password.into(),
+            permissions: Permissions::all(),
+            method: CryptMethod::AESV3,
+            encrypt_metadata: true,
+        }
+    }
+
+    /// The encryption dictionary for a document with the (first) file identifier `id`,
+    /// and the decoder that encrypts its objects.
+    pub fn security_handler(&self, id: &[u8]) -> Result<(CryptDict, Decoder)> {
+        let owner_password = match self.owner_password.as_str() {
+            "" => self.user_password.as_str(),
+            p => p
+        };
+        let p = self.permissions.to_p();
+        let (v, r, bits) = match self.method {
+            CryptMethod::V2 => (2, 3, 128),
+            CryptMethod::AESV2 => (4, 4, 128),
+            CryptMethod::AESV3 => (5, 6, 256),
+            CryptMethod::None => bail!("no encryption method given")
+        };
+        if !self.encrypt_metadata && r < 4 {
+            bail!("unencrypted metadata needs a crypt filter, use AES");
+        }
+        let mut dict = CryptDict {
+            o: PdfString::new(vec![]),
+            u: PdfString::new(vec![]),
+            r,
+            p,
+            v,
+            bits,
+            crypt_filters: HashMap::new(),
+            default_crypt_filter: None,
+            string_crypt_filter: None,
+            embedded_file_crypt_filter: None,
+            encrypt_metadata: self.encrypt_metadata,
+            oe: None,
+            ue: None,
+            _other: Dictionary::new(),
+        };
+        dict._other.insert("Filter", Primitive::name("Standard"));
+        if v >= 4 {
+            let filter = CryptFilter {
+                method: self.method,
+                auth_event: AuthEvent::DocOpen,
+                length: Some(bits / 8),
+                _other: Dictionary::new(),
+            };
+            dict.crypt_filters.insert("StdCF".into(), filter);
+            dict.default_crypt_filter = Some("StdCF".into());
+            dict.string_crypt_filter = Some("StdCF".into());
+        }
+
+        let key = if r <= 4 {
+            // algorithm 3: O is the padded user password, encrypted with a key derived from the owner password
+            let owner_key = key_derivation_owner_password_rc4(r, 16, owner_password.as_bytes());
+            let mut o = padded(self.user_password.as_bytes()).to_vec();
+            for i in 0u8 ..= 19 {
+                let key: Vec<u8> = owner_key.iter().map(|b| b ^ i).collect();
+                Rc4::encrypt(&key, &mut o);
+            }
+            dict.o = PdfString::new(o);
+
+            // algorithm 5
+            let key = key_derivation_user_password_rc4(r, 16, &dict, id, self.user_password.as_bytes());
+            let mut u = compute_u_rev_3_4(id, &key[.. 16]);
+            u.extend_from_slice(&PADDING[.. 16]);
+            dict.u = PdfString::new(u);
+            key
+        } else {
+            // algorithms 8 and 9
+            let key = random_bytes::<32>()?;
+            let user = prepare_password(&self.user_password)?;
+            let owner = prepare_password(owner_password)?;
+            let salts = random_bytes::<32>()?;
+            let (user_validation_salt, user_key_salt) = (&salts[0 .. 8], &salts[8 .. 16]);
+            let (owner_validation_salt, owner_key_salt) = (&salts[16 .. 24], &salts[24 .. 32]);
+
+            let mut u = Decoder::revision_6_kdf(&user, user_validation_salt, b"").to_vec();
+            u.extend_from_slice(user_validation_salt);
+            u.extend_from_slice(user_key_salt);
+            let ue = wrap_key(&Decoder::revision_6_kdf(&user, user_key_salt, b""), &key)?;
+
+            let mut o = Decoder::revision_6_kdf(&owner, owner_validation_salt, &u).to_vec();
+            o.extend_from_slice(owner_validation_salt);
+            o.extend_from_slice(owner_key_salt);
+            let oe = wrap_key(&Decoder::revision_6_kdf(&owner, owner_key_salt, &u), &key)?;
+
+            // algorithm 10, a single block, so CBC with a zero IV is the same as ECB
+            let mut perms = [0u8; 16];
+            perms[.. 4].copy_from_slice(&p.to_le_bytes());
+            perms[4 .. 8].copy_from_slice(&[0xff; 4]);
+            perms[8] = if self.encrypt_metadata { b'T' } else { b'F' };
+            perms[9 .. 12].copy_from_slice(b"adb");
+            perms[12 ..].copy_from_slice(&random_bytes::<4>()?);
+            let perms = wrap_key(&key, &perms)?;
+
+            dict.u = PdfString::new(u);
+            dict.ue = Some(PdfString::new(ue));
+            dict.o = PdfString::new(o);
+            dict.oe = Some(PdfString::new(oe));
+            dict._other.insert("Perms", Primitive::String(PdfString::new(perms)));
+            key
+        };
+
+        let key_size = bits as usize / 8;
+        Ok((dict, Decoder::new(key, key_size, self.method, self.encrypt_metadata)))
+    }
+}
+
+/// SHA-1 (FIPS 180-4), only used for the file key of the public-key security handler
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16 .. 80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0 ..= 19 => ((b & c) | (!b & d), 0x5A827999),
+                20 ..= 39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40 ..= 59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+    let mut out = [0; 20];
+    for (o, h) in out.chunks_mut(4).zip(h) {
+        o.copy_from_slice(&h.to_be_bytes());
+    }
+    out
+}
+
+/// The password padded or truncated to 32 bytes (algorithm 2, step a)
+fn padded(pass: &[u8]) -> [u8; 32] {
+    let mut out = PADDING;
+    let n = pass.len().min(32);
+    out[.. n].copy_from_slice(&pass[.. n]);
+    out[n ..].copy_from_slice(&PADDING[.. 32 - n]);
+    out
+}
+
+/// The password for revision 6: SASLprep, UTF-8, at most 127 bytes
+fn prepare_password(pass: &str) -> Result<Vec<u8>> {
+    let prepped = t!(stringprep::saslprep(pass).map_err(|_| PdfError::InvalidPassword));
+    let bytes = prepped.as_bytes();
+    Ok(bytes[.. bytes.len().min(127)].to_vec())
+}
+
+/// AES-256 encryption of whole blocks without padding and with a zero IV, used for `UE`, `OE` and `Perms`
+fn wrap_key(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let cipher = t!(Cbc::<Aes256, NoPadding>::new_var(key, &[0; 16]).map_err(|_| PdfError::DecryptionFailure));
+    Ok(cipher.encrypt_vec(data))
+}
+
+/// Whether `dict` is a signature dictionary, whose `Contents` are not encrypted (7.6.2)
+pub(crate) fn is_signature(dict: &Dictionary) -> bool {
+    match dict.get("Type") {
+        Some(Primitive::Name(ref t)) => t == "Sig" || t == "DocTimeStamp",
+        _ => dict.get("ByteRange").is_some()
+    }
+}
+
+/// The name of the crypt filter of a stream with a `Crypt` filter, which has to come first
+fn crypt_filter_name(info: &Dictionary) -> Option<&str> {
+    let is_crypt = |p: &Primitive| matches!(p, Primitive::Name(ref n) if n == "Crypt");
+    let params = match info.get("Filter")? {
+        Primitive::Array(ref filters) if filters.first().is_some_and(is_crypt) => match info.get("DecodeParms") {
+            Some(Primitive::Array(ref params)) => params.first(),
+            params => params
+        },
+        p if is_crypt(p) => info.get("DecodeParms"),
+        _ => return None
+    };
+    match params {
+        Some(Primitive::Dictionary(ref params)) => match params.get("Name") {
+            Some(Primitive::Name(ref name)) => Some(name.as_str()),
+            _ => Some("Identity")
+        },
+        _ => Some("Identity")
+    }
+}
+
+/// Algorithm 13: `Perms` holds `P` and `EncryptMetadata`, encrypted with the file `key`.
+///
+/// If the values differ from the dictionary, it was changed after encryption.
+fn check_perms(dict: &CryptDict, key: &[u8]) -> Result<()> {
+    let mut perms = match dict._other.get("Perms") {
+        Some(Primitive::String(perms)) if perms.as_bytes().len() >= 16 => perms.as_bytes()[.. 16].to_vec(),
+        Some(p) => bail!("Perms should be a string of 16 bytes, found {:?}", p),
+        None => err!(PdfError::MissingEntry { typ: "Encrypt", field: "Perms".into() })
+    };
+    let cipher = t!(Cbc::<Aes256, NoPadding>::new_var(key, &[0; 16]).map_err(|_| PdfError::DecryptionFailure));
+    let perms = t!(cipher.decrypt(&mut perms).map_err(|_| PdfError::DecryptionFailure));
+    if &perms[9 .. 12] != b"adb" {
+        err!(PdfError::DecryptionFailure);
+    }
+    let p = i32::from_le_bytes([perms[0], perms[1], perms[2], perms[3]]);
+    let encrypt_metadata = perms[8] == b'T';
+    if p != dict.p || encrypt_metadata != dict.encrypt_metadata {
+        warn!("P or EncryptMetadata of the encryption dictionary differ from Perms, the dictionary was modified");
+    }
+    Ok(())
+}
+
 impl fmt::Debug for Decoder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Decoder")
@@ -683,4 +1259,141 @@ mod tests {
             b"owner pwd hash!!",
         );
     }
+    #[test]
+    fn aes_round_trip() {
+        use super::*;
+
+        for method in [CryptMethod::AESV2, CryptMethod::AESV3] {
+            let key_size = if let CryptMethod::AESV2 = method { 16 } else { 32 };
+            let decoder = Decoder::new([7; 32], key_size, method, true);
+            let mut data = decoder.encrypt(12, 0, b"Hello World!").unwrap();
+            assert_eq!(data.len(), 32);
+            assert_eq!(decoder.decrypt(12, 0, &mut data).unwrap(), b"Hello World!");
+
+            // a stream that only has the initialization vector is empty
+            let mut iv = [0; 16];
+            assert!(decoder.decrypt(12, 0, &mut iv).unwrap().is_empty());
+        }
+    }
+    #[test]
+    fn aes256_perms() {
+        use super::*;
+
+        let id = b"0123456789abcdef";
+        let (mut dict, _) = EncryptOptions::aes256("user", "owner").security_handler(id).unwrap();
+        assert!(Decoder::from_password(&dict, id, b"user").is_ok());
+        assert!(Decoder::from_password(&dict, id, b"owner").is_ok());
+        assert!(Decoder::from_password(&dict, id, b"").unwrap_err().is_invalid_password());
+
+        // a changed P is only reported
+        dict.p = 0;
+        assert!(Decoder::from_password(&dict, id, b"user").is_ok());
+
+        let mut perms = match dict._other.get("Perms") {
+            Some(Primitive::String(s)) => s.as_bytes().to_vec(),
+            _ => panic!("no Perms")
+        };
+        perms[0] ^= 1;
+        dict._other.insert("Perms", Primitive::String(PdfString::new(perms)));
+        assert!(Decoder::from_password(&dict, id, b"user").is_err());
+    }
+    #[test]
+    fn crypt_filters() {
+        use super::*;
+
+        let id = b"0123456789abcdef";
+        let options = EncryptOptions { method: CryptMethod::AESV2, ..EncryptOptions::aes256("user", "owner") };
+        let (mut dict, encrypter) = options.security_handler(id).unwrap();
+        dict.string_crypt_filter = Some("Identity".into());
+        dict.embedded_file_crypt_filter = Some("Identity".into());
+        let decoder = Decoder::from_password(&dict, id, b"user").unwrap();
+
+        let mut string = b"not encrypted".to_vec();
+        assert_eq!(decoder.decrypt_string(5, 0, &mut string).unwrap(), b"not encrypted");
+
+        let mut data = encrypter.encrypt(5, 0, b"stream data").unwrap();
+        assert_eq!(decoder.decrypt_stream(5, 0, &Dictionary::new(), &mut data).unwrap(), b"stream data");
+
+        let mut embedded = Dictionary::new();
+        embedded.insert("Type", Primitive::name("EmbeddedFile"));
+        let mut data = b"file".to_vec();
+        assert_eq!(decoder.decrypt_stream(5, 0, &embedded, &mut data).unwrap(), b"file");
+
+        // a Crypt filter without parameters is the identity, otherwise it names the crypt filter
+        let mut info = Dictionary::new();
+        info.insert("Filter", Primitive::Array(vec![Primitive::name("Crypt"), Primitive::name("FlateDecode")]));
+        let mut data = b"plain".to_vec();
+        assert_eq!(decoder.decrypt_stream(5, 0, &info, &mut data).unwrap(), b"plain");
+        let mut params = Dictionary::new();
+        params.insert("Name", Primitive::name("StdCF"));
+        info.insert("DecodeParms", Primitive::Array(vec![Primitive::Dictionary(params), Primitive::Null]));
+        let mut data = encrypter.encrypt(5, 0, b"stream data").unwrap();
+        assert_eq!(decoder.decrypt_stream(5, 0, &info, &mut data).unwrap(), b"stream data");
+
+        // RC4 and AES-128 can't use the 256 bit key of AES-256
+        let (mut dict, _) = EncryptOptions::aes256("user", "owner").security_handler(id).unwrap();
+        let rc4 = CryptFilter { method: CryptMethod::V2, auth_event: AuthEvent::DocOpen, length: Some(16), _other: Dictionary::new() };
+        dict.crypt_filters.insert("RC4".into(), rc4);
+        let mut decoder = Decoder::from_password(&dict, id, b"user").unwrap();
+        dict.string_crypt_filter = Some("RC4".into());
+        assert!(Decoder::from_password(&dict, id, b"user").is_err());
+        // not even for streams naming it
+        decoder.filters.insert("RC4".into(), CryptMethod::V2);
+        let mut params = Dictionary::new();
+        params.insert("Name", Primitive::name("RC4"));
+        info.insert("DecodeParms", Primitive::Array(vec![Primitive::Dictionary(params), Primitive::Null]));
+        assert!(decoder.decrypt_stream(5, 0, &info, &mut b"stream data".to_vec()).is_err());
+    }
+    #[test]
+    fn public_key_handler() {
+        use super::*;
+
+        assert_eq!(sha1(b"abc")[..], [
+            0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e,
+            0x25, 0x71, 0x78, 0x50, 0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d
+        ]);
+        assert_eq!(sha1(&[b'a'; 64])[.. 4], [0x00, 0x98, 0xba, 0x82]);
+
+        let mut filter = CryptFilter {
+            method: CryptMethod::AESV2,
+            auth_event: AuthEvent::DocOpen,
+            length: Some(16),
+            _other: Dictionary::new(),
+        };
+        filter._other.insert("Recipients", Primitive::Array(vec![
+            Primitive::String(PdfString::new(b"envelope of someone else".to_vec())),
+            Primitive::String(PdfString::new(b"my envelope".to_vec())),
+        ]));
+        let (mut dict, _) = EncryptOptions { method: CryptMethod::AESV2, ..EncryptOptions::aes256("", "") }
+            .security_handler(b"id").unwrap();
+        dict._other.insert("Filter", Primitive::name("Adobe.PubSec"));
+        dict.crypt_filters.insert("DefaultCryptFilter".into(), filter);
+        dict.default_crypt_filter = Some("DefaultCryptFilter".into());
+        dict.string_crypt_filter = Some("DefaultCryptFilter".into());
+        assert!(Decoder::from_password(&dict, b"id", b"").is_err());
+
+        let seed = [7u8; 20];
+        let mut content = seed.to_vec();
+        content.extend_from_slice(&Permissions::PRINT.0.to_be_bytes());
+        let decoder = Decoder::from_recipients(&dict, |r| Some(content.clone()).filter(|_| r == b"my envelope")).unwrap();
+        assert!(decoder.recipient_permissions().unwrap().contains(Permissions::PRINT));
+        assert!(!decoder.recipient_permissions().unwrap().contains(Permissions::MODIFY));
+
+        let mut key = [0; 32];
+        key[.. 20].copy_from_slice(&sha1(&[&seed[..], b"envelope of someone else", b"my envelope"].concat()));
+        let encrypter = Decoder::new(key, 16, CryptMethod::AESV2, true);
+        let mut data = encrypter.encrypt(3, 0, b"for my eyes only").unwrap();
+        assert_eq!(decoder.decrypt(3, 0, &mut data).unwrap(), b"for my eyes only");
+
+        let e = Decoder::from_recipients(&dict, |_| None).unwrap_err();
+        assert!(e.is_invalid_password());
+
+        // key lengths the hash can't provide are errors, not panics
+        for bits in [44, 136, 1024] {
+            let filter = dict.crypt_filters.get_mut("DefaultCryptFilter").unwrap();
+            filter.method = CryptMethod::V2;
+            filter.length = Some(bits);
+            assert!(Decoder::from_recipients(&dict, |_| Some(content.clone())).is_err(), "{} bits", bits);
+        }
+    }
 }
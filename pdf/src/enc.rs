@@ -1,6 +1,6 @@
 use itertools::Itertools;
 use inflate::{inflate_bytes_zlib, inflate_bytes};
-use deflate::deflate_bytes;
+use deflate::deflate_bytes_zlib;
 
 use crate as pdf;
 use crate::error::*;
@@ -22,6 +22,12 @@ pub struct LZWFlateParams {
     #[pdf(key="EarlyChange", default="1")]
     early_change: i32,
 }
+impl LZWFlateParams {
+    /// No predictor and the default `EarlyChange`, so `DecodeParms` can be omitted
+    pub(crate) fn is_default(&self) -> bool {
+        self.predictor == 1 && self.early_change == 1
+    }
+}
 impl Default for LZWFlateParams {
     fn default() -> LZWFlateParams {
         LZWFlateParams {
@@ -237,6 +243,53 @@ fn encode_85(data: &[u8]) -> Vec<u8> {
     buf
 }
 
+#[test]
+fn adobe_cmyk_jpeg() {
+    // 8×8 baseline CMYK with all samples 0: an Adobe segment without a transform, and
+    // Huffman tables with only the DC category 11 and the end of block
+    let mut jpeg = vec![0xff, 0xd8, 0xff, 0xee, 0, 14];
+    jpeg.extend_from_slice(b"Adobe\0\x64\0\0\0\0\0");
+    jpeg.extend_from_slice(&[0xff, 0xdb, 0, 67, 0]);
+    jpeg.extend_from_slice(&[1; 64]);
+    jpeg.extend_from_slice(&[0xff, 0xc0, 0, 20, 8, 0, 8, 0, 8, 4, 1, 0x11, 0, 2, 0x11, 0, 3, 0x11, 0, 4, 0x11, 0]);
+    for (class, symbol) in [(0x00, 11), (0x10, 0)] {
+        jpeg.extend_from_slice(&[0xff, 0xc4, 0, 20, class, 1]);
+        jpeg.extend_from_slice(&[0; 15]);
+        jpeg.push(symbol);
+    }
+    jpeg.extend_from_slice(&[0xff, 0xda, 0, 14, 4, 1, 0, 2, 0, 3, 0, 4, 0, 0, 63, 0]);
+    jpeg.extend_from_slice(&[0x3f, 0xf1, 0xff, 0x00, 0x8f, 0xfc, 0x7f, 0xef, 0xff, 0xd9]);
+
+    assert_eq!(adobe_transform(&jpeg), Some(0));
+    let pixels = dct_decode(&jpeg, &DCTDecodeParams { color_transform: None }).unwrap();
+    assert_eq!(pixels.len(), 8 * 8 * 4);
+    assert!(pixels.iter().all(|&c| c == 0), "{:?}", &pixels[.. 4]);
+}
+
+#[test]
+fn predictors() {
+    let params = |predictor, n_components, bits_per_component, columns| LZWFlateParams {
+        predictor, n_components, bits_per_component, columns, early_change: 1
+    };
+    // PNG Sub works on whole pixels: 2 bytes for 16 bit gray, 1 byte for 4 bit RGB
+    let data = vec![1, 0x01, 0x00, 0x00, 0x01, 0x00, 0xff];
+    assert_eq!(unpredict(data, &params(11, 1, 16, 3)).unwrap(), [0x01, 0x00, 0x01, 0x01, 0x01, 0x00]);
+    let data = vec![1, 0x12, 0x31, 0x11];
+    assert_eq!(unpredict(data, &params(15, 3, 4, 2)).unwrap(), [0x12, 0x31, 0x23]);
+    // PNG Up with rows of 5 pixels of 1 bit: a byte per row
+    let data = vec![0, 0b1010_1000, 2, 0b0101_0000];
+    assert_eq!(unpredict(data, &params(12, 1, 1, 5)).unwrap(), [0b1010_1000, 0b1111_1000]);
+
+    // TIFF: per sample, per row
+    let data = vec![10, 20, 1, 2, 5, 6, 1, 1];
+    assert_eq!(unpredict(data, &params(2, 2, 8, 2)).unwrap(), [10, 20, 11, 22, 5, 6, 6, 7]);
+    let data = vec![0x00, 0xff, 0x00, 0x02];
+    assert_eq!(unpredict(data, &params(2, 1, 16, 2)).unwrap(), [0x00, 0xff, 0x01, 0x01]);
+    let data = vec![0b0011_0101, 0b1100_0000];
+    assert_eq!(unpredict(data.clone(), &params(2, 1, 2, 8)).unwrap(), [0b0011_0001, 0b0000_0000]);
+    assert_eq!(unpredict(data, &params(2, 1, 2, 4)).unwrap(), [0b0011_0001, 0b1111_1111]);
+}
+
 #[test]
 fn base_85() {
     fn s(b: &[u8]) -> &str { std::str::from_utf8(b).unwrap() }
@@ -261,11 +314,6 @@ fn base_85() {
 
 
 fn flate_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
-    let predictor = params.predictor as usize;
-    let n_components = params.n_components as usize;
-    let columns = params.columns as usize;
-    let stride = columns * n_components;
-
     // First flate decode
     let decoded = match inflate_bytes_zlib(data) {
         Ok(data) => data,
@@ -274,58 +322,115 @@ fn flate_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
             inflate_bytes(data)?
         }
     };
-    // Then unfilter (PNG)
-    // For this, take the old out as input, and write output to out
-
-    if predictor > 10 {
-        let inp = decoded; // input buffer
-        let rows = inp.len() / (stride+1);
-        
-        // output buffer
-        let mut out = vec![0; rows * stride];
-    
-        // Apply inverse predictor
-        let null_vec = vec![0; stride];
-        
-        let mut in_off = 0; // offset into input buffer
-        
-        let mut out_off = 0; // offset into output buffer
-        let mut last_out_off = 0; // last offset to output buffer
-        
-        while in_off < inp.len() {
-            let predictor = PredictorType::from_u8(inp[in_off])?;
-            in_off += 1; // +1 because the first byte on each row is predictor
-            
-            let row_in = &inp[in_off .. in_off + stride];
-            let (prev_row, row_out) = if out_off == 0 {
-                (&null_vec[..], &mut out[out_off .. out_off+stride])
-            } else {
-                let (prev, curr) = out.split_at_mut(out_off);
-                (&prev[last_out_off ..], &mut curr[.. stride])
-            };
-            unfilter(predictor, n_components, prev_row, row_in, row_out);
-            
-            last_out_off = out_off;
-            
-            in_off += stride;
-            out_off += stride;
+    unpredict(decoded, params)
+}
+
+/// Undo the predictor (`Predictor`) of `params`, for rows of `Columns` pixels
+/// with `Colors` components of `BitsPerComponent` bits each
+fn unpredict(data: Vec<u8>, params: &LZWFlateParams) -> Result<Vec<u8>> {
+    let n_components = params.n_components.max(1) as usize;
+    let bpc = params.bits_per_component.max(1) as usize;
+    let columns = params.columns.max(1) as usize;
+    // rows start at a byte boundary
+    let stride = (columns * n_components * bpc).div_ceil(8);
+    match params.predictor {
+        2 => Ok(tiff_unpredict(data, stride, columns * n_components, n_components, bpc)),
+        p if p >= 10 => {
+            // PNG: each row starts with its predictor. They work on bytes, with the
+            // previous pixel (rounded up to a byte) to the left.
+            let bpp = (n_components * bpc).div_ceil(8);
+            let rows = data.len() / (stride + 1);
+            let mut out = vec![0; rows * stride];
+            let null_row = vec![0; stride];
+            for (row, inp) in data.chunks_exact(stride + 1).enumerate() {
+                let predictor = PredictorType::from_u8(inp[0])?;
+                let (prev, curr) = out.split_at_mut(row * stride);
+                let prev_row = match row {
+                    0 => &null_row[..],
+                    _ => &prev[(row - 1) * stride ..]
+                };
+                unfilter(predictor, bpp, prev_row, &inp[1 ..], &mut curr[.. stride]);
+            }
+            Ok(out)
         }
-        Ok(out)
-    } else {
-        Ok(decoded)
+        _ => Ok(data)
     }
 }
+
+/// Undo the TIFF predictor 2: each sample is the difference to the same component of the
+/// pixel to the left. Rows have `samples` samples of `bpc` bits in `stride` bytes.
+fn tiff_unpredict(mut data: Vec<u8>, stride: usize, samples: usize, n_components: usize, bpc: usize) -> Vec<u8> {
+    for row in data.chunks_mut(stride) {
+        match bpc {
+            8 => for i in n_components .. row.len() {
+                row[i] = row[i].wrapping_add(row[i - n_components]);
+            }
+            16 => for i in n_components .. row.len() / 2 {
+                let left = u16::from_be_bytes([row[2 * (i - n_components)], row[2 * (i - n_components) + 1]]);
+                let value = u16::from_be_bytes([row[2 * i], row[2 * i + 1]]).wrapping_add(left);
+                row[2 * i .. 2 * i + 2].copy_from_slice(&value.to_be_bytes());
+            }
+            1 | 2 | 4 => {
+                let mask = (1u8 << bpc) - 1;
+                let shift = |i: usize| 8 - bpc - i * bpc % 8;
+                let get = |row: &[u8], i: usize| row[i * bpc / 8] >> shift(i) & mask;
+                for i in n_components .. samples.min(row.len() * 8 / bpc) {
+                    let value = get(row, i).wrapping_add(get(row, i - n_components)) & mask;
+                    let byte = &mut row[i * bpc / 8];
+                    *byte = *byte & !(mask << shift(i)) | value << shift(i);
+                }
+            }
+            _ => {
+                warn!("ignoring the TIFF predictor for {} bits per component", bpc);
+                break;
+            }
+        }
+    }
+    data
+}
 fn flate_encode(data: &[u8]) -> Vec<u8> {
-    deflate_bytes(data)
+    deflate_bytes_zlib(data)
 }
 
 fn dct_decode(data: &[u8], _params: &DCTDecodeParams) -> Result<Vec<u8>> {
-    use jpeg_decoder::Decoder;
+    use jpeg_decoder::{Decoder, PixelFormat};
     let mut decoder = Decoder::new(data);
-    let pixels = decoder.decode()?;
+    let mut pixels = decoder.decode()?;
+    // The decoder inverts Adobe CMYK (and the K of YCCK), as stored by Photoshop. In PDF,
+    // the samples are what is stored, and such images come with a `Decode` array to invert them.
+    if let Some(PixelFormat::CMYK32) = decoder.info().map(|i| i.pixel_format) {
+        let inverted = match adobe_transform(data) {
+            Some(2) => 3 .. 4,
+            _ => 0 .. 4
+        };
+        for pixel in pixels.chunks_exact_mut(4) {
+            for c in &mut pixel[inverted.clone()] {
+                *c = 255 - *c;
+            }
+        }
+    }
     Ok(pixels)
 }
 
+/// The color transform of the Adobe (`APP14`) segment of a JPEG: 0 for none (RGB or CMYK),
+/// 1 for YCbCr and 2 for YCCK
+fn adobe_transform(data: &[u8]) -> Option<u8> {
+    let mut pos = 2;
+    // the segments before the image data
+    while let (Some(0xff), Some(&marker)) = (data.get(pos), data.get(pos + 1)) {
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        let segment = data.get(pos + 4 .. pos + 2 + len)?;
+        if marker == 0xee && segment.starts_with(b"Adobe") {
+            return segment.get(11).copied();
+        }
+        if marker == 0xda {
+            break;
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
 fn lzw_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
     use weezl::{BitOrder, decode::Decoder};
     let mut out = vec![];
@@ -339,7 +444,7 @@ fn lzw_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
     decoder
         .into_stream(&mut out)
         .decode_all(data).status?;
-    Ok(out)
+    unpredict(out, params)
 }
 fn lzw_encode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
     use weezl::{BitOrder, encode::Encoder};
@@ -357,19 +462,22 @@ fn fax_decode(data: &[u8], params: &CCITTFaxDecodeParams) -> Result<Vec<u8>> {
     use fax::{Color, decoder::{pels, decode_g4}};
 
     if params.k < 0 {
+        // one byte per pixel, 0 for black unless `BlackIs1`
+        let (black, white) = if params.black_is_1 { (255, 0) } else { (0, 255) };
         let mut buf = Vec::with_capacity(params.columns as usize * params.rows as usize);
         decode_g4(data.iter().cloned(), params.columns as u16, |line| {
             buf.extend(pels(line, params.columns as u16).map(|c| match c {
-                Color::Black => 0,
-                Color::White => 255
+                Color::Black => black,
+                Color::White => white
             }));
         });
-        if buf.len() != params.columns as usize * params.rows as usize {
+        // without `Rows`, as many as there are
+        if params.rows != 0 && buf.len() != params.columns as usize * params.rows as usize {
             bail!("decoded length does not match (expected {}∙{}, got {})", params.rows, params.columns, buf.len());
         }
         Ok(buf)
     } else {
-        unimplemented!()
+        bail!("CCITT group 3 encoding (K = {}) is not supported", params.k)
     }
 }
 
@@ -381,6 +489,8 @@ pub fn decode(data: &[u8], filter: &StreamFilter) -> Result<Vec<u8>> {
         StreamFilter::FlateDecode(ref params) => flate_decode(data, params),
         StreamFilter::DCTDecode(ref params) => dct_decode(data, params),
         StreamFilter::CCITTFaxDecode(ref params) => fax_decode(data, params),
+        // streams are decrypted when they are read
+        StreamFilter::Crypt => Ok(data.to_vec()),
         _ => unimplemented!(),
     }
 }
@@ -390,8 +500,9 @@ pub fn encode(data: &[u8], filter: &StreamFilter) -> Result<Vec<u8>> {
         StreamFilter::ASCIIHexDecode => Ok(encode_hex(data)),
         StreamFilter::ASCII85Decode => Ok(encode_85(data)),
         StreamFilter::LZWDecode(ref params) => lzw_encode(data, params),
+        StreamFilter::FlateDecode (ref params) if params.predictor > 1 => bail!("encoding with a predictor is not supported"),
         StreamFilter::FlateDecode (ref _params) => Ok(flate_encode(data)),
-        _ => unimplemented!(),
+        _ => bail!("encoding with {:?} is not supported", filter),
     }
 }
 
@@ -1,8 +1,10 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use crate as pdf;
-use crate::object::{Object, Resolve};
-use crate::primitive::Primitive;
-use crate::error::{Result};
+use crate::object::{Object, ObjectWrite, Resolve, Updater};
+use crate::primitive::{Primitive, Dictionary};
+use crate::error::*;
 
 #[derive(Debug, Clone)]
 pub struct Encoding {
@@ -10,7 +12,7 @@ pub struct Encoding {
     pub differences: HashMap<u32, String>,
 }
 
-#[derive(Object, Debug, Clone, Eq, PartialEq)]
+#[derive(Object, ObjectWrite, Debug, Clone, Eq, PartialEq)]
 pub enum BaseEncoding {
     StandardEncoding,
     SymbolEncoding,
@@ -19,6 +21,8 @@ pub enum BaseEncoding {
     MacExpertEncoding,
     #[pdf(name="Identity-H")]
     IdentityH,
+    #[pdf(name="Identity-V")]
+    IdentityV,
     None
 }
 impl Object for Encoding {
@@ -47,17 +51,43 @@ impl Object for Encoding {
                                 differences.insert(gid, name);
                                 gid += 1;
                             },
-                            _ => panic!()
+                            p => bail!("unexpected primitive in Differences: {:?}", p)
                         }
                     }
                 }
                 Ok(Encoding { base, differences })
             }
             Primitive::Reference(r) => Self::from_primitive(resolve.resolve(r)?, resolve),
-            _ => panic!()
+            p => Err(PdfError::UnexpectedPrimitive { expected: "Name or Dictionary", found: p.get_debug_name() })
         }
     }
 }
+impl ObjectWrite for Encoding {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        if self.differences.is_empty() {
+            return self.base.to_primitive(update);
+        }
+        let mut dict = Dictionary::new();
+        dict.insert("Type", Primitive::name("Encoding"));
+        if self.base != BaseEncoding::None {
+            dict.insert("BaseEncoding", self.base.to_primitive(update)?);
+        }
+        let mut codes: Vec<_> = self.differences.iter().collect();
+        codes.sort_unstable_by_key(|&(&code, _)| code);
+
+        let mut differences = vec![];
+        let mut next = None;
+        for (&code, name) in codes {
+            if next != Some(code) {
+                differences.push(Primitive::Integer(code as i32));
+            }
+            differences.push(Primitive::name(name.as_str()));
+            next = Some(code + 1);
+        }
+        dict.insert("Differences", Primitive::Array(differences));
+        Ok(Primitive::Dictionary(dict))
+    }
+}
 impl Encoding { 
     pub fn standard() -> Encoding {
         Encoding {
@@ -65,4 +95,72 @@ impl Encoding {
             differences: HashMap::new()
         }
     }
+    /// Unicode text of `code`, from `Differences` or else the base encoding.
+    pub fn unicode(&self, code: u8) -> Option<Cow<'static, str>> {
+        match self.differences.get(&(code as u32)) {
+            Some(name) => glyph_name_to_unicode(name),
+            None => self.base.unicode(code).map(|c| Cow::Owned(c.to_string()))
+        }
+    }
+    /// Code of `c`, from `Differences` or else the base encoding.
+    pub fn encode(&self, c: char) -> Option<u8> {
+        let difference = self.differences.iter()
+            .filter(|(_, name)| glyph_name_to_unicode(name).is_some_and(|s| s.chars().eq(Some(c))))
+            .filter_map(|(&code, _)| u8::try_from(code).ok())
+            .min();
+        // codes replaced by `Differences` no longer show the character of the base encoding
+        difference.or_else(|| self.base.encode(c).filter(|&code| !self.differences.contains_key(&(code as u32))))
+    }
+}
+impl BaseEncoding {
+    /// Unicode character of `code` in one of the single byte encodings
+    pub fn unicode(&self, code: u8) -> Option<char> {
+        let map = match self {
+            BaseEncoding::StandardEncoding => &pdf_encoding::STANDARD,
+            BaseEncoding::SymbolEncoding => &pdf_encoding::SYMBOL,
+            BaseEncoding::MacRomanEncoding => &pdf_encoding::MACROMAN,
+            BaseEncoding::WinAnsiEncoding => &pdf_encoding::WINANSI,
+            BaseEncoding::MacExpertEncoding => &pdf_encoding::MACEXPERT,
+            _ => return None
+        };
+        map.get(code)
+    }
+    /// Code of `c` in one of the single byte encodings
+    pub fn encode(&self, c: char) -> Option<u8> {
+        let encoding = match self {
+            BaseEncoding::StandardEncoding => pdf_encoding::Encoding::AdobeStandard,
+            BaseEncoding::SymbolEncoding => pdf_encoding::Encoding::AdobeSymbol,
+            BaseEncoding::MacRomanEncoding => pdf_encoding::Encoding::MacRomanEncoding,
+            BaseEncoding::WinAnsiEncoding => pdf_encoding::Encoding::WinAnsiEncoding,
+            BaseEncoding::MacExpertEncoding => pdf_encoding::Encoding::AdobeExpert,
+            _ => return None
+        };
+        encoding.reverse_map()?.get(c as u32)
+    }
+}
+
+/// Unicode text of a glyph name, using the Adobe Glyph List
+/// and the `uniXXXX` / `uXXXX` conventions.
+pub fn glyph_name_to_unicode(name: &str) -> Option<Cow<'static, str>> {
+    if let Some(s) = pdf_encoding::glyphname_to_unicode(name) {
+        return Some(Cow::Borrowed(s));
+    }
+    // ligatures and variants: "f_i", "a.sc"
+    let name = name.split('.').next()?;
+    if name.contains('_') {
+        return name.split('_').map(glyph_name_to_unicode).collect::<Option<String>>().map(Cow::Owned);
+    }
+    let hex = |s: &str| u32::from_str_radix(s, 16).ok().and_then(std::char::from_u32);
+    if let Some(codes) = name.strip_prefix("uni") {
+        if codes.len() % 4 == 0 && !codes.is_empty() {
+            let chars: Option<String> = (0 .. codes.len()).step_by(4).map(|i| codes.get(i .. i + 4).and_then(hex)).collect();
+            return chars.map(Cow::Owned);
+        }
+    }
+    if let Some(code) = name.strip_prefix('u') {
+        if (4 ..= 6).contains(&code.len()) {
+            return hex(code).map(|c| Cow::Owned(c.to_string()));
+        }
+    }
+    pdf_encoding::glyphname_to_unicode(name).map(Cow::Borrowed)
 }
@@ -146,6 +146,14 @@ impl PdfError {
             _ => false
         }
     }
+    /// Whether the error was caused by a wrong password
+    pub fn is_invalid_password(&self) -> bool {
+        match self {
+            &PdfError::InvalidPassword => true,
+            &PdfError::Try { ref source, .. } | PdfError::TryContext { ref source, .. } => source.is_invalid_password(),
+            _ => false
+        }
+    }
 }
 fn trace(err: &dyn Error, depth: usize) {
     println!("{}: {}", depth, err);
@@ -1,23 +1,40 @@
 //! This is kind of the entry-point of the type-safe PDF functionality.
 use std::fs;
 use std::marker::PhantomData;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::path::Path;
+use std::ops::{Bound, Range, RangeBounds};
 use std::io::Write;
 
+use chrono::{DateTime, FixedOffset};
+
 use crate as pdf;
 use crate::error::*;
 use crate::object::*;
-use crate::primitive::{Primitive, Dictionary, PdfString};
+use crate::primitive::{Primitive, Dictionary, PdfString, PdfStream};
 use crate::backend::Backend;
 use crate::any::{Any};
 use crate::parser::Lexer;
 use crate::parser::{parse_indirect_object, parse};
-use crate::xref::{XRef, XRefTable, XRefInfo};
-use crate::crypt::Decoder;
+use crate::xref::{XRef, XRefTable, XRefInfo, byte_len};
+use crate::enc::{encode, StreamFilter};
+use crate::crypt::{random_bytes, Decoder, EncryptOptions, Permissions};
 use crate::crypt::CryptDict;
+use crate::font::{Font, FontCache, FontInfo};
+use crate::text;
+use crate::build::{text_string, DocumentBuilder, PageBuilder};
+use crate::xmp;
+use crate::form::{self, FieldValue, FieldWidget, FormField, Xfa};
+use crate::sign::{self, Signature, SignatureOptions, SignedData};
+use crate::impose::PageForm;
+use crate::outline::{self, Outline};
+use crate::thread::{self, Article};
+use crate::structure::{self, StructTree};
+use crate::portfolio::{self, Portfolio};
+use crate::javascript::{self, Script};
+use crate::redact;
 
 #[must_use]
 pub struct PromisedRef<T> {
@@ -48,6 +65,9 @@ pub struct Storage<B: Backend> {
 
     // Position of the PDF header in the file.
     start_offset: usize,
+
+    // Objects were created or updated since the last save
+    dirty: bool,
 }
 impl<B: Backend> Storage<B> {
     pub fn new(backend: B, refs: XRefTable, start_offset: usize) -> Storage<B> {
@@ -58,6 +78,50 @@ impl<B: Backend> Storage<B> {
             cache: RefCell::new(HashMap::new()),
             changes: HashMap::new(),
             decoder: None,
+            dirty: false,
+        }
+    }
+
+    /// Whether objects were created or updated since loading or the last save
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Forget the parsed object `id`, so it is read again from the changes or the backend.
+    fn uncache(&self, id: ObjNr) {
+        self.cache.borrow_mut().retain(|r, _| r.id != id);
+    }
+
+    /// Streams can only be written as indirect objects.
+    /// Moves every stream nested inside `p` into a new object and replaces it by a reference.
+    fn hoist_streams(&mut self, p: Primitive) -> Primitive {
+        match p {
+            Primitive::Stream(mut stream) => {
+                stream.info = self.hoist_nested(stream.info);
+                Primitive::Stream(stream)
+            }
+            Primitive::Dictionary(dict) => Primitive::Dictionary(self.hoist_nested(dict)),
+            Primitive::Array(arr) => Primitive::Array(arr.into_iter().map(|p| self.hoist_nested_primitive(p)).collect()),
+            p => p
+        }
+    }
+    fn hoist_nested(&mut self, dict: Dictionary) -> Dictionary {
+        let mut out = Dictionary::new();
+        for (key, val) in dict {
+            out.insert(key, self.hoist_nested_primitive(val));
+        }
+        out
+    }
+    fn hoist_nested_primitive(&mut self, p: Primitive) -> Primitive {
+        match p {
+            Primitive::Stream(_) => {
+                let id = self.refs.len() as u64;
+                self.refs.push(XRef::Promised);
+                let stream = self.hoist_streams(p);
+                self.changes.insert(id, stream);
+                Primitive::Reference(PlainRef { id, gen: 0 })
+            }
+            p => self.hoist_streams(p)
         }
     }
 }
@@ -103,22 +167,27 @@ impl<B: Backend> Updater for Storage<B> {
         let id = self.refs.len() as u64;
         self.refs.push(XRef::Promised);
         let primitive = obj.to_primitive(self)?;
+        let primitive = self.hoist_streams(primitive);
         self.changes.insert(id, primitive);
+        self.dirty = true;
         let rc = Rc::new(obj);
         let r = PlainRef { id, gen: 0 };
         
         Ok(RcRef::new(r, rc))
     }
     fn update<T: ObjectWrite>(&mut self, old: PlainRef, obj: T) -> Result<RcRef<T>> {
+        // the object keeps its number and generation
         let r = match self.refs.get(old.id)? {
-            XRef::Free { .. } => panic!(),
-            XRef::Raw { gen_nr, .. } => PlainRef { id: old.id, gen: gen_nr + 1 },
-            XRef::Stream { .. } => return self.create(obj),
-            XRef::Promised => PlainRef { id: old.id, gen: 0 },
-            XRef::Invalid => panic!()
+            XRef::Free { .. } => err!(PdfError::FreeObject { obj_nr: old.id }),
+            XRef::Raw { gen_nr, .. } => PlainRef { id: old.id, gen: gen_nr },
+            XRef::Stream { .. } | XRef::Promised => PlainRef { id: old.id, gen: 0 },
+            XRef::Invalid => err!(PdfError::NullRef { obj_nr: old.id })
         };
         let primitive = obj.to_primitive(self)?;
+        let primitive = self.hoist_streams(primitive);
         self.changes.insert(old.id, primitive);
+        self.uncache(old.id);
+        self.dirty = true;
         let rc = Rc::new(obj);
         
         Ok(RcRef::new(r, rc))
@@ -143,7 +212,520 @@ impl<B: Backend> Updater for Storage<B> {
     }
 }
 
+/// How `File::save_with` writes the file
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    /// Append the changes to the original file instead of writing a new one,
+    /// see `Storage::save_incremental`
+    pub incremental: bool,
+    /// Write a cross-reference stream (PDF 1.5) instead of a classic xref table
+    pub xref_stream: bool,
+    /// Pack small objects into compressed object streams (PDF 1.5).
+    /// Implies `xref_stream`, as only xref streams can point into object streams.
+    pub object_streams: bool,
+    /// Encrypt the written file with the standard security handler.
+    /// Incremental updates can't be encrypted.
+    pub encrypt: Option<EncryptOptions>,
+    /// Compress streams without filters, merge identical streams, fonts and font descriptors,
+    /// and strip private application data (`PieceInfo`). Only for full rewrites.
+    pub optimize: bool,
+}
+impl SaveOptions {
+    /// Write the smallest file: optimized, with object streams
+    pub fn optimize() -> SaveOptions {
+        SaveOptions { optimize: true, object_streams: true, ..SaveOptions::default() }
+    }
+
+    fn uses_xref_stream(&self) -> bool {
+        self.xref_stream || self.object_streams
+    }
+}
+
+/// Objects larger than this are not put into object streams
+const MAX_PACKED_SIZE: usize = 4096;
+/// Maximum number of objects in one object stream
+const OBJECTS_PER_STREAM: usize = 100;
+
+impl<B: Backend> Storage<B> {
+    /// Write a complete PDF with every object in use, followed by the xref section and `trailer`.
+    ///
+    /// Only objects reachable from `trailer` are written, others (like the old versions
+    /// orphaned by incremental updates) become free entries.
+    /// Objects are written decrypted unless `options` encrypt them; object streams and xref
+    /// streams are not copied, their contents are written as plain objects.
+    /// Objects that were not changed are copied byte for byte from the original file,
+    /// unless the file is encrypted or `options` transform them.
+    pub fn rewrite(&self, mut trailer: Dictionary, options: &SaveOptions) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let version = match self.backend.read(self.start_offset .. self.start_offset + 8) {
+            Ok(header) if header.starts_with(b"%PDF-") => String::from_utf8_lossy(&header[5 ..]).into_owned(),
+            _ => "1.7".into()
+        };
+        let version = match (version.trim(), options.uses_xref_stream()) {
+            (v, true) if v < "1.5" => "1.5",
+            (v, _) => v
+        };
+        writeln!(out, "%PDF-{}", version)?;
+        out.extend_from_slice(b"%\xe2\xe3\xcf\xd3\n");
+
+        trailer.remove("Prev");
+        trailer.remove("Encrypt");
+        // the encryption dictionary of the new file, and the decoder that encrypts with it
+        let crypt = match options.encrypt {
+            Some(ref encrypt) => {
+                let id = file_id(&mut trailer)?;
+                Some(t!(encrypt.security_handler(&id)))
+            }
+            None => None
+        };
+        let encrypter = crypt.as_ref().map(|(_, decoder)| decoder);
+
+        let encrypt = self.decoder.as_ref().and_then(|d| d.encrypt_indirect_object);
+        let reachable = self.reachable(&trailer);
+        // unchanged objects are copied as they are, unless they have to be transformed
+        let verbatim = self.decoder.is_none() && encrypter.is_none() && !options.optimize;
+        let mut raw_objects = HashMap::new();
+        let mut objects = vec![];
+        for id in 1 .. self.refs.len() as ObjNr {
+            if !reachable.contains(&id) {
+                continue;
+            }
+            let gen = match self.refs.get(id)? {
+                XRef::Raw { gen_nr, .. } => gen_nr,
+                _ if self.changes.contains_key(&id) => 0,
+                XRef::Stream { .. } => 0,
+                _ => continue
+            };
+            if encrypt.is_some_and(|r| r.id == id) {
+                continue;
+            }
+            let primitive = match self.read_raw(id, verbatim) {
+                Some((primitive, raw)) => {
+                    raw_objects.insert(id, raw);
+                    primitive
+                }
+                None => t!(self.resolve(PlainRef { id, gen }))
+            };
+            if let Primitive::Stream(ref stream) = primitive {
+                if let Some(Primitive::Name(typ)) = stream.info.get("Type") {
+                    if typ == "XRef" || typ == "ObjStm" {
+                        continue;
+                    }
+                }
+            }
+            objects.push((id, gen, primitive));
+        }
+        if options.optimize {
+            t!(optimize(&mut objects, &trailer));
+        }
+
+        // position and generation of the objects written
+        let mut offsets = vec![None; self.refs.len()];
+        let mut packed = vec![];
+        for (id, gen, primitive) in objects {
+            if let Some(data) = packable(gen, &primitive, options)? {
+                packed.push((id, data));
+                continue;
+            }
+            let primitive = match encrypter {
+                Some(encrypter) => t!(encrypter.encrypt_primitive(id, gen, primitive)),
+                None => primitive
+            };
+            offsets[id as usize] = Some((out.len(), gen));
+            match raw_objects.get(&id) {
+                Some(raw) => {
+                    out.extend_from_slice(raw);
+                    out.push(b'\n');
+                }
+                None => write_object(&mut out, id, gen, primitive)?
+            }
+        }
+        let num_packed = packed.len();
+        let stream_entries = write_object_streams(&mut out, 0, self.refs.len() as ObjNr, packed, encrypter)?;
+        // the object streams are numbered after the existing objects
+        offsets.resize(self.refs.len() + stream_entries.len() - num_packed, None);
+        if let Some((ref dict, _)) = crypt {
+            let id = offsets.len() as ObjNr;
+            offsets.push(Some((out.len(), 0)));
+            write_object(&mut out, id, 0, dict.to_primitive(&mut NoUpdate)?)?;
+            trailer.insert("Encrypt", Primitive::Reference(PlainRef { id, gen: 0 }));
+        }
+
+        let mut entries: Vec<_> = offsets.iter().enumerate().map(|(id, entry)| {
+            let xref = match *entry {
+                Some((pos, gen_nr)) => XRef::Raw { pos, gen_nr },
+                None => XRef::Free { next_obj_nr: 0, gen_nr: 0 }
+            };
+            (id as ObjNr, xref)
+        }).collect();
+        for (id, xref) in stream_entries {
+            entries[id as usize].1 = xref;
+        }
+        // free objects form a linked list starting at object 0
+        let free: Vec<usize> = (1 .. entries.len()).filter(|&id| matches!(entries[id].1, XRef::Free { .. })).collect();
+        entries[0].1 = XRef::Free { next_obj_nr: free.first().map_or(0, |&id| id as ObjNr), gen_nr: 0xffff };
+        for (i, &id) in free.iter().enumerate() {
+            entries[id].1 = XRef::Free { next_obj_nr: free.get(i + 1).map_or(0, |&id| id as ObjNr), gen_nr: 0 };
+        }
+
+        trailer.insert("Size", Primitive::Integer(entries.len() as _));
+        write_xref(&mut out, 0, entries, trailer, options)?;
+
+        Ok(out)
+    }
+
+    /// The unchanged object `id` and its bytes in the file, from `obj` to `endobj`,
+    /// if `enabled` and the object is neither in an object stream nor encrypted.
+    fn read_raw(&self, id: ObjNr, enabled: bool) -> Option<(Primitive, &[u8])> {
+        if !enabled || self.decoder.is_some() || self.changes.contains_key(&id) {
+            return None;
+        }
+        let (pos, gen) = match self.refs.get(id) {
+            Ok(XRef::Raw { pos, gen_nr }) => (pos, gen_nr),
+            _ => return None
+        };
+        let data = self.backend.read(self.start_offset + pos ..).ok()?;
+        let mut lexer = Lexer::new(data);
+        match parse_indirect_object(&mut lexer, self, None) {
+            Ok((r, primitive)) if r == (PlainRef { id, gen }) => {
+                let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(0);
+                Some((primitive, &data[start .. lexer.get_pos()]))
+            }
+            _ => None
+        }
+    }
+
+    /// Numbers of the objects referenced directly or indirectly by `trailer`
+    fn reachable(&self, trailer: &Dictionary) -> HashSet<ObjNr> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![];
+        for (_, value) in trailer.iter() {
+            collect_refs(value, &mut stack);
+        }
+        while let Some(r) = stack.pop() {
+            if !seen.insert(r.id) {
+                continue;
+            }
+            match self.resolve(r) {
+                Ok(p) => collect_refs(&p, &mut stack),
+                Err(e) => warn!("can't resolve {:?}: {:?}", r, e)
+            }
+        }
+        seen
+    }
+
+    /// The original file followed by an incremental update: the changed and new objects,
+    /// an xref section for them, and a trailer linked to the previous one by `/Prev`.
+    ///
+    /// The original bytes are preserved, so existing signatures stay valid.
+    pub fn save_incremental(&self, mut trailer: Dictionary, options: &SaveOptions) -> Result<Vec<u8>> {
+        if self.decoder.is_some() || options.encrypt.is_some() {
+            bail!("incremental updates of encrypted files are not supported");
+        }
+        if options.optimize {
+            bail!("incremental updates can't be optimized");
+        }
+        let prev = t!(self.backend.locate_xref_offset());
+        let mut out = t!(self.backend.read(..)).to_vec();
+        if !out.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+
+        let mut changes: Vec<_> = self.changes.iter().collect();
+        changes.sort_unstable_by_key(|&(&id, _)| id);
+        let mut entries = Vec::with_capacity(changes.len());
+        let mut packed = vec![];
+        for (&id, primitive) in changes {
+            let gen_nr = match self.refs.get(id)? {
+                XRef::Raw { gen_nr, .. } => gen_nr,
+                _ => 0
+            };
+            if let Some(data) = packable(gen_nr, primitive, options)? {
+                packed.push((id, data));
+                continue;
+            }
+            entries.push((id, XRef::Raw { pos: out.len() - self.start_offset, gen_nr }));
+            write_object(&mut out, id, gen_nr, primitive.clone())?;
+        }
+        entries.extend(write_object_streams(&mut out, self.start_offset, self.refs.len() as ObjNr, packed, None)?);
+        entries.sort_unstable_by_key(|&(id, _)| id);
+        let size = entries.last().map_or(0, |&(id, _)| id as usize + 1).max(self.refs.len());
+
+        trailer.insert("Size", Primitive::Integer(size as _));
+        trailer.insert("Prev", Primitive::Integer(prev as _));
+        write_xref(&mut out, self.start_offset, entries, trailer, options)?;
+
+        Ok(out)
+    }
+
+    /// Write the file as described by `options`
+    pub fn save_with(&self, trailer: Dictionary, options: &SaveOptions) -> Result<Vec<u8>> {
+        if options.incremental {
+            self.save_incremental(trailer, options)
+        } else {
+            self.rewrite(trailer, options)
+        }
+    }
+}
+
+fn write_object(out: &mut Vec<u8>, id: ObjNr, gen: GenNr, primitive: Primitive) -> Result<()> {
+    let primitive = match primitive {
+        Primitive::Stream(mut stream) => {
+            stream.info.insert("Length", Primitive::Integer(stream.data.len() as _));
+            Primitive::Stream(stream)
+        }
+        p => p
+    };
+    writeln!(out, "{} {} obj", id, gen)?;
+    primitive.serialize(out, 0)?;
+    writeln!(out, "\nendobj")?;
+    Ok(())
+}
+
+fn collect_refs(p: &Primitive, out: &mut Vec<PlainRef>) {
+    match *p {
+        Primitive::Reference(r) => out.push(r),
+        Primitive::Array(ref arr) => arr.iter().for_each(|p| collect_refs(p, out)),
+        Primitive::Dictionary(ref dict) => dict.iter().for_each(|(_, p)| collect_refs(p, out)),
+        Primitive::Stream(ref stream) => stream.info.iter().for_each(|(_, p)| collect_refs(p, out)),
+        _ => {}
+    }
+}
+
+/// Shrink the `objects` to write: strip `PieceInfo`, compress streams without filters,
+/// and merge identical streams, fonts and font descriptors.
+/// Objects no longer reachable from `trailer` afterwards are dropped.
+fn optimize(objects: &mut Vec<(ObjNr, GenNr, Primitive)>, trailer: &Dictionary) -> Result<()> {
+    for (_, _, primitive) in objects.iter_mut() {
+        strip_private_data(primitive);
+        if let Primitive::Stream(ref mut stream) = *primitive {
+            // written by `write_object`, and may be a reference that keeps identical streams apart
+            stream.info.remove("Length");
+            // XMP metadata should stay readable without decoding
+            let is_metadata = matches!(stream.info.get("Type"), Some(Primitive::Name(typ)) if typ == "Metadata");
+            if stream.info.get("Filter").is_none() && !is_metadata && !stream.data.is_empty() {
+                let data = t!(encode(&stream.data, &StreamFilter::FlateDecode(Default::default())));
+                if data.len() < stream.data.len() {
+                    stream.data = data;
+                    stream.info.insert("Filter", Primitive::name("FlateDecode"));
+                }
+            }
+        }
+    }
+
+    // merging objects can make the objects referencing them identical, like fonts
+    // sharing a font file, so repeat until nothing changes
+    loop {
+        let mut seen = HashMap::new();
+        let mut merged = HashMap::new();
+        for &(id, gen, ref primitive) in objects.iter() {
+            let mergeable = match *primitive {
+                Primitive::Stream(_) => true,
+                Primitive::Dictionary(ref dict) => matches!(dict.get("Type"), Some(Primitive::Name(typ)) if typ == "Font" || typ == "FontDescriptor"),
+                _ => false
+            };
+            if !mergeable {
+                continue;
+            }
+            let mut data = vec![];
+            primitive.serialize(&mut data, 0)?;
+            let r = PlainRef { id, gen };
+            // keyed by the bytes themselves, so only identical objects are merged
+            let original = *seen.entry(data).or_insert(r);
+            if original != r {
+                merged.insert(id, original);
+            }
+        }
+        if merged.is_empty() {
+            break;
+        }
+        objects.retain(|(id, _, _)| !merged.contains_key(id));
+        for (_, _, primitive) in objects.iter_mut() {
+            replace_refs(primitive, &merged);
+        }
+    }
+
+    // like the `Length` objects of stripped stream dictionaries
+    let by_id: HashMap<ObjNr, &Primitive> = objects.iter().map(|(id, _, p)| (*id, p)).collect();
+    let mut reachable = HashSet::new();
+    let mut stack = vec![];
+    for (_, value) in trailer.iter() {
+        collect_refs(value, &mut stack);
+    }
+    while let Some(r) = stack.pop() {
+        if reachable.insert(r.id) {
+            if let Some(p) = by_id.get(&r.id) {
+                collect_refs(p, &mut stack);
+            }
+        }
+    }
+    objects.retain(|(id, _, _)| reachable.contains(id));
+    Ok(())
+}
+
+fn strip_private_data(p: &mut Primitive) {
+    let dict = match *p {
+        Primitive::Dictionary(ref mut dict) => dict,
+        Primitive::Stream(ref mut stream) => &mut stream.info,
+        Primitive::Array(ref mut arr) => {
+            arr.iter_mut().for_each(strip_private_data);
+            return;
+        }
+        _ => return
+    };
+    dict.remove("PieceInfo");
+    for (_, value) in dict.iter_mut() {
+        strip_private_data(value);
+    }
+}
+
+fn replace_refs(p: &mut Primitive, map: &HashMap<ObjNr, PlainRef>) {
+    match *p {
+        Primitive::Reference(ref mut r) => if let Some(&new) = map.get(&r.id) {
+            *r = new;
+        },
+        Primitive::Array(ref mut arr) => arr.iter_mut().for_each(|p| replace_refs(p, map)),
+        Primitive::Dictionary(ref mut dict) => dict.iter_mut().for_each(|(_, p)| replace_refs(p, map)),
+        Primitive::Stream(ref mut stream) => stream.info.iter_mut().for_each(|(_, p)| replace_refs(p, map)),
+        _ => {}
+    }
+}
+
+/// The first file identifier in `trailer`. A new, random one is added if there is none.
+fn file_id(trailer: &mut Dictionary) -> Result<Vec<u8>> {
+    if let Some(Primitive::Array(ref ids)) = trailer.get("ID") {
+        if let Some(Primitive::String(ref id)) = ids.first() {
+            return Ok(id.as_bytes().to_vec());
+        }
+    }
+    let id = random_bytes::<16>()?.to_vec();
+    let id = Primitive::String(PdfString::new(id.clone()));
+    trailer.insert("ID", Primitive::Array(vec![id.clone(), id]));
+    match trailer.get("ID") {
+        Some(Primitive::Array(ref ids)) => Ok(ids[0].as_string()?.as_bytes().to_vec()),
+        _ => unreachable!()
+    }
+}
+
+/// The serialized object if it should go into an object stream.
+///
+/// Streams, objects with a generation other than 0 and large objects are written on their own.
+fn packable(gen: GenNr, primitive: &Primitive, options: &SaveOptions) -> Result<Option<Vec<u8>>> {
+    if !options.object_streams || gen != 0 || matches!(primitive, Primitive::Stream(_)) {
+        return Ok(None);
+    }
+    let mut data = vec![];
+    primitive.serialize(&mut data, 0)?;
+    Ok(if data.len() <= MAX_PACKED_SIZE { Some(data) } else { None })
+}
+
+/// Write the `packed` objects into object streams numbered from `first_id`.
+/// The streams are encrypted as a whole with `crypt`, if given.
+///
+/// Returns the xref entries of both the packed objects and the object streams.
+fn write_object_streams(out: &mut Vec<u8>, start_offset: usize, first_id: ObjNr, packed: Vec<(ObjNr, Vec<u8>)>, crypt: Option<&Decoder>) -> Result<Vec<(ObjNr, XRef)>> {
+    let mut entries = vec![];
+    for (stream_id, objects) in (first_id ..).zip(packed.chunks(OBJECTS_PER_STREAM)) {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        for (index, (id, data)) in objects.iter().enumerate() {
+            write!(header, "{} {} ", id, body.len())?;
+            body.extend_from_slice(data);
+            body.push(b'\n');
+            entries.push((*id, XRef::Stream { stream_id, index }));
+        }
+        let first = header.len();
+        header.extend_from_slice(&body);
+
+        let mut info = Dictionary::new();
+        info.insert("Type", Primitive::name("ObjStm"));
+        info.insert("N", Primitive::Integer(objects.len() as _));
+        info.insert("First", Primitive::Integer(first as _));
+        info.insert("Filter", Primitive::name("FlateDecode"));
+        let mut data = t!(encode(&header, &StreamFilter::FlateDecode(Default::default())));
+        if let Some(crypt) = crypt {
+            data = t!(crypt.encrypt(stream_id, 0, &data));
+        }
+        entries.push((stream_id, XRef::Raw { pos: out.len() - start_offset, gen_nr: 0 }));
+        write_object(out, stream_id, 0, Primitive::Stream(PdfStream { info, data }))?;
+    }
+    Ok(entries)
+}
+
+/// Write the xref section for `entries` (sorted by object number) and the trailer.
+///
+/// Positions are relative to `start_offset`.
+/// An xref stream gets the object number `Size` and carries the trailer entries.
+fn write_xref(out: &mut Vec<u8>, start_offset: usize, mut entries: Vec<(ObjNr, XRef)>, mut trailer: Dictionary, options: &SaveOptions) -> Result<()> {
+    if let Some(Primitive::Array(ref id)) = trailer.get("ID") {
+        if id.is_empty() {
+            trailer.remove("ID");
+        }
+    }
+    let xref_pos = out.len() - start_offset;
+
+    if options.uses_xref_stream() {
+        let id = t!(trailer.require("Trailer", "Size")?.as_u32()) as ObjNr;
+        entries.push((id, XRef::Raw { pos: xref_pos, gen_nr: 0 }));
+
+        let fields: Vec<(u8, u64, u64)> = entries.iter().map(|&(_, xref)| match xref {
+            XRef::Raw { pos, gen_nr } => (1, pos as u64, gen_nr as u64),
+            XRef::Stream { stream_id, index } => (2, stream_id, index as u64),
+            XRef::Free { next_obj_nr, gen_nr } => (0, next_obj_nr, gen_nr as u64),
+            _ => (0, 0, 0)
+        }).collect();
+        let a_w = byte_len(fields.iter().map(|f| f.1).max().unwrap_or(0));
+        let b_w = byte_len(fields.iter().map(|f| f.2).max().unwrap_or(0));
+        let mut data = Vec::with_capacity((1 + a_w + b_w) * fields.len());
+        for (t, a, b) in fields {
+            data.push(t);
+            data.extend_from_slice(&a.to_be_bytes()[8 - a_w ..]);
+            data.extend_from_slice(&b.to_be_bytes()[8 - b_w ..]);
+        }
+
+        // subsections, unless the entries cover all objects
+        let mut index = vec![];
+        for section in entries.chunk_by(|a, b| a.0 + 1 == b.0) {
+            index.push(Primitive::Integer(section[0].0 as _));
+            index.push(Primitive::Integer(section.len() as _));
+        }
+        let mut info = trailer;
+        info.insert("Type", Primitive::name("XRef"));
+        info.insert("Size", Primitive::Integer(id as i32 + 1));
+        if entries[0].0 != 0 || index.len() > 2 {
+            info.insert("Index", Primitive::Array(index));
+        }
+        info.insert("W", Primitive::Array(vec![Primitive::Integer(1), Primitive::Integer(a_w as _), Primitive::Integer(b_w as _)]));
+        info.insert("Filter", Primitive::name("FlateDecode"));
+        let data = t!(encode(&data, &StreamFilter::FlateDecode(Default::default())));
+        write_object(out, id, 0, Primitive::Stream(PdfStream { info, data }))?;
+    } else {
+        writeln!(out, "xref")?;
+        for section in entries.chunk_by(|a, b| a.0 + 1 == b.0) {
+            writeln!(out, "{} {}", section[0].0, section.len())?;
+            for &(_, xref) in section {
+                match xref {
+                    XRef::Raw { pos, gen_nr } => writeln!(out, "{:010} {:05} n ", pos, gen_nr)?,
+                    XRef::Free { next_obj_nr, gen_nr } => writeln!(out, "{:010} {:05} f ", next_obj_nr, gen_nr)?,
+                    x => bail!("can't write xref entry {:?} in a table", x)
+                }
+            }
+        }
+        writeln!(out, "trailer")?;
+        Primitive::Dictionary(trailer).serialize(out, 0)?;
+    }
+    writeln!(out, "startxref\n{}\n%%EOF", xref_pos)?;
+    Ok(())
+}
+
 impl Storage<Vec<u8>> {
+    /// Storage for a new document, without any objects yet
+    pub fn empty() -> Storage<Vec<u8>> {
+        let mut refs = XRefTable::new(1);
+        refs.set(0, XRef::Free { next_obj_nr: 0, gen_nr: 0xffff });
+        Storage::new(Vec::new(), refs, 0)
+    }
+
     pub fn save(&mut self, trailer: &mut Trailer) -> Result<&[u8]> {
         let xref_promise = self.promise::<Stream<XRefInfo>>();
 
@@ -190,6 +772,23 @@ pub fn load_storage_and_trailer<B: Backend>(backend: B) -> Result<(Storage<B>, D
 pub fn load_storage_and_trailer_password<B: Backend>(
     backend: B,
     password: &[u8],
+) -> Result<(Storage<B>, Dictionary)> {
+    load_storage_and_trailer_with(backend, |dict, id| Decoder::from_password(dict, id, password))
+}
+
+/// Load a document encrypted for certificates, see `Decoder::from_recipients`.
+pub fn load_storage_and_trailer_recipient<B: Backend>(
+    backend: B,
+    decrypt: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+) -> Result<(Storage<B>, Dictionary)> {
+    load_storage_and_trailer_with(backend, |dict, _| Decoder::from_recipients(dict, decrypt))
+}
+
+/// Load the document, `decoder` creates the decoder from the encryption dictionary
+/// and the first file identifier if it is encrypted
+fn load_storage_and_trailer_with<B: Backend>(
+    backend: B,
+    decoder: impl FnOnce(&CryptDict, &[u8]) -> Result<Decoder>,
 ) -> Result<(Storage<B>, Dictionary)> {
     let start_offset = t!(backend.locate_start_offset());
     let (refs, trailer) = t!(backend.read_xref_table_and_trailer(start_offset));
@@ -206,7 +805,7 @@ pub fn load_storage_and_trailer_password<B: Backend>(
             .as_string()?
             .as_bytes();
         let dict = CryptDict::from_primitive(crypt.clone(), &storage)?;
-        storage.decoder = Some(t!(Decoder::from_password(&dict, key, password)));
+        storage.decoder = Some(t!(decoder(&dict, key)));
         if let Primitive::Reference(reference) = crypt {
             storage.decoder.as_mut().unwrap().encrypt_indirect_object = Some(*reference);
         }
@@ -220,9 +819,71 @@ pub fn load_storage_and_trailer_password<B: Backend>(
     Ok((storage, trailer))
 }
 
+/// How to open a file, for passwords that are not known up front
+///
+/// ```
+/// let file = FileOptions::new()
+///     .password_provider(|attempt| ask_user(attempt))
+///     .open("secret.pdf")?;
+/// ```
+#[derive(Default)]
+pub struct FileOptions<'a> {
+    password: Vec<u8>,
+    password_provider: Option<Box<dyn FnMut(u32) -> Option<String> + 'a>>,
+}
+impl<'a> FileOptions<'a> {
+    pub fn new() -> FileOptions<'a> {
+        FileOptions::default()
+    }
+
+    /// The password to try first, empty by default
+    pub fn password(mut self, password: &[u8]) -> FileOptions<'a> {
+        self.password = password.to_vec();
+        self
+    }
+
+    /// Ask for a password while the ones given so far are wrong.
+    ///
+    /// `provider` gets the number of the attempt, starting at 0, and returns `None`
+    /// to give up, which fails with `PdfError::InvalidPassword`.
+    /// The file is only parsed once, each password is checked against the encryption dictionary.
+    pub fn password_provider(mut self, provider: impl FnMut(u32) -> Option<String> + 'a) -> FileOptions<'a> {
+        self.password_provider = Some(Box::new(provider));
+        self
+    }
+
+    /// Open the file at `path` and use Vec<u8> as backend.
+    pub fn open(self, path: impl AsRef<Path>) -> Result<File<Vec<u8>>> {
+        self.load(fs::read(path)?)
+    }
+
+    pub fn load<B: Backend>(self, backend: B) -> Result<File<B>> {
+        let FileOptions { password, mut password_provider } = self;
+        let (storage, trailer) = load_storage_and_trailer_with(backend, |dict, id| {
+            let mut decoder = Decoder::from_password(dict, id, &password);
+            for attempt in 0 .. {
+                match (&decoder, &mut password_provider) {
+                    (Err(e), Some(provider)) if e.is_invalid_password() => match provider(attempt) {
+                        Some(password) => decoder = Decoder::from_password(dict, id, password.as_bytes()),
+                        None => break
+                    },
+                    _ => break
+                }
+            }
+            decoder
+        })?;
+        File::from_storage(storage, trailer)
+    }
+}
+
 pub struct File<B: Backend> {
     storage:    Storage<B>,
     pub trailer:    Trailer,
+    fonts:      FontCache,
+    // the Info dictionary was changed with `set_info`, so the XMP metadata is out of date
+    info_changed: bool,
+    // the object of the Info dictionary, which is written as an indirect object
+    info_ref: Option<PlainRef>,
 }
 impl<B: Backend> Resolve for File<B> {
     fn resolve(&self, r: PlainRef) -> Result<Primitive> {
@@ -237,6 +898,9 @@ impl<B: Backend> Updater for File<B> {
         self.storage.create(obj)
     }
     fn update<T: ObjectWrite>(&mut self, old: PlainRef, obj: T) -> Result<RcRef<T>> {
+        // a FontInfo is built from the font and the objects it refers to (descriptor, font file, ToUnicode),
+        // any of which might be the one replaced here
+        self.fonts.clear();
         self.storage.update(old, obj)
     }
     fn promise<T: Object>(&mut self) -> PromisedRef<T> {
@@ -254,13 +918,14 @@ impl File<Vec<u8>> {
     }
 
     /// Opens the file at `path`, with a password, and uses Vec<u8> as backend.
+    /// Open an encrypted file with the user or the owner password, see `permissions`.
     pub fn open_password(path: impl AsRef<Path>, password: &[u8]) -> Result<Self> {
         Self::from_data_password(fs::read(path)?, password)
     }
 
-    pub fn save_to(&mut self, path: impl AsRef<Path>) -> Result<()> {
-        std::fs::write(path, self.storage.save(&mut self.trailer)?)?;
-        Ok(())
+    /// Opens the file at `path`, encrypted for certificates, see `from_data_recipient`.
+    pub fn open_recipient(path: impl AsRef<Path>, decrypt: impl FnMut(&[u8]) -> Option<Vec<u8>>) -> Result<Self> {
+        Self::from_data_recipient(fs::read(path)?, decrypt)
     }
 }
 impl<B: Backend> File<B> {
@@ -272,13 +937,82 @@ impl<B: Backend> File<B> {
         Self::from_data_password(backend, b"")
     }
 
+    /// Open a document encrypted with the public-key security handler (`Adobe.PubSec`).
+    ///
+    /// `decrypt` is called with the CMS enveloped data of the recipients, and decrypts the one
+    /// addressed to the private key of the caller, see `Decoder::from_recipients`.
+    pub fn from_data_recipient(backend: B, decrypt: impl FnMut(&[u8]) -> Option<Vec<u8>>) -> Result<Self> {
+        let (storage, trailer) = load_storage_and_trailer_recipient(backend, decrypt)?;
+        Self::from_storage(storage, trailer)
+    }
+
     fn load_data(backend: B, password: &[u8]) -> Result<Self> {
         let (storage, trailer) = load_storage_and_trailer_password(backend, password)?;
+        Self::from_storage(storage, trailer)
+    }
+
+    fn from_storage(storage: Storage<B>, trailer: Dictionary) -> Result<Self> {
+        let info_ref = match trailer.get("Info") {
+            Some(&Primitive::Reference(r)) => Some(r),
+            _ => None
+        };
         let trailer = t!(Trailer::from_primitive(
             Primitive::Dictionary(trailer),
             &storage,
         ));
-        Ok(File { storage, trailer })
+        Ok(File { storage, trailer, fonts: FontCache::new(), info_changed: false, info_ref })
+    }
+
+    /// Write the document, including all changes, as a new, self-contained file at `path`.
+    pub fn save_to(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.save_with(path, &SaveOptions::default())
+    }
+
+    /// Append the changes to the original file and write the result to `path`,
+    /// see `Storage::save_incremental`.
+    pub fn save_incremental(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.save_with(path, &SaveOptions { incremental: true, .. SaveOptions::default() })
+    }
+
+    /// Write the document to `path` as described by `options`.
+    pub fn save_with(&mut self, path: impl AsRef<Path>, options: &SaveOptions) -> Result<()> {
+        let data = self.save_to_bytes(options)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// The document as described by `options`, see `save_with`.
+    pub fn save_to_bytes(&mut self, options: &SaveOptions) -> Result<Vec<u8>> {
+        if self.info_changed {
+            t!(self.update_xmp());
+            self.info_changed = false;
+        }
+        let mut trailer = self.trailer.to_dict(&mut self.storage)?;
+        // the strings of the Info dictionary can only be encrypted in an indirect object
+        if let Some(info) = trailer.remove("Info") {
+            let r = match self.info_ref {
+                Some(r) => self.storage.update(r, info)?.get_ref().get_inner(),
+                None => self.storage.create(info)?.get_ref().get_inner()
+            };
+            self.info_ref = Some(r);
+            trailer.insert("Info", Primitive::Reference(r));
+        }
+        let data = self.storage.save_with(trailer, options)?;
+        self.storage.dirty = false;
+        Ok(data)
+    }
+
+    /// What the document may be used for: everything if it is not encrypted or was opened
+    /// with the owner password, otherwise the permissions of the user password,
+    /// or of the recipient for documents encrypted for certificates.
+    ///
+    /// The permissions are not enforced by this library.
+    pub fn permissions(&self) -> Permissions {
+        match (&self.storage.decoder, &self.trailer.encrypt_dict) {
+            (Some(decoder), _) if decoder.recipient_permissions().is_some() => decoder.recipient_permissions().unwrap(),
+            (Some(decoder), Some(dict)) if !decoder.is_owner() => dict.permissions(),
+            _ => Permissions::all()
+        }
     }
 
     pub fn get_root(&self) -> &Catalog {
@@ -288,6 +1022,10 @@ impl<B: Backend> File<B> {
     pub fn pages<'a>(&'a self) -> impl Iterator<Item=Result<PageRc>> + 'a {
         (0 .. self.num_pages()).map(move |n| self.get_page(n))
     }
+    /// The bytes the file was read from, with the changes that haven't been saved left out
+    pub fn raw_data(&self) -> Result<&[u8]> {
+        self.storage.backend.read(..)
+    }
     pub fn num_pages(&self) -> u32 {
         self.trailer.root.pages.count
     }
@@ -296,13 +1034,658 @@ impl<B: Backend> File<B> {
         self.trailer.root.pages.page(self, n)
     }
 
+    /// The number of the page `page` (counting from 0), like the page of a `Dest`
+    pub fn page_nr(&self, page: PlainRef) -> Result<Option<u32>> {
+        self.trailer.root.pages.page_nr(self, page)
+    }
+
+    /// The font at `font` with its decoded tables, shared across the document.
+    pub fn get_font(&self, font: Ref<Font>) -> Result<Rc<FontInfo>> {
+        self.fonts.get(font, self)
+    }
+
+    /// Search the text of all pages for `needle`, see `text::find`.
+    pub fn find_text(&self, needle: &str) -> Result<Vec<text::Match>> {
+        text::find(self, needle)
+    }
+
+    /// Whether there are changes that were not saved yet
+    pub fn is_dirty(&self) -> bool {
+        self.storage.is_dirty() || self.info_changed
+    }
+
+    /// The Info dictionary of the trailer, if there is one
+    pub fn info(&self) -> Result<Option<DocInfo>> {
+        match self.trailer.info_dict {
+            Some(ref info) => Ok(Some(t!(DocInfo::from_primitive(Primitive::Dictionary(info.clone()), self)))),
+            None => Ok(None)
+        }
+    }
+
+    /// Set the text entry `key` of the Info dictionary
+    /// (`Title`, `Author`, `Subject`, `Keywords`, `Creator`, `Producer`, ...).
+    ///
+    /// When saving, the XMP metadata of the catalog is replaced by a packet matching the Info dictionary.
+    pub fn set_info(&mut self, key: &str, value: &str) {
+        self.trailer.info_dict.get_or_insert_with(Dictionary::new)
+            .insert(key, Primitive::String(text_string(value)));
+        self.info_changed = true;
+    }
+
+    /// Set the date entry `key` of the Info dictionary (`CreationDate` or `ModDate`), see `set_info`.
+    pub fn set_info_date(&mut self, key: &str, date: DateTime<FixedOffset>) -> Result<()> {
+        let date = date.to_primitive(&mut NoUpdate)?;
+        self.trailer.info_dict.get_or_insert_with(Dictionary::new).insert(key, date);
+        self.info_changed = true;
+        Ok(())
+    }
+
+    /// The XMP packet in the `Metadata` stream of the catalog, if there is one
+    pub fn xmp_packet(&self) -> Result<Option<Vec<u8>>> {
+        match self.trailer.root.metadata {
+            Some(metadata) => Ok(Some(t!(t!(self.get(metadata)).data()).to_vec())),
+            None => Ok(None)
+        }
+    }
+
+    /// The properties of the XMP metadata of the catalog, see `xmp::parse`
+    #[cfg(feature = "xmp")]
+    pub fn xmp_metadata(&self) -> Result<Option<xmp::Metadata>> {
+        match self.xmp_packet()? {
+            Some(packet) => Ok(Some(t!(xmp::parse(&packet)))),
+            None => Ok(None)
+        }
+    }
+
+    /// Write the Info dictionary as XMP to the `Metadata` stream of the catalog
+    fn update_xmp(&mut self) -> Result<()> {
+        let info = self.trailer.info_dict.clone().unwrap_or_default();
+        let stream = xmp::metadata_stream(xmp::from_info(&info));
+        match self.trailer.root.metadata {
+            Some(metadata) => {
+                self.update(metadata.get_inner(), stream)?;
+            }
+            None => {
+                let metadata = self.create(stream)?.get_ref().get_inner();
+                // update the catalog as it is, the `Catalog` struct doesn't have all entries
+                let catalog = self.trailer.root.get_ref().get_inner();
+                let mut dict = t!(self.resolve(catalog)?.into_dictionary(self));
+                dict.insert("Metadata", Primitive::Reference(metadata));
+                self.update(catalog, Primitive::Dictionary(dict))?;
+                self.reload_root()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Append a page at the end of the document.
+    pub fn add_page(&mut self, page: PageBuilder) -> Result<PageRc> {
+        let root = self.trailer.root.pages.clone();
+        let page = PageRc::create(page.build(root.clone()), self)?;
+
+        let mut tree = (*root).clone();
+        tree.kids.push(page.get_ref());
+        tree.count += 1;
+        self.update(root.get_ref().get_inner(), PagesNode::Tree(tree))?;
+        self.reload_root()?;
+        Ok(page)
+    }
+
+    /// Read the catalog again, after the page tree was updated
+    pub(crate) fn reload_root(&mut self) -> Result<()> {
+        let catalog = self.trailer.root.get_ref();
+        self.storage.uncache(catalog.get_inner().id);
+        self.trailer.root = self.get(catalog)?;
+        Ok(())
+    }
+
+    /// Replace the page tree by a flat one with `pages` as the kids of the root.
+    ///
+    /// Attributes the pages inherited from the old tree are set on the pages themselves.
+    /// Intermediate nodes are no longer referenced.
+    fn set_pages(&mut self, pages: Vec<PageRc>) -> Result<()> {
+        let root = self.trailer.root.pages.clone();
+        let mut kids = Vec::with_capacity(pages.len());
+        for page in pages {
+            let mut leaf = (*page).clone();
+            t!(leaf.resolve_inherited());
+            leaf.parent = root.clone();
+            self.update(page.get_ref().get_inner(), PagesNode::Leaf(leaf))?;
+            kids.push(page.get_ref());
+        }
+        let mut tree = (*root).clone();
+        tree.count = kids.len() as u32;
+        tree.kids = kids;
+        self.update(root.get_ref().get_inner(), PagesNode::Tree(tree))?;
+        self.reload_root()
+    }
+
+    /// Remove page `n` (counting from 0).
+    pub fn remove_page(&mut self, n: u32) -> Result<()> {
+        let mut pages: Vec<_> = t!(self.pages().collect());
+        if n as usize >= pages.len() {
+            bail!("page {} out of range, the document has {} pages", n, pages.len());
+        }
+        pages.remove(n as usize);
+        self.set_pages(pages)
+    }
+
+    /// Rearrange the pages: page `i` of the result is the current page `order[i]`.
+    ///
+    /// `order` has to contain every page number exactly once.
+    pub fn reorder_pages(&mut self, order: &[u32]) -> Result<()> {
+        let mut seen = vec![false; self.num_pages() as usize];
+        for &n in order {
+            match seen.get_mut(n as usize) {
+                Some(s) if !*s => *s = true,
+                Some(_) => bail!("page {} appears more than once", n),
+                None => bail!("page {} out of range, the document has {} pages", n, seen.len())
+            }
+        }
+        if order.len() != seen.len() {
+            bail!("expected {} page numbers, got {}", seen.len(), order.len());
+        }
+        let pages = t!(order.iter().map(|&n| self.get_page(n)).collect());
+        self.set_pages(pages)
+    }
+
+    /// Append the pages `range` of `other` to this document.
+    ///
+    /// Everything the pages use (content, resources, annotations, ...) is copied
+    /// and renumbered. References to other pages or the page tree of `other`,
+    /// like the `Dest` of a link to a page that is not copied, become `null`.
+    pub fn import_pages<C: Backend>(&mut self, other: &File<C>, range: impl RangeBounds<u32>) -> Result<Vec<PageRc>> {
+        let range = page_range(range, other.num_pages());
+        let sources: Vec<PageRc> = t!(range.map(|n| other.get_page(n)).collect());
+        let mut importer = Importer { source: other, map: HashMap::new() };
+
+        // number the pages first, so links between them are kept
+        let promises: Vec<PromisedRef<Primitive>> = sources.iter().map(|page| {
+            let promise = self.promise();
+            importer.map.insert(page.get_ref().get_inner(), promise.get_inner());
+            promise
+        }).collect();
+
+        let root = self.trailer.root.pages.clone();
+        let mut new_refs = Vec::with_capacity(sources.len());
+        for (page, promise) in sources.iter().zip(promises) {
+            let dict = t!(importer.page_dict(page.get_ref().get_inner()));
+            let mut dict = t!(importer.copy_dict(dict, self));
+            dict.insert("Parent", Primitive::Reference(root.get_ref().get_inner()));
+            new_refs.push(Ref::new(promise.get_inner()));
+            self.fulfill(promise, Primitive::Dictionary(dict))?;
+        }
+
+        let mut tree = (*root).clone();
+        tree.count += new_refs.len() as u32;
+        tree.kids.extend(new_refs.iter().cloned());
+        self.update(root.get_ref().get_inner(), PagesNode::Tree(tree))?;
+        self.reload_root()?;
+
+        let first = self.num_pages() - new_refs.len() as u32;
+        (first .. self.num_pages()).map(|n| self.get_page(n)).collect()
+    }
+
+    /// A new document with the pages `range` of this one, and only the objects they use,
+    /// see `import_pages`. `extract_pages(n ..= n)` splits off a single page.
+    pub fn extract_pages(&self, range: impl RangeBounds<u32>) -> Result<File<Vec<u8>>> {
+        let mut file = t!(File::from_data(DocumentBuilder::new().to_bytes()?));
+        file.import_pages(self, range)?;
+        Ok(file)
+    }
+
+    /// Copy the pages `range` of `other` into this document as Form XObjects,
+    /// to draw them on other pages, see `impose`.
+    ///
+    /// Each form has the page's content and resources, and shows the crop box as the page is displayed,
+    /// rotated by `Rotate`, with the lower left corner at the origin. Annotations are not copied.
+    pub fn import_pages_as_forms<C: Backend>(&mut self, other: &File<C>, range: impl RangeBounds<u32>) -> Result<Vec<PageForm>> {
+        let range = page_range(range, other.num_pages());
+        let mut importer = Importer { source: other, map: HashMap::new() };
+        let mut forms = Vec::with_capacity(range.len());
+        for n in range {
+            let page = t!(other.get_page(n));
+            let mut dict = t!(importer.page_dict(page.get_ref().get_inner()));
+
+            // the content streams are joined into one
+            let mut data = vec![];
+            let contents = match dict.remove("Contents") {
+                Some(Primitive::Reference(r)) => t!(other.resolve(r)),
+                Some(p) => p,
+                None => Primitive::Array(vec![])
+            };
+            let contents = match contents {
+                Primitive::Array(parts) => parts,
+                p => vec![p]
+            };
+            for part in contents {
+                let stream = t!(Stream::<()>::from_primitive(part, other));
+                data.extend_from_slice(t!(stream.data()));
+                data.push(b'\n');
+            }
+
+            let bbox = t!(page.crop_box());
+            let display = t!(page.display_transform());
+            let (width, height) = match t!(page.rotation()) {
+                90 | 270 => (bbox.top - bbox.bottom, bbox.right - bbox.left),
+                _ => (bbox.right - bbox.left, bbox.top - bbox.bottom)
+            };
+            let mut info = Dictionary::new();
+            info.insert("Type", Primitive::name("XObject"));
+            info.insert("Subtype", Primitive::name("Form"));
+            info.insert("BBox", bbox.to_primitive(self)?);
+            info.insert("Matrix", display.to_primitive(self)?);
+            for &key in &["Resources", "Group"] {
+                if let Some(val) = dict.remove(key) {
+                    info.insert(key, importer.copy(val, self)?);
+                }
+            }
+            info.insert("Filter", Primitive::name("FlateDecode"));
+            let data = t!(encode(&data, &StreamFilter::FlateDecode(Default::default())));
+            info.insert("Length", Primitive::Integer(data.len() as _));
+            let xobject = self.create(Primitive::Stream(PdfStream { info, data }))?;
+            forms.push(PageForm {
+                xobject: Ref::new(xobject.get_ref().get_inner()),
+                width,
+                height
+            });
+        }
+        Ok(forms)
+    }
+
+    /// Embed the file `data` as the attachment `name`, with the MIME type `mime`
+    /// (like `text/plain`) and a `description`. Both are omitted if empty.
+    ///
+    /// The file specification is added to the `EmbeddedFiles` name tree of the catalog,
+    /// replacing an attachment with the same name.
+    pub fn attach(&mut self, name: &str, data: &[u8], mime: &str, description: &str) -> Result<()> {
+        let mut params = Dictionary::new();
+        params.insert("Size", Primitive::Integer(data.len() as _));
+        params.insert("CheckSum", Primitive::String(PdfString::new(md5::compute(data).0.to_vec())));
+        let mut info = Dictionary::new();
+        info.insert("Type", Primitive::name("EmbeddedFile"));
+        if !mime.is_empty() {
+            info.insert("Subtype", Primitive::name(mime));
+        }
+        info.insert("Params", Primitive::Dictionary(params));
+        info.insert("Filter", Primitive::name("FlateDecode"));
+        let data = t!(encode(data, &StreamFilter::FlateDecode(Default::default())));
+        let stream = self.create(Primitive::Stream(PdfStream { info, data }))?.get_ref().get_inner();
+
+        let mut ef = Dictionary::new();
+        ef.insert("F", Primitive::Reference(stream));
+        ef.insert("UF", Primitive::Reference(stream));
+        let mut spec = Dictionary::new();
+        spec.insert("Type", Primitive::name("Filespec"));
+        spec.insert("F", Primitive::String(text_string(name)));
+        spec.insert("UF", Primitive::String(text_string(name)));
+        if !description.is_empty() {
+            spec.insert("Desc", Primitive::String(text_string(description)));
+        }
+        spec.insert("EF", Primitive::Dictionary(ef));
+        let spec = self.create(Primitive::Dictionary(spec))?.get_ref().get_inner();
+
+        // update the catalog and name dictionary as they are, their structs don't have all entries
+        let catalog = self.trailer.root.get_ref().get_inner();
+        let mut catalog_dict = t!(self.resolve(catalog)?.into_dictionary(self));
+        let (names_ref, mut names) = match catalog_dict.get("Names") {
+            Some(&Primitive::Reference(r)) => (Some(r), t!(self.resolve(r)?.into_dictionary(self))),
+            Some(p) => (None, t!(p.clone().into_dictionary(self))),
+            None => (None, Dictionary::new())
+        };
+        let mut entries = vec![];
+        if let Some(tree) = names.remove("EmbeddedFiles") {
+            t!(name_tree_entries(tree, self, &mut entries, 0));
+        }
+        let key = text_string(name);
+        entries.retain(|(k, _)| k.as_bytes() != key.as_bytes());
+        entries.push((key, Primitive::Reference(spec)));
+        entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+
+        // a single leaf as the root of the tree
+        let list = entries.into_iter().flat_map(|(k, v)| [Primitive::String(k), v]).collect();
+        let mut tree = Dictionary::new();
+        tree.insert("Names", Primitive::Array(list));
+        names.insert("EmbeddedFiles", Primitive::Dictionary(tree));
+        match names_ref {
+            Some(r) => {
+                self.update(r, Primitive::Dictionary(names))?;
+            }
+            None => {
+                catalog_dict.insert("Names", Primitive::Dictionary(names));
+                self.update(catalog, Primitive::Dictionary(catalog_dict))?;
+            }
+        }
+        self.reload_root()
+    }
+
+    /// The embedded files of the document with their decoded contents: the `EmbeddedFiles`
+    /// name tree first, then the associated files (`AF`) of the document, and those of the
+    /// pages with the files of their `FileAttachment` annotations in page order.
+    /// A file that is listed more than once is returned once.
+    pub fn attachments(&self) -> Result<Vec<Attachment>> {
+        let mut specs = vec![];
+        if let Some(tree) = self.get_root().names.as_ref().and_then(|names| names.embedded_files.as_ref()) {
+            for (name, spec) in t!(tree.entries(self)) {
+                specs.push((Some(name), None, spec));
+            }
+        }
+        for spec in self.get_root().associated_files.iter().flatten() {
+            specs.push((None, None, spec.clone()));
+        }
+        for (page_nr, page) in self.pages().enumerate() {
+            let page = page?;
+            for spec in page.associated_files.iter().flatten() {
+                specs.push((None, Some(page_nr as u32), spec.clone()));
+            }
+            for annot in t!(page.annots(self)) {
+                if let Annot::FileAttachment(_, attachment) = annot {
+                    if let FileAttachmentAnnot { file: Some(spec), .. } = *attachment {
+                        specs.push((None, Some(page_nr as u32), spec));
+                    }
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut attachments = vec![];
+        for (key, page, spec) in specs {
+            let stream_ref = match spec.embedded() {
+                Some(r) => r,
+                None => continue
+            };
+            if !seen.insert(stream_ref.get_inner()) {
+                continue;
+            }
+            let stream = t!(self.get(stream_ref));
+            let params = stream.params.as_ref();
+            attachments.push(Attachment {
+                name: spec.name().or_else(|| key.map(|k| String::from_utf8_lossy(k.as_bytes()).into_owned())).unwrap_or_default(),
+                description: spec.description_text(),
+                mime: stream.subtype.clone(),
+                creation_date: params.and_then(|p| p.creation_date),
+                mod_date: params.and_then(|p| p.mod_date),
+                checksum: params.and_then(|p| p.checksum.as_ref()).map(|c| c.as_bytes().to_vec()),
+                data: t!(stream.data()).to_vec(),
+                page,
+                spec,
+            });
+        }
+        Ok(attachments)
+    }
+
+    /// The fields of the form, see `form::fields`.
+    pub fn form_fields(&self) -> Result<Vec<FormField>> {
+        form::fields(self)
+    }
+
+    /// The field the widget annotation `widget` belongs to, see `form::field_of_widget`.
+    pub fn field_of_widget(&self, widget: PlainRef) -> Result<Option<FormField>> {
+        form::field_of_widget(self, widget)
+    }
+
+    /// The widgets of `field` with their pages and rectangles, see `form::field_widgets`.
+    pub fn field_widgets(&self, field: &FormField) -> Result<Vec<FieldWidget>> {
+        form::field_widgets(self, field)
+    }
+
+    /// The XFA form of the document, see `form::xfa`.
+    pub fn xfa(&self) -> Result<Option<Xfa>> {
+        form::xfa(self)
+    }
+
+    /// The structure tree of a tagged document, see `structure::struct_tree`.
+    pub fn struct_tree(&self) -> Result<Option<StructTree>> {
+        structure::struct_tree(self)
+    }
+
+    /// The associated files (`AF`) of the document, its pages, their annotations and the
+    /// XObjects in their resources. Associated files of structure elements are in `struct_tree`.
+    pub fn associated_files(&self) -> Result<Vec<(AssociatedWith, FileSpec)>> {
+        let mut files = vec![];
+        for spec in self.get_root().associated_files.iter().flatten() {
+            files.push((AssociatedWith::Document, spec.clone()));
+        }
+        for (page_nr, page) in self.pages().enumerate() {
+            let page = page?;
+            let page_nr = page_nr as u32;
+            for spec in page.associated_files.iter().flatten() {
+                files.push((AssociatedWith::Page(page_nr), spec.clone()));
+            }
+            for (index, annot) in t!(page.annots(self)).iter().enumerate() {
+                for spec in annot.dict().associated_files.iter().flatten() {
+                    files.push((AssociatedWith::Annotation { page: page_nr, index }, spec.clone()));
+                }
+            }
+            let resources = match page.resources() {
+                Ok(resources) => resources,
+                Err(_) => continue
+            };
+            let mut xobjects: Vec<_> = resources.xobjects.iter().collect();
+            xobjects.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, &r) in xobjects {
+                let xobject = t!(self.get(r));
+                let af = match *xobject {
+                    XObject::Image(ref image) => image.associated_files.as_ref(),
+                    XObject::Form(ref form) => form.dict().associated_files.as_ref(),
+                    XObject::Postscript(_) => None
+                };
+                for spec in af.into_iter().flatten() {
+                    files.push((AssociatedWith::XObject { page: page_nr, name: name.clone() }, spec.clone()));
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// The XML of an electronic invoice (Factur-X, ZUGFeRD or XRechnung) attached to the document
+    pub fn embedded_invoice(&self) -> Result<Option<Attachment>> {
+        const NAMES: &[&str] = &["factur-x.xml", "zugferd-invoice.xml", "xrechnung.xml", "order-x.xml"];
+        Ok(self.attachments()?.into_iter()
+            .find(|a| NAMES.contains(&a.name.to_lowercase().as_str())))
+    }
+
+    /// The members of the portfolio, if the document is one, see `portfolio::portfolio`.
+    pub fn portfolio(&self) -> Result<Option<Portfolio>> {
+        portfolio::portfolio(self, self.get_root())
+    }
+
+    /// The label of page `page_nr` as viewers show it ("iv", "A-3"), see `Catalog::page_label`.
+    pub fn page_label(&self, page_nr: u32) -> Result<Option<String>> {
+        self.get_root().page_label(self, page_nr)
+    }
+
+    /// The destination called `name`, see `Catalog::named_dest`.
+    pub fn named_dest(&self, name: &[u8]) -> Result<Option<Dest>> {
+        self.get_root().named_dest(self, name)
+    }
+
+    /// Look up `dest` if it is a name, see `Catalog::resolve_dest`.
+    pub fn resolve_dest(&self, dest: &MaybeNamedDest) -> Result<Option<Dest>> {
+        self.get_root().resolve_dest(self, dest)
+    }
+
+    /// The document-level scripts, see `Catalog::scripts`.
+    pub fn scripts(&self) -> Result<Vec<(PdfString, String)>> {
+        self.get_root().scripts(self)
+    }
+
+    /// All JavaScript of the document with where it is, see `javascript::scripts`.
+    pub fn javascript(&self) -> Result<Vec<Script>> {
+        javascript::scripts(self)
+    }
+
+    /// What to do when the document is opened, see `Catalog::open_action`.
+    pub fn open_action(&self) -> Result<Option<Vec<Action>>> {
+        self.get_root().open_action(self)
+    }
+
+    /// The page the document opens at and its view, see `Catalog::open_destination`.
+    pub fn open_destination(&self) -> Result<Option<(u32, DestView)>> {
+        self.get_root().open_destination(self)
+    }
+
+    /// The outline (bookmarks) of the document, see `outline::outline`.
+    pub fn outline(&self) -> Result<Vec<Outline>> {
+        match self.get_root().outlines {
+            Some(ref outlines) => outline::outline(self, outlines),
+            None => Ok(vec![])
+        }
+    }
+
+    /// The article threads of the document, see `thread::threads`.
+    pub fn threads(&self) -> Result<Vec<Article>> {
+        thread::threads(self, self.get_root())
+    }
+
+    /// Set the value of the form field `name` and regenerate its appearance, see `form::set_field`.
+    pub fn set_field(&mut self, name: &str, value: FieldValue) -> Result<()> {
+        form::set_field(self, name, value)
+    }
+
+    /// Apply the redaction annotations of all pages, see `redact::apply_redactions`.
+    pub fn apply_redactions(&mut self) -> Result<usize> {
+        redact::apply_redactions(self)
+    }
+
+    /// The signatures of the document, see `sign::signatures`.
+    pub fn signatures(&self) -> Result<Vec<Signature>> {
+        sign::signatures(self)
+    }
+
+    /// Add a signature field and return the signed file, see `sign::sign`.
+    pub fn sign(&mut self, options: &SignatureOptions, signer: impl FnOnce(&SignedData) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+        sign::sign(self, options, signer)
+    }
+
     pub fn update_catalog(&mut self, catalog: Catalog) -> Result<()> {
         self.trailer.root = self.create(catalog)?;
         Ok(())
     }
 }
 
-    
+/// Append the keys and values of the name tree `node` to `out`
+fn name_tree_entries(node: Primitive, resolve: &impl Resolve, out: &mut Vec<(PdfString, Primitive)>, depth: usize) -> Result<()> {
+    if depth > 32 {
+        bail!("name tree is nested too deeply");
+    }
+    let mut dict = t!(node.into_dictionary(resolve));
+    if let Some(kids) = dict.remove("Kids") {
+        for kid in t!(kids.into_array(resolve)) {
+            name_tree_entries(kid, resolve, out, depth + 1)?;
+        }
+    } else if let Some(names) = dict.remove("Names") {
+        for pair in t!(names.into_array(resolve)).chunks_exact(2) {
+            out.push((t!(pair[0].clone().into_string()), pair[1].clone()));
+        }
+    }
+    Ok(())
+}
+
+/// The page numbers in `range`, limited to `num_pages`
+pub(crate) fn page_range(range: impl RangeBounds<u32>, num_pages: u32) -> Range<u32> {
+    let start = match range.start_bound() {
+        Bound::Included(&n) => n,
+        Bound::Excluded(&n) => n.saturating_add(1),
+        Bound::Unbounded => 0
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&n) => n.saturating_add(1),
+        Bound::Excluded(&n) => n,
+        Bound::Unbounded => num_pages
+    };
+    start.min(num_pages) .. end.min(num_pages)
+}
+
+/// Copies objects from `source` into another file, giving them new numbers.
+///
+/// References to page tree nodes and to pages that were not numbered in advance
+/// become `null`, so copying a page doesn't pull in the rest of its document.
+struct Importer<'a, R: Resolve> {
+    source: &'a R,
+    // old to new numbers
+    map: HashMap<PlainRef, PlainRef>,
+}
+impl<'a, R: Resolve> Importer<'a, R> {
+    /// The dictionary of the page `r`, with the inherited attributes and without `Parent`
+    fn page_dict(&self, r: PlainRef) -> Result<Dictionary> {
+        let mut dict = t!(self.source.resolve(r)?.into_dictionary(self.source));
+        let mut parent = dict.remove("Parent");
+        let mut visited = HashSet::new();
+        while let Some(Primitive::Reference(p)) = parent {
+            if !visited.insert(p) {
+                break;
+            }
+            let node = t!(self.source.resolve(p)?.into_dictionary(self.source));
+            for &key in &["Resources", "MediaBox", "CropBox", "Rotate"] {
+                if dict.get(key).is_none() {
+                    if let Some(val) = node.get(key) {
+                        dict.insert(key, val.clone());
+                    }
+                }
+            }
+            parent = node.get("Parent").cloned();
+        }
+        if dict.get("Resources").is_none() {
+            dict.insert("Resources", Primitive::Dictionary(Dictionary::new()));
+        }
+        Ok(dict)
+    }
+
+    fn copy_ref(&mut self, r: PlainRef, dest: &mut impl Updater) -> Result<Primitive> {
+        if let Some(&new) = self.map.get(&r) {
+            return Ok(Primitive::Reference(new));
+        }
+        let p = match self.source.resolve(r) {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("can't copy {:?}: {:?}", r, e);
+                return Ok(Primitive::Null);
+            }
+        };
+        if is_page_tree_node(&p) {
+            return Ok(Primitive::Null);
+        }
+        let promise = dest.promise::<Primitive>();
+        let new = promise.get_inner();
+        self.map.insert(r, new);
+        let p = self.copy(p, dest)?;
+        dest.fulfill(promise, p)?;
+        Ok(Primitive::Reference(new))
+    }
+
+    fn copy_dict(&mut self, dict: Dictionary, dest: &mut impl Updater) -> Result<Dictionary> {
+        let mut out = Dictionary::new();
+        for (key, val) in dict {
+            out.insert(key, self.copy(val, dest)?);
+        }
+        Ok(out)
+    }
+
+    fn copy(&mut self, p: Primitive, dest: &mut impl Updater) -> Result<Primitive> {
+        Ok(match p {
+            Primitive::Reference(r) => self.copy_ref(r, dest)?,
+            Primitive::Array(arr) => Primitive::Array(
+                arr.into_iter().map(|p| self.copy(p, dest)).collect::<Result<_>>()?
+            ),
+            Primitive::Dictionary(dict) => Primitive::Dictionary(self.copy_dict(dict, dest)?),
+            Primitive::Stream(stream) => Primitive::Stream(PdfStream {
+                info: self.copy_dict(stream.info, dest)?,
+                data: stream.data
+            }),
+            p => p
+        })
+    }
+}
+
+/// `Page` and `Pages` dictionaries; pages are only copied explicitly
+fn is_page_tree_node(p: &Primitive) -> bool {
+    match *p {
+        Primitive::Dictionary(ref dict) => matches!(dict.get("Type"), Some(Primitive::Name(ref t)) if t == "Page" || t == "Pages"),
+        _ => false
+    }
+}
+
 #[derive(Object, ObjectWrite)]
 pub struct Trailer {
     #[pdf(key = "Size")]
@@ -2,7 +2,12 @@ use crate as pdf;
 use crate::object::*;
 use crate::primitive::*;
 use crate::error::*;
-use crate::encoding::Encoding;
+use crate::encoding::{Encoding, BaseEncoding};
+use crate::cmap::CMap;
+use crate::standard_fonts::{StandardFont, StandardFontProvider};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::collections::HashMap;
 use crate::parser::{Lexer, parse_with_lexer};
 use utf16_ext::Utf16ReadExt;
@@ -10,7 +15,7 @@ use byteorder::BE;
 use std::convert::TryInto;
 
 #[allow(non_upper_case_globals, dead_code)] 
-mod flags {
+pub(crate) mod flags {
     pub const FixedPitch: u32    = 1 << 0;
     pub const Serif: u32         = 1 << 1;
     pub const Symbolic: u32      = 1 << 2;
@@ -22,7 +27,7 @@ mod flags {
     pub const ForceBold: u32     = 1 << 18;
 }
 
-#[derive(Object, Debug, Copy, Clone)]
+#[derive(Object, ObjectWrite, Debug, Copy, Clone)]
 pub enum FontType {
     Type0,
     Type1,
@@ -39,11 +44,14 @@ pub struct Font {
     pub name: String,
     pub data: Result<FontData>,
     
-    encoding: Option<Encoding>,
+    pub(crate) encoding: Option<Encoding>,
+
+    /// `Encoding` of a Type0 font
+    pub(crate) cmap: Option<CMap>,
     
-    to_unicode: Option<Stream>,
+    pub(crate) to_unicode: Option<Stream>,
     
-    _other: Dictionary
+    pub(crate) _other: Dictionary
 }
 
 #[derive(Debug)]
@@ -64,7 +72,28 @@ impl Object for Font {
         let base_font = dict.require("Font", "BaseFont")?.into_name()?;
         let subtype = FontType::from_primitive(dict.require("Font", "Subtype")?, resolve)?;
         
-        let encoding = dict.remove("Encoding").map(|p| Object::from_primitive(p, resolve)).transpose()?;
+        let (encoding, cmap) = match (subtype, dict.remove("Encoding")) {
+            (FontType::Type0, Some(p)) => {
+                let p = match p {
+                    Primitive::Reference(r) => resolve.resolve(r)?,
+                    p => p
+                };
+                let encoding = match p {
+                    Primitive::Name(ref name) => BaseEncoding::from_primitive(Primitive::name(name.as_str()), resolve).ok()
+                        .map(|base| Encoding { base, differences: HashMap::new() }),
+                    _ => None
+                };
+                let cmap = match CMap::from_primitive(p, resolve) {
+                    Ok(cmap) => Some(cmap),
+                    Err(e) => {
+                        warn!("can't use CMap of font {}: {}", base_font, e);
+                        None
+                    }
+                };
+                (encoding, cmap)
+            }
+            (_, p) => (p.map(|p| Object::from_primitive(p, resolve)).transpose()?, None)
+        };
 
         let to_unicode = match dict.remove("ToUnicode") {
             Some(p) => Some(Stream::from_primitive(p, resolve)?),
@@ -98,13 +127,55 @@ impl Object for Font {
             name: base_font,
             data,
             encoding,
+            cmap,
             to_unicode,
             _other
         })
     }
 }
+impl ObjectWrite for Font {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        let mut dict = match self.data {
+            Ok(FontData::Type0(ref t0)) => t0.to_dict(update)?,
+            Ok(FontData::Type1(ref info)) | Ok(FontData::TrueType(ref info)) => info.to_dict(update)?,
+            Ok(FontData::CIDFontType0(ref cid)) => cid.to_dict(update)?,
+            Ok(FontData::CIDFontType2(ref cid, ref map)) => {
+                let mut dict = cid.to_dict(update)?;
+                let map = match map {
+                    Some(ref map) => {
+                        let data = map.iter().flat_map(|gid| gid.to_be_bytes()).collect();
+                        Stream::new((), data).to_primitive(update)?
+                    }
+                    None => Primitive::name("Identity")
+                };
+                dict.insert("CIDToGIDMap", map);
+                dict
+            }
+            Ok(FontData::Other(ref dict)) => dict.clone(),
+            Ok(FontData::None) => Dictionary::new(),
+            Err(ref e) => bail!("can't write a font that failed to load: {}", e),
+        };
+        for (key, val) in self._other.iter() {
+            if dict.get(key).is_none() {
+                dict.insert(key.clone(), val.clone());
+            }
+        }
+        dict.insert("Type", Primitive::name("Font"));
+        dict.insert("Subtype", self.subtype.to_primitive(update)?);
+        dict.insert("BaseFont", Primitive::name(self.name.clone()));
+        if let Some(ref encoding) = self.encoding {
+            dict.insert("Encoding", encoding.to_primitive(update)?);
+        } else if let Some(ref cmap) = self.cmap {
+            dict.insert("Encoding", cmap.to_primitive(update)?);
+        }
+        if let Some(ref to_unicode) = self.to_unicode {
+            dict.insert("ToUnicode", to_unicode.to_primitive(update)?);
+        }
+        Ok(Primitive::Dictionary(dict))
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Widths {
     values: Vec<f32>,
     default: f32,
@@ -126,9 +197,9 @@ impl Widths {
         }
     }
     fn ensure_cid(&mut self, cid: usize) {
-        if cid - self.first_char > self.values.capacity() {
-            let missing = cid - self.values.len();
-            self.values.reserve(missing);
+        let needed = cid.saturating_sub(self.first_char) + 1;
+        if needed > self.values.len() {
+            self.values.reserve(needed - self.values.len());
         }
     }
     fn set(&mut self, cid: usize, width: f32) {
@@ -171,7 +242,81 @@ impl Font {
         match self.data.as_ref().ok()? {
             FontData::Type0(ref t) => t.descendant_fonts.get(0).and_then(|f| f.embedded_data()),
             FontData::CIDFontType0(ref c) | FontData::CIDFontType2(ref c, _) => c.font_descriptor.data(),
-            FontData::Type1(ref t) | FontData::TrueType(ref t) => t.font_descriptor.data(),
+            FontData::Type1(ref t) | FontData::TrueType(ref t) => t.font_descriptor.as_ref()?.data(),
+            _ => None
+        }
+    }
+    /// The standard font this font refers to, if it is not embedded.
+    pub fn standard_font(&self) -> Option<StandardFont> {
+        match self.embedded_data() {
+            Some(_) => None,
+            None => StandardFont::from_name(&self.name)
+        }
+    }
+    /// The embedded font program, or the one `provider` has for the standard font.
+    pub fn font_data(&self, provider: &impl StandardFontProvider) -> Option<Result<Cow<'_, [u8]>>> {
+        match self.embedded_data() {
+            Some(data) => Some(data.map(Cow::Borrowed)),
+            None => provider.font_data(StandardFont::from_name(&self.name)?).map(|data| Ok(Cow::Owned(data)))
+        }
+    }
+    /// Glyph id in the embedded font program for the character code `code`.
+    ///
+    /// For simple TrueType fonts this parses the font program on every call,
+    /// `FontInfo::glyph_id` only does so once.
+    pub fn glyph_id(&self, code: u32) -> Option<u16> {
+        match self.data.as_ref().ok()? {
+            FontData::Type0(ref t0) => {
+                let cid = match self.cmap {
+                    Some(ref cmap) => cmap.cid(code)?,
+                    None => code
+                };
+                t0.descendant_fonts.first()?.glyph_id(cid)
+            }
+            FontData::CIDFontType2(_, Some(ref map)) => map.get(code as usize).cloned(),
+            FontData::CIDFontType2(_, None) | FontData::CIDFontType0(_) => code.try_into().ok(),
+            FontData::TrueType(_) => {
+                let face = ttf_parser::Face::from_slice(self.embedded_data()?.ok()?, 0).ok()?;
+                truetype_glyph_id(&face, code, self.symbolic_truetype())
+            }
+            _ => None
+        }
+    }
+    /// Glyph ids of the codes 0 to 255 of a simple TrueType font, parsing the font program once
+    fn truetype_glyph_ids(&self) -> Option<Vec<Option<u16>>> {
+        if !matches!(self.data, Ok(FontData::TrueType(_))) {
+            return None;
+        }
+        let face = ttf_parser::Face::from_slice(self.embedded_data()?.ok()?, 0).ok()?;
+        let symbolic = self.symbolic_truetype();
+        Some((0 .. 256).map(|code| truetype_glyph_id(&face, code, symbolic)).collect())
+    }
+    /// Whether codes of this TrueType font are mapped as symbolic: flagged so, without an encoding
+    fn symbolic_truetype(&self) -> bool {
+        let symbolic = match self.data {
+            Ok(FontData::TrueType(ref info)) => info.font_descriptor.as_ref().is_some_and(|d| d.flags & flags::Symbolic != 0),
+            _ => false
+        };
+        symbolic && self.encoding.is_none()
+    }
+    /// The encoding built into an embedded Type1 font program (`FontFile`).
+    ///
+    /// Used when the font dictionary has no `Encoding`.
+    pub fn builtin_encoding(&self) -> Option<Result<Encoding>> {
+        let descriptor = match self.data.as_ref().ok()? {
+            FontData::Type1(ref info) => info.font_descriptor.as_ref()?,
+            _ => return None
+        };
+        let file = descriptor.font_file.as_ref()?;
+        let length1 = file.info.info.length1;
+        Some(file.data().and_then(|data| type1_encoding(data, length1.map(|n| n as usize))))
+    }
+    /// The font descriptor (of the descendant font for Type0 fonts)
+    pub fn descriptor(&self) -> Option<&FontDescriptor> {
+        match self.data.as_ref().ok()? {
+            FontData::Type0(ref t) => t.descendant_fonts.first()?.descriptor(),
+            FontData::CIDFontType0(ref c) | FontData::CIDFontType2(ref c, _) => Some(&c.font_descriptor),
+            FontData::Type1(ref t) | FontData::TrueType(ref t) => t.font_descriptor.as_ref(),
             _ => None
         }
     }
@@ -188,6 +333,30 @@ impl Font {
     pub fn encoding(&self) -> Option<&Encoding> {
         self.encoding.as_ref()
     }
+    /// The CMap of a Type0 font
+    pub fn cmap(&self) -> Option<&CMap> {
+        self.cmap.as_ref()
+    }
+    /// Split `data` into character codes and their CIDs.
+    ///
+    /// For simple fonts every byte is a code and the CID is the code itself.
+    pub fn codes<'a>(&'a self, data: &'a [u8]) -> Box<dyn Iterator<Item=(u32, u32)> + 'a> {
+        match self.cmap {
+            Some(ref cmap) => Box::new(cmap.decode(data)),
+            None if matches!(self.subtype, FontType::Type0) => Box::new(data.chunks(2)
+                .map(|c| c.iter().fold(0, |code, &b| code << 8 | b as u32))
+                .map(|code| (code, code))),
+            None => Box::new(data.iter().map(|&b| (b as u32, b as u32)))
+        }
+    }
+    /// Split `data` into the bytes of each character code, in the order of `codes`
+    pub fn split_codes<'a>(&'a self, data: &'a [u8]) -> Box<dyn Iterator<Item=&'a [u8]> + 'a> {
+        match self.cmap {
+            Some(ref cmap) => Box::new(cmap.split(data)),
+            None if matches!(self.subtype, FontType::Type0) => Box::new(data.chunks(2)),
+            None => Box::new(data.chunks(1))
+        }
+    }
     pub fn info(&self) -> Option<&TFont> {
         match self.data.as_ref().ok()? {
             FontData::Type1(ref info) => Some(info),
@@ -197,47 +366,53 @@ impl Font {
     }
     pub fn widths(&self, resolve: &impl Resolve) -> Result<Option<Widths>> {
         match self.data {
-            Ok(FontData::Type0(ref t0)) => t0.descendant_fonts[0].widths(resolve),
+            Ok(FontData::Type0(ref t0)) => match t0.descendant_fonts.first() {
+                Some(font) => font.widths(resolve),
+                None => Ok(None)
+            },
             Ok(FontData::Type1(ref info)) | Ok(FontData::TrueType(ref info)) => {
-                match info {
-                    &TFont { first_char: Some(first), ref widths, .. } => Ok(Some(Widths {
-                        default: 0.0,
-                        first_char: first as usize,
-                        values: widths.clone()
-                    })),
+                let default = info.font_descriptor.as_ref().map(|d| d.missing_width).unwrap_or(0.0);
+                match *info {
+                    TFont { first_char: Some(first), last_char, ref widths, .. } => {
+                        let mut values = widths.clone();
+                        if let Some(last) = last_char {
+                            values.truncate((last - first + 1).max(0) as usize);
+                        }
+                        Ok(Some(Widths {
+                            default,
+                            first_char: first.max(0) as usize,
+                            values
+                        }))
+                    }
                     _ => Ok(None)
                 }
             },
             Ok(FontData::CIDFontType0(ref cid)) | Ok(FontData::CIDFontType2(ref cid, _)) => {
+                let resolved = |p: &Primitive| match *p {
+                    Primitive::Reference(r) => resolve.resolve(r),
+                    ref p => Ok(p.clone())
+                };
                 let mut widths = Widths::new(cid.default_width);
                 let mut iter = cid.widths.iter();
-                while let Some(ref p) = iter.next() {
-                    let c1 = p.as_integer()? as usize;
-                    match iter.next() {
-                        Some(&Primitive::Array(ref array)) => {
+                while let Some(p) = iter.next() {
+                    let c1 = resolved(p)?.as_u32()? as usize;
+                    match iter.next().map(resolved).transpose()? {
+                        Some(Primitive::Array(array)) => {
+                            if array.is_empty() {
+                                continue;
+                            }
                             widths.ensure_cid(c1 + array.len() - 1);
                             for (i, w) in array.iter().enumerate() {
-                                widths.set(c1 + i, w.as_number()?);
+                                widths.set(c1 + i, resolved(w)?.as_number()?);
                             }
                         },
-                        Some(&Primitive::Reference(r)) => {
-                            match resolve.resolve(r)? {
-                                Primitive::Array(array) => {
-                                    widths.ensure_cid(c1 + array.len() - 1);
-                                    for (i, w) in array.iter().enumerate() {
-                                        widths.set(c1 + i, w.as_number()?);
-                                    }
-                                }
-                                p => return Err(PdfError::Other { msg: format!("unexpected primitive in W array: {:?}", p) })
-                            }
-                        }
-                        Some(&Primitive::Integer(c2)) => {
-                            let w = try_opt!(iter.next()).as_number()?;
-                            for c in (c1 as usize) ..= (c2 as usize) {
+                        Some(Primitive::Integer(c2)) => {
+                            let w = resolved(try_opt!(iter.next()))?.as_number()?;
+                            for c in c1 ..= (c2.max(0) as usize) {
                                 widths.set(c, w);
                             }
                         },
-                        p => return Err(PdfError::Other { msg: format!("unexpected primitive in W array: {:?}", p) })
+                        p => bail!("unexpected primitive in W array: {:?}", p)
                     }
                 }
                 Ok(Some(widths))
@@ -249,7 +424,81 @@ impl Font {
         self.to_unicode.as_ref().map(|s| s.data().map(parse_cmap))
     }
 }
-#[derive(Object, Debug)]
+/// Extract the encoding vector from the clear-text part of a Type1 font program (PFA or PFB).
+fn type1_encoding(data: &[u8], length1: Option<usize>) -> Result<Encoding> {
+    let data = match *data {
+        // PFB segment header: 0x80, type 1 (ASCII), u32 LE length
+        [0x80, 1, a, b, c, d, ref rest @ ..] => {
+            let len = u32::from_le_bytes([a, b, c, d]) as usize;
+            &rest[.. len.min(rest.len())]
+        }
+        _ => {
+            let end = length1.filter(|&n| n <= data.len())
+                .or_else(|| data.windows(5).position(|w| w == b"eexec"))
+                .unwrap_or(data.len());
+            &data[.. end]
+        }
+    };
+    let start = match data.windows(9).position(|w| w == b"/Encoding") {
+        Some(pos) => pos + 9,
+        None => bail!("no /Encoding in Type1 font")
+    };
+    // names may directly follow the code (`dup 32/space put`)
+    let mut text = Vec::with_capacity(data.len() - start);
+    for &b in &data[start ..] {
+        if b == b'/' {
+            text.push(b' ');
+        }
+        text.push(b);
+    }
+    let mut tokens = text
+        .split(|b| b.is_ascii_whitespace())
+        .filter(|t| !t.is_empty());
+
+    let mut differences = HashMap::new();
+    loop {
+        match tokens.next() {
+            Some(b"StandardEncoding") => return Ok(Encoding::standard()),
+            Some(b"dup") => {
+                let code = tokens.next().and_then(|t| std::str::from_utf8(t).ok()).and_then(|t| t.parse::<u32>().ok());
+                let name = tokens.next().filter(|t| t.starts_with(b"/"));
+                if let (Some(code), Some(name)) = (code, name) {
+                    differences.insert(code, String::from_utf8_lossy(&name[1 ..]).into_owned());
+                }
+            }
+            Some(b"def") | Some(b"readonly") | None => break,
+            Some(_) => {}
+        }
+    }
+    Ok(Encoding { base: BaseEncoding::None, differences })
+}
+
+/// Map a code of a simple TrueType font to a glyph id (9.6.6.4).
+///
+/// Symbolic fonts without an encoding use the (3, 0) cmap, where codes may be
+/// offset into the private use area, or the (1, 0) cmap.
+fn truetype_glyph_id(face: &ttf_parser::Face, code: u32, symbolic: bool) -> Option<u16> {
+    use ttf_parser::PlatformId;
+
+    let subtable = |platform: PlatformId, encoding: u16| face.character_mapping_subtables()
+        .find(|s| s.platform_id() == platform && s.encoding_id() == encoding);
+
+    let symbol = subtable(PlatformId::Windows, 0).and_then(|cmap| {
+        [code, 0xF000 + code, 0xF100 + code, 0xF200 + code].iter()
+            .find_map(|&c| cmap.glyph_index(c))
+    });
+    let mac = || subtable(PlatformId::Macintosh, 0).and_then(|cmap| cmap.glyph_index(code));
+    let unicode = || subtable(PlatformId::Windows, 1).and_then(|cmap| cmap.glyph_index(code));
+
+    let gid = if symbolic {
+        symbol.or_else(mac).or_else(unicode)
+    } else {
+        unicode().or(symbol).or_else(mac)
+    };
+    gid.map(|gid| gid.0)
+}
+
+#[derive(Object, ObjectWrite, Debug)]
 pub struct TFont {
     #[pdf(key="Name")]
     pub name: Option<String>,
@@ -265,39 +514,40 @@ pub struct TFont {
     #[pdf(key="Widths")]
     pub widths: Vec<f32>,
     
+    /// may be missing for the standard fonts
     #[pdf(key="FontDescriptor")]
-    pub font_descriptor: FontDescriptor
+    pub font_descriptor: Option<FontDescriptor>
 }
 
-#[derive(Object, Debug)]
+#[derive(Object, ObjectWrite, Debug)]
 pub struct Type0Font {
     #[pdf(key="DescendantFonts")]
-    descendant_fonts: Vec<RcRef<Font>>,
+    pub(crate) descendant_fonts: Vec<RcRef<Font>>,
     
     #[pdf(key="ToUnicode")]
-    to_unicode: Option<Stream>,
+    pub(crate) to_unicode: Option<Stream>,
 }
 
-#[derive(Object, Debug)]
+#[derive(Object, ObjectWrite, Debug)]
 pub struct CIDFont {
     #[pdf(key="CIDSystemInfo")]
-    system_info: Dictionary,
+    pub(crate) system_info: Dictionary,
     
     #[pdf(key="FontDescriptor")]
-    font_descriptor: FontDescriptor,
+    pub(crate) font_descriptor: FontDescriptor,
     
     #[pdf(key="DW", default="1000.")]
-    default_width: f32,
+    pub(crate) default_width: f32,
     
     #[pdf(key="W")]
     pub widths: Vec<Primitive>,
 
     #[pdf(other)]
-    _other: Dictionary
+    pub(crate) _other: Dictionary
 }
 
 
-#[derive(Object, Debug)]
+#[derive(Object, ObjectWrite, Debug)]
 pub struct FontDescriptor {
     #[pdf(key="FontName")]
     pub font_name: String,
@@ -352,10 +602,10 @@ pub struct FontDescriptor {
     pub missing_width: f32,
     
     #[pdf(key="FontFile")]
-    pub font_file: Option<Stream>,
+    pub font_file: Option<Stream<FontFileInfo>>,
     
     #[pdf(key="FontFile2")]
-    pub font_file2: Option<Stream>,
+    pub font_file2: Option<Stream<FontFileInfo>>,
     
     #[pdf(key="FontFile3")]
     pub font_file3: Option<Stream<FontStream3>>,
@@ -377,20 +627,36 @@ impl FontDescriptor {
     }
 }
 
-#[derive(Object, Debug, Clone)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 #[pdf(key="Subtype")]
 pub enum FontTypeExt {
     Type1C,
     CIDFontType0C,
     OpenType
 }
-#[derive(Object, Debug, Clone)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 pub struct FontStream3 {
     #[pdf(key="Subtype")]
     pub subtype: FontTypeExt
 }
 
-#[derive(Object, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+/// Stream dictionary of `FontFile` and `FontFile2`
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct FontFileInfo {
+    /// length of the clear-text portion (Type1), or of the whole font program (TrueType)
+    #[pdf(key="Length1")]
+    pub length1: Option<u32>,
+
+    /// length of the encrypted portion (Type1)
+    #[pdf(key="Length2")]
+    pub length2: Option<u32>,
+
+    /// length of the fixed-content portion (Type1)
+    #[pdf(key="Length3")]
+    pub length3: Option<u32>,
+}
+
+#[derive(Object, ObjectWrite, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum FontStretch {
     UltraCondensed,
     ExtraCondensed,
@@ -403,6 +669,126 @@ pub enum FontStretch {
     UltraExpanded
 }
 
+/// A font together with the tables needed to decode and measure text.
+#[derive(Debug)]
+pub struct FontInfo {
+    pub font: RcRef<Font>,
+    pub widths: Option<Widths>,
+    pub to_unicode: Option<ToUnicodeMap>,
+    /// `Encoding` of the font dictionary, or the one built into the font program
+    pub encoding: Option<Encoding>,
+    /// Width of the space glyph in thousandths of the font size, if the font has one
+    pub space_width: Option<f32>,
+    /// Glyph ids of the codes of a simple TrueType font
+    glyph_ids: Option<Vec<Option<u16>>>,
+}
+impl FontInfo {
+    pub fn new(font: RcRef<Font>, resolve: &impl Resolve) -> Result<FontInfo> {
+        let widths = t!(font.widths(resolve));
+        let to_unicode = font.to_unicode().transpose()?;
+        let encoding = match font.encoding() {
+            Some(encoding) => Some(encoding.clone()),
+            None => font.builtin_encoding().and_then(|r| r.ok())
+        };
+        let glyph_ids = font.truetype_glyph_ids();
+        let mut info = FontInfo { font, widths, to_unicode, encoding, space_width: None, glyph_ids };
+        info.space_width = info.find_space_width();
+        Ok(info)
+    }
+
+    /// Glyph id for the character code `code`, see `Font::glyph_id`
+    pub fn glyph_id(&self, code: u32) -> Option<u16> {
+        match self.glyph_ids {
+            Some(ref ids) => ids.get(code as usize).copied().flatten(),
+            None => self.font.glyph_id(code)
+        }
+    }
+
+    fn find_space_width(&self) -> Option<f32> {
+        let widths = self.widths.as_ref()?;
+        let cid = match self.font.subtype {
+            FontType::Type0 => {
+                let (code, _) = self.to_unicode.as_ref()?.iter().find(|&(_, s)| s == " ")?;
+                match self.font.cmap() {
+                    Some(cmap) => cmap.cid(code as u32)?,
+                    None => code as u32
+                }
+            }
+            _ => 32
+        };
+        Some(widths.get(cid as usize)).filter(|&w| w > 0.)
+    }
+
+    /// Unicode text of the character code `code`.
+    ///
+    /// `ToUnicode` is used if present. Otherwise simple fonts fall back to their encoding,
+    /// and finally to the code itself.
+    pub fn unicode(&self, code: u32) -> Option<Cow<'_, str>> {
+        if let Some(s) = self.to_unicode.as_ref().and_then(|map| map.get(code.try_into().ok()?)) {
+            return Some(Cow::Borrowed(s));
+        }
+        if matches!(self.font.subtype, FontType::Type0) {
+            return None;
+        }
+        let code: u8 = code.try_into().ok()?;
+        if let Some(s) = self.encoding.as_ref().and_then(|e| e.unicode(code)) {
+            return Some(s);
+        }
+        let builtin = match self.font.standard_font() {
+            Some(StandardFont::Symbol) => pdf_encoding::SYMBOL.get(code),
+            Some(StandardFont::ZapfDingbats) => pdf_encoding::ZDINGBAT.get(code),
+            _ if self.is_symbolic() => None,
+            _ => BaseEncoding::StandardEncoding.unicode(code),
+        };
+        match builtin {
+            Some(c) => Some(Cow::Owned(c.to_string())),
+            None if code >= 0x20 => Some(Cow::Owned((code as char).to_string())),
+            None => None
+        }
+    }
+
+    /// Width of `cid` in thousandths of the font size.
+    ///
+    /// Fonts without widths (usually unembedded standard fonts) get an average of 500.
+    pub fn width(&self, cid: u32) -> f32 {
+        match self.widths {
+            Some(ref widths) => widths.get(cid as usize),
+            None => 500.
+        }
+    }
+
+    fn is_symbolic(&self) -> bool {
+        self.font.info().and_then(|info| info.font_descriptor.as_ref())
+            .is_some_and(|d| d.flags & flags::Symbolic != 0)
+    }
+}
+
+/// Fonts are usually shared by many pages.
+/// `FontCache` keeps their `FontInfo` by reference, so it is only built once.
+/// `File` clears it whenever an object is updated.
+#[derive(Default)]
+pub struct FontCache {
+    fonts: RefCell<HashMap<PlainRef, Rc<FontInfo>>>,
+}
+impl FontCache {
+    pub fn new() -> FontCache {
+        FontCache::default()
+    }
+    pub fn get(&self, font: Ref<Font>, resolve: &impl Resolve) -> Result<Rc<FontInfo>> {
+        let key = font.get_inner();
+        if let Some(info) = self.fonts.borrow().get(&key) {
+            return Ok(info.clone());
+        }
+        let info = Rc::new(FontInfo::new(t!(resolve.get(font)), resolve)?);
+        self.fonts.borrow_mut().insert(key, info.clone());
+        Ok(info)
+    }
+    /// Forget all fonts
+    pub fn clear(&self) {
+        self.fonts.borrow_mut().clear();
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ToUnicodeMap {
     // todo: reduce allocations
@@ -418,31 +804,116 @@ impl ToUnicodeMap {
     pub fn get(&self, gid: u16) -> Option<&str> {
         self.inner.get(&gid).map(|s| s.as_str())
     }
+    pub fn iter(&self) -> impl Iterator<Item=(u16, &str)> {
+        self.inner.iter().map(|(&gid, s)| (gid, s.as_str()))
+    }
+
+    /// Write the map as a ToUnicode CMap program for two byte codes.
+    ///
+    /// Runs of codes that map to consecutive characters become `bfrange` entries,
+    /// everything else `bfchar` entries.
+    pub fn to_cmap(&self) -> Vec<u8> {
+        let mut entries: Vec<(u16, Vec<u16>)> = self.inner.iter()
+            .filter(|(_, s)| !s.is_empty())
+            .map(|(&code, s)| (code, s.encode_utf16().collect()))
+            .collect();
+        entries.sort_unstable();
+
+        let mut chars = vec![];
+        let mut ranges = vec![];
+        let mut i = 0;
+        while i < entries.len() {
+            let (start, ref unicode) = entries[i];
+            let mut end = i;
+            // a range may only change the last byte of the code and of the destination
+            if let [u] = unicode[..] {
+                while let Some(&(code, ref next)) = entries.get(end + 1) {
+                    let n = (end + 1 - i) as u16;
+                    if code != start + n || code >> 8 != start >> 8 || next[..] != [u.wrapping_add(n)] || u.wrapping_add(n) >> 8 != u >> 8 {
+                        break;
+                    }
+                    end += 1;
+                }
+            }
+            if end > i {
+                ranges.push((start, entries[end].0, unicode));
+            } else {
+                chars.push((start, unicode));
+            }
+            i = end + 1;
+        }
+
+        let mut out = String::from(concat!(
+            "/CIDInit /ProcSet findresource begin\n",
+            "12 dict begin\n",
+            "begincmap\n",
+            "/CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n",
+            "/CMapName /Adobe-Identity-UCS def\n",
+            "/CMapType 2 def\n",
+            "1 begincodespacerange\n",
+            "<0000> <FFFF>\n",
+            "endcodespacerange\n",
+        ));
+        let hex = |units: &[u16]| units.iter().map(|u| format!("{:04X}", u)).collect::<String>();
+        // at most 100 entries per block
+        for block in chars.chunks(100) {
+            out += &format!("{} beginbfchar\n", block.len());
+            for &(code, unicode) in block {
+                out += &format!("<{:04X}> <{}>\n", code, hex(unicode));
+            }
+            out += "endbfchar\n";
+        }
+        for block in ranges.chunks(100) {
+            out += &format!("{} beginbfrange\n", block.len());
+            for &(start, end, unicode) in block {
+                out += &format!("<{:04X}> <{:04X}> <{}>\n", start, end, hex(unicode));
+            }
+            out += "endbfrange\n";
+        }
+        out += concat!(
+            "endcmap\n",
+            "CMapName currentdict /CMap defineresource pop\n",
+            "end\n",
+            "end\n",
+        );
+        out.into_bytes()
+    }
 }
 
 fn utf16be_to_string(mut data: &[u8]) -> String {
     (&mut data)
         .utf16_chars::<BE>()
-        .map(|c| c.unwrap())
+        .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
         .collect()
 }
+/// A one or two byte code
+fn cmap_code(data: &[u8]) -> Option<u16> {
+    match *data {
+        [b] => Some(b as u16),
+        [hi, lo] => Some(u16::from_be_bytes([hi, lo])),
+        _ => None
+    }
+}
+fn starts_string(lexer: &mut Lexer) -> bool {
+    lexer.peek().is_ok_and(|s| s.equals(b"<") || s.equals(b"("))
+}
 fn parse_cmap(data: &[u8]) -> ToUnicodeMap {
     let mut lexer = Lexer::new(data);
     let mut map = HashMap::new();
     while let Ok(substr) = lexer.next() {
         match substr.as_slice() {
             b"beginbfchar" => loop {
+                // stop at `endbfchar` without parsing it, a failed parse skips ahead
+                if !starts_string(&mut lexer) {
+                    break;
+                }
                 let a = parse_with_lexer(&mut lexer, &NoResolve);
                 let b = parse_with_lexer(&mut lexer, &NoResolve);
                 match (a, b) {
                     (Ok(Primitive::String(cid_data)), Ok(Primitive::String(unicode_data))) => {
-                        let data = cid_data.as_bytes();
-                        let cid = match data.len() {
-                            1 => data[0] as u16,
-                            2 => u16::from_be_bytes(data.try_into().unwrap()),
-                            _ => {
-                                continue;
-                            }
+                        let cid = match cmap_code(cid_data.as_bytes()) {
+                            Some(cid) => cid,
+                            None => continue
                         };
                         let unicode = utf16be_to_string(unicode_data.as_bytes());
                         map.insert(cid, unicode);
@@ -451,6 +922,9 @@ fn parse_cmap(data: &[u8]) -> ToUnicodeMap {
                 }
             },
             b"beginbfrange" => loop {
+                if !starts_string(&mut lexer) {
+                    break;
+                }
                 let a = parse_with_lexer(&mut lexer, &NoResolve);
                 let b = parse_with_lexer(&mut lexer, &NoResolve);
                 let c = parse_with_lexer(&mut lexer, &NoResolve);
@@ -460,16 +934,18 @@ fn parse_cmap(data: &[u8]) -> ToUnicodeMap {
                         Ok(Primitive::String(cid_end_data)),
                         Ok(Primitive::String(unicode_data)),
                     ) => {
-                        let cid_start =
-                            u16::from_be_bytes(cid_start_data.as_bytes().try_into().unwrap());
-                        let cid_end =
-                            u16::from_be_bytes(cid_end_data.as_bytes().try_into().unwrap());
+                        let (cid_start, cid_end) = match (cmap_code(cid_start_data.as_bytes()), cmap_code(cid_end_data.as_bytes())) {
+                            (Some(start), Some(end)) => (start, end),
+                            _ => continue
+                        };
                         let mut unicode_data = unicode_data.into_bytes();
 
                         for cid in cid_start..=cid_end {
                             let unicode = utf16be_to_string(&unicode_data);
                             map.insert(cid, unicode);
-                            *unicode_data.last_mut().unwrap() += 1;
+                            if let Some(last) = unicode_data.last_mut() {
+                                *last = last.wrapping_add(1);
+                            }
                         }
                     }
                     (
@@ -477,15 +953,15 @@ fn parse_cmap(data: &[u8]) -> ToUnicodeMap {
                         Ok(Primitive::String(cid_end_data)),
                         Ok(Primitive::Array(unicode_data_arr)),
                     ) => {
-                        let cid_start =
-                            u16::from_be_bytes(cid_start_data.as_bytes().try_into().unwrap());
-                        let cid_end =
-                            u16::from_be_bytes(cid_end_data.as_bytes().try_into().unwrap());
+                        let (cid_start, cid_end) = match (cmap_code(cid_start_data.as_bytes()), cmap_code(cid_end_data.as_bytes())) {
+                            (Some(start), Some(end)) => (start, end),
+                            _ => continue
+                        };
 
                         for (cid, unicode_data) in (cid_start..=cid_end).zip(unicode_data_arr) {
-                            let unicode =
-                                utf16be_to_string(&unicode_data.as_string().unwrap().as_bytes());
-                            map.insert(cid, unicode);
+                            if let Ok(unicode_data) = unicode_data.as_string() {
+                                map.insert(cid, utf16be_to_string(unicode_data.as_bytes()));
+                            }
                         }
                     }
                     _ => break,
@@ -497,4 +973,72 @@ fn parse_cmap(data: &[u8]) -> ToUnicodeMap {
     }
 
     ToUnicodeMap { inner: map }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn font(data: &[u8]) -> Font {
+        Font::from_primitive(parse(data, &NoResolve).unwrap(), &NoResolve).unwrap()
+    }
+
+    #[test]
+    fn type1_builtin_encoding() {
+        let data = b"%!PS-AdobeFont-1.0: Test 001.000
+/FontName /Test def
+/Encoding 256 array
+0 1 255 {1 index exch /.notdef put} for
+dup 65 /A put
+dup 97/a put
+dup 200 /Adieresis put
+readonly def
+currentdict end
+currentfile eexec \x12\x34";
+        let encoding = type1_encoding(data, None).unwrap();
+        assert_eq!(encoding.differences.len(), 3);
+        assert_eq!(encoding.differences[&200], "Adieresis");
+
+        let standard = type1_encoding(b"/FontName /Test def /Encoding StandardEncoding def", None).unwrap();
+        assert_eq!(standard.base, BaseEncoding::StandardEncoding);
+    }
+
+    #[test]
+    fn simple_widths() {
+        let font = font(b"<< /Type /Font /Subtype /TrueType /BaseFont /Test /FirstChar 32 /LastChar 33 /Widths [250 300 999]
+            /FontDescriptor << /FontName /Test /Flags 32 /FontBBox [0 0 1000 1000] /ItalicAngle 0 /MissingWidth 500 >> >>");
+        let widths = font.widths(&NoResolve).unwrap().unwrap();
+        assert_eq!(widths.get(32), 250.);
+        assert_eq!(widths.get(33), 300.);
+        assert_eq!(widths.get(34), 500.);
+        assert_eq!(widths.get(10), 500.);
+    }
+
+    #[test]
+    fn cid_widths() {
+        let font = font(b"<< /Type /Font /Subtype /CIDFontType2 /BaseFont /Test /CIDSystemInfo << >> /DW 700
+            /W [1 [100 200] 10 12 300 20 []]
+            /FontDescriptor << /FontName /Test /Flags 4 /FontBBox [0 0 1000 1000] /ItalicAngle 0 >> >>");
+        let widths = font.widths(&NoResolve).unwrap().unwrap();
+        assert_eq!(widths.get(0), 700.);
+        assert_eq!(widths.get(2), 200.);
+        assert_eq!(widths.get(11), 300.);
+        assert_eq!(widths.get(13), 700.);
+    }
+
+    #[test]
+    fn to_unicode_roundtrip() {
+        let pairs = [(3, "A"), (4, "B"), (5, "C"), (7, "fi"), (8, "\u{1d400}"), (0x1ff, "x"), (0x200, "y"), (9, "")];
+        let map = ToUnicodeMap::create(pairs.iter().map(|&(code, s)| (code, s.to_string())));
+        let data = map.to_cmap();
+        let text = std::str::from_utf8(&data).unwrap();
+        assert!(text.contains("<0003> <0005> <0041>"));
+
+        let parsed = parse_cmap(&data);
+        for &(code, s) in &pairs[.. 7] {
+            assert_eq!(parsed.get(code), Some(s));
+        }
+        assert_eq!(parsed.get(9), None);
+        assert_eq!(parsed.get(6), None);
+    }
+}
@@ -0,0 +1,654 @@
+//! Reading and filling in AcroForm fields.
+//!
+//! Fields are addressed by their fully qualified name, the partial names (`T`)
+//! of the field and its ancestors joined by periods. `fields` reads the field tree.
+//! Setting a value also regenerates the appearance streams of the field's widgets,
+//! so the new value shows in viewers that don't build appearances themselves.
+//! `field_of_widget` and `field_widgets` go from widget annotations to their fields and back.
+//! Forms can also be XFA forms, XML embedded in the document, which `xfa` reads.
+use std::collections::HashMap;
+
+use crate as pdf;
+use crate::backend::Backend;
+use crate::build::{text_string, ContentBuilder};
+use crate::content::{Color, Matrix, Op, Ops};
+use crate::encoding::BaseEncoding;
+use crate::error::*;
+use crate::file::File;
+use crate::font::{FontInfo, FontType};
+use crate::object::*;
+use crate::primitive::{Dictionary, PdfString, Primitive};
+
+/// Field flags (`Ff`)
+const READ_ONLY: u32 = 1 << 0;
+const REQUIRED: u32 = 1 << 1;
+const MULTILINE: u32 = 1 << 12;
+const PASSWORD: u32 = 1 << 13;
+const RADIO: u32 = 1 << 15;
+const PUSHBUTTON: u32 = 1 << 16;
+
+/// Attributes a field inherits from its ancestors
+const INHERITED: &[&str] = &["FT", "Ff", "DA", "Q"];
+
+/// Distance of text from the border of a widget
+const PADDING: f32 = 2.;
+
+/// Field type (`FT`)
+#[derive(Object, ObjectWrite, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    #[pdf(name="Tx")]
+    Text,
+    /// Check boxes, radio buttons and push buttons
+    #[pdf(name="Btn")]
+    Button,
+    /// List and combo boxes
+    #[pdf(name="Ch")]
+    Choice,
+    #[pdf(name="Sig")]
+    Signature,
+}
+
+/// A field of the form, with the attributes inherited from its ancestors, see `fields`
+#[derive(Debug, Clone)]
+pub struct FormField {
+    /// The field dictionary
+    pub r: PlainRef,
+    /// Fully qualified name
+    pub name: String,
+    pub field_type: Option<FieldType>,
+    /// Value (`V`): a text string for text fields, the name of the state for buttons,
+    /// a string or an array of strings for choice fields
+    pub value: Option<Primitive>,
+    /// Default value (`DV`) used when the form is reset
+    pub default_value: Option<Primitive>,
+    /// Field flags (`Ff`)
+    pub flags: u32,
+    /// Child fields
+    pub kids: Vec<FormField>,
+    /// Widget annotations of the field, the field itself if they are merged
+    pub widgets: Vec<PlainRef>,
+}
+impl FormField {
+    pub fn is_read_only(&self) -> bool {
+        self.flags & READ_ONLY != 0
+    }
+    pub fn is_required(&self) -> bool {
+        self.flags & REQUIRED != 0
+    }
+    pub fn is_terminal(&self) -> bool {
+        self.kids.is_empty()
+    }
+
+    /// The value as text, for text strings and names
+    pub fn value_text(&self) -> Option<String> {
+        match self.value {
+            Some(Primitive::String(ref s)) => Some(text(s)),
+            Some(Primitive::Name(ref name)) => Some(name.clone()),
+            _ => None
+        }
+    }
+
+    /// This field and all its descendants, parents before their kids
+    pub fn descendants(&self) -> Vec<&FormField> {
+        let mut fields = vec![self];
+        for kid in &self.kids {
+            fields.extend(kid.descendants());
+        }
+        fields
+    }
+}
+
+/// The fields of the form of `file`, as a tree. Documents without a form have none.
+pub fn fields<B: Backend>(file: &File<B>) -> Result<Vec<FormField>> {
+    let catalog = t!(file.resolve(file.trailer.root.get_ref().get_inner())?.into_dictionary(file));
+    let form = match catalog.get("AcroForm") {
+        Some(form) => t!(form.clone().into_dictionary(file)),
+        None => return Ok(vec![])
+    };
+    let fields = match form.get("Fields") {
+        Some(fields) => t!(fields.clone().into_array(file)),
+        None => vec![]
+    };
+    field_tree(file, fields, "", &Dictionary::new(), 0)
+}
+
+fn field_tree(resolve: &impl Resolve, kids: Vec<Primitive>, prefix: &str, attrs: &Dictionary, depth: usize) -> Result<Vec<FormField>> {
+    if depth > 32 {
+        bail!("form field tree is nested too deeply");
+    }
+    let mut fields = vec![];
+    for kid in kids {
+        let r = match kid {
+            Primitive::Reference(r) => r,
+            _ => continue
+        };
+        let dict = t!(resolve.resolve(r)?.into_dictionary(resolve));
+        let name = match partial_name(&dict) {
+            Some(partial) if prefix.is_empty() => partial,
+            Some(partial) => format!("{}.{}", prefix, partial),
+            None => continue
+        };
+        let mut attrs = attrs.clone();
+        for &key in INHERITED.iter().chain(&["V", "DV"]) {
+            if let Some(value) = dict.get(key) {
+                attrs.insert(key, value.clone());
+            }
+        }
+        let mut field = FormField {
+            r,
+            field_type: match attrs.get("FT") {
+                Some(ft) => Some(t!(FieldType::from_primitive(ft.clone(), resolve))),
+                None => None
+            },
+            value: attrs.get("V").cloned(),
+            default_value: attrs.get("DV").cloned(),
+            flags: attrs.get("Ff").and_then(|p| p.as_u32().ok()).unwrap_or(0),
+            kids: vec![],
+            widgets: vec![],
+            name,
+        };
+        match dict.get("Kids") {
+            Some(kids) => {
+                let kids = t!(kids.clone().into_array(resolve));
+                for kid in &kids {
+                    if let Primitive::Reference(r) = *kid {
+                        // kids without a name are widgets
+                        if partial_name(&t!(resolve.resolve(r)?.into_dictionary(resolve))).is_none() {
+                            field.widgets.push(r);
+                        }
+                    }
+                }
+                field.kids = field_tree(resolve, kids, &field.name, &attrs, depth + 1)?;
+            }
+            None if dict.get("Subtype").and_then(|p| p.as_name().ok()) == Some("Widget") => field.widgets.push(r),
+            None => {}
+        }
+        fields.push(field);
+    }
+    Ok(fields)
+}
+
+/// A widget annotation of a field with where it is, see `field_widgets`
+#[derive(Debug, Clone)]
+pub struct FieldWidget {
+    /// The widget annotation, the field itself if they are merged
+    pub r: PlainRef,
+    /// The page with the widget in its `Annots`, counting from 0,
+    /// or the page of `P` if no page has it
+    pub page: Option<u32>,
+    pub rect: Rect,
+}
+
+/// The field the widget annotation `widget` belongs to, `None` if it isn't a widget of the form
+pub fn field_of_widget<B: Backend>(file: &File<B>, widget: PlainRef) -> Result<Option<FormField>> {
+    let fields = t!(fields(file));
+    let field = fields.iter().flat_map(|f| f.descendants()).find(|f| f.widgets.contains(&widget));
+    Ok(field.cloned())
+}
+
+/// The widgets of `field` with their pages and rectangles, with the corners in order
+pub fn field_widgets<B: Backend>(file: &File<B>, field: &FormField) -> Result<Vec<FieldWidget>> {
+    let mut pages = HashMap::new();
+    for (page_nr, page) in file.pages().enumerate() {
+        for annot in page?.annotations.iter().flatten() {
+            if let Primitive::Reference(r) = *annot {
+                pages.entry(r).or_insert(page_nr as u32);
+            }
+        }
+    }
+    let mut widgets = Vec::with_capacity(field.widgets.len());
+    for &r in &field.widgets {
+        let page = match pages.get(&r) {
+            Some(&page_nr) => Some(page_nr),
+            None => match t!(file.resolve(r)?.into_dictionary(file)).get("P") {
+                Some(&Primitive::Reference(page)) => t!(file.page_nr(page)),
+                _ => None
+            }
+        };
+        widgets.push(FieldWidget { r, page, rect: t!(widget_rect(file, r)) });
+    }
+    Ok(widgets)
+}
+
+/// The XFA form of a document (`XFA` in the AcroForm), see `xfa`
+#[derive(Debug, Clone)]
+pub struct Xfa {
+    /// The packets in document order, like `template` and `datasets`, with their XML.
+    /// A form given as a single stream is one packet with an empty name.
+    pub packets: Vec<(String, Vec<u8>)>,
+    /// Whether the viewer has to render the pages from the XFA form (`NeedsRendering` in the catalog).
+    /// The pages of dynamic forms usually only say that the document can't be shown.
+    pub needs_rendering: bool,
+}
+impl Xfa {
+    /// The XML of the packet `name`
+    pub fn packet(&self, name: &str) -> Option<&[u8]> {
+        self.packets.iter().find(|(n, _)| n == name).map(|(_, data)| data.as_slice())
+    }
+
+    /// The whole XDP document, the packets concatenated
+    pub fn xml(&self) -> Vec<u8> {
+        self.packets.iter().flat_map(|(_, data)| data.iter().copied()).collect()
+    }
+}
+
+/// The XFA form of `file`, `None` if the document doesn't have one
+pub fn xfa<B: Backend>(file: &File<B>) -> Result<Option<Xfa>> {
+    let catalog = t!(file.resolve(file.trailer.root.get_ref().get_inner())?.into_dictionary(file));
+    let needs_rendering = match catalog.get("NeedsRendering") {
+        Some(p) => t!(p.as_bool()),
+        None => false
+    };
+    let form = match catalog.get("AcroForm") {
+        Some(form) => t!(form.clone().into_dictionary(file)),
+        None => return Ok(None)
+    };
+    let packets = match form.get("XFA") {
+        Some(&Primitive::Reference(r)) => match t!(file.resolve(r)) {
+            Primitive::Array(items) => t!(xfa_packets(file, items)),
+            p => vec![(String::new(), t!(stream_data(file, p)))]
+        },
+        Some(Primitive::Array(items)) => t!(xfa_packets(file, items.clone())),
+        Some(p) => vec![(String::new(), t!(stream_data(file, p.clone())))],
+        None => return Ok(None)
+    };
+    Ok(Some(Xfa { packets, needs_rendering }))
+}
+
+/// Read the packets of an XFA array, pairs of a name and a stream
+fn xfa_packets(resolve: &impl Resolve, items: Vec<Primitive>) -> Result<Vec<(String, Vec<u8>)>> {
+    if !items.chunks_exact(2).remainder().is_empty() {
+        bail!("XFA array has an odd number of elements");
+    }
+    let mut packets = Vec::with_capacity(items.len() / 2);
+    let mut items = items.into_iter();
+    while let (Some(name), Some(data)) = (items.next(), items.next()) {
+        let name = match name {
+            Primitive::String(ref s) => text(s),
+            p => err!(PdfError::UnexpectedPrimitive { expected: "String", found: p.get_debug_name() })
+        };
+        packets.push((name, t!(stream_data(resolve, data))));
+    }
+    Ok(packets)
+}
+
+fn stream_data(resolve: &impl Resolve, p: Primitive) -> Result<Vec<u8>> {
+    let p = match p {
+        Primitive::Reference(r) => t!(resolve.resolve(r)),
+        p => p
+    };
+    match p {
+        Primitive::Stream(s) => Ok(t!(Stream::<()>::from_stream(s, resolve)?.data()).to_vec()),
+        p => Err(PdfError::UnexpectedPrimitive { expected: "Stream", found: p.get_debug_name() })
+    }
+}
+
+/// The new value of a field, see `set_field`
+#[derive(Debug, Clone)]
+pub enum FieldValue {
+    /// Text of a text field, or the selected option of a choice field
+    Text(String),
+    /// Whether a check box is checked
+    Check(bool),
+    /// The appearance state of the radio button to select, or `Off` for none
+    Radio(String),
+}
+
+/// A terminal field with the attributes inherited from its ancestors
+struct Field {
+    r: PlainRef,
+    dict: Dictionary,
+    attrs: Dictionary,
+}
+
+fn partial_name(dict: &Dictionary) -> Option<String> {
+    match dict.get("T") {
+        Some(Primitive::String(s)) => Some(text(s)),
+        _ => None
+    }
+}
+
+fn text(s: &PdfString) -> String {
+    match s.as_str() {
+        Ok(s) => s.into_owned(),
+        Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+    }
+}
+
+fn find_field(resolve: &impl Resolve, kids: Vec<Primitive>, prefix: &str, attrs: &Dictionary, name: &str, depth: usize) -> Result<Option<Field>> {
+    if depth > 32 {
+        bail!("form field tree is nested too deeply");
+    }
+    for kid in kids {
+        let r = match kid {
+            Primitive::Reference(r) => r,
+            _ => continue
+        };
+        let dict = t!(resolve.resolve(r)?.into_dictionary(resolve));
+        // kids without a name are widgets
+        let full = match partial_name(&dict) {
+            Some(partial) if prefix.is_empty() => partial,
+            Some(partial) => format!("{}.{}", prefix, partial),
+            None => continue
+        };
+        let is_ancestor = name.len() > full.len() && name.starts_with(&full) && name[full.len() ..].starts_with('.');
+        if full != name && !is_ancestor {
+            continue;
+        }
+        let mut attrs = attrs.clone();
+        for &key in INHERITED {
+            if let Some(value) = dict.get(key) {
+                attrs.insert(key, value.clone());
+            }
+        }
+        if full == name {
+            return Ok(Some(Field { r, dict, attrs }));
+        }
+        if let Some(kids) = dict.get("Kids") {
+            let kids = t!(kids.clone().into_array(resolve));
+            if let Some(field) = find_field(resolve, kids, &full, &attrs, name, depth + 1)? {
+                return Ok(Some(field));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The widget annotations of `field`, which is its own widget if it has no kids
+fn widgets(resolve: &impl Resolve, field: &Field) -> Result<Vec<PlainRef>> {
+    let kids = match field.dict.get("Kids") {
+        Some(kids) => t!(kids.clone().into_array(resolve)),
+        None => return Ok(vec![field.r])
+    };
+    let mut widgets = Vec::with_capacity(kids.len());
+    for kid in kids {
+        if let Primitive::Reference(r) = kid {
+            if partial_name(&t!(resolve.resolve(r)?.into_dictionary(resolve))).is_some() {
+                bail!("form field {} has child fields, only terminal fields have a value", partial_name(&field.dict).unwrap_or_default());
+            }
+            widgets.push(r);
+        }
+    }
+    Ok(widgets)
+}
+
+/// Set the value of the field `name` and regenerate the appearances of its widgets.
+///
+/// Text and choice fields take `FieldValue::Text`, and need the font of their `DA` string
+/// in the default resources of the form. Check boxes take `FieldValue::Check`,
+/// radio buttons `FieldValue::Radio`. Check boxes without appearances get a check mark.
+pub fn set_field<B: Backend>(file: &mut File<B>, name: &str, value: FieldValue) -> Result<()> {
+    let catalog = t!(file.resolve(file.trailer.root.get_ref().get_inner())?.into_dictionary(file));
+    let form = match catalog.get("AcroForm") {
+        Some(form) => t!(form.clone().into_dictionary(file)),
+        None => bail!("the document has no form")
+    };
+    let mut attrs = Dictionary::new();
+    for &key in &["DA", "Q"] {
+        if let Some(value) = form.get(key) {
+            attrs.insert(key, value.clone());
+        }
+    }
+    let fields = match form.get("Fields") {
+        Some(fields) => t!(fields.clone().into_array(file)),
+        None => vec![]
+    };
+    let field = match find_field(file, fields, "", &attrs, name, 0)? {
+        Some(field) => field,
+        None => bail!("form field {} not found", name)
+    };
+    let resources = match form.get("DR") {
+        Some(dr) => t!(Resources::from_primitive(dr.clone(), file)),
+        None => Resources::default()
+    };
+
+    let field_type = field.attrs.get("FT").and_then(|p| p.as_name().ok()).unwrap_or("").to_owned();
+    let flags = field.attrs.get("Ff").and_then(|p| p.as_u32().ok()).unwrap_or(0);
+    let widgets = widgets(file, &field)?;
+    let is_button = |flag| field_type == "Btn" && flags & (RADIO | PUSHBUTTON) == flag;
+    match value {
+        FieldValue::Text(ref text) if field_type == "Tx" || field_type == "Ch" => {
+            set_entry(file, field.r, "V", Primitive::String(text_string(text)))?;
+            for widget in widgets {
+                let rect = widget_rect(file, widget)?;
+                let appearance = text_appearance(file, &field.attrs, flags, rect, &resources, text)?;
+                let mut ap = Dictionary::new();
+                ap.insert("N", Primitive::Reference(appearance));
+                set_entry(file, widget, "AP", Primitive::Dictionary(ap))?;
+            }
+        }
+        FieldValue::Check(checked) if is_button(0) => {
+            let mut value = "Off".to_owned();
+            for widget in widgets {
+                let on = match on_state(file, widget)? {
+                    Some(on) => on,
+                    None => {
+                        check_box_appearances(file, widget, &field.attrs, "Yes")?;
+                        "Yes".into()
+                    }
+                };
+                let state = if checked { on } else { "Off".into() };
+                set_entry(file, widget, "AS", Primitive::Name(state.clone()))?;
+                value = state;
+            }
+            set_entry(file, field.r, "V", Primitive::Name(value))?;
+        }
+        FieldValue::Radio(ref state) if is_button(RADIO) => {
+            let mut selected = Vec::with_capacity(widgets.len());
+            for &widget in &widgets {
+                selected.push(on_state(file, widget)?.is_some_and(|on| on == *state));
+            }
+            if state != "Off" && !selected.contains(&true) {
+                bail!("radio button field {} has no state {}", name, state);
+            }
+            for (widget, selected) in widgets.into_iter().zip(selected) {
+                let state = if selected { state.clone() } else { "Off".into() };
+                set_entry(file, widget, "AS", Primitive::Name(state))?;
+            }
+            set_entry(file, field.r, "V", Primitive::Name(state.clone()))?;
+        }
+        value => bail!("can't set {:?} on the {} field {}", value, field_type, name)
+    }
+    Ok(())
+}
+
+/// Set `key` in the dictionary `r`, keeping all other entries
+fn set_entry<B: Backend>(file: &mut File<B>, r: PlainRef, key: &str, value: Primitive) -> Result<()> {
+    let mut dict = t!(file.resolve(r)?.into_dictionary(file));
+    dict.insert(key, value);
+    file.update(r, Primitive::Dictionary(dict))?;
+    Ok(())
+}
+
+fn widget_rect(resolve: &impl Resolve, widget: PlainRef) -> Result<Rect> {
+    let mut dict = t!(resolve.resolve(widget)?.into_dictionary(resolve));
+    let rect = t!(Rect::from_primitive(dict.require("Widget", "Rect")?, resolve));
+    Ok(Rect {
+        left: rect.left.min(rect.right),
+        bottom: rect.bottom.min(rect.top),
+        right: rect.left.max(rect.right),
+        top: rect.bottom.max(rect.top),
+    })
+}
+
+/// The appearance state of a button widget other than `Off`
+fn on_state(resolve: &impl Resolve, widget: PlainRef) -> Result<Option<String>> {
+    let dict = t!(resolve.resolve(widget)?.into_dictionary(resolve));
+    let ap = match dict.get("AP") {
+        Some(ap) => t!(ap.clone().into_dictionary(resolve)),
+        None => return Ok(None)
+    };
+    let normal = match ap.get("N") {
+        Some(&Primitive::Reference(r)) => resolve.resolve(r)?,
+        Some(p) => p.clone(),
+        None => return Ok(None)
+    };
+    match normal {
+        Primitive::Dictionary(states) => Ok(states.iter().map(|(state, _)| state).find(|&state| state != "Off").cloned()),
+        _ => Ok(None)
+    }
+}
+
+/// The parsed default appearance string (`DA`)
+struct DefaultAppearance {
+    /// font name and size (`Tf`), a size of 0 means auto size
+    font: Option<(String, f32)>,
+    /// the other operations, usually a color
+    ops: Vec<Op>,
+}
+
+fn default_appearance(attrs: &Dictionary) -> Result<DefaultAppearance> {
+    let da = match attrs.get("DA") {
+        Some(Primitive::String(da)) => da.as_bytes().to_vec(),
+        _ => vec![]
+    };
+    let mut font = None;
+    let mut ops = vec![];
+    for op in Ops::new(&da) {
+        match t!(op) {
+            Op::TextFont { name, size } => font = Some((name, size)),
+            op => ops.push(op)
+        }
+    }
+    Ok(DefaultAppearance { font, ops })
+}
+
+/// `text` in the encoding of the simple font `font`. Characters it can't show become `?`.
+fn encode(font: &FontInfo, text: &str) -> Vec<u8> {
+    text.chars().map(|c| {
+        let code = match font.encoding {
+            Some(ref encoding) => encoding.encode(c),
+            None => BaseEncoding::StandardEncoding.encode(c)
+        };
+        code.unwrap_or(b'?')
+    }).collect()
+}
+
+/// Width of the encoded `text` in units of the font size
+fn text_width(font: &FontInfo, text: &[u8]) -> f32 {
+    text.iter().map(|&b| font.width(b as u32)).sum::<f32>() * 0.001
+}
+
+/// Break `text` into lines no wider than `width` (in units of the font size)
+fn wrap(font: &FontInfo, text: &str, width: f32) -> Vec<Vec<u8>> {
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let candidate = if line.is_empty() { word.to_owned() } else { format!("{} {}", line, word) };
+            if !line.is_empty() && text_width(font, &encode(font, &candidate)) > width {
+                lines.push(encode(font, &line));
+                line = word.to_owned();
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(encode(font, &line));
+    }
+    lines
+}
+
+/// A form XObject showing `text` in a widget of size `rect`, as described by the
+/// `DA` string, quadding `Q` and the multiline and password flags of the field.
+fn text_appearance<B: Backend>(file: &mut File<B>, attrs: &Dictionary, flags: u32, rect: Rect, resources: &Resources, text: &str) -> Result<PlainRef> {
+    let DefaultAppearance { font, ops: style } = default_appearance(attrs)?;
+    let (font_name, size) = match font {
+        Some(font) => font,
+        None => bail!("the default appearance of the field has no font")
+    };
+    let font_ref = match resources.fonts.get(&font_name) {
+        Some(&font) => font,
+        None => bail!("font {} is not in the default resources of the form", font_name)
+    };
+    let font = t!(file.get_font(font_ref));
+    if matches!(font.font.subtype, FontType::Type0) {
+        bail!("composite fonts are not supported in form fields");
+    }
+
+    let (width, height) = (rect.right - rect.left, rect.top - rect.bottom);
+    let available = width - 2. * PADDING;
+    let text = match flags & PASSWORD {
+        0 => text.to_owned(),
+        _ => text.chars().map(|_| '*').collect()
+    };
+    let multiline = flags & MULTILINE != 0;
+    let (size, lines) = if multiline {
+        let size = if size > 0. { size } else { 12. };
+        (size, wrap(&font, &text, available / size))
+    } else {
+        let line = encode(&font, &text.replace('\n', " "));
+        let size = match size {
+            // auto size: fit the height and the width of the text
+            s if s <= 0. => {
+                let line_width = text_width(&font, &line);
+                let size = ((height - 2. * PADDING) / 1.15).min(12.);
+                if line_width > 0. { size.min(available / line_width) } else { size }
+            }
+            s => s
+        };
+        (size, vec![line])
+    };
+    let leading = 1.15 * size;
+    let quadding = attrs.get("Q").and_then(|q| q.as_integer().ok()).unwrap_or(0);
+
+    let mut content = ContentBuilder::new()
+        .op(Op::BeginMarkedContent { tag: "Tx".into(), properties: None })
+        .save()
+        .rect(1., 1., width - 2., height - 2.)
+        .clip();
+    for op in style {
+        content = content.op(op);
+    }
+    content = content.font(font_name.as_str(), size);
+    let mut y = if multiline { height - PADDING - size } else { 0.5 * (height - size) + 0.22 * size };
+    for line in lines {
+        let line_width = text_width(&font, &line) * size;
+        let x = match quadding {
+            1 => 0.5 * (width - line_width),
+            2 => width - PADDING - line_width,
+            _ => PADDING
+        };
+        content = content.text_matrix(Matrix::translate(x, y)).show_encoded(PdfString::new(line));
+        y -= leading;
+    }
+    let content = content.end_text().restore().op(Op::EndMarkedContent);
+
+    let mut form_resources = Resources::default();
+    form_resources.fonts.insert(font_name, font_ref);
+    let bbox = Rect { left: 0., bottom: 0., right: width, top: height };
+    let form = t!(content.build_form(bbox, Some(form_resources)));
+    Ok(file.create(XObject::Form(form))?.get_ref().get_inner())
+}
+
+/// Give a check box widget without appearances a check mark for the state `on`,
+/// and an empty appearance for `Off`.
+fn check_box_appearances<B: Backend>(file: &mut File<B>, widget: PlainRef, attrs: &Dictionary, on: &str) -> Result<()> {
+    let rect = widget_rect(file, widget)?;
+    let (width, height) = (rect.right - rect.left, rect.top - rect.bottom);
+    let bbox = Rect { left: 0., bottom: 0., right: width, top: height };
+    let style = default_appearance(attrs)?.ops;
+    let color = style.iter().rev().find_map(|op| match *op {
+        Op::FillColor { ref color } => Some(color.clone()),
+        _ => None
+    }).unwrap_or(Color::Gray(0.));
+
+    let content = ContentBuilder::new()
+        .save()
+        .stroke_color(color)
+        .line_width(0.1 * width.min(height))
+        .move_to(0.2 * width, 0.5 * height)
+        .line_to(0.4 * width, 0.25 * height)
+        .line_to(0.8 * width, 0.8 * height)
+        .stroke();
+    let on_form = t!(content.build_form(bbox, None));
+    let off_form = t!(ContentBuilder::new().build_form(bbox, None));
+    let on_ref = file.create(XObject::Form(on_form))?.get_ref().get_inner();
+    let off_ref = file.create(XObject::Form(off_form))?.get_ref().get_inner();
+
+    let mut states = Dictionary::new();
+    states.insert(on, Primitive::Reference(on_ref));
+    states.insert("Off", Primitive::Reference(off_ref));
+    let mut ap = Dictionary::new();
+    ap.insert("N", Primitive::Dictionary(states));
+    set_entry(file, widget, "AP", Primitive::Dictionary(ap))
+}
@@ -0,0 +1,644 @@
+//! Executing the graphics operators of a content stream
+use crate::backend::Backend;
+use crate::content::{Cmyk, Color, FormXObject, LineCap, LineJoin, Matrix, Op, Point, Rgb, Winding};
+use crate::error::*;
+use crate::file::File;
+use crate::object::{self, ColorSpace, ImageDict, MaybeRef, Page, RcRef, Rect, Ref, RenderingIntent, Resolve, Resources, Stream, XObject};
+use crate::primitive::Primitive;
+use crate::text::{TextItem, TextState, MAX_FORM_DEPTH};
+
+/// The device independent parameters of the graphics state, except for the text state.
+#[derive(Debug, Clone)]
+pub struct GraphicsState {
+    /// current transformation matrix: user space to device space
+    pub ctm: Matrix,
+    pub line_width: f32,
+    pub line_cap: LineCap,
+    pub line_join: LineJoin,
+    pub miter_limit: f32,
+    pub dash_pattern: Vec<f32>,
+    pub dash_phase: f32,
+    pub flatness: f32,
+    pub rendering_intent: RenderingIntent,
+    /// name of the stroking color space (`CS`)
+    pub stroke_color_space: String,
+    /// name of the non-stroking color space (`cs`)
+    pub fill_color_space: String,
+    pub stroke_color: Color,
+    pub fill_color: Color,
+    /// `CA`
+    pub stroke_alpha: f32,
+    /// `ca`
+    pub fill_alpha: f32,
+}
+impl GraphicsState {
+    /// The initial state, with `ctm` as the current transformation matrix
+    pub fn with_ctm(ctm: Matrix) -> GraphicsState {
+        GraphicsState {
+            ctm,
+            line_width: 1.,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 10.,
+            dash_pattern: vec![],
+            dash_phase: 0.,
+            flatness: 1.,
+            rendering_intent: RenderingIntent::RelativeColorimetric,
+            stroke_color_space: "DeviceGray".into(),
+            fill_color_space: "DeviceGray".into(),
+            stroke_color: Color::Gray(0.),
+            fill_color: Color::Gray(0.),
+            stroke_alpha: 1.,
+            fill_alpha: 1.,
+        }
+    }
+
+    /// Apply the parameters of an `ExtGState` dictionary (`gs`)
+    pub fn set_parameters(&mut self, params: &object::GraphicsStateParameters) {
+        if let Some(width) = params.line_width {
+            self.line_width = width;
+        }
+        if let Some(ref cap) = params.line_cap {
+            self.line_cap = match *cap {
+                object::LineCap::Butt => LineCap::Butt,
+                object::LineCap::Round => LineCap::Round,
+                object::LineCap::Square => LineCap::Square,
+            };
+        }
+        if let Some(ref join) = params.line_join {
+            self.line_join = match *join {
+                object::LineJoin::Miter => LineJoin::Miter,
+                object::LineJoin::Round => LineJoin::Round,
+                object::LineJoin::Bevel => LineJoin::Bevel,
+            };
+        }
+        if let Some(limit) = params.miter_limit {
+            self.miter_limit = limit;
+        }
+        // [[pattern] phase]
+        if let Some([Primitive::Array(ref pattern), ref phase]) = params.dash_pattern.as_deref() {
+            if let (Ok(pattern), Ok(phase)) = (pattern.iter().map(|p| p.as_number()).collect(), phase.as_number()) {
+                self.dash_pattern = pattern;
+                self.dash_phase = phase;
+            }
+        }
+        if let Some(intent) = params.rendering_intent.as_deref().and_then(RenderingIntent::from_str) {
+            self.rendering_intent = intent;
+        }
+        if let Some(alpha) = params.stroke_alpha {
+            self.stroke_alpha = alpha;
+        }
+        if let Some(alpha) = params.fill_alpha {
+            self.fill_alpha = alpha;
+        }
+    }
+}
+impl Default for GraphicsState {
+    fn default() -> Self {
+        GraphicsState::with_ctm(Matrix::default())
+    }
+}
+
+/// Initial color of a color space
+fn initial_color(color_space: &str) -> Color {
+    match color_space {
+        "DeviceGray" | "CalGray" => Color::Gray(0.),
+        "DeviceRGB" | "CalRGB" => Color::Rgb(Rgb { red: 0., green: 0., blue: 0. }),
+        "DeviceCMYK" => Color::Cmyk(Cmyk { cyan: 0., magenta: 0., yellow: 0., key: 1. }),
+        _ => Color::Components(vec![]),
+    }
+}
+
+/// A segment of a path, in device space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PathSegment {
+    MoveTo(Point),
+    LineTo(Point),
+    CurveTo(Point, Point, Point),
+    Close,
+}
+
+/// A path that was painted or used for clipping
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub segments: Vec<PathSegment>,
+    /// Filled with this rule
+    pub fill: Option<Winding>,
+    pub stroke: bool,
+    /// Intersected with the clipping path, using this rule
+    pub clip: Option<Winding>,
+}
+
+/// What an operation draws, in device space.
+///
+/// The graphics state at the time (colors, line width ...) is `GraphicsStateMachine::state`.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Text shown (`Tj`, `TJ`, `'`, `"`)
+    Text(TextItem),
+    /// A path painted (`S`, `f`, `B`, ...) or only used to clip (`W n`)
+    Path(Path),
+    /// The image XObject `name` (`Do`), drawn into the unit square mapped by `transform`
+    Image { name: String, transform: Matrix },
+    /// An inline image, drawn into the unit square mapped by `transform`
+    InlineImage { image: Box<Stream<ImageDict>>, transform: Matrix },
+    /// The form XObject `name` (`Do`). `transform` maps form space (including the form `Matrix`) to device space.
+    Form { name: String, transform: Matrix },
+    /// The shading `name` painted over the clipping area (`sh`)
+    Shading { name: String, transform: Matrix },
+}
+
+/// Executes content stream operations, keeping track of the graphics state (with the save / restore stack)
+/// and the text state, and reports what is drawn.
+pub struct GraphicsStateMachine {
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    text: TextState,
+    // current path, in device space
+    path: Vec<PathSegment>,
+    clip: Option<Winding>,
+}
+impl GraphicsStateMachine {
+    /// Start with `ctm` as the current transformation matrix,
+    /// for example `Page::display_transform` or the transform to device space.
+    pub fn new(ctm: Matrix) -> GraphicsStateMachine {
+        GraphicsStateMachine {
+            state: GraphicsState::with_ctm(ctm),
+            stack: vec![],
+            text: TextState::with_ctm(ctm),
+            path: vec![],
+            clip: None,
+        }
+    }
+    /// Start with `state`, for example the state at the `Do` of a form XObject
+    pub fn with_state(state: GraphicsState, text: TextState) -> GraphicsStateMachine {
+        GraphicsStateMachine { state, text, ..GraphicsStateMachine::new(Matrix::default()) }
+    }
+
+    /// The current graphics state
+    pub fn state(&self) -> &GraphicsState {
+        &self.state
+    }
+    /// The current text state
+    pub fn text_state(&self) -> &TextState {
+        &self.text
+    }
+    /// Depth of the save / restore stack
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn point(&self, p: Point) -> Point {
+        self.state.ctm.transform(p)
+    }
+    fn paint(&mut self, fill: Option<Winding>, stroke: bool) -> Vec<Event> {
+        let path = Path {
+            segments: std::mem::take(&mut self.path),
+            fill,
+            stroke,
+            clip: self.clip.take(),
+        };
+        if path.segments.is_empty() || (fill.is_none() && !stroke && path.clip.is_none()) {
+            return vec![];
+        }
+        vec![Event::Path(path)]
+    }
+
+    /// Execute `op`, and return what it draws.
+    ///
+    /// `resources` are used to look up fonts, graphics states and XObjects.
+    pub fn apply<B: Backend>(&mut self, op: &Op, file: &File<B>, resources: &Resources) -> Vec<Event> {
+        let mut events: Vec<Event> = self.text.apply(op, file, resources).into_iter().map(Event::Text).collect();
+        let ctm = self.state.ctm;
+        match *op {
+            Op::Save => self.stack.push(self.state.clone()),
+            Op::Restore => if let Some(state) = self.stack.pop() {
+                self.state = state;
+            },
+            Op::Transform { matrix } => self.state.ctm = matrix * self.state.ctm,
+            Op::LineWidth { width } => self.state.line_width = width,
+            Op::LineCap { cap } => self.state.line_cap = cap,
+            Op::LineJoin { join } => self.state.line_join = join,
+            Op::MiterLimit { limit } => self.state.miter_limit = limit,
+            Op::Dash { ref pattern, phase } => {
+                self.state.dash_pattern = pattern.clone();
+                self.state.dash_phase = phase;
+            }
+            Op::Flatness { tolerance } => self.state.flatness = tolerance,
+            Op::RenderingIntent { intent } => self.state.rendering_intent = intent,
+            Op::GraphicsState { ref name } => match resources.graphics_states.get(name) {
+                Some(params) => self.state.set_parameters(params),
+                None => warn!("graphics state {} not found", name)
+            },
+            Op::StrokeColorSpace { ref name } => {
+                self.state.stroke_color = initial_color(name);
+                self.state.stroke_color_space = name.clone();
+            }
+            Op::FillColorSpace { ref name } => {
+                self.state.fill_color = initial_color(name);
+                self.state.fill_color_space = name.clone();
+            }
+            Op::StrokeColor { ref color } => {
+                self.state.stroke_color = color.clone();
+                match *color {
+                    Color::Gray(_) => self.state.stroke_color_space = "DeviceGray".into(),
+                    Color::Rgb(_) => self.state.stroke_color_space = "DeviceRGB".into(),
+                    Color::Cmyk(_) => self.state.stroke_color_space = "DeviceCMYK".into(),
+                    _ => {}
+                }
+            }
+            Op::FillColor { ref color } => {
+                self.state.fill_color = color.clone();
+                match *color {
+                    Color::Gray(_) => self.state.fill_color_space = "DeviceGray".into(),
+                    Color::Rgb(_) => self.state.fill_color_space = "DeviceRGB".into(),
+                    Color::Cmyk(_) => self.state.fill_color_space = "DeviceCMYK".into(),
+                    _ => {}
+                }
+            }
+
+            Op::MoveTo { p } => self.path.push(PathSegment::MoveTo(self.point(p))),
+            Op::LineTo { p } => self.path.push(PathSegment::LineTo(self.point(p))),
+            Op::CurveTo { c1, c2, p } => self.path.push(PathSegment::CurveTo(self.point(c1), self.point(c2), self.point(p))),
+            Op::Rect { rect } => {
+                let corners = [
+                    Point { x: rect.x, y: rect.y },
+                    Point { x: rect.x + rect.width, y: rect.y },
+                    Point { x: rect.x + rect.width, y: rect.y + rect.height },
+                    Point { x: rect.x, y: rect.y + rect.height },
+                ];
+                self.path.push(PathSegment::MoveTo(self.point(corners[0])));
+                for &p in &corners[1..] {
+                    self.path.push(PathSegment::LineTo(self.point(p)));
+                }
+                self.path.push(PathSegment::Close);
+            }
+            Op::Close => self.path.push(PathSegment::Close),
+            Op::Clip { winding } => self.clip = Some(winding),
+            Op::Stroke => events.extend(self.paint(None, true)),
+            Op::Fill { winding } => events.extend(self.paint(Some(winding), false)),
+            Op::FillAndStroke { winding } => events.extend(self.paint(Some(winding), true)),
+            Op::EndPath => events.extend(self.paint(None, false)),
+
+            Op::Shade { ref name } => events.push(Event::Shading { name: name.clone(), transform: ctm }),
+            Op::InlineImage { ref image } => events.push(Event::InlineImage { image: Box::new(image.clone()), transform: ctm }),
+            Op::XObject { ref name } => match resources.xobjects.get(name).map(|&r| file.get(r)) {
+                Some(Ok(xobject)) => match *xobject {
+                    XObject::Image(_) => events.push(Event::Image { name: name.clone(), transform: ctm }),
+                    XObject::Form(ref form) => events.push(Event::Form { name: name.clone(), transform: form_transform(form, ctm) }),
+                    XObject::Postscript(_) => {}
+                },
+                Some(Err(e)) => warn!("can't load XObject {}: {:?}", name, e),
+                None => warn!("XObject {} not found", name),
+            },
+            _ => {}
+        }
+        events
+    }
+}
+
+/// Form space to the space of `ctm`
+pub fn form_transform(form: &FormXObject, ctm: Matrix) -> Matrix {
+    match form.dict().matrix {
+        Some(matrix) => matrix * ctm,
+        None => ctm
+    }
+}
+
+/// `color` (set in the color space `color_space`, the operand of `cs` or `CS`),
+/// as a `Gray`, `Rgb` or `Cmyk` color if it can be converted.
+///
+/// ICC based colors are taken as the device color with the same number of components,
+/// indexed colors are looked up, and separations use their tint transform.
+/// To convert colors to sRGB, see `icc::ColorManagement::color_to_rgb`.
+pub fn resolve_color(color: &Color, color_space: &str, resources: &Resources) -> Color {
+    if let Color::Components(ref components) = *color {
+        let device = match color_space {
+            "DeviceGray" => Some(ColorSpace::DeviceGray),
+            "DeviceRGB" => Some(ColorSpace::DeviceRGB),
+            "DeviceCMYK" => Some(ColorSpace::DeviceCMYK),
+            _ => None
+        };
+        let resolved = match device {
+            Some(ref cs) => device_color(cs, components),
+            None => resources.color_spaces.get(color_space).and_then(|cs| device_color(cs, components))
+        };
+        if let Some(color) = resolved {
+            return color;
+        }
+    }
+    color.clone()
+}
+
+fn num_components(cs: &ColorSpace) -> Option<usize> {
+    match *cs {
+        ColorSpace::DeviceGray | ColorSpace::Indexed(..) | ColorSpace::Separation(..) => Some(1),
+        ColorSpace::DeviceRGB => Some(3),
+        ColorSpace::DeviceCMYK => Some(4),
+        ColorSpace::Icc(ref icc) => Some(icc.info.info.components as usize),
+        ColorSpace::Other(_) => None,
+    }
+}
+
+fn device_color(cs: &ColorSpace, c: &[f32]) -> Option<Color> {
+    match (cs, c) {
+        (ColorSpace::DeviceGray, &[gray]) => Some(Color::Gray(gray)),
+        (ColorSpace::DeviceRGB, &[red, green, blue]) => Some(Color::Rgb(Rgb { red, green, blue })),
+        (ColorSpace::DeviceCMYK, &[cyan, magenta, yellow, key]) => Some(Color::Cmyk(Cmyk { cyan, magenta, yellow, key })),
+        (ColorSpace::Icc(_), c) => match c.len() {
+            1 => device_color(&ColorSpace::DeviceGray, c),
+            3 => device_color(&ColorSpace::DeviceRGB, c),
+            4 => device_color(&ColorSpace::DeviceCMYK, c),
+            _ => None
+        },
+        (ColorSpace::Indexed(ref base, ref lookup), &[index]) => {
+            let n = num_components(base)?;
+            let start = index.max(0.) as usize * n;
+            let values: Vec<f32> = lookup.get(start .. start + n)?.iter().map(|&b| b as f32 / 255.).collect();
+            device_color(base, &values)
+        }
+        (ColorSpace::Separation(_, ref alternate, ref tint), &[t]) => {
+            let mut values = vec![0.; num_components(alternate)?];
+            tint.apply(&[t], &mut values).ok()?;
+            device_color(alternate, &values)
+        }
+        _ => None
+    }
+}
+
+/// A path painted on a page, with the colors and line style it was painted with
+#[derive(Debug, Clone)]
+pub struct PathItem {
+    /// The segments, in page space
+    pub path: Path,
+    /// Color of the fill if the path is filled, converted as by `resolve_color`
+    pub fill_color: Option<Color>,
+    /// Color of the outline if the path is stroked, converted as by `resolve_color`
+    pub stroke_color: Option<Color>,
+    pub fill_alpha: f32,
+    pub stroke_alpha: f32,
+    /// Line width in page space
+    pub line_width: f32,
+    pub line_cap: LineCap,
+    pub line_join: LineJoin,
+    /// Dash pattern in user space
+    pub dash_pattern: Vec<f32>,
+    pub dash_phase: f32,
+    /// User space to page space
+    pub transform: Matrix,
+}
+impl PathItem {
+    fn new(path: Path, state: &GraphicsState, resources: &Resources) -> PathItem {
+        let m = state.ctm;
+        PathItem {
+            fill_color: path.fill.map(|_| resolve_color(&state.fill_color, &state.fill_color_space, resources)),
+            stroke_color: path.stroke.then(|| resolve_color(&state.stroke_color, &state.stroke_color_space, resources)),
+            path,
+            fill_alpha: state.fill_alpha,
+            stroke_alpha: state.stroke_alpha,
+            line_width: state.line_width * (m.a * m.d - m.b * m.c).abs().sqrt(),
+            line_cap: state.line_cap,
+            line_join: state.line_join,
+            dash_pattern: state.dash_pattern.clone(),
+            dash_phase: state.dash_phase,
+            transform: m,
+        }
+    }
+}
+
+/// The paths painted (or used to clip) on `page`, including those in Form XObjects, in content stream order.
+pub fn paths<'a, B: Backend>(file: &'a File<B>, page: &'a Page) -> Result<Paths<'a, B>> {
+    Ok(Paths {
+        file,
+        ops: flatten(file, page)?,
+        pending: vec![].into_iter(),
+        machine: GraphicsStateMachine::new(page.display_transform().unwrap_or_default()),
+    })
+}
+
+/// Iterator over the `PathItem`s of a page, see `paths`
+pub struct Paths<'a, B: Backend> {
+    file: &'a File<B>,
+    ops: Flatten<'a, B>,
+    /// rest of the items of the last operation
+    pending: std::vec::IntoIter<PathItem>,
+    machine: GraphicsStateMachine,
+}
+impl<'a, B: Backend> Iterator for Paths<'a, B> {
+    type Item = PathItem;
+    fn next(&mut self) -> Option<PathItem> {
+        loop {
+            if let Some(item) = self.pending.next() {
+                return Some(item);
+            }
+            let ScopedOp { op, resources, .. } = self.ops.next()?;
+            let events = self.machine.apply(&op, self.file, &resources);
+            let state = self.machine.state();
+            self.pending = events.into_iter().filter_map(|event| match event {
+                Event::Path(path) => Some(PathItem::new(path, state, &resources)),
+                _ => None
+            }).collect::<Vec<_>>().into_iter();
+        }
+    }
+}
+
+/// An image XObject drawn on a page, see `images`
+#[derive(Debug, Clone)]
+pub struct ImageItem {
+    /// Name of the image in the resources it was drawn with
+    pub name: String,
+    pub xobject: Ref<XObject>,
+    /// Size of the image in pixels
+    pub width: u32,
+    pub height: u32,
+    /// The CTM at the `Do`: maps the unit square the image is drawn into to page space.
+    /// Like for `paths`, it starts from `Page::display_transform`, so it includes the page rotation.
+    pub transform: Matrix,
+    /// Number of Form XObjects the image is drawn in, 0 for the page itself
+    pub depth: usize,
+}
+impl ImageItem {
+    /// The area covered by the image, in page space
+    pub fn bbox(&self) -> Rect {
+        bounding_box(self.transform, Rect { left: 0., bottom: 0., right: 1., top: 1. })
+    }
+    /// Resolution along the width and the height of the image, in pixels per inch
+    pub fn dpi(&self) -> (f32, f32) {
+        let m = self.transform;
+        let (width, height) = ((m.a * m.a + m.b * m.b).sqrt(), (m.c * m.c + m.d * m.d).sqrt());
+        (self.width as f32 * 72. / width, self.height as f32 * 72. / height)
+    }
+}
+
+/// The image XObjects drawn on `page`, including those in Form XObjects, in content stream
+/// order. Images drawn more than once are listed each time. Inline images are not included.
+pub fn images<'a, B: Backend>(file: &'a File<B>, page: &'a Page) -> Result<Images<'a, B>> {
+    Ok(Images {
+        file,
+        ops: flatten(file, page)?,
+        machine: GraphicsStateMachine::new(page.display_transform().unwrap_or_default()),
+        pending: vec![].into_iter(),
+    })
+}
+
+/// Iterator over the `ImageItem`s of a page, see `images`
+pub struct Images<'a, B: Backend> {
+    file: &'a File<B>,
+    ops: Flatten<'a, B>,
+    machine: GraphicsStateMachine,
+    pending: std::vec::IntoIter<ImageItem>,
+}
+impl<'a, B: Backend> Iterator for Images<'a, B> {
+    type Item = ImageItem;
+    fn next(&mut self) -> Option<ImageItem> {
+        loop {
+            if let Some(item) = self.pending.next() {
+                return Some(item);
+            }
+            let ScopedOp { op, resources, depth } = self.ops.next()?;
+            let file = self.file;
+            let events = self.machine.apply(&op, file, &resources);
+            self.pending = events.into_iter().filter_map(|event| match event {
+                Event::Image { name, transform } => {
+                    let xobject = *resources.xobjects.get(&name)?;
+                    let (width, height) = match *file.get(xobject).ok()? {
+                        XObject::Image(ref image) => (image.width.max(0) as u32, image.height.max(0) as u32),
+                        _ => return None
+                    };
+                    Some(ImageItem { name, xobject, width, height, transform, depth })
+                }
+                _ => None
+            }).collect::<Vec<_>>().into_iter();
+        }
+    }
+}
+
+/// Bounding box of `rect` mapped by `m`
+pub(crate) fn bounding_box(m: Matrix, rect: Rect) -> Rect {
+    let corners = [(rect.left, rect.bottom), (rect.right, rect.bottom), (rect.left, rect.top), (rect.right, rect.top)]
+        .map(|(x, y)| m.transform(Point { x, y }));
+    Rect {
+        left: corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min),
+        bottom: corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min),
+        right: corners.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max),
+        top: corners.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max),
+    }
+}
+
+/// An operation of a page, or of a Form XObject drawn by it. See `flatten`.
+#[derive(Debug, Clone)]
+pub struct ScopedOp {
+    pub op: Op,
+    /// The resources the names in `op` refer to
+    pub resources: MaybeRef<Resources>,
+    /// Number of Form XObjects `op` is nested in, 0 for the page itself
+    pub depth: usize,
+}
+
+/// The operations of `page`, with Form XObjects expanded in place.
+///
+/// The `Do` of a form is replaced by `q`, the form `Matrix` (as `cm`), the operations of the form
+/// and `Q`. The operations of the form use its own resources, or those of the parent if it has none.
+/// All other operations are passed on as they are, including the `Do` of images
+/// and of forms that could not be loaded. The form `BBox` is not applied as a clip.
+pub fn flatten<'a, B: Backend>(file: &'a File<B>, page: &'a Page) -> Result<Flatten<'a, B>> {
+    let ops: &[Op] = match page.contents {
+        Some(ref contents) => &contents.operations,
+        None => &[]
+    };
+    let resources = t!(page.resources()).clone();
+    Ok(Flatten {
+        file,
+        stack: vec![Frame { ops: FrameOps::Page(ops), pos: 0, resources }],
+        pending: None,
+    })
+}
+
+/// Iterator over the operations of a page and its Form XObjects, see `flatten`
+pub struct Flatten<'a, B: Backend> {
+    file: &'a File<B>,
+    // the page, and the forms being drawn
+    stack: Vec<Frame<'a>>,
+    // the form matrix, after the `q` of a form
+    pending: Option<ScopedOp>,
+}
+struct Frame<'a> {
+    ops: FrameOps<'a>,
+    // index of the next operation
+    pos: usize,
+    resources: MaybeRef<Resources>,
+}
+enum FrameOps<'a> {
+    Page(&'a [Op]),
+    Form(RcRef<XObject>),
+}
+impl<'a> FrameOps<'a> {
+    fn get(&self, i: usize) -> Option<&Op> {
+        match *self {
+            FrameOps::Page(ops) => ops.get(i),
+            FrameOps::Form(ref xobject) => match **xobject {
+                XObject::Form(ref form) => form.operations.get(i),
+                _ => None
+            }
+        }
+    }
+}
+impl<'a, B: Backend> Flatten<'a, B> {
+    /// The form XObject `name`, if it can be drawn at `depth`
+    fn form(&self, resources: &Resources, name: &str, depth: usize) -> Option<RcRef<XObject>> {
+        let xobject = match resources.xobjects.get(name).map(|&r| self.file.get(r)) {
+            Some(Ok(xobject)) => xobject,
+            Some(Err(e)) => {
+                warn!("can't load XObject {}: {:?}", name, e);
+                return None;
+            }
+            None => {
+                warn!("XObject {} not found", name);
+                return None;
+            }
+        };
+        match *xobject {
+            XObject::Form(_) if depth < MAX_FORM_DEPTH => Some(xobject),
+            XObject::Form(_) => {
+                warn!("Form XObjects nested too deep");
+                None
+            }
+            _ => None
+        }
+    }
+}
+impl<'a, B: Backend> Iterator for Flatten<'a, B> {
+    type Item = ScopedOp;
+    fn next(&mut self) -> Option<ScopedOp> {
+        if let Some(op) = self.pending.take() {
+            return Some(op);
+        }
+        let depth = self.stack.len().checked_sub(1)?;
+        let frame = &mut self.stack[depth];
+        let op = match frame.ops.get(frame.pos) {
+            Some(op) => op.clone(),
+            None => {
+                self.stack.pop();
+                return self.stack.last().map(|parent| {
+                    ScopedOp { op: Op::Restore, resources: parent.resources.clone(), depth: depth - 1 }
+                });
+            }
+        };
+        frame.pos += 1;
+        let resources = frame.resources.clone();
+
+        if let Op::XObject { ref name } = op {
+            if let Some(xobject) = self.form(&resources, name, depth) {
+                if let XObject::Form(ref form) = *xobject {
+                    if let Some(matrix) = form.dict().matrix {
+                        self.pending = Some(ScopedOp { op: Op::Transform { matrix }, resources: resources.clone(), depth });
+                    }
+                    let form_resources = form.dict().resources.clone().unwrap_or_else(|| resources.clone());
+                    self.stack.push(Frame { ops: FrameOps::Form(xobject.clone()), pos: 0, resources: form_resources });
+                }
+                return Some(ScopedOp { op: Op::Save, resources, depth });
+            }
+        }
+        Some(ScopedOp { op, resources, depth })
+    }
+}
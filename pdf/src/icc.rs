@@ -0,0 +1,646 @@
+//! ICC color profiles (`ICCBased` color spaces and the profiles of output intents).
+//!
+//! `ColorManagement` converts colors and images (`ImageXObject::image_data_with`) to sRGB.
+//! Without the `icc` feature colors are converted naively, see `content::Color::to_rgb`, and
+//! ICC based colors are taken as device colors with the same number of components.
+//!
+//! With it, `Profile` converts colors to sRGB with the lookup table from the device space to
+//! the profile connection space (`A2B0`, as `lut8`, `lut16` or `lutAtoB`), or the tone curves
+//! and colorants of RGB and gray profiles. `ColorManagement` uses the profiles of `ICCBased`
+//! color spaces, and with `ColorManagement::with_output_intent` converts device colors like
+//! the printer of the output intent (`DestOutputProfile`) would.
+//!
+//! This is not a full color management engine like lcms2, it only goes from the device space
+//! to the profile connection space and from there to sRGB:
+//! - only gray, RGB, CMY and CMYK profiles, no n-color or Lab device spaces
+//! - no `B2A` or `mBA` tags, so nothing is converted into the space of a profile
+//! - no `D2B` (`mpet`) tags, only the tags listed above
+//! - no rendering intent selection: the rendering intent of the content (`ri`, `/Intent`) is
+//!   ignored and `A2B0` (perceptual) is used, `A2B1` only without it
+//! - no black point compensation, no `chad` tag: the profile connection space is taken as D50
+//!   and adapted to D65 with the Bradford transform, colors out of the sRGB gamut are clipped
+
+use crate::content::{Color, Rgb};
+use crate::object::{ColorSpace, IccInfo, RcRef, Resources, Stream};
+
+#[cfg(feature = "icc")]
+use crate::error::*;
+#[cfg(feature = "icc")]
+use crate::object::{OutputIntent, PlainRef};
+#[cfg(feature = "icc")]
+use std::sync::{Arc, Mutex};
+
+/// Converts colors to sRGB, see the module documentation.
+///
+/// The default converts device colors naively and, with the `icc` feature, ICC based colors
+/// with their profiles. Profiles are parsed once and kept, so reuse it for a document.
+#[derive(Debug, Default)]
+pub struct ColorManagement {
+    /// For device colors with the same number of components
+    #[cfg(feature = "icc")]
+    output: Option<Arc<Profile>>,
+    /// Profiles of `ICCBased` color spaces, `None` if they couldn't be parsed
+    #[cfg(feature = "icc")]
+    parsed: Mutex<Vec<(PlainRef, Option<Arc<Profile>>)>>,
+}
+
+impl ColorManagement {
+    /// Convert device colors with the components of `output` with it instead
+    #[cfg(feature = "icc")]
+    pub fn with_output_profile(output: Profile) -> ColorManagement {
+        ColorManagement { output: Some(Arc::new(output)), ..ColorManagement::default() }
+    }
+
+    /// Convert the device colors of the output intent (usually CMYK) with its
+    /// `DestOutputProfile`, if it has one
+    #[cfg(feature = "icc")]
+    pub fn with_output_intent(intent: &OutputIntent) -> Result<ColorManagement> {
+        Ok(match t!(intent.profile_data()) {
+            Some(data) => ColorManagement::with_output_profile(t!(Profile::parse(data))),
+            None => ColorManagement::default()
+        })
+    }
+
+    /// `color`, set in the color space `color_space` (the operand of `cs` or `CS`, a device
+    /// color space or one of `resources`), in sRGB. `None` for patterns and colors that can't
+    /// be converted.
+    pub fn color_to_rgb(&self, color: &Color, color_space: &str, resources: &Resources) -> Option<Rgb> {
+        let (cs, components) = match *color {
+            Color::Gray(gray) => (ColorSpace::DeviceGray, vec![gray]),
+            Color::Rgb(Rgb { red, green, blue }) => (ColorSpace::DeviceRGB, vec![red, green, blue]),
+            Color::Cmyk(cmyk) => (ColorSpace::DeviceCMYK, vec![cmyk.cyan, cmyk.magenta, cmyk.yellow, cmyk.key]),
+            Color::Components(ref c) => match color_space {
+                "DeviceGray" => (ColorSpace::DeviceGray, c.clone()),
+                "DeviceRGB" => (ColorSpace::DeviceRGB, c.clone()),
+                "DeviceCMYK" => (ColorSpace::DeviceCMYK, c.clone()),
+                name => (resources.color_spaces.get(name)?.clone(), c.clone())
+            },
+            _ => return None
+        };
+        let [red, green, blue] = crate::image::rgb(&cs, &components, self)?;
+        Some(Rgb { red, green, blue })
+    }
+
+    /// `c` converted with the output profile, if it has as many components
+    #[cfg(feature = "icc")]
+    pub(crate) fn device_rgb(&self, c: &[f32]) -> Option<[f32; 3]> {
+        self.output.as_ref()?.to_rgb(c)
+    }
+    #[cfg(not(feature = "icc"))]
+    pub(crate) fn device_rgb(&self, _c: &[f32]) -> Option<[f32; 3]> {
+        None
+    }
+
+    /// `c` converted with the profile of `icc`, if it can be parsed
+    #[cfg(feature = "icc")]
+    pub(crate) fn icc_rgb(&self, icc: &RcRef<Stream<IccInfo>>, c: &[f32]) -> Option<[f32; 3]> {
+        let r = icc.get_ref().get_inner();
+        let profile = {
+            let mut parsed = self.parsed.lock().unwrap_or_else(|e| e.into_inner());
+            match parsed.iter().find(|p| p.0 == r) {
+                Some(p) => p.1.clone(),
+                None => {
+                    let profile = match icc.data().and_then(Profile::parse) {
+                        Ok(profile) if profile.components() == icc.info.info.components as usize => Some(Arc::new(profile)),
+                        Ok(_) => {
+                            warn!("ignoring an ICC profile for a different number of components");
+                            None
+                        }
+                        Err(e) => {
+                            warn!("converting ICC based colors without their profile: {}", e);
+                            None
+                        }
+                    };
+                    parsed.push((r, profile.clone()));
+                    profile
+                }
+            }
+        };
+        profile?.to_rgb(c)
+    }
+    #[cfg(not(feature = "icc"))]
+    pub(crate) fn icc_rgb(&self, _icc: &RcRef<Stream<IccInfo>>, _c: &[f32]) -> Option<[f32; 3]> {
+        None
+    }
+}
+
+/// A parsed ICC profile, see `Profile::parse`
+#[cfg(feature = "icc")]
+#[derive(Debug, Clone)]
+pub struct Profile {
+    components: usize,
+    /// The profile connection space is Lab, not XYZ
+    lab: bool,
+    transform: Transform,
+}
+
+#[cfg(feature = "icc")]
+#[derive(Debug, Clone)]
+enum Transform {
+    /// Curves, color lookup table, curves, matrix and curves (`lutAtoB`).
+    /// `lut8` and `lut16` only have the first curves, the table and the last curves.
+    Lut {
+        a: Vec<Curve>,
+        clut: Option<Clut>,
+        m: Vec<Curve>,
+        /// 3×3 matrix (row by row) and offsets
+        matrix: Option<[f32; 12]>,
+        b: Vec<Curve>,
+        /// `lut16` with Lab: L goes to 0xff00 instead of 0xffff
+        legacy_lab: bool,
+    },
+    /// Tone curves and the XYZ of the colorants in the columns (gray: the white point)
+    Matrix { curves: Vec<Curve>, matrix: [f32; 9] },
+}
+
+#[cfg(feature = "icc")]
+#[derive(Debug, Clone)]
+enum Curve {
+    /// Sampled, from 0 to 1. Empty is the identity.
+    Table(Vec<f32>),
+    Gamma(f32),
+    /// Parametric curve (`para`) of the function type with its parameters
+    Parametric(u16, [f32; 7]),
+}
+
+#[cfg(feature = "icc")]
+impl Curve {
+    fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0., 1.);
+        match *self {
+            Curve::Table(ref t) if t.len() >= 2 => {
+                let pos = x * (t.len() - 1) as f32;
+                let i = (pos as usize).min(t.len() - 2);
+                t[i] + (t[i + 1] - t[i]) * (pos - i as f32)
+            }
+            Curve::Table(ref t) => t.first().copied().unwrap_or(x),
+            Curve::Gamma(g) => x.powf(g),
+            Curve::Parametric(kind, [g, a, b, c, d, e, f]) => {
+                let pow = |x: f32| if x > 0. { x.powf(g) } else { 0. };
+                match kind {
+                    0 => pow(x),
+                    1 => if x >= -b / a { pow(a * x + b) } else { 0. },
+                    2 => if x >= -b / a { pow(a * x + b) + c } else { c },
+                    3 => if x >= d { pow(a * x + b) } else { c * x },
+                    _ => if x >= d { pow(a * x + b) + e } else { c * x + f },
+                }
+            }
+        }
+    }
+}
+
+/// A multidimensional color lookup table
+#[cfg(feature = "icc")]
+#[derive(Debug, Clone)]
+struct Clut {
+    /// Number of grid points of each input, the first input varies slowest
+    grid: Vec<usize>,
+    outputs: usize,
+    /// From 0 to 1
+    data: Vec<f32>,
+}
+
+#[cfg(feature = "icc")]
+impl Clut {
+    /// Interpolate between the grid points around `input`
+    fn apply(&self, input: &[f32], out: &mut [f32]) {
+        let n = self.grid.len();
+        let mut strides = vec![self.outputs; n];
+        for d in (0 .. n.saturating_sub(1)).rev() {
+            strides[d] = strides[d + 1] * self.grid[d + 1];
+        }
+        let mut cells = Vec::with_capacity(n);
+        for (d, &x) in input.iter().enumerate().take(n) {
+            let last = self.grid[d].saturating_sub(1);
+            let pos = x.clamp(0., 1.) * last as f32;
+            let i = (pos as usize).min(last.saturating_sub(1));
+            cells.push((i, if last > 0 { pos - i as f32 } else { 0. }, last > 0));
+        }
+        for o in out.iter_mut() {
+            *o = 0.;
+        }
+        for corner in 0 .. 1usize << n {
+            let mut weight = 1.;
+            let mut offset = 0;
+            for (d, &(i, frac, more)) in cells.iter().enumerate() {
+                let upper = corner >> d & 1 == 1;
+                weight *= if upper { frac } else { 1. - frac };
+                offset += (i + (upper && more) as usize) * strides[d];
+            }
+            if weight == 0. {
+                continue;
+            }
+            for (k, o) in out.iter_mut().enumerate() {
+                *o += weight * self.data.get(offset + k).copied().unwrap_or(0.);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "icc")]
+struct Reader<'a>(&'a [u8]);
+
+#[cfg(feature = "icc")]
+impl<'a> Reader<'a> {
+    fn bytes(&self, pos: usize, len: usize) -> Result<&'a [u8]> {
+        match pos.checked_add(len).and_then(|end| self.0.get(pos .. end)) {
+            Some(b) => Ok(b),
+            None => bail!("ICC profile is too short ({} bytes, reading {} at {})", self.0.len(), len, pos)
+        }
+    }
+    fn u8(&self, pos: usize) -> Result<u8> {
+        Ok(self.bytes(pos, 1)?[0])
+    }
+    fn u16(&self, pos: usize) -> Result<u16> {
+        let b = self.bytes(pos, 2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn u32(&self, pos: usize) -> Result<u32> {
+        let b = self.bytes(pos, 4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    /// s15Fixed16Number
+    fn fixed(&self, pos: usize) -> Result<f32> {
+        Ok(self.u32(pos)? as i32 as f32 / 65536.)
+    }
+    fn signature(&self, pos: usize) -> Result<&'a [u8]> {
+        self.bytes(pos, 4)
+    }
+
+    /// A `curv` or `para` curve at `pos`, and where it ends
+    fn curve(&self, pos: usize) -> Result<(Curve, usize)> {
+        match self.signature(pos)? {
+            b"curv" => {
+                let count = self.u32(pos + 8)? as usize;
+                let curve = match count {
+                    0 => Curve::Table(vec![]),
+                    1 => Curve::Gamma(self.u16(pos + 12)? as f32 / 256.),
+                    _ => Curve::Table((0 .. count).map(|i| Ok(self.u16(pos + 12 + 2 * i)? as f32 / 65535.)).collect::<Result<_>>()?)
+                };
+                Ok((curve, pos + 12 + 2 * count))
+            }
+            b"para" => {
+                let kind = self.u16(pos + 8)?;
+                let n = match kind {
+                    0 => 1,
+                    1 => 3,
+                    2 => 4,
+                    3 => 5,
+                    4 => 7,
+                    _ => bail!("unknown parametric curve type {}", kind)
+                };
+                let mut params = [0.; 7];
+                for (i, p) in params.iter_mut().enumerate().take(n) {
+                    *p = self.fixed(pos + 12 + 4 * i)?;
+                }
+                Ok((Curve::Parametric(kind, params), pos + 12 + 4 * n))
+            }
+            sig => bail!("unknown curve type {:?}", String::from_utf8_lossy(sig))
+        }
+    }
+    /// `n` curves starting at `pos`, each aligned to 4 bytes
+    fn curves(&self, mut pos: usize, n: usize) -> Result<Vec<Curve>> {
+        let mut curves = Vec::with_capacity(n);
+        for _ in 0 .. n {
+            let (curve, end) = self.curve(pos)?;
+            curves.push(curve);
+            pos = (end + 3) & !3;
+        }
+        Ok(curves)
+    }
+    /// `n` tables with `entries` values of `size` bytes each
+    fn tables(&self, pos: usize, n: usize, entries: usize, size: usize) -> Result<Vec<Curve>> {
+        (0 .. n).map(|i| Ok(Curve::Table(self.values(pos + i * entries * size, entries, size)?))).collect()
+    }
+    /// `count` values of `size` (1 or 2) bytes, from 0 to 1
+    fn values(&self, pos: usize, count: usize, size: usize) -> Result<Vec<f32>> {
+        let len = match count.checked_mul(size) {
+            Some(len) => len,
+            None => bail!("ICC table of {} values is too large", count)
+        };
+        let data = self.bytes(pos, len)?;
+        Ok(match size {
+            1 => data.iter().map(|&b| b as f32 / 255.).collect(),
+            _ => data.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 / 65535.).collect()
+        })
+    }
+    fn xyz(&self, pos: usize) -> Result<[f32; 3]> {
+        if self.signature(pos)? != b"XYZ " {
+            bail!("expected an XYZ tag");
+        }
+        Ok([self.fixed(pos + 8)?, self.fixed(pos + 12)?, self.fixed(pos + 16)?])
+    }
+
+    /// A `lut8`, `lut16` or `lutAtoB` tag at `pos`, for colors of `components` values
+    fn lut(&self, pos: usize, components: usize, lab: bool) -> Result<Transform> {
+        let inputs = self.u8(pos + 8)? as usize;
+        let outputs = self.u8(pos + 9)? as usize;
+        if inputs != components || outputs != 3 {
+            bail!("unsupported lookup table with {} inputs and {} outputs for {} components", inputs, outputs, components);
+        }
+        let too_large = || PdfError::Other { msg: format!("ICC lookup table with {} inputs is too large", inputs) };
+        match self.signature(pos)? {
+            sig @ b"mft1" | sig @ b"mft2" => {
+                let grid = self.u8(pos + 10)? as usize;
+                let (size, in_entries, out_entries, start) = match sig {
+                    b"mft1" => (1, 256, 256, pos + 48),
+                    _ => (2, self.u16(pos + 48)? as usize, self.u16(pos + 50)? as usize, pos + 52),
+                };
+                let a = self.tables(start, inputs, in_entries, size)?;
+                let clut_start = start + inputs * in_entries * size;
+                let clut_len = grid.checked_pow(inputs as u32).and_then(|n| n.checked_mul(outputs)).ok_or_else(too_large)?;
+                let clut = Clut { grid: vec![grid; inputs], outputs, data: self.values(clut_start, clut_len, size)? };
+                // `values` checked that the table fits in the profile
+                let b = self.tables(clut_start + clut_len * size, outputs, out_entries, size)?;
+                Ok(Transform::Lut { a, clut: Some(clut), m: vec![], matrix: None, b, legacy_lab: lab && size == 2 })
+            }
+            b"mAB " => {
+                let offset = |at: usize| -> Result<Option<usize>> {
+                    Ok(match self.u32(pos + at)? as usize {
+                        0 => None,
+                        off => Some(pos + off)
+                    })
+                };
+                let b = match offset(12)? {
+                    Some(at) => self.curves(at, outputs)?,
+                    None => bail!("lutAtoB without B curves")
+                };
+                let matrix = match offset(16)? {
+                    Some(at) => {
+                        let mut m = [0.; 12];
+                        for (i, v) in m.iter_mut().enumerate() {
+                            *v = self.fixed(at + 4 * i)?;
+                        }
+                        Some(m)
+                    }
+                    None => None
+                };
+                let m = match offset(20)? {
+                    Some(at) => self.curves(at, outputs)?,
+                    None => vec![]
+                };
+                let clut = match offset(24)? {
+                    Some(at) => {
+                        let grid: Vec<usize> = self.bytes(at, inputs)?.iter().map(|&g| g as usize).collect();
+                        let size = self.u8(at + 16)? as usize;
+                        let count = grid.iter().try_fold(outputs, |n, &g| n.checked_mul(g)).ok_or_else(too_large)?;
+                        Some(Clut { grid, outputs, data: self.values(at + 20, count, size.clamp(1, 2))? })
+                    }
+                    None => None
+                };
+                let a = match offset(28)? {
+                    Some(at) => self.curves(at, inputs)?,
+                    None => vec![]
+                };
+                if clut.is_none() && inputs != outputs {
+                    bail!("lutAtoB without a lookup table from {} to {} channels", inputs, outputs);
+                }
+                Ok(Transform::Lut { a, clut, m, matrix, b, legacy_lab: false })
+            }
+            sig => bail!("unsupported lookup table type {:?}", String::from_utf8_lossy(sig))
+        }
+    }
+}
+
+#[cfg(feature = "icc")]
+impl Profile {
+    /// Parse the profile `data`, for example the stream of an `ICCBased` color space
+    pub fn parse(data: &[u8]) -> Result<Profile> {
+        let r = Reader(data);
+        if r.signature(36)? != b"acsp" {
+            bail!("not an ICC profile");
+        }
+        let components = match r.signature(16)? {
+            b"GRAY" => 1,
+            b"RGB " | b"CMY " => 3,
+            b"CMYK" => 4,
+            sig => bail!("unsupported ICC color space {:?}", String::from_utf8_lossy(sig))
+        };
+        let lab = match r.signature(20)? {
+            b"Lab " => true,
+            b"XYZ " => false,
+            sig => bail!("invalid profile connection space {:?}", String::from_utf8_lossy(sig))
+        };
+        let count = r.u32(128)? as usize;
+        let mut tags = Vec::with_capacity(count.min(256));
+        for i in 0 .. count.min(256) {
+            let entry = 132 + 12 * i;
+            tags.push((r.signature(entry)?, r.u32(entry + 4)? as usize));
+        }
+        let tag = |sig: &[u8]| tags.iter().find(|t| t.0 == sig).map(|t| t.1);
+
+        let transform = if let Some(pos) = tag(b"A2B0").or_else(|| tag(b"A2B1")) {
+            r.lut(pos, components, lab)?
+        } else if let (1, Some(k)) = (components, tag(b"kTRC")) {
+            // gray maps to the white point
+            let w = match tag(b"wtpt") {
+                Some(pos) => r.xyz(pos)?,
+                None => D50
+            };
+            Transform::Matrix { curves: vec![r.curve(k)?.0], matrix: [w[0], 0., 0., w[1], 0., 0., w[2], 0., 0.] }
+        } else if let (3, Some(rc), Some(gc), Some(bc)) = (components, tag(b"rTRC"), tag(b"gTRC"), tag(b"bTRC")) {
+            let columns = match (tag(b"rXYZ"), tag(b"gXYZ"), tag(b"bXYZ")) {
+                (Some(rx), Some(gx), Some(bx)) => [r.xyz(rx)?, r.xyz(gx)?, r.xyz(bx)?],
+                _ => bail!("RGB profile without colorants")
+            };
+            let mut matrix = [0.; 9];
+            for (col, xyz) in columns.iter().enumerate() {
+                for (row, &v) in xyz.iter().enumerate() {
+                    matrix[3 * row + col] = v;
+                }
+            }
+            Transform::Matrix { curves: vec![r.curve(rc)?.0, r.curve(gc)?.0, r.curve(bc)?.0], matrix }
+        } else {
+            bail!("ICC profile without a supported transform to the profile connection space");
+        };
+        let lab = lab && !matches!(transform, Transform::Matrix { .. });
+        Ok(Profile { components, lab, transform })
+    }
+
+    /// Number of components of the colors
+    pub fn components(&self) -> usize {
+        self.components
+    }
+
+    /// `color` (`components` values from 0 to 1) in sRGB, `None` if the number of values is wrong
+    pub fn to_rgb(&self, color: &[f32]) -> Option<[f32; 3]> {
+        if color.len() != self.components {
+            return None;
+        }
+        let xyz = match self.transform {
+            Transform::Lut { ref a, ref clut, ref m, ref matrix, ref b, legacy_lab } => {
+                let input: Vec<f32> = match a.len() {
+                    0 => color.to_vec(),
+                    _ => color.iter().zip(a).map(|(&x, c)| c.apply(x)).collect()
+                };
+                let mut v = [0.; 3];
+                match *clut {
+                    Some(ref clut) => clut.apply(&input, &mut v),
+                    None => v.copy_from_slice(&input[.. 3]),
+                }
+                for (x, c) in v.iter_mut().zip(m) {
+                    *x = c.apply(*x);
+                }
+                if let Some(ref mx) = *matrix {
+                    let [x, y, z] = v;
+                    for (row, out) in v.iter_mut().enumerate() {
+                        *out = mx[3 * row] * x + mx[3 * row + 1] * y + mx[3 * row + 2] * z + mx[9 + row];
+                    }
+                }
+                for (x, c) in v.iter_mut().zip(b) {
+                    *x = c.apply(*x);
+                }
+                if self.lab {
+                    let (l, a, b) = if legacy_lab {
+                        (v[0] * 65535. / 65280. * 100., v[1] * 65535. / 256. - 128., v[2] * 65535. / 256. - 128.)
+                    } else {
+                        (v[0] * 100., v[1] * 255. - 128., v[2] * 255. - 128.)
+                    };
+                    lab_to_xyz(l, a, b)
+                } else {
+                    // u1Fixed15Number
+                    v.map(|x| x * 65535. / 32768.)
+                }
+            }
+            Transform::Matrix { ref curves, ref matrix } => {
+                let linear: Vec<f32> = color.iter().zip(curves).map(|(&x, c)| c.apply(x)).collect();
+                let mut xyz = [0.; 3];
+                for (row, out) in xyz.iter_mut().enumerate() {
+                    *out = (0 .. linear.len()).map(|col| matrix[3 * row + col] * linear[col]).sum();
+                }
+                xyz
+            }
+        };
+        Some(xyz_to_srgb(xyz))
+    }
+}
+
+/// D50, the white point of the profile connection space
+#[cfg(feature = "icc")]
+const D50: [f32; 3] = [0.9642, 1.0, 0.8249];
+
+#[cfg(feature = "icc")]
+fn lab_to_xyz(l: f32, a: f32, b: f32) -> [f32; 3] {
+    let fy = (l + 16.) / 116.;
+    let f = |t: f32| if t > 6. / 29. { t * t * t } else { 3. * (6f32 / 29.).powi(2) * (t - 4. / 29.) };
+    [D50[0] * f(fy + a / 500.), D50[1] * f(fy), D50[2] * f(fy - b / 200.)]
+}
+
+/// XYZ (D50) to sRGB, adapted to D65 with the Bradford transform
+#[cfg(feature = "icc")]
+fn xyz_to_srgb([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let linear = [
+        3.133856 * x - 1.616867 * y - 0.4906146 * z,
+        -0.9787684 * x + 1.916142 * y + 0.033454 * z,
+        0.0719453 * x - 0.2289914 * y + 1.405243 * z,
+    ];
+    linear.map(|c| {
+        let c = c.clamp(0., 1.);
+        if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1. / 2.4) - 0.055 }
+    })
+}
+
+#[cfg(all(test, feature = "icc"))]
+mod tests {
+    use super::*;
+
+    /// A profile with a header for `space` and `tags`, placed one after the other
+    fn profile(space: &[u8; 4], pcs: &[u8; 4], tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0; 128];
+        data[16 .. 20].copy_from_slice(space);
+        data[20 .. 24].copy_from_slice(pcs);
+        data[36 .. 40].copy_from_slice(b"acsp");
+        data.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+        let mut offset = 132 + 12 * tags.len();
+        let mut table = vec![];
+        let mut body = vec![];
+        for (sig, tag) in tags {
+            table.extend_from_slice(*sig);
+            table.extend_from_slice(&(offset as u32).to_be_bytes());
+            table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+            body.extend_from_slice(tag);
+            // aligned to 4 bytes
+            while body.len() % 4 != 0 {
+                body.push(0);
+            }
+            offset = 132 + 12 * tags.len() + body.len();
+        }
+        data.extend(table);
+        data.extend(body);
+        data
+    }
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn gray_curve() {
+        let mut curve = b"curv\0\0\0\0".to_vec();
+        curve.extend_from_slice(&1u32.to_be_bytes());
+        curve.extend(u16s(&[256])); // gamma 1
+        let mut white = b"XYZ \0\0\0\0".to_vec();
+        for v in D50 {
+            white.extend_from_slice(&((v * 65536.).round() as i32).to_be_bytes());
+        }
+        let profile = Profile::parse(&profile(b"GRAY", b"XYZ ", &[(b"kTRC", curve), (b"wtpt", white)])).unwrap();
+        assert_eq!(profile.components(), 1);
+        let [r, g, b] = profile.to_rgb(&[1.]).unwrap();
+        assert!(r > 0.99 && g > 0.99 && b > 0.99, "{:?}", (r, g, b));
+        // linear 0.5 is 0.735 in sRGB
+        let [r, _, _] = profile.to_rgb(&[0.5]).unwrap();
+        assert!((r - 0.735).abs() < 0.01, "{}", r);
+        assert_eq!(profile.to_rgb(&[0.5, 0.5]), None);
+    }
+
+    #[test]
+    fn cmyk_lut16() {
+        // 2 grid points per ink, white without black, black with it
+        let mut lut = b"mft2\0\0\0\0".to_vec();
+        lut.extend_from_slice(&[4, 3, 2, 0]);
+        for i in 0 .. 9 {
+            lut.extend_from_slice(&(if i % 4 == 0 { 65536i32 } else { 0 }).to_be_bytes());
+        }
+        lut.extend(u16s(&[2, 2]));
+        for _ in 0 .. 4 {
+            lut.extend(u16s(&[0, 65535]));
+        }
+        for corner in 0 .. 16 {
+            let black = corner & 1 == 1;
+            lut.extend(u16s(&[if black { 0 } else { 0xff00 }, 0x8000, 0x8000]));
+        }
+        for _ in 0 .. 3 {
+            lut.extend(u16s(&[0, 65535]));
+        }
+        let cmyk = Profile::parse(&profile(b"CMYK", b"Lab ", &[(b"A2B0", lut.clone())])).unwrap();
+        assert_eq!(cmyk.components(), 4);
+        // the table has to match the color space, and fit in the profile
+        assert!(Profile::parse(&profile(b"RGB ", b"Lab ", &[(b"A2B0", lut.clone())])).is_err());
+        assert!(Profile::parse(&profile(b"CMYK", b"Lab ", &[(b"A2B0", lut[.. lut.len() - 8].to_vec())])).is_err());
+        let mut huge = lut.clone();
+        huge[10] = 255;
+        assert!(Profile::parse(&profile(b"CMYK", b"Lab ", &[(b"A2B0", huge)])).is_err());
+
+        let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 0.01);
+        assert!(close(cmyk.to_rgb(&[0., 0., 0., 0.]).unwrap(), [1., 1., 1.]));
+        assert!(close(cmyk.to_rgb(&[1., 0., 1., 1.]).unwrap(), [0., 0., 0.]));
+        // L = 50
+        let gray = cmyk.to_rgb(&[0., 0., 0., 0.5]).unwrap();
+        assert!(close(gray, [0.466, 0.466, 0.466]), "{:?}", gray);
+    }
+
+    #[test]
+    fn output_profile() {
+        let mut curve = b"curv\0\0\0\0".to_vec();
+        curve.extend_from_slice(&1u32.to_be_bytes());
+        curve.extend(u16s(&[512])); // gamma 2
+        let profile = Profile::parse(&profile(b"GRAY", b"XYZ ", &[(b"kTRC", curve)])).unwrap();
+        let cm = ColorManagement::with_output_profile(profile);
+        let resources = Resources::default();
+        // linear 0.25 is 0.537 in sRGB
+        let gray = cm.color_to_rgb(&Color::Gray(0.5), "DeviceGray", &resources).unwrap();
+        assert!((gray.red - 0.537).abs() < 0.01, "{:?}", gray);
+        // the profile is only for gray
+        let red = cm.color_to_rgb(&Color::Rgb(Rgb { red: 1., green: 0., blue: 0. }), "DeviceRGB", &resources).unwrap();
+        assert_eq!((red.red, red.green, red.blue), (1., 0., 0.));
+    }
+}
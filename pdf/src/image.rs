@@ -0,0 +1,406 @@
+//! Decoding image XObjects to pixels.
+//!
+//! `Stream::data` only undoes the filters of an image. `ImageXObject::image_data` also
+//! unpacks the samples (`BitsPerComponent`), maps them with the `Decode` array, converts
+//! the color space to RGB and applies the soft mask (`SMask`) or mask (`Mask`) as alpha,
+//! see `ImageXObject::alpha`. `ImageXObject::color_samples` stops before converting to RGB,
+//! with the palette of `Indexed` images expanded to colors of the base color space.
+//!
+//! Device colors are converted without color management, CMYK naively (see `Cmyk::to_rgb`),
+//! unless `image_data_with` gets an output profile. ICC based colors are converted with their
+//! profile with the `icc` feature (see `icc::ColorManagement`), and otherwise taken as device
+//! colors with the same number of components.
+use crate::content::Cmyk;
+use crate::enc::StreamFilter;
+use crate::error::*;
+use crate::icc::ColorManagement;
+use crate::object::*;
+use crate::primitive::Primitive;
+
+/// The pixels of an image, see `ImageXObject::image_data`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    /// 4 bytes (red, green, blue and alpha) per pixel, row by row from the top
+    pub data: Vec<u8>,
+    /// The image is a stencil mask painted in black, it should be painted with the fill color
+    /// instead, see `StencilMask::paint`
+    pub needs_fill_color: bool,
+}
+impl RgbaImage {
+    /// The pixel at column `x` and row `y` (from the top)
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        Some([self.data[i], self.data[i + 1], self.data[i + 2], self.data[i + 3]])
+    }
+}
+
+/// An image mask (`ImageMask`): a 1 bit stencil that is painted with the fill color
+/// at the time it is drawn. See `ImageXObject::stencil_mask`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StencilMask {
+    pub width: u32,
+    pub height: u32,
+    /// Whether each pixel is painted, row by row from the top. The `Decode` array is applied.
+    pub painted: Vec<bool>,
+}
+impl StencilMask {
+    /// The mask painted with `color` (red, green, blue): transparent where it isn't painted
+    pub fn paint(&self, color: [u8; 3]) -> RgbaImage {
+        let [red, green, blue] = color;
+        let data = self.painted.iter().flat_map(|&painted| [red, green, blue, if painted { 255 } else { 0 }]).collect();
+        RgbaImage { width: self.width, height: self.height, data, needs_fill_color: false }
+    }
+}
+
+/// The colors of an image in its color space, see `ImageXObject::color_samples`
+#[derive(Debug, Clone)]
+pub struct ImageSamples {
+    pub width: u32,
+    pub height: u32,
+    /// The color space of `data`: the base color space for `Indexed` images
+    pub color_space: ColorSpace,
+    /// The components of each pixel, row by row from the top, with the `Decode` array applied
+    pub data: Vec<f32>,
+}
+
+impl ImageXObject {
+    /// The pixels of the image, as 8 bit RGBA.
+    ///
+    /// Image masks (`ImageMask`) are painted in black with `needs_fill_color` set, as the fill
+    /// color isn't known here. Use `stencil_mask` to paint them with it.
+    /// Fails for JPEG 2000 images, color spaces that can't be converted to RGB, like `Lab`,
+    /// images of more than 2^30 pixels, and images with less than half of their data.
+    pub fn image_data(&self, resolve: &impl Resolve) -> Result<RgbaImage> {
+        self.image_data_with(resolve, &ColorManagement::default())
+    }
+
+    /// Like `image_data`, with the colors converted by `cm`: with the `icc` feature, it can
+    /// convert device colors with the profile of an output intent.
+    pub fn image_data_with(&self, resolve: &impl Resolve, cm: &ColorManagement) -> Result<RgbaImage> {
+        if let Some(mask) = t!(self.stencil_mask()) {
+            return Ok(RgbaImage { needs_fill_color: true, ..mask.paint([0, 0, 0]) });
+        }
+        let (width, height) = (self.width.max(0) as usize, self.height.max(0) as usize);
+        let color_space = match self.color_space {
+            Some(ref cs) => t!(ColorSpace::from_primitive(cs.clone(), resolve)),
+            None => bail!("image without a color space")
+        };
+        let raw_n = t!(components(&color_space));
+        let raw = t!(samples(self, raw_n));
+        let (color_space, colors) = color_values(self, color_space, raw_n, &raw);
+        let n = t!(components(&color_space));
+        // `samples` checked the size
+        let mut data = Vec::with_capacity(width * height * 4);
+
+        // color key masking, on the samples before decoding
+        let key: Option<Vec<u16>> = match self.mask {
+            Some(Primitive::Array(ref ranges)) => ranges.iter().map(|p| p.as_integer().map(|i| i.max(0) as u16)).collect::<Result<_>>().ok(),
+            _ => None
+        };
+        for (values, pixel) in colors.chunks_exact(n).zip(raw.chunks_exact(raw_n)) {
+            let rgb = rgb(&color_space, values, cm).unwrap_or([0.; 3]);
+            let masked = match key {
+                Some(ref key) if key.len() >= 2 * raw_n => pixel.iter().enumerate().all(|(i, &s)| key[2 * i] <= s && s <= key[2 * i + 1]),
+                _ => false
+            };
+            data.extend_from_slice(&[byte(rgb[0]), byte(rgb[1]), byte(rgb[2]), if masked { 0 } else { 255 }]);
+        }
+
+        if let Some(alpha) = t!(self.alpha(resolve)) {
+            for (pixel, a) in data.chunks_exact_mut(4).zip(alpha) {
+                pixel[3] = (pixel[3] as u32 * a as u32 / 255) as u8;
+            }
+        }
+        // colors premultiplied with the matte color of the soft mask
+        if let (Some(smask), Some(ref cs)) = (self.smask, &self.color_space) {
+            let smask = t!(resolve.get(smask));
+            let cs = t!(ColorSpace::from_primitive(cs.clone(), resolve));
+            if let Some(matte) = smask.matte.as_ref().and_then(|m| rgb(&cs, m, cm)) {
+                for pixel in data.chunks_exact_mut(4).filter(|p| p[3] != 0) {
+                    let a = pixel[3] as f32 / 255.;
+                    for (c, &m) in pixel[.. 3].iter_mut().zip(&matte) {
+                        *c = byte(m + (*c as f32 / 255. - m) / a);
+                    }
+                }
+            }
+        }
+        Ok(RgbaImage { width: width as u32, height: height as u32, data, needs_fill_color: false })
+    }
+
+    /// The colors of the image in its color space, without converting them to RGB.
+    ///
+    /// The indices of `Indexed` images are looked up in the palette, so the colors are in
+    /// its base color space. Fails for image masks (see `stencil_mask`), JPEG 2000 images and
+    /// color spaces with an unknown number of components.
+    pub fn color_samples(&self, resolve: &impl Resolve) -> Result<ImageSamples> {
+        if self.image_mask {
+            bail!("image masks have no colors");
+        }
+        let color_space = match self.color_space {
+            Some(ref cs) => t!(ColorSpace::from_primitive(cs.clone(), resolve)),
+            None => bail!("image without a color space")
+        };
+        let n = t!(components(&color_space));
+        let raw = t!(samples(self, n));
+        let (color_space, data) = color_values(self, color_space, n, &raw);
+        Ok(ImageSamples { width: self.width.max(0) as u32, height: self.height.max(0) as u32, color_space, data })
+    }
+
+    /// Which pixels an image mask paints, `None` if the image isn't one (`ImageMask`)
+    pub fn stencil_mask(&self) -> Result<Option<StencilMask>> {
+        if !self.image_mask {
+            return Ok(None);
+        }
+        Ok(Some(StencilMask {
+            width: self.width.max(0) as u32,
+            height: self.height.max(0) as u32,
+            painted: t!(stencil(self)),
+        }))
+    }
+
+    /// The alpha of each pixel from the soft mask (`SMask`) or the stencil mask (`Mask`),
+    /// `None` if the image has neither or is an image mask, see `stencil_mask`.
+    ///
+    /// Masks with another size are resampled to the size of the image, soft masks smoothly.
+    /// JPEG 2000 images with their own alpha channel (`SMaskInData`) aren't supported.
+    pub fn alpha(&self, resolve: &impl Resolve) -> Result<Option<Vec<u8>>> {
+        if self.image_mask {
+            return Ok(None);
+        }
+        let size = (self.width.max(0) as usize, self.height.max(0) as usize);
+        let size_of = |mask: &ImageXObject| (mask.width.max(0) as usize, mask.height.max(0) as usize);
+        // soft masks take precedence over masks
+        let alpha = match (self.smask, &self.mask) {
+            (Some(smask), _) => {
+                let smask = t!(resolve.get(smask));
+                resample(&t!(gray(&smask)), size_of(&smask), size, true)
+            }
+            (None, &Some(Primitive::Reference(r))) => {
+                let mask = t!(resolve.get(Ref::<ImageXObject>::new(r)));
+                let painted: Vec<f32> = t!(stencil(&mask)).into_iter().map(|p| if p { 1. } else { 0. }).collect();
+                resample(&painted, size_of(&mask), size, false)
+            }
+            _ => return Ok(None)
+        };
+        Ok(Some(alpha.into_iter().map(byte).collect()))
+    }
+}
+
+/// `values` of an image of `from` (width, height) pixels, for an image of `to` pixels covering
+/// the same area. `smooth` interpolates between the centers of the pixels, otherwise the nearest is taken.
+fn resample(values: &[f32], from: (usize, usize), to: (usize, usize), smooth: bool) -> Vec<f32> {
+    let (w, h) = from;
+    let value = |x: usize, y: usize| values.get(y.min(h.saturating_sub(1)) * w + x.min(w.saturating_sub(1))).copied().unwrap_or(1.);
+    if from == to {
+        return (0 .. w * h).map(|i| value(i % w, i / w)).collect();
+    }
+    let mut out = Vec::with_capacity(to.0 * to.1);
+    for y in 0 .. to.1 {
+        // position of the center of the pixel in the mask
+        let my = (y as f32 + 0.5) * h as f32 / to.1 as f32 - 0.5;
+        for x in 0 .. to.0 {
+            let mx = (x as f32 + 0.5) * w as f32 / to.0 as f32 - 0.5;
+            if !smooth {
+                out.push(value((mx + 0.5).max(0.) as usize, (my + 0.5).max(0.) as usize));
+                continue;
+            }
+            let (x0, y0) = (mx.floor().max(0.), my.floor().max(0.));
+            let (fx, fy) = ((mx - x0).clamp(0., 1.), (my - y0).clamp(0., 1.));
+            let (x0, y0) = (x0 as usize, y0 as usize);
+            let top = value(x0, y0) * (1. - fx) + value(x0 + 1, y0) * fx;
+            let bottom = value(x0, y0 + 1) * (1. - fx) + value(x0 + 1, y0 + 1) * fx;
+            out.push(top * (1. - fy) + bottom * fy);
+        }
+    }
+    out
+}
+
+fn byte(v: f32) -> u8 {
+    (v.clamp(0., 1.) * 255. + 0.5) as u8
+}
+
+/// Bits per sample of the decoded data. The DCT and CCITT decoders give bytes.
+fn bits_per_component(image: &ImageXObject) -> u32 {
+    match image.info.filters.last() {
+        Some(StreamFilter::DCTDecode(_)) | Some(StreamFilter::CCITTFaxDecode(_)) => 8,
+        _ if image.image_mask => 1,
+        _ => image.bits_per_component.max(0) as u32
+    }
+}
+
+/// The largest image `samples` accepts, in pixels
+const MAX_IMAGE_PIXELS: usize = 1 << 30;
+
+/// The unpacked samples, `n` per pixel
+fn samples(image: &ImageXObject, n: usize) -> Result<Vec<u16>> {
+    if image.info.filters.iter().any(|f| matches!(f, StreamFilter::JPXDecode)) {
+        bail!("JPEG 2000 images are not supported");
+    }
+    let bpc = bits_per_component(image) as usize;
+    if !matches!(bpc, 1 | 2 | 4 | 8 | 16) {
+        bail!("invalid BitsPerComponent {}", bpc);
+    }
+    let (width, height) = (image.width.max(0) as usize, image.height.max(0) as usize);
+    let data = t!(image.data());
+    // rows start at a byte boundary
+    let size = width.checked_mul(height).filter(|&pixels| pixels <= MAX_IMAGE_PIXELS).and_then(|pixels| {
+        let stride = width.checked_mul(n)?.checked_mul(bpc)?.div_ceil(8);
+        Some((stride, stride.checked_mul(height)?, pixels.checked_mul(n)?))
+    });
+    let (stride, len, count) = match size {
+        Some(size) => size,
+        None => bail!("image of {}x{} pixels is too large", width, height)
+    };
+    // missing data is filled in below, but not more than there is, so a few bytes can't make
+    // a huge image
+    if data.len() < len - len / 2 {
+        bail!("image data is too short ({} bytes instead of {})", data.len(), len);
+    }
+    if data.len() < len {
+        warn!("image data is too short ({} bytes instead of {}), the rest is black", data.len(), len);
+    }
+    let mask = ((1u32 << bpc) - 1) as u16;
+    let mut samples = Vec::with_capacity(count);
+    for y in 0 .. height {
+        let row = data.get(y * stride ..).unwrap_or(&[]);
+        let byte = |i: usize| row.get(i).copied().unwrap_or(0);
+        for i in 0 .. width * n {
+            samples.push(match bpc {
+                8 => byte(i) as u16,
+                16 => (byte(2 * i) as u16) << 8 | byte(2 * i + 1) as u16,
+                _ => {
+                    let bit = i * bpc;
+                    (byte(bit / 8) >> (8 - bpc - bit % 8)) as u16 & mask
+                }
+            });
+        }
+    }
+    Ok(samples)
+}
+
+/// The decoded samples of `image` (`n` per pixel) in `color_space`, with `Indexed` colors
+/// looked up in the palette: the color space they are in and the components
+fn color_values(image: &ImageXObject, color_space: ColorSpace, n: usize, raw: &[u16]) -> (ColorSpace, Vec<f32>) {
+    let bpc = bits_per_component(image);
+    let decode = decode_array(image, &color_space, n, bpc);
+    let max = ((1u32 << bpc) - 1) as f32;
+    let values = raw.chunks_exact(n).flat_map(|pixel| pixel.iter().enumerate()
+        .map(|(i, &s)| decode[2 * i] + s as f32 * (decode[2 * i + 1] - decode[2 * i]) / max)
+        .collect::<Vec<_>>()
+    );
+    match color_space {
+        ColorSpace::Indexed(base, lookup) => match components(&base) {
+            Ok(base_n) => {
+                let colors = values.flat_map(|index| palette_entry(&lookup, base_n, index)).collect();
+                (*base, colors)
+            }
+            // not convertible anyway
+            Err(_) => (ColorSpace::Indexed(base, lookup), values.collect())
+        },
+        cs => (cs, values.collect())
+    }
+}
+
+/// Entry `index` of the palette `lookup` of an `Indexed` color space with `n` base components.
+/// Indices are clamped to the palette, all components are 0 without one.
+fn palette_entry(lookup: &[u8], n: usize, index: f32) -> Vec<f32> {
+    let hival = match (lookup.len() / n).checked_sub(1) {
+        Some(hival) => hival,
+        None => return vec![0.; n]
+    };
+    let start = (index.round().max(0.) as usize).min(hival) * n;
+    lookup[start .. start + n].iter().map(|&b| b as f32 / 255.).collect()
+}
+
+/// The `Decode` array, or the default for the color space
+fn decode_array(image: &ImageXObject, color_space: &ColorSpace, n: usize, bpc: u32) -> Vec<f32> {
+    match image.decode {
+        Some(ref decode) if decode.len() >= 2 * n => decode.clone(),
+        _ => match *color_space {
+            ColorSpace::Indexed(..) => vec![0., ((1u32 << bpc) - 1) as f32],
+            _ => [0., 1.].repeat(n)
+        }
+    }
+}
+
+/// Whether each pixel of an image mask (or a stencil `Mask`) is painted
+fn stencil(image: &ImageXObject) -> Result<Vec<bool>> {
+    let samples = t!(samples(image, 1));
+    let invert = matches!(image.decode.as_deref(), Some(&[a, b]) if a > b);
+    let max = (1u32 << bits_per_component(image)) - 1;
+    // painted where the sample is 0, with the default Decode
+    Ok(samples.into_iter().map(|s| ((s as u32) < (max + 1).div_ceil(2)) != invert).collect())
+}
+
+/// The gray values of a soft mask, from 0 to 1
+fn gray(image: &ImageXObject) -> Result<Vec<f32>> {
+    let samples = t!(samples(image, 1));
+    let max = ((1u32 << bits_per_component(image)) - 1) as f32;
+    let (d0, d1) = match image.decode.as_deref() {
+        Some(&[d0, d1]) => (d0, d1),
+        _ => (0., 1.)
+    };
+    Ok(samples.into_iter().map(|s| d0 + s as f32 * (d1 - d0) / max).collect())
+}
+
+/// Number of components of the colors of `cs`, or an error if they can't be converted to RGB
+fn components(cs: &ColorSpace) -> Result<usize> {
+    Ok(match *cs {
+        ColorSpace::DeviceGray | ColorSpace::Indexed(..) | ColorSpace::Separation(..) => 1,
+        ColorSpace::DeviceRGB => 3,
+        ColorSpace::DeviceCMYK => 4,
+        ColorSpace::Icc(ref icc) => match icc.info.info.components {
+            n @ 1 | n @ 3 | n @ 4 => n as usize,
+            n => bail!("ICC based color space with {} components", n)
+        },
+        ColorSpace::Other(ref parts) => match parts.first().and_then(|p| p.as_name().ok()) {
+            Some("CalGray") => 1,
+            Some("CalRGB") => 3,
+            Some(name) => bail!("unsupported color space {}", name),
+            None => bail!("invalid color space {:?}", parts)
+        }
+    })
+}
+
+/// `c`, a color of `cs`, as RGB
+pub(crate) fn rgb(cs: &ColorSpace, c: &[f32], cm: &ColorManagement) -> Option<[f32; 3]> {
+    if matches!(*cs, ColorSpace::DeviceGray | ColorSpace::DeviceRGB | ColorSpace::DeviceCMYK) {
+        if let Some(rgb) = cm.device_rgb(c) {
+            return Some(rgb);
+        }
+    }
+    match (cs, c) {
+        (ColorSpace::DeviceGray, &[g]) => Some([g, g, g]),
+        (ColorSpace::DeviceRGB, &[r, g, b]) => Some([r, g, b]),
+        (ColorSpace::DeviceCMYK, &[cyan, magenta, yellow, key]) => {
+            let rgb = Cmyk { cyan, magenta, yellow, key }.to_rgb();
+            Some([rgb.red, rgb.green, rgb.blue])
+        }
+        (ColorSpace::Icc(ref icc), c) => cm.icc_rgb(icc, c).or_else(|| device_rgb(c, cm)),
+        (ColorSpace::Other(_), c) => device_rgb(c, cm),
+        (ColorSpace::Indexed(ref base, ref lookup), &[index]) =>
+            rgb(base, &palette_entry(lookup, components(base).ok()?, index), cm),
+        (ColorSpace::Separation(_, ref alternate, ref tint), &[t]) => {
+            let mut values = vec![0.; components(alternate).ok()?];
+            tint.apply(&[t], &mut values).ok()?;
+            rgb(alternate, &values, cm)
+        }
+        _ => None
+    }
+}
+
+/// `c` as the device color with the same number of components
+fn device_rgb(c: &[f32], cm: &ColorManagement) -> Option<[f32; 3]> {
+    let cs = match c.len() {
+        1 => ColorSpace::DeviceGray,
+        3 => ColorSpace::DeviceRGB,
+        4 => ColorSpace::DeviceCMYK,
+        _ => return None
+    };
+    rgb(&cs, c, cm)
+}
@@ -0,0 +1,127 @@
+//! Placing pages on other pages: overlays, N-up and booklets.
+//!
+//! Source pages are copied as Form XObjects with `File::import_pages_as_forms` and drawn
+//! with `build::stamp` or as cells of new sheets. Each form keeps its own resources,
+//! so names from different pages don't clash.
+use crate::backend::Backend;
+use crate::build::{stamp, DocumentBuilder, PageBuilder};
+use crate::content::{Content, Matrix, Op};
+use crate::error::*;
+use crate::file::File;
+use crate::object::*;
+
+/// A page copied as a Form XObject, see `File::import_pages_as_forms`
+#[derive(Debug, Clone, Copy)]
+pub struct PageForm {
+    pub xobject: Ref<XObject>,
+    /// Size of the page as displayed
+    pub width: f32,
+    pub height: f32,
+}
+impl PageForm {
+    /// The transformation that scales the page to fit into `cell`, keeping its aspect ratio,
+    /// and centers it.
+    pub fn fit(&self, cell: Rect) -> Matrix {
+        let (w, h) = (cell.right - cell.left, cell.top - cell.bottom);
+        let scale = (w / self.width).min(h / self.height);
+        Matrix {
+            a: scale,
+            d: scale,
+            e: cell.left + (w - self.width * scale) / 2.,
+            f: cell.bottom + (h - self.height * scale) / 2.,
+            ..Matrix::default()
+        }
+    }
+}
+
+/// `cols` × `rows` cells covering `area`, row by row from the top left
+pub fn grid(area: Rect, cols: u32, rows: u32) -> Vec<Rect> {
+    let w = (area.right - area.left) / cols as f32;
+    let h = (area.top - area.bottom) / rows as f32;
+    (0 .. rows).flat_map(|row| (0 .. cols).map(move |col| Rect {
+        left: area.left + col as f32 * w,
+        bottom: area.top - (row + 1) as f32 * h,
+        right: area.left + (col + 1) as f32 * w,
+        top: area.top - row as f32 * h,
+    })).collect()
+}
+
+/// The pages on each side of the sheets of a booklet with `pages` pages, as `[left, right]`.
+///
+/// Printed on both sides, stacked and folded in the middle, the sheets read in order.
+/// `None` is a blank page, added so the number of pages is a multiple of 4.
+pub fn booklet_order(pages: u32) -> Vec<[Option<u32>; 2]> {
+    let n = pages.div_ceil(4) * 4;
+    let page = |i: u32| Some(i).filter(|&i| i < pages);
+    (0 .. n / 4).flat_map(|sheet| [
+        [page(n - 1 - 2 * sheet), page(2 * sheet)],
+        [page(2 * sheet + 1), page(n - 2 - 2 * sheet)],
+    ]).collect()
+}
+
+/// Draw page `n` of `other` on top of page `target` of `file`, transformed by `transform`.
+///
+/// The page is drawn with its lower left corner at the origin of `target`.
+pub fn overlay<B: Backend, C: Backend>(file: &mut File<B>, target: u32, other: &File<C>, n: u32, transform: Matrix) -> Result<()> {
+    let form = match file.import_pages_as_forms(other, n ..= n)?.pop() {
+        Some(form) => form,
+        None => bail!("page {} out of range, the document has {} pages", n, other.num_pages())
+    };
+    let page = t!(file.get_page(target));
+    let mut page_copy = (*page).clone();
+    stamp(&mut page_copy, form.xobject, transform)?;
+    file.update(page.get_ref().get_inner(), PagesNode::Leaf(page_copy))?;
+    Ok(())
+}
+
+/// A new document with the pages of `source` placed on sheets the size of `sheet`,
+/// `cols` × `rows` pages per sheet in reading order, each scaled to fit its cell.
+pub fn n_up<B: Backend>(source: &File<B>, cols: u32, rows: u32, sheet: Rect) -> Result<File<Vec<u8>>> {
+    if cols == 0 || rows == 0 {
+        bail!("can't place pages on a {}×{} grid", cols, rows);
+    }
+    let pages: Vec<_> = (0 .. source.num_pages()).map(Some).collect();
+    let sheets: Vec<_> = pages.chunks((cols * rows) as usize).map(|c| c.to_vec()).collect();
+    impose(source, &sheets, &grid(sheet, cols, rows), sheet)
+}
+
+/// A new document with the pages of `source` arranged as a booklet, two pages side by side
+/// per sheet side, see `booklet_order`. `sheet` is the size of a sheet, usually landscape.
+pub fn booklet<B: Backend>(source: &File<B>, sheet: Rect) -> Result<File<Vec<u8>>> {
+    let sheets: Vec<_> = booklet_order(source.num_pages()).iter().map(|s| s.to_vec()).collect();
+    impose(source, &sheets, &grid(sheet, 2, 1), sheet)
+}
+
+/// A new document with one page per entry of `sheets`, with the listed pages of `source`
+/// in the corresponding `cells`
+pub fn impose<B: Backend>(source: &File<B>, sheets: &[Vec<Option<u32>>], cells: &[Rect], sheet: Rect) -> Result<File<Vec<u8>>> {
+    let mut file = t!(File::from_data(DocumentBuilder::new().to_bytes()?));
+    let forms = file.import_pages_as_forms(source, ..)?;
+    for pages in sheets {
+        let mut resources = Resources::default();
+        let mut ops = vec![];
+        for (&n, &cell) in pages.iter().zip(cells) {
+            let n = match n {
+                Some(n) => n,
+                None => continue
+            };
+            let form = match forms.get(n as usize) {
+                Some(form) => form,
+                None => bail!("page {} out of range, the document has {} pages", n, forms.len())
+            };
+            let name = format!("Page{}", n + 1);
+            resources.xobjects.insert(name.clone(), form.xobject);
+            ops.extend([
+                Op::Save,
+                Op::Transform { matrix: form.fit(cell) },
+                Op::XObject { name },
+                Op::Restore,
+            ]);
+        }
+        let mut page = PageBuilder::from_content(Content::from_ops(ops));
+        page.media_box(sheet);
+        page.resources(resources);
+        file.add_page(page)?;
+    }
+    Ok(file)
+}
@@ -0,0 +1,143 @@
+//! Finding the JavaScript of a document.
+//!
+//! Scripts can be in the `JavaScript` name tree, where they run when the document is opened,
+//! and in any action: the `OpenAction`, the actions performed on events (`AA`) of the document,
+//! pages, annotations and form fields, the actions of annotations and outline items, and the
+//! actions performed after them (`Next`). `scripts` collects them all with where they are.
+use crate::backend::Backend;
+use crate::error::*;
+use crate::file::File;
+use crate::form;
+use crate::object::*;
+use crate::outline;
+use crate::primitive::{Dictionary, Primitive};
+
+/// Where a script is, see `scripts`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptLocation {
+    /// The `JavaScript` name tree, with the name of the script
+    Names(String),
+    /// The action performed when the document is opened
+    OpenAction,
+    /// An event of the document (`AA` in the catalog), like `WC` (will close)
+    Document { event: String },
+    /// An event of the page, `O` (opened) or `C` (closed). Pages count from 0.
+    Page { page: u32, event: String },
+    /// The annotation at `index` in the `Annots` of the page. `event` is `None`
+    /// for the action of the annotation (`A`).
+    Annotation { page: u32, index: usize, event: Option<String> },
+    /// An event of a field that isn't merged with its widget, like `K` (keystroke)
+    Field { name: String, event: String },
+    /// The action of the outline item with `title`
+    Outline { title: String },
+}
+
+/// A script with its location
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub location: ScriptLocation,
+    pub source: String,
+}
+
+/// All scripts of `file`, in the order of `ScriptLocation`.
+///
+/// Actions that can't be read are skipped with a warning, so one broken action doesn't
+/// hide the other scripts.
+pub fn scripts<B: Backend>(file: &File<B>) -> Result<Vec<Script>> {
+    let catalog = file.get_root();
+    let mut scripts = vec![];
+    for (name, source) in t!(catalog.scripts(file)) {
+        let name = match name.as_str() {
+            Ok(s) => s.into_owned(),
+            Err(_) => String::from_utf8_lossy(name.as_bytes()).into_owned()
+        };
+        scripts.push(Script { location: ScriptLocation::Names(name), source });
+    }
+    match catalog.open_action(file) {
+        Ok(actions) => push(&mut scripts, ScriptLocation::OpenAction, actions.unwrap_or_default()),
+        Err(e) => warn!("ignoring the OpenAction: {}", e)
+    }
+
+    let catalog_dict = t!(file.resolve(file.trailer.root.get_ref().get_inner())?.into_dictionary(file));
+    for (event, actions) in events(file, &catalog_dict, "the document") {
+        push(&mut scripts, ScriptLocation::Document { event }, actions);
+    }
+
+    for (page_nr, page) in file.pages().enumerate() {
+        let page = page?;
+        let page_nr = page_nr as u32;
+        let page_dict = t!(file.resolve(page.get_ref().get_inner())?.into_dictionary(file));
+        for (event, actions) in events(file, &page_dict, "a page") {
+            push(&mut scripts, ScriptLocation::Page { page: page_nr, event }, actions);
+        }
+        for (index, annot) in t!(page.annots(file)).iter().enumerate() {
+            let action = match *annot {
+                Annot::Link(_, ref link) => link.action.clone(),
+                Annot::Widget(_, ref widget) => widget.action.clone(),
+                _ => None
+            };
+            let action = action.map(Primitive::Dictionary)
+                .or_else(|| annot.dict().other().get("A").cloned());
+            if let Some(action) = action {
+                match Action::chain(action, file) {
+                    Ok(actions) => push(&mut scripts, ScriptLocation::Annotation { page: page_nr, index, event: None }, actions),
+                    Err(e) => warn!("ignoring the action of an annotation: {}", e)
+                }
+            }
+            for (event, actions) in events(file, annot.dict().other(), "an annotation") {
+                push(&mut scripts, ScriptLocation::Annotation { page: page_nr, index, event: Some(event) }, actions);
+            }
+        }
+    }
+
+    for field in t!(form::fields(file)).iter().flat_map(|f| f.descendants()) {
+        // the actions of merged fields are those of the annotation
+        if field.widgets.contains(&field.r) {
+            continue;
+        }
+        let dict = t!(file.resolve(field.r)?.into_dictionary(file));
+        for (event, actions) in events(file, &dict, "a form field") {
+            push(&mut scripts, ScriptLocation::Field { name: field.name.clone(), event }, actions);
+        }
+    }
+
+    for (_, item) in outline::iter(&t!(file.outline())) {
+        if let Some(ref action) = item.action {
+            match Action::chain(Primitive::Dictionary(action.clone()), file) {
+                Ok(actions) => push(&mut scripts, ScriptLocation::Outline { title: item.title.clone() }, actions),
+                Err(e) => warn!("ignoring the action of an outline item: {}", e)
+            }
+        }
+    }
+    Ok(scripts)
+}
+
+/// The actions performed on events (`AA`) of `dict`, without the events that can't be read
+fn events(resolve: &impl Resolve, dict: &Dictionary, what: &str) -> Vec<(String, Vec<Action>)> {
+    let aa = match dict.get("AA") {
+        Some(aa) => match aa.clone().into_dictionary(resolve) {
+            Ok(aa) => aa,
+            Err(e) => {
+                warn!("ignoring the additional actions of {}: {}", what, e);
+                return vec![];
+            }
+        },
+        None => return vec![]
+    };
+    let mut events = vec![];
+    for (event, p) in aa {
+        match Action::chain(p, resolve) {
+            Ok(actions) => events.push((event, actions)),
+            Err(e) => warn!("ignoring the {} action of {}: {}", event, what, e)
+        }
+    }
+    events
+}
+
+fn push(scripts: &mut Vec<Script>, location: ScriptLocation, actions: Vec<Action>) {
+    for action in actions {
+        if let Action::JavaScript(source) = action {
+            scripts.push(Script { location: location.clone(), source });
+        }
+    }
+}
@@ -17,10 +17,26 @@ pub mod parser;
 pub mod font;
 pub mod any;
 pub mod encoding;
+pub mod cmap;
+pub mod standard_fonts;
 pub mod build;
+pub mod text;
+pub mod graphics;
+pub mod image;
+pub mod xmp;
+pub mod icc;
+pub mod form;
+pub mod sign;
+pub mod impose;
+pub mod outline;
+pub mod thread;
+pub mod structure;
+pub mod portfolio;
+pub mod javascript;
+pub mod redact;
 
 // mod content;
-mod enc;
+pub mod enc;
 pub mod crypt;
 
 // pub use content::*;
@@ -0,0 +1,265 @@
+//! Actions (12.6)
+//!
+//! An action dictionary can name further actions to perform after it (`Next`).
+//! `Action::chain` reads them all in the order they are performed.
+
+use std::collections::HashSet;
+
+use crate::error::*;
+use crate::object::*;
+use crate::primitive::*;
+
+/// Flags of `SubmitForm` and `ResetForm` actions
+pub const ACTION_EXCLUDE: u32 = 1 << 0;
+
+/// An action, without the ones performed after it, see `Action::chain`
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Go to a destination in the document
+    GoTo { dest: MaybeNamedDest },
+    /// Go to a destination in another document
+    GoToR {
+        /// The other document (`F`)
+        file: Option<FileSpec>,
+        /// A name, string or destination array, with a page number instead of a page
+        dest: Option<Primitive>,
+        new_window: Option<bool>,
+    },
+    /// Open a URI. `is_map` asks for the mouse position to be added as a query.
+    Uri { uri: PdfString, is_map: bool },
+    /// Open or print a file
+    Launch { file: Option<FileSpec>, new_window: Option<bool> },
+    /// A predefined action of the viewer: `NextPage`, `PrevPage`, `FirstPage` or `LastPage`
+    Named(String),
+    /// Run the script
+    JavaScript(String),
+    /// Send the values of form fields to `url`.
+    /// `fields` are references or names of fields; all fields if empty, see `ACTION_EXCLUDE`.
+    SubmitForm { url: Option<Primitive>, fields: Vec<Primitive>, flags: u32 },
+    /// Reset form fields to their default values
+    ResetForm { fields: Vec<Primitive>, flags: u32 },
+    /// Hide or show annotations, given by reference or field name
+    Hide { targets: Vec<Primitive>, hide: bool },
+    /// Any other type of action (`S`), with the action dictionary
+    Other { subtype: String, dict: Dictionary },
+}
+
+impl Action {
+    /// The type of the action (`S`)
+    pub fn subtype(&self) -> &str {
+        match *self {
+            Action::GoTo { .. } => "GoTo",
+            Action::GoToR { .. } => "GoToR",
+            Action::Uri { .. } => "URI",
+            Action::Launch { .. } => "Launch",
+            Action::Named(_) => "Named",
+            Action::JavaScript(_) => "JavaScript",
+            Action::SubmitForm { .. } => "SubmitForm",
+            Action::ResetForm { .. } => "ResetForm",
+            Action::Hide { .. } => "Hide",
+            Action::Other { ref subtype, .. } => subtype,
+        }
+    }
+
+    /// The action `p` and the actions performed after it (`Next`), in order.
+    ///
+    /// Actions that were already read are skipped, so cycles of `Next` entries end.
+    pub fn chain(p: Primitive, resolve: &impl Resolve) -> Result<Vec<Action>> {
+        let mut actions = vec![];
+        chain(p, resolve, &mut HashSet::new(), &mut actions, 0)?;
+        Ok(actions)
+    }
+}
+
+fn chain(p: Primitive, resolve: &impl Resolve, visited: &mut HashSet<PlainRef>, actions: &mut Vec<Action>, depth: usize) -> Result<()> {
+    if depth > 32 {
+        bail!("actions are nested too deeply");
+    }
+    let p = match p {
+        Primitive::Reference(r) => {
+            if !visited.insert(r) {
+                warn!("action {:?} is linked more than once", r);
+                return Ok(());
+            }
+            resolve.resolve(r)?
+        }
+        p => p
+    };
+    let next = match p {
+        Primitive::Dictionary(ref dict) => dict.get("Next").cloned(),
+        _ => None
+    };
+    actions.push(t!(Action::from_primitive(p, resolve)));
+    match next {
+        Some(Primitive::Array(next)) => {
+            for p in next {
+                chain(p, resolve, visited, actions, depth + 1)?;
+            }
+        }
+        Some(p) => chain(p, resolve, visited, actions, depth + 1)?,
+        None => {}
+    }
+    Ok(())
+}
+
+fn resolved(p: Primitive, resolve: &impl Resolve) -> Result<Primitive> {
+    match p {
+        Primitive::Reference(r) => resolve.resolve(r),
+        p => Ok(p)
+    }
+}
+
+fn file_spec(p: Option<Primitive>, resolve: &impl Resolve) -> Result<Option<FileSpec>> {
+    match p {
+        Some(p) => Ok(Some(t!(FileSpec::from_primitive(p, resolve)))),
+        None => Ok(None)
+    }
+}
+
+fn list(p: Option<Primitive>, resolve: &impl Resolve) -> Result<Vec<Primitive>> {
+    match p {
+        Some(Primitive::Array(items)) => Ok(items),
+        Some(Primitive::Reference(r)) => match resolve.resolve(r)? {
+            Primitive::Array(items) => Ok(items),
+            _ => Ok(vec![Primitive::Reference(r)])
+        },
+        Some(p) => Ok(vec![p]),
+        None => Ok(vec![])
+    }
+}
+
+impl Object for Action {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let mut dict = t!(p.into_dictionary(resolve));
+        let subtype = t!(dict.require("Action", "S")?.into_name());
+        let flag = |p: Option<Primitive>| -> Result<Option<bool>> {
+            match p {
+                Some(p) => Ok(Some(p.as_bool()?)),
+                None => Ok(None)
+            }
+        };
+        Ok(match subtype.as_str() {
+            "GoTo" => Action::GoTo {
+                dest: t!(MaybeNamedDest::from_primitive(dict.require("GoTo", "D")?, resolve)),
+            },
+            "GoToR" => Action::GoToR {
+                file: file_spec(dict.remove("F"), resolve)?,
+                dest: dict.remove("D"),
+                new_window: flag(dict.remove("NewWindow"))?,
+            },
+            "URI" => Action::Uri {
+                uri: t!(resolved(dict.require("URI", "URI")?, resolve)?.into_string()),
+                is_map: flag(dict.remove("IsMap"))?.unwrap_or(false),
+            },
+            "Launch" => Action::Launch {
+                file: file_spec(dict.remove("F"), resolve)?,
+                new_window: flag(dict.remove("NewWindow"))?,
+            },
+            "Named" => Action::Named(t!(dict.require("Named", "N")?.into_name())),
+            "JavaScript" => {
+                let js = match resolved(dict.require("JavaScript", "JS")?, resolve)? {
+                    Primitive::String(s) => s.into_bytes(),
+                    Primitive::Stream(s) => t!(Stream::<()>::from_stream(s, resolve)?.data()).to_vec(),
+                    p => err!(PdfError::UnexpectedPrimitive { expected: "String | Stream", found: p.get_debug_name() })
+                };
+                let js = PdfString::new(js);
+                Action::JavaScript(match js.as_str() {
+                    Ok(s) => s.into_owned(),
+                    Err(_) => String::from_utf8_lossy(js.as_bytes()).into_owned()
+                })
+            }
+            "SubmitForm" => Action::SubmitForm {
+                url: dict.remove("F"),
+                fields: list(dict.remove("Fields"), resolve)?,
+                flags: match dict.remove("Flags") {
+                    Some(flags) => flags.as_u32()?,
+                    None => 0
+                },
+            },
+            "ResetForm" => Action::ResetForm {
+                fields: list(dict.remove("Fields"), resolve)?,
+                flags: match dict.remove("Flags") {
+                    Some(flags) => flags.as_u32()?,
+                    None => 0
+                },
+            },
+            "Hide" => Action::Hide {
+                targets: list(dict.remove("T"), resolve)?,
+                hide: flag(dict.remove("H"))?.unwrap_or(true),
+            },
+            _ => Action::Other { subtype, dict },
+        })
+    }
+}
+impl ObjectWrite for Action {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        let mut dict = match *self {
+            Action::Other { ref dict, .. } => dict.clone(),
+            _ => Dictionary::new()
+        };
+        dict.insert("Type", Primitive::name("Action"));
+        dict.insert("S", Primitive::name(self.subtype()));
+        let mut opt = |key: &str, p: &Option<Primitive>| if let Some(ref p) = *p {
+            dict.insert(key, p.clone());
+        };
+        match *self {
+            Action::GoTo { ref dest } => opt("D", &Some(dest.to_primitive(update)?)),
+            Action::GoToR { ref file, ref dest, new_window } => {
+                opt("F", &file.as_ref().map(|f| f.to_primitive(update)).transpose()?);
+                opt("D", dest);
+                opt("NewWindow", &new_window.map(Primitive::Boolean));
+            }
+            Action::Uri { ref uri, is_map } => {
+                opt("URI", &Some(Primitive::String(uri.clone())));
+                opt("IsMap", &Some(is_map).filter(|&m| m).map(Primitive::Boolean));
+            }
+            Action::Launch { ref file, new_window } => {
+                opt("F", &file.as_ref().map(|f| f.to_primitive(update)).transpose()?);
+                opt("NewWindow", &new_window.map(Primitive::Boolean));
+            }
+            Action::Named(ref name) => opt("N", &Some(Primitive::name(name.as_str()))),
+            Action::JavaScript(ref js) => opt("JS", &Some(Primitive::String(crate::build::text_string(js)))),
+            Action::SubmitForm { ref url, ref fields, flags } => {
+                opt("F", url);
+                opt("Fields", &Some(Primitive::Array(fields.clone())).filter(|_| !fields.is_empty()));
+                opt("Flags", &Some(Primitive::Integer(flags as i32)).filter(|_| flags != 0));
+            }
+            Action::ResetForm { ref fields, flags } => {
+                opt("Fields", &Some(Primitive::Array(fields.clone())).filter(|_| !fields.is_empty()));
+                opt("Flags", &Some(Primitive::Integer(flags as i32)).filter(|_| flags != 0));
+            }
+            Action::Hide { ref targets, hide } => {
+                opt("T", &Some(Primitive::Array(targets.clone())));
+                opt("H", &Some(Primitive::Boolean(hide)));
+            }
+            Action::Other { .. } => {}
+        }
+        Ok(Primitive::Dictionary(dict))
+    }
+}
+
+/// Actions performed on events (`AA`), like `E` (the mouse enters an annotation),
+/// `K` (a field is changed) or `O` (a page is opened)
+#[derive(Debug, Clone, Default)]
+pub struct AdditionalActions {
+    /// The event and the chain of actions performed on it, see `Action::chain`
+    pub events: Vec<(String, Vec<Action>)>,
+}
+impl AdditionalActions {
+    /// The actions performed on `event`
+    pub fn get(&self, event: &str) -> &[Action] {
+        match self.events.iter().find(|(e, _)| e == event) {
+            Some((_, actions)) => actions,
+            None => &[]
+        }
+    }
+}
+impl Object for AdditionalActions {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let mut events = vec![];
+        for (event, p) in t!(p.into_dictionary(resolve)) {
+            events.push((event, Action::chain(p, resolve)?));
+        }
+        Ok(AdditionalActions { events })
+    }
+}
@@ -0,0 +1,633 @@
+//! Annotations (12.5)
+//!
+//! `Annot` has a variant for each common subtype. The entries of the subtype are in the
+//! struct of the variant, those shared by all annotations and unknown ones in `AnnotDict`.
+
+use crate as pdf;
+use crate::content::{FormXObject, Matrix, Point};
+use crate::error::*;
+use crate::object::*;
+use crate::primitive::*;
+use chrono::{DateTime, FixedOffset};
+
+/// Annotation flags (`F`, 12.5.3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct AnnotFlags(pub u32);
+impl AnnotFlags {
+    pub const INVISIBLE: AnnotFlags = AnnotFlags(1 << 0);
+    pub const HIDDEN: AnnotFlags = AnnotFlags(1 << 1);
+    pub const PRINT: AnnotFlags = AnnotFlags(1 << 2);
+    pub const NO_ZOOM: AnnotFlags = AnnotFlags(1 << 3);
+    pub const NO_ROTATE: AnnotFlags = AnnotFlags(1 << 4);
+    pub const NO_VIEW: AnnotFlags = AnnotFlags(1 << 5);
+    pub const READ_ONLY: AnnotFlags = AnnotFlags(1 << 6);
+    pub const LOCKED: AnnotFlags = AnnotFlags(1 << 7);
+    pub const TOGGLE_NO_VIEW: AnnotFlags = AnnotFlags(1 << 8);
+    pub const LOCKED_CONTENTS: AnnotFlags = AnnotFlags(1 << 9);
+
+    pub fn contains(self, other: AnnotFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+impl std::ops::BitOr for AnnotFlags {
+    type Output = AnnotFlags;
+    fn bitor(self, rhs: AnnotFlags) -> AnnotFlags {
+        AnnotFlags(self.0 | rhs.0)
+    }
+}
+impl Object for AnnotFlags {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        Ok(AnnotFlags(i32::from_primitive(p, resolve)? as u32))
+    }
+}
+impl ObjectWrite for AnnotFlags {
+    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+        Ok(Primitive::Integer(self.0 as i32))
+    }
+}
+
+/// The appearances of an annotation (`AP`, 12.5.5)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AppearanceKind {
+    /// How it is usually shown (`N`)
+    Normal,
+    /// When the mouse is over it (`R`)
+    Rollover,
+    /// When the mouse button is pressed on it (`D`)
+    Down,
+}
+impl AppearanceKind {
+    fn key(self) -> &'static str {
+        match self {
+            AppearanceKind::Normal => "N",
+            AppearanceKind::Rollover => "R",
+            AppearanceKind::Down => "D",
+        }
+    }
+}
+
+/// Entries shared by all annotations (Table 166), and the ones that are not known
+///
+/// `Type` is not checked, some writers make it a string.
+#[derive(Object, ObjectWrite, Debug, Clone)]
+pub struct AnnotDict {
+    #[pdf(key="Subtype")]
+    pub subtype: String,
+
+    #[pdf(key="Rect")]
+    pub rect: Rect,
+
+    /// Text shown for the annotation, or describing it if it doesn't show text
+    #[pdf(key="Contents")]
+    pub contents: Option<PdfString>,
+
+    /// Unique name of the annotation on its page (`NM`)
+    #[pdf(key="NM")]
+    pub name: Option<PdfString>,
+
+    /// Date of the last change (`M`), not always in the date format
+    #[pdf(key="M")]
+    pub modified: Option<PdfString>,
+
+    #[pdf(key="F", default="AnnotFlags::default()")]
+    pub flags: AnnotFlags,
+
+    /// Appearance streams (`AP`)
+    #[pdf(key="AP")]
+    pub appearance: Option<Dictionary>,
+
+    /// Appearance state (`AS`), selects the appearance if there are several
+    #[pdf(key="AS")]
+    pub appearance_state: Option<String>,
+
+    /// Color (`C`), with 0 (transparent), 1 (gray), 3 (RGB) or 4 (CMYK) components
+    #[pdf(key="C")]
+    pub color: Option<Vec<f32>>,
+
+    /// Author of markup annotations (`T`)
+    #[pdf(key="T")]
+    pub author: Option<PdfString>,
+
+    /// Subject of markup annotations (`Subj`)
+    #[pdf(key="Subj")]
+    pub subject: Option<PdfString>,
+
+    /// Associated files (`AF`)
+    #[pdf(key="AF")]
+    pub associated_files: Option<Vec<FileSpec>>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+impl AnnotDict {
+    pub fn new(subtype: &str, rect: Rect) -> AnnotDict {
+        AnnotDict {
+            subtype: subtype.into(),
+            rect,
+            contents: None,
+            name: None,
+            modified: None,
+            flags: AnnotFlags::default(),
+            appearance: None,
+            appearance_state: None,
+            color: None,
+            author: None,
+            subject: None,
+            associated_files: None,
+            _other: Dictionary::new(),
+        }
+    }
+
+    /// `modified` as a date, if it is one
+    pub fn modified_date(&self) -> Option<DateTime<FixedOffset>> {
+        let modified = self.modified.as_ref()?;
+        DateTime::from_primitive(Primitive::String(modified.clone()), &NoResolve).ok()
+    }
+
+    /// The appearance stream that shows the annotation as `kind`, `None` if there is none.
+    ///
+    /// Appearances with several states, like the `Off` and `Yes` of a check box, are a
+    /// dictionary of streams; the one of the appearance state (`AS`) is returned.
+    /// Rollover and down appearances fall back to the normal one.
+    pub fn appearance_stream(&self, resolve: &impl Resolve, kind: AppearanceKind) -> Result<Option<FormXObject>> {
+        let p = match t!(self.appearance_entry(resolve, kind)) {
+            Some(Primitive::Dictionary(mut states)) => match self.appearance_state {
+                Some(ref state) => match states.remove(state) {
+                    Some(p) => p,
+                    None => return Ok(None)
+                },
+                None => return Ok(None)
+            },
+            Some(p) => p,
+            None => return Ok(None)
+        };
+        Ok(Some(t!(FormXObject::from_primitive(p, resolve))))
+    }
+
+    /// The names of the states of the appearance `kind`, empty if it only has one appearance
+    pub fn appearance_states(&self, resolve: &impl Resolve, kind: AppearanceKind) -> Result<Vec<String>> {
+        match t!(self.appearance_entry(resolve, kind)) {
+            Some(Primitive::Dictionary(states)) => Ok(states.iter().map(|(state, _)| state.clone()).collect()),
+            _ => Ok(vec![])
+        }
+    }
+
+    fn appearance_entry(&self, resolve: &impl Resolve, kind: AppearanceKind) -> Result<Option<Primitive>> {
+        let ap = match self.appearance {
+            Some(ref ap) => ap,
+            None => return Ok(None)
+        };
+        let p = match ap.get(kind.key()).or_else(|| ap.get("N")) {
+            Some(p) => p.clone(),
+            None => return Ok(None)
+        };
+        // a stream stays a reference, `FormXObject` resolves it
+        match p {
+            Primitive::Reference(r) => match t!(resolve.resolve(r)) {
+                Primitive::Dictionary(states) => Ok(Some(Primitive::Dictionary(states))),
+                _ => Ok(Some(p))
+            },
+            p => Ok(Some(p))
+        }
+    }
+
+    /// Maps the form space of the appearance `form`, after its `Matrix`, to the default user
+    /// space of the page: the bounding box of the transformed `BBox` is fitted into `Rect` (12.5.5).
+    pub fn appearance_matrix(&self, form: &FormXObject) -> Matrix {
+        let rect = self.rect;
+        let matrix = form.dict().matrix.unwrap_or_default();
+        let bbox = form.dict().bbox;
+        let corners = [(bbox.left, bbox.bottom), (bbox.right, bbox.bottom), (bbox.left, bbox.top), (bbox.right, bbox.top)]
+            .map(|(x, y)| matrix.transform(Point { x, y }));
+        let left = corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let bottom = corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let right = corners.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+        let top = corners.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+
+        let scale = |to: f32, from: f32| if from != 0. { to / from } else { 1. };
+        let a = scale(rect.right - rect.left, right - left);
+        let d = scale(rect.top - rect.bottom, top - bottom);
+        Matrix { a, b: 0., c: 0., d, e: rect.left - left * a, f: rect.bottom - bottom * d }
+    }
+
+    /// Actions performed on events like the mouse entering the annotation (`AA`)
+    pub fn additional_actions(&self, resolve: &impl Resolve) -> Result<Option<AdditionalActions>> {
+        match self._other.get("AA") {
+            Some(aa) => Ok(Some(t!(AdditionalActions::from_primitive(aa.clone(), resolve)))),
+            None => Ok(None)
+        }
+    }
+
+    /// Entries that are not fields of `AnnotDict` or of the subtype
+    pub fn other(&self) -> &Dictionary {
+        &self._other
+    }
+    pub fn other_mut(&mut self) -> &mut Dictionary {
+        &mut self._other
+    }
+}
+
+/// 12.5.6.5
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct LinkAnnot {
+    /// Destination to jump to, if there is no action
+    #[pdf(key="Dest")]
+    pub dest: Option<Primitive>,
+
+    /// Action (`A`)
+    #[pdf(key="A")]
+    pub action: Option<Dictionary>,
+
+    /// Highlighting mode (`H`): `N`, `I` (the default), `O` or `P`
+    #[pdf(key="H")]
+    pub highlight: Option<String>,
+
+    #[pdf(key="QuadPoints")]
+    pub quad_points: Option<Vec<f32>>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+impl LinkAnnot {
+    /// The action and the ones performed after it, see `Action::chain`
+    pub fn actions(&self, resolve: &impl Resolve) -> Result<Vec<Action>> {
+        match self.action {
+            Some(ref action) => Action::chain(Primitive::Dictionary(action.clone()), resolve),
+            None => Ok(vec![])
+        }
+    }
+    /// Where the link goes: `dest`, or the destination of a `GoTo` action
+    pub fn destination(&self, resolve: &impl Resolve) -> Result<Option<MaybeNamedDest>> {
+        match (&self.dest, &self.action) {
+            (Some(dest), _) => Ok(Some(t!(MaybeNamedDest::from_primitive(dest.clone(), resolve)))),
+            (None, Some(action)) => MaybeNamedDest::from_action(action, resolve),
+            (None, None) => Ok(None)
+        }
+    }
+    /// The target of a URI action
+    pub fn uri(&self) -> Option<&PdfString> {
+        let action = self.action.as_ref()?;
+        match (action.get("S"), action.get("URI")) {
+            (Some(Primitive::Name(ref s)), Some(Primitive::String(ref uri))) if s == "URI" => Some(uri),
+            _ => None
+        }
+    }
+}
+
+/// A link of a page, see `Page::links`
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// The area of the link annotation on the page
+    pub rect: Rect,
+    pub target: LinkTarget,
+}
+
+/// Where a link goes
+#[derive(Debug, Clone)]
+pub enum LinkTarget {
+    /// A page of the document, counting from 0
+    Page { page: u32, view: DestView },
+    Uri(String),
+    /// A destination in another document (`GoToR`), see `Action::GoToR`
+    Remote { file: Option<FileSpec>, dest: Option<Primitive> },
+    /// The first action of any other kind, like `Named` or `JavaScript`
+    Action(Action),
+}
+
+/// A sticky note (12.5.6.4)
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct TextAnnot {
+    /// Whether the popup is open initially
+    #[pdf(key="Open", default="false")]
+    pub open: bool,
+
+    /// Icon (`Name`), like `Comment`, `Note` (the default) or `Help`
+    #[pdf(key="Name")]
+    pub icon: Option<String>,
+
+    /// Review state, like `Accepted`, for annotations in reply to another one
+    #[pdf(key="State")]
+    pub state: Option<String>,
+
+    #[pdf(key="StateModel")]
+    pub state_model: Option<String>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+/// Text shown directly on the page (12.5.6.6)
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct FreeTextAnnot {
+    /// Default appearance string (`DA`), with the font and color of the text
+    #[pdf(key="DA")]
+    pub default_appearance: Option<PdfString>,
+
+    /// Alignment (`Q`): 0 left, 1 centered, 2 right
+    #[pdf(key="Q", default="0")]
+    pub alignment: i32,
+
+    /// Default style string (`DS`)
+    #[pdf(key="DS")]
+    pub default_style: Option<PdfString>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+/// Highlight, Underline, Squiggly and StrikeOut annotations (12.5.6.10)
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct TextMarkupAnnot {
+    /// The marked regions, 8 numbers (4 points) per quadrilateral
+    #[pdf(key="QuadPoints", default="vec![]")]
+    pub quad_points: Vec<f32>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+/// Square and Circle annotations (12.5.6.8)
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct ShapeAnnot {
+    /// Interior color (`IC`)
+    #[pdf(key="IC")]
+    pub interior_color: Option<Vec<f32>>,
+
+    /// Border style (`BS`)
+    #[pdf(key="BS")]
+    pub border_style: Option<Dictionary>,
+
+    /// Differences between `Rect` and the drawn shape (`RD`): left, top, right, bottom
+    #[pdf(key="RD")]
+    pub rect_differences: Option<Vec<f32>>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+/// 12.5.6.12
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct StampAnnot {
+    /// Icon (`Name`), like `Approved` or `Draft` (the default)
+    #[pdf(key="Name")]
+    pub icon: Option<String>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+/// The widget of a form field (12.5.6.19), often merged with the field
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct WidgetAnnot {
+    /// Partial name of the field (`T`), if the widget is merged with it
+    #[pdf(key="T")]
+    pub field_name: Option<PdfString>,
+
+    /// Highlighting mode (`H`)
+    #[pdf(key="H")]
+    pub highlight: Option<String>,
+
+    /// Appearance characteristics (`MK`)
+    #[pdf(key="MK")]
+    pub appearance_characteristics: Option<Dictionary>,
+
+    /// Action (`A`)
+    #[pdf(key="A")]
+    pub action: Option<Dictionary>,
+
+    /// The field, if the widget is not merged with it
+    #[pdf(key="Parent")]
+    pub parent: Option<Primitive>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+impl WidgetAnnot {
+    /// The action performed when the widget is activated and the ones after it, see `Action::chain`
+    pub fn actions(&self, resolve: &impl Resolve) -> Result<Vec<Action>> {
+        match self.action {
+            Some(ref action) => Action::chain(Primitive::Dictionary(action.clone()), resolve),
+            None => Ok(vec![])
+        }
+    }
+}
+
+/// 12.5.6.15
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct FileAttachmentAnnot {
+    /// The attached file (`FS`)
+    #[pdf(key="FS")]
+    pub file: Option<FileSpec>,
+
+    /// Icon (`Name`), like `Paperclip` or `PushPin` (the default)
+    #[pdf(key="Name")]
+    pub icon: Option<String>,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+/// A region marked for redaction (12.5.6.23), see `redact::apply_redactions`
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+pub struct RedactAnnot {
+    /// The regions to remove, 8 numbers (4 points) per quadrilateral. If absent, `Rect` is removed.
+    #[pdf(key="QuadPoints")]
+    pub quad_points: Option<Vec<f32>>,
+
+    /// The color to fill the regions with after removing their content (`IC`)
+    #[pdf(key="IC")]
+    pub interior_color: Option<Vec<f32>>,
+
+    /// Form XObject drawn over the regions after redaction (`RO`)
+    #[pdf(key="RO")]
+    pub overlay: Option<Primitive>,
+
+    /// Text shown in the regions after redaction (`OverlayText`)
+    #[pdf(key="OverlayText")]
+    pub overlay_text: Option<PdfString>,
+
+    /// Whether `overlay_text` is repeated to fill the regions (`Repeat`)
+    #[pdf(key="Repeat", default="false")]
+    pub repeat: bool,
+
+    /// Default appearance string of the overlay text (`DA`)
+    #[pdf(key="DA")]
+    pub default_appearance: Option<PdfString>,
+
+    /// Justification of the overlay text (`Q`): 0 left, 1 centered, 2 right
+    #[pdf(key="Q", default="0")]
+    pub justification: i32,
+
+    #[pdf(other)]
+    _other: Dictionary
+}
+
+impl RedactAnnot {
+    /// The regions to remove: the bounding box of each quadrilateral, or `rect` (the `Rect` of
+    /// the annotation) if there are no `QuadPoints`
+    pub fn areas(&self, rect: Rect) -> Vec<Rect> {
+        let quads = match self.quad_points {
+            Some(ref points) if points.len() >= 8 => points,
+            _ => return vec![Rect {
+                left: rect.left.min(rect.right),
+                bottom: rect.bottom.min(rect.top),
+                right: rect.left.max(rect.right),
+                top: rect.bottom.max(rect.top),
+            }]
+        };
+        quads.chunks_exact(8).map(|q| {
+            let xs = [q[0], q[2], q[4], q[6]];
+            let ys = [q[1], q[3], q[5], q[7]];
+            Rect {
+                left: xs.iter().copied().fold(f32::INFINITY, f32::min),
+                bottom: ys.iter().copied().fold(f32::INFINITY, f32::min),
+                right: xs.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+                top: ys.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            }
+        }).collect()
+    }
+}
+
+/// An annotation of a page, see `Page::annots`
+///
+/// The bigger subtypes are boxed, so a `Vec<Annot>` doesn't take the size of the biggest one for every element.
+#[derive(Debug, Clone)]
+pub enum Annot {
+    Link(AnnotDict, LinkAnnot),
+    Text(AnnotDict, TextAnnot),
+    FreeText(AnnotDict, FreeTextAnnot),
+    Highlight(AnnotDict, TextMarkupAnnot),
+    Underline(AnnotDict, TextMarkupAnnot),
+    Squiggly(AnnotDict, TextMarkupAnnot),
+    StrikeOut(AnnotDict, TextMarkupAnnot),
+    Square(AnnotDict, ShapeAnnot),
+    Circle(AnnotDict, ShapeAnnot),
+    Stamp(AnnotDict, StampAnnot),
+    Widget(AnnotDict, Box<WidgetAnnot>),
+    FileAttachment(AnnotDict, Box<FileAttachmentAnnot>),
+    Redact(AnnotDict, RedactAnnot),
+    /// Any other subtype, `AnnotDict::other` has its entries
+    Other(AnnotDict),
+}
+impl Annot {
+    /// The entries shared by all annotations
+    pub fn dict(&self) -> &AnnotDict {
+        match *self {
+            Annot::Link(ref d, _) | Annot::Text(ref d, _) | Annot::FreeText(ref d, _) |
+            Annot::Highlight(ref d, _) | Annot::Underline(ref d, _) | Annot::Squiggly(ref d, _) | Annot::StrikeOut(ref d, _) |
+            Annot::Square(ref d, _) | Annot::Circle(ref d, _) | Annot::Stamp(ref d, _) |
+            Annot::Widget(ref d, _) | Annot::FileAttachment(ref d, _) | Annot::Redact(ref d, _) |
+            Annot::Other(ref d) => d
+        }
+    }
+    pub fn dict_mut(&mut self) -> &mut AnnotDict {
+        match *self {
+            Annot::Link(ref mut d, _) | Annot::Text(ref mut d, _) | Annot::FreeText(ref mut d, _) |
+            Annot::Highlight(ref mut d, _) | Annot::Underline(ref mut d, _) | Annot::Squiggly(ref mut d, _) | Annot::StrikeOut(ref mut d, _) |
+            Annot::Square(ref mut d, _) | Annot::Circle(ref mut d, _) | Annot::Stamp(ref mut d, _) |
+            Annot::Widget(ref mut d, _) | Annot::FileAttachment(ref mut d, _) | Annot::Redact(ref mut d, _) |
+            Annot::Other(ref mut d) => d
+        }
+    }
+    pub fn subtype(&self) -> &str {
+        &self.dict().subtype
+    }
+    pub fn rect(&self) -> Rect {
+        self.dict().rect
+    }
+    pub fn contents(&self) -> Option<&PdfString> {
+        self.dict().contents.as_ref()
+    }
+    pub fn flags(&self) -> AnnotFlags {
+        self.dict().flags
+    }
+}
+
+/// Parse the entries of the subtype `T` first, the rest of `dict` goes into `AnnotDict`.
+fn split<T: Object>(dict: Dictionary, resolve: &impl Resolve, other: impl FnOnce(&mut T) -> &mut Dictionary) -> Result<(AnnotDict, T)> {
+    let mut specific = t!(T::from_primitive(Primitive::Dictionary(dict), resolve));
+    let rest = std::mem::take(other(&mut specific));
+    let dict = t!(AnnotDict::from_primitive(Primitive::Dictionary(rest), resolve));
+    Ok((dict, specific))
+}
+
+impl Object for Annot {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let dict = t!(p.into_dictionary(resolve));
+        let subtype = match dict.get("Subtype") {
+            Some(Primitive::Name(ref name)) => name.clone(),
+            Some(p) => err!(PdfError::UnexpectedPrimitive { expected: "Name", found: p.get_debug_name() }),
+            None => err!(PdfError::MissingEntry { typ: "Annot", field: "Subtype".into() })
+        };
+        Ok(match subtype.as_str() {
+            "Link" => {
+                let (d, a) = split(dict, resolve, |a: &mut LinkAnnot| &mut a._other)?;
+                Annot::Link(d, a)
+            }
+            "Text" => {
+                let (d, a) = split(dict, resolve, |a: &mut TextAnnot| &mut a._other)?;
+                Annot::Text(d, a)
+            }
+            "FreeText" => {
+                let (d, a) = split(dict, resolve, |a: &mut FreeTextAnnot| &mut a._other)?;
+                Annot::FreeText(d, a)
+            }
+            "Highlight" | "Underline" | "Squiggly" | "StrikeOut" => {
+                let (d, a) = split(dict, resolve, |a: &mut TextMarkupAnnot| &mut a._other)?;
+                match subtype.as_str() {
+                    "Highlight" => Annot::Highlight(d, a),
+                    "Underline" => Annot::Underline(d, a),
+                    "Squiggly" => Annot::Squiggly(d, a),
+                    _ => Annot::StrikeOut(d, a),
+                }
+            }
+            "Square" | "Circle" => {
+                let (d, a) = split(dict, resolve, |a: &mut ShapeAnnot| &mut a._other)?;
+                match subtype.as_str() {
+                    "Square" => Annot::Square(d, a),
+                    _ => Annot::Circle(d, a),
+                }
+            }
+            "Stamp" => {
+                let (d, a) = split(dict, resolve, |a: &mut StampAnnot| &mut a._other)?;
+                Annot::Stamp(d, a)
+            }
+            "Widget" => {
+                let (d, a) = split(dict, resolve, |a: &mut WidgetAnnot| &mut a._other)?;
+                Annot::Widget(d, Box::new(a))
+            }
+            "FileAttachment" => {
+                let (d, a) = split(dict, resolve, |a: &mut FileAttachmentAnnot| &mut a._other)?;
+                Annot::FileAttachment(d, Box::new(a))
+            }
+            "Redact" => {
+                let (d, a) = split(dict, resolve, |a: &mut RedactAnnot| &mut a._other)?;
+                Annot::Redact(d, a)
+            }
+            _ => Annot::Other(t!(AnnotDict::from_primitive(Primitive::Dictionary(dict), resolve)))
+        })
+    }
+}
+impl ObjectWrite for Annot {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        let specific = match *self {
+            Annot::Link(_, ref a) => a.to_dict(update)?,
+            Annot::Text(_, ref a) => a.to_dict(update)?,
+            Annot::FreeText(_, ref a) => a.to_dict(update)?,
+            Annot::Highlight(_, ref a) | Annot::Underline(_, ref a) |
+            Annot::Squiggly(_, ref a) | Annot::StrikeOut(_, ref a) => a.to_dict(update)?,
+            Annot::Square(_, ref a) | Annot::Circle(_, ref a) => a.to_dict(update)?,
+            Annot::Stamp(_, ref a) => a.to_dict(update)?,
+            Annot::Widget(_, ref a) => a.to_dict(update)?,
+            Annot::FileAttachment(_, ref a) => a.to_dict(update)?,
+            Annot::Redact(_, ref a) => a.to_dict(update)?,
+            Annot::Other(_) => Dictionary::new(),
+        };
+        let mut dict = self.dict().to_dict(update)?;
+        for (key, value) in specific {
+            dict.insert(key, value);
+        }
+        Ok(Primitive::Dictionary(dict))
+    }
+}
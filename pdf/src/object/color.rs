@@ -2,7 +2,7 @@ use crate as pdf;
 use crate::object::*;
 use crate::error::*;
 
-#[derive(Object, Debug)]
+#[derive(Object, ObjectWrite, Debug)]
 pub struct IccInfo {
     #[pdf(key="N")]
     pub components: u32,
@@ -17,7 +17,7 @@ pub struct IccInfo {
     pub metadata: Option<Stream<()>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ColorSpace {
     DeviceGray,
     DeviceRGB,
@@ -40,7 +40,7 @@ impl Object for ColorSpace {
                 "DeviceGray" => ColorSpace::DeviceGray,
                 "DeviceRGB" => ColorSpace::DeviceRGB,
                 "DeviceCMYK" => ColorSpace::DeviceCMYK,
-                _ => unimplemented!()
+                _ => ColorSpace::Other(vec![p])
             };
             return Ok(cs);
         }
@@ -82,11 +82,41 @@ impl Object for ColorSpace {
     }
 }
 impl ObjectWrite for ColorSpace {
-    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
         match *self {
+            ColorSpace::DeviceGray => Ok(Primitive::name("DeviceGray")),
             ColorSpace::DeviceCMYK => Ok(Primitive::name("DeviceCMYK")),
             ColorSpace::DeviceRGB => Ok(Primitive::name("DeviceRGB")),
-            _ => unimplemented!()
+            ColorSpace::Indexed(ref base, ref lookup) => {
+                let components = match **base {
+                    ColorSpace::DeviceGray => 1,
+                    ColorSpace::DeviceRGB => 3,
+                    ColorSpace::DeviceCMYK => 4,
+                    ColorSpace::Icc(ref icc) => icc.info.info.components as usize,
+                    ref cs => bail!("unsupported base color space {:?}", cs)
+                };
+                let hival = (lookup.len() / components) as i32 - 1;
+                Ok(Primitive::Array(vec![
+                    Primitive::name("Indexed"),
+                    base.to_primitive(update)?,
+                    Primitive::Integer(hival),
+                    Primitive::String(PdfString::new(lookup.clone())),
+                ]))
+            }
+            ColorSpace::Separation(ref name, ref alternate, ref tint) => Ok(Primitive::Array(vec![
+                Primitive::name("Separation"),
+                Primitive::name(name.as_str()),
+                alternate.to_primitive(update)?,
+                tint.to_primitive(update)?,
+            ])),
+            ColorSpace::Icc(ref icc) => Ok(Primitive::Array(vec![
+                Primitive::name("ICCBased"),
+                icc.to_primitive(update)?,
+            ])),
+            ColorSpace::Other(ref parts) => match parts.as_slice() {
+                [p @ Primitive::Name(_)] => Ok(p.clone()),
+                parts => Ok(Primitive::Array(parts.to_vec())),
+            }
         }
     }
 }
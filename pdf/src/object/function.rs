@@ -29,7 +29,7 @@ struct Function2 {
     exponent: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Function {
     Sampled(SampledFunction),
     Interpolated(Vec<InterpolatedFunctionDim>),
@@ -125,7 +125,34 @@ impl Object for Function {
 }
 
 
-#[derive(Debug)]
+impl ObjectWrite for Function {
+    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+        match *self {
+            Function::Interpolated(ref parts) => {
+                let first = match parts.first() {
+                    Some(first) => first,
+                    None => bail!("interpolated function without outputs")
+                };
+                let floats = |v: Vec<f32>| Primitive::Array(v.into_iter().map(Primitive::Number).collect());
+                let mut dict = Dictionary::new();
+                dict.insert("FunctionType", Primitive::Integer(2));
+                dict.insert("Domain", floats(vec![first.input_range.0, first.input_range.1]));
+                if parts.iter().all(|p| p.output_range.0.is_finite() && p.output_range.1.is_finite()) {
+                    dict.insert("Range", floats(parts.iter().flat_map(|p| vec![p.output_range.0, p.output_range.1]).collect()));
+                }
+                dict.insert("C0", floats(parts.iter().map(|p| p.c0).collect()));
+                dict.insert("C1", floats(parts.iter().map(|p| p.c1).collect()));
+                dict.insert("N", Primitive::Number(first.exponent));
+                Ok(Primitive::Dictionary(dict))
+            }
+            // these don't keep the domain, range and samples
+            _ => bail!("can't write function {:?}", self)
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
 struct SampledFunctionInput {
     domain: (f32, f32),
     encode_offset: f32,
@@ -139,20 +166,20 @@ impl SampledFunctionInput {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct SampledFunctionOutput {
     output_offset: f32,
     output_scale: f32
 
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Interpolation {
     Linear,
     Cubic,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SampledFunction {
     input: Vec<SampledFunctionInput>,
     data: Vec<u8>,
@@ -170,7 +197,7 @@ impl SampledFunction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InterpolatedFunctionDim {
     pub input_range: (f32, f32),
     pub output_range: (f32, f32),
@@ -186,12 +213,12 @@ impl InterpolatedFunctionDim {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PostScriptError {
     StackUnderflow,
     IncorrectStackSize
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PsFunc {
     pub ops: Vec<PsOp>
 }
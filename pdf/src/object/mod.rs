@@ -6,11 +6,15 @@ mod types;
 mod stream;
 mod color;
 mod function;
+mod annot;
+mod action;
 
 pub use self::types::*;
 pub use self::stream::*;
 pub use self::color::*;
 pub use self::function::*;
+pub use self::annot::*;
+pub use self::action::*;
 pub use crate::file::PromisedRef;
 
 use crate::primitive::*;
@@ -48,9 +52,13 @@ pub trait Object: Sized + 'static {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self>;
 }
 
+/// Adds and replaces indirect objects
 pub trait Updater {
+    /// Add `obj` as a new indirect object
     fn create<T: ObjectWrite>(&mut self, obj: T) -> Result<RcRef<T>>;
+    /// Replace the object `old` by `obj`
     fn update<T: ObjectWrite>(&mut self, old: PlainRef, obj: T) -> Result<RcRef<T>>;
+    /// Reserve an object number, to be filled in with `fulfill`
     fn promise<T: Object>(&mut self) -> PromisedRef<T>;
     fn fulfill<T: ObjectWrite>(&mut self, promise: PromisedRef<T>, obj: T) -> Result<RcRef<T>>;
 }
@@ -84,6 +84,22 @@ impl<I: Object + fmt::Debug> Stream<I> {
         }
     }
 
+    /// Encode the data with `filter`, on top of the filters it is already encoded with.
+    ///
+    /// The `Filter` and `DecodeParms` entries are written accordingly.
+    /// ```
+    /// # use pdf::object::Stream;
+    /// # use pdf::enc::StreamFilter;
+    /// let stream = Stream::new((), b"q 1 0 0 1 10 10 cm Q".to_vec())
+    ///     .with_filter(StreamFilter::FlateDecode(Default::default())).unwrap();
+    /// assert_eq!(stream.data().unwrap(), b"q 1 0 0 1 10 10 cm Q");
+    /// ```
+    pub fn with_filter(mut self, filter: StreamFilter) -> Result<Self> {
+        self.raw_data = t!(enc::encode(&self.raw_data, &filter));
+        self.info.filters.insert(0, filter);
+        Ok(self)
+    }
+
     pub fn hexencode(mut self) -> Self {
         self.raw_data = enc::encode_hex(&self.raw_data);
         self.info.filters.push(StreamFilter::ASCIIHexDecode);
@@ -111,44 +127,48 @@ impl<I: ObjectWrite> Stream<I> {
             Primitive::Null => Dictionary::new(),
             p => bail!("stream info has to be a dictionary (found {:?})", p)
         };
-        let mut params = None;
-        if self.info.filters.len() > 0 {
-            for f in self.info.filters.iter() {
-                if let Some(para) = match f {
-                    StreamFilter::LZWDecode(ref p) => Some(p.to_primitive(update)?),
-                    StreamFilter::FlateDecode(ref p) => Some(p.to_primitive(update)?),
-                    StreamFilter::DCTDecode(ref p) => Some(p.to_primitive(update)?),
-                    _ => None
-                } {
-                    if params.is_some() {
-                        panic!();
-                    }
-                    params = Some(para);
-                }
+        let mut params = Vec::with_capacity(self.info.filters.len());
+        for f in self.info.filters.iter() {
+            params.push(match f {
+                StreamFilter::LZWDecode(ref p) | StreamFilter::FlateDecode(ref p) if p.is_default() => Primitive::Null,
+                StreamFilter::LZWDecode(ref p) => p.to_primitive(update)?,
+                StreamFilter::FlateDecode(ref p) => p.to_primitive(update)?,
+                StreamFilter::DCTDecode(ref p) => p.to_primitive(update)?,
+                StreamFilter::CCITTFaxDecode(ref p) => p.to_primitive(update)?,
+                _ => Primitive::Null
+            });
+        }
+        let mut filters = self.info.filters.iter().map(|filter| match filter {
+            StreamFilter::ASCIIHexDecode => "ASCIIHexDecode",
+            StreamFilter::ASCII85Decode => "ASCII85Decode",
+            StreamFilter::LZWDecode(ref _p) => "LZWDecode",
+            StreamFilter::FlateDecode(ref _p) => "FlateDecode",
+            StreamFilter::JPXDecode => "JPXDecode",
+            StreamFilter::DCTDecode(ref _p) => "DCTDecode",
+            StreamFilter::CCITTFaxDecode(ref _p) => "CCITTFaxDecode",
+            StreamFilter::Crypt => "Crypt",
+        })
+        .map(Primitive::name);
+        match self.info.filters.len() {
+            0 => {},
+            1 => {
+                info.insert("Filter", filters.next().unwrap());
             }
-            let mut filters = self.info.filters.iter().map(|filter| match filter {
-                StreamFilter::ASCIIHexDecode => "ASCIIHexDecode",
-                StreamFilter::ASCII85Decode => "ASCII85Decode",
-                StreamFilter::LZWDecode(ref _p) => "LZWDecode",
-                StreamFilter::FlateDecode(ref _p) => "FlateDecode",
-                StreamFilter::JPXDecode => "JPXDecode",
-                StreamFilter::DCTDecode(ref _p) => "DCTDecode",
-                StreamFilter::CCITTFaxDecode(ref _p) => "CCITTFaxDecode",
-                StreamFilter::Crypt => "Crypt",
-            })
-            .map(|s| Primitive::Name(s.into()));
-            match self.info.filters.len() {
-                0 => {},
-                1 => {
-                    info.insert("Filter", filters.next().unwrap().to_primitive(update)?);
-                }
-                _ => {
-                    info.insert("Filter", Primitive::array::<Primitive, _, _, _>(filters, update)?);
-                }
+            _ => {
+                info.insert("Filter", Primitive::Array(filters.collect()));
             }
         }
-        if let Some(para) = params {
-            info.insert("DecodeParms", para);
+        // with several filters, `DecodeParms` is an array with null for the filters without parameters
+        match params.as_slice() {
+            [] => {},
+            [Primitive::Dictionary(_)] => {
+                info.insert("DecodeParms", params.pop().unwrap());
+            }
+            [_] => {},
+            p if p.iter().all(|p| matches!(p, Primitive::Null)) => {},
+            _ => {
+                info.insert("DecodeParms", Primitive::Array(params));
+            }
         }
         info.insert("Length", Primitive::Integer(self.raw_data.len() as _));
 
@@ -288,7 +308,7 @@ impl<T: Object> Object for StreamInfo<T> {
     }
 }
 
-#[derive(Object, Default, Debug)]
+#[derive(Object, ObjectWrite, Default, Debug)]
 #[pdf(Type = "ObjStm")]
 pub struct ObjStmInfo {
     #[pdf(key = "N")]
@@ -5,8 +5,12 @@ use std::collections::HashMap;
 use crate as pdf;
 use crate::object::*;
 use crate::error::*;
-use crate::content::{Content, FormXObject};
+use crate::content::{Content, FormXObject, Matrix, Op};
 use crate::font::Font;
+use crate::backend::Backend;
+use crate::file::File;
+use crate::graphics::{images, Images};
+use chrono::{DateTime, FixedOffset};
 
 /// Node in a page tree - type is either `Page` or `PageTree`
 #[derive(Debug, Clone)]
@@ -19,7 +23,12 @@ impl Object for PagesNode {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<PagesNode> {
         let mut dict = p.into_dictionary(resolve)?;
         match dict.require("PagesNode", "Type")?.as_name()? {
-            "Page" => Ok(PagesNode::Leaf(t!(Page::from_dict(dict, resolve)))),
+            "Page" => {
+                // so `resources`, `media_box`, `crop_box` and `rotate` don't depend on the parents
+                let mut page = t!(Page::from_dict(dict, resolve));
+                t!(page.resolve_inherited());
+                Ok(PagesNode::Leaf(page))
+            }
             "Pages" => Ok(PagesNode::Tree(t!(PageTree::from_dict(dict, resolve)))),
             other => Err(PdfError::WrongDictionaryType {expected: "Page or Pages".into(), found: other.into()}),
         }
@@ -65,6 +74,9 @@ impl PageRc {
     pub fn create(page: Page, update: &mut impl Updater) -> Result<PageRc> {
         Ok(PageRc(update.create(PagesNode::Leaf(page))?))
     }
+    pub fn get_ref(&self) -> Ref<PagesNode> {
+        self.0.get_ref()
+    }
 }
 
 /// A `PagesNode::Tree` wrapped in a `RcRef`
@@ -84,6 +96,9 @@ impl PagesRc {
     pub fn create(tree: PageTree, update: &mut impl Updater) -> Result<PagesRc> {
         Ok(PagesRc(update.create(PagesNode::Tree(tree))?))
     }
+    pub fn get_ref(&self) -> Ref<PagesNode> {
+        self.0.get_ref()
+    }
 }
 impl Object for PagesRc {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<PagesRc> {
@@ -96,7 +111,7 @@ impl Object for PagesRc {
 }
 impl ObjectWrite for PagesRc {
     fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
-        (**self).to_primitive(update)
+        self.0.to_primitive(update)
     }
 }
 
@@ -106,21 +121,33 @@ pub struct Catalog {
     #[pdf(key="Pages")]
     pub pages: PagesRc,
 
-// PageLabels: number_tree,
+    #[pdf(key="PageLabels")]
+    pub page_labels: Option<NumberTree<PageLabel>>,
+
     #[pdf(key="Names")]
     pub names: Option<MaybeRef<NameDictionary>>,
     
     #[pdf(key="Dests")]
     pub dests: Option<MaybeRef<Dictionary>>,
 
-// ViewerPreferences: dict
-// PageLayout: name
-// PageMode: name
+    #[pdf(key="ViewerPreferences")]
+    pub viewer_preferences: Option<ViewerPreferences>,
+
+    #[pdf(key="PageLayout")]
+    pub page_layout: Option<PageLayout>,
+
+    #[pdf(key="PageMode")]
+    pub page_mode: Option<PageMode>,
 
     #[pdf(key="Outlines")]
     pub outlines: Option<Outlines>,
-// Threads: array
-// OpenAction: array or dict
+
+    /// Article threads, see `thread::threads`
+    #[pdf(key="Threads")]
+    pub threads: Option<Vec<Ref<Thread>>>,
+    /// Destination or action to show or perform when the document is opened, see `Catalog::open_action`
+    #[pdf(key="OpenAction")]
+    pub open_action: Option<Primitive>,
 // AA: dict
 // URI: dict
 // AcroForm: dict
@@ -130,19 +157,373 @@ pub struct Catalog {
 
     #[pdf(key="StructTreeRoot")]
     pub struct_tree_root: Option<StructTreeRoot>,
+
+    /// Files associated with the whole document (`AF`), see `FileSpec::relationship`
+    #[pdf(key="AF")]
+    pub associated_files: Option<Vec<FileSpec>>,
 // MarkInfo: dict
 // Lang: text string
 // SpiderInfo: dict
-// OutputIntents: array
+    /// See `Catalog::output_intent`
+    #[pdf(key="OutputIntents")]
+    pub output_intents: Option<Vec<OutputIntent>>,
 // PieceInfo: dict
 // OCProperties: dict
 // Perms: dict
 // Legal: dict
 // Requirements: array
-// Collection: dict
+    /// Makes the document a portfolio of its embedded files, see `portfolio`
+    #[pdf(key="Collection")]
+    pub collection: Option<Collection>,
 // NeedsRendering: bool
 }
 
+impl Catalog {
+    /// The destination called `name`, from the `Dests` name tree or the older `Dests` dictionary
+    pub fn named_dest(&self, resolve: &impl Resolve, name: &[u8]) -> Result<Option<Dest>> {
+        if let Some(dests) = self.names.as_ref().and_then(|names| names.dests.as_ref()) {
+            if let Some(dest) = dests.get(resolve, name)? {
+                return Ok(Some(dest));
+            }
+        }
+        let dest = match (&self.dests, std::str::from_utf8(name)) {
+            (Some(dests), Ok(name)) => dests.get(name),
+            _ => None
+        };
+        match dest {
+            Some(dest) => Ok(Some(t!(Dest::from_primitive(dest.clone(), resolve)))),
+            None => Ok(None)
+        }
+    }
+
+    /// The label of page `page_nr` (counting from 0) as viewers show it,
+    /// or `None` if the document has no labels for it
+    pub fn page_label(&self, resolve: &impl Resolve, page_nr: u32) -> Result<Option<String>> {
+        let labels = match self.page_labels {
+            Some(ref labels) => labels,
+            None => return Ok(None)
+        };
+        // the range starting at the closest page at or before `page_nr`
+        let mut range: Option<(i32, PageLabel)> = None;
+        labels.walk(resolve, &mut |start, label| {
+            if start as i64 <= page_nr as i64 && !matches!(range, Some((s, _)) if start < s) {
+                range = Some((start, label.clone()));
+            }
+        })?;
+        Ok(range.map(|(start, label)| label.label((page_nr as i64 - start as i64) as usize)))
+    }
+
+    /// The document-level scripts (the `JavaScript` name tree), with their names
+    pub fn scripts(&self, resolve: &impl Resolve) -> Result<Vec<(PdfString, String)>> {
+        let tree = match self.names.as_ref().and_then(|names| names.javascript.as_ref()) {
+            Some(tree) => tree,
+            None => return Ok(vec![])
+        };
+        let mut scripts = vec![];
+        for (name, action) in tree.entries(resolve)? {
+            match t!(Action::from_primitive(action, resolve)) {
+                Action::JavaScript(js) => scripts.push((name, js)),
+                action => warn!("ignoring {} action in the JavaScript name tree", action.subtype())
+            }
+        }
+        Ok(scripts)
+    }
+
+    /// What to do when the document is opened (`OpenAction`). A destination is
+    /// returned as a `GoTo` action.
+    pub fn open_action(&self, resolve: &impl Resolve) -> Result<Option<Vec<Action>>> {
+        let p = match self.open_action {
+            Some(Primitive::Reference(r)) => resolve.resolve(r)?,
+            Some(ref p) => p.clone(),
+            None => return Ok(None)
+        };
+        match p {
+            Primitive::Dictionary(_) => Ok(Some(Action::chain(p, resolve)?)),
+            p => Ok(Some(vec![Action::GoTo { dest: t!(MaybeNamedDest::from_primitive(p, resolve)) }]))
+        }
+    }
+
+    /// The output intent of the kind `subtype`, like the one of PDF/A documents
+    pub fn output_intent(&self, subtype: &OutputIntentSubtype) -> Option<&OutputIntent> {
+        self.output_intents.as_ref()?.iter().find(|intent| intent.subtype == *subtype)
+    }
+
+    /// The page the document opens at, counting from 0, and how it is shown.
+    ///
+    /// This is the destination of the first `GoTo` action of `OpenAction`, with named
+    /// destinations looked up. `None` if there is none, or it isn't a page of the document.
+    pub fn open_destination(&self, resolve: &impl Resolve) -> Result<Option<(u32, DestView)>> {
+        let actions = match self.open_action(resolve)? {
+            Some(actions) => actions,
+            None => return Ok(None)
+        };
+        let dest = match actions.iter().find_map(|a| match *a {
+            Action::GoTo { ref dest } => Some(dest),
+            _ => None
+        }) {
+            Some(dest) => dest,
+            None => return Ok(None)
+        };
+        let dest = match self.resolve_dest(resolve, dest)? {
+            Some(dest) => dest,
+            None => return Ok(None)
+        };
+        Ok(self.pages.page_nr(resolve, dest.page.get_inner())?.map(|page_nr| (page_nr, dest.view)))
+    }
+
+    /// Look up `dest` if it is a name. Names that aren't defined give `None`.
+    pub fn resolve_dest(&self, resolve: &impl Resolve, dest: &MaybeNamedDest) -> Result<Option<Dest>> {
+        match *dest {
+            MaybeNamedDest::Named(ref name) => self.named_dest(resolve, name.as_bytes()),
+            MaybeNamedDest::Direct(ref dest) => Ok(Some(dest.clone())),
+        }
+    }
+}
+
+/// Whether the document has been trapped (`Trapped`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Trapped {
+    True,
+    False,
+    Unknown,
+}
+impl Object for Trapped {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Reference(r) => Trapped::from_primitive(resolve.resolve(r)?, resolve),
+            Primitive::Boolean(true) => Ok(Trapped::True),
+            Primitive::Boolean(false) => Ok(Trapped::False),
+            Primitive::Name(ref name) => match name.as_str() {
+                "True" => Ok(Trapped::True),
+                "False" => Ok(Trapped::False),
+                "Unknown" => Ok(Trapped::Unknown),
+                name => Err(PdfError::UnknownVariant { id: "Trapped", name: name.into() })
+            },
+            p => Err(PdfError::UnexpectedPrimitive { expected: "Name | Boolean", found: p.get_debug_name() })
+        }
+    }
+}
+impl ObjectWrite for Trapped {
+    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+        Ok(Primitive::name(match *self {
+            Trapped::True => "True",
+            Trapped::False => "False",
+            Trapped::Unknown => "Unknown",
+        }))
+    }
+}
+
+/// The document information dictionary (`Info` in the trailer), see `File::info`
+#[derive(ObjectWrite, Debug, Clone, Default)]
+pub struct DocInfo {
+    #[pdf(key="Title")]
+    pub title: Option<PdfString>,
+
+    #[pdf(key="Author")]
+    pub author: Option<PdfString>,
+
+    #[pdf(key="Subject")]
+    pub subject: Option<PdfString>,
+
+    #[pdf(key="Keywords")]
+    pub keywords: Option<PdfString>,
+
+    /// The application that created the original document
+    #[pdf(key="Creator")]
+    pub creator: Option<PdfString>,
+
+    /// The application that converted it to PDF
+    #[pdf(key="Producer")]
+    pub producer: Option<PdfString>,
+
+    #[pdf(key="CreationDate")]
+    pub creation_date: Option<DateTime<FixedOffset>>,
+
+    #[pdf(key="ModDate")]
+    pub mod_date: Option<DateTime<FixedOffset>>,
+
+    #[pdf(key="Trapped")]
+    pub trapped: Option<Trapped>,
+
+    /// Custom entries
+    #[pdf(other)]
+    pub other: Dictionary,
+}
+/// Remove `key` from `dict` and read it, or drop it with a warning if it has the wrong type
+fn take<T: Object>(dict: &mut Dictionary, key: &str, what: &str, resolve: &impl Resolve) -> Option<T> {
+    let p = dict.remove(key)?;
+    match T::from_primitive(p, resolve) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("ignoring the {} entry of the {}: {}", key, what, e);
+            None
+        }
+    }
+}
+
+/// Info dictionaries are often sloppy: entries of the wrong type are dropped with a warning.
+impl Object for DocInfo {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let mut dict = t!(p.into_dictionary(resolve));
+        Ok(DocInfo {
+            title: take(&mut dict, "Title", "Info dictionary", resolve),
+            author: take(&mut dict, "Author", "Info dictionary", resolve),
+            subject: take(&mut dict, "Subject", "Info dictionary", resolve),
+            keywords: take(&mut dict, "Keywords", "Info dictionary", resolve),
+            creator: take(&mut dict, "Creator", "Info dictionary", resolve),
+            producer: take(&mut dict, "Producer", "Info dictionary", resolve),
+            creation_date: take(&mut dict, "CreationDate", "Info dictionary", resolve),
+            mod_date: take(&mut dict, "ModDate", "Info dictionary", resolve),
+            trapped: take(&mut dict, "Trapped", "Info dictionary", resolve),
+            other: dict,
+        })
+    }
+}
+
+/// How the document is shown when it is opened (`PageMode`)
+#[derive(Object, ObjectWrite, Debug, Clone, PartialEq, Eq)]
+pub enum PageMode {
+    UseNone,
+    /// Show the outline
+    UseOutlines,
+    /// Show the page thumbnails
+    UseThumbs,
+    FullScreen,
+    /// Show the optional content group panel
+    UseOC,
+    /// Show the attachments
+    UseAttachments,
+    #[pdf(other)]
+    Other(String),
+}
+
+/// How the pages are arranged when the document is opened (`PageLayout`)
+#[derive(Object, ObjectWrite, Debug, Clone, PartialEq, Eq)]
+pub enum PageLayout {
+    SinglePage,
+    OneColumn,
+    /// Two columns, odd pages on the left
+    TwoColumnLeft,
+    /// Two columns, odd pages on the right
+    TwoColumnRight,
+    /// Two pages at a time, odd pages on the left
+    TwoPageLeft,
+    /// Two pages at a time, odd pages on the right
+    TwoPageRight,
+    #[pdf(other)]
+    Other(String),
+}
+
+/// Reading order of the text, for arranging pages side by side
+#[derive(Object, ObjectWrite, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    L2R,
+    R2L,
+}
+
+/// Page scaling preset of the print dialog
+#[derive(Object, ObjectWrite, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PrintScaling {
+    #[pdf(name="None")]
+    NoScaling,
+    AppDefault,
+}
+
+/// Paper handling preset of the print dialog
+#[derive(Object, ObjectWrite, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Duplex {
+    Simplex,
+    DuplexFlipShortEdge,
+    DuplexFlipLongEdge,
+}
+
+/// How viewers should show and print the document (`ViewerPreferences` in the catalog)
+#[derive(ObjectWrite, Debug, Clone, Default)]
+pub struct ViewerPreferences {
+    #[pdf(key="HideToolbar")]
+    pub hide_toolbar: bool,
+
+    #[pdf(key="HideMenubar")]
+    pub hide_menubar: bool,
+
+    /// Hide scroll bars, navigation controls and the like (`HideWindowUI`)
+    #[pdf(key="HideWindowUI")]
+    pub hide_window_ui: bool,
+
+    /// Resize the window to the first page
+    #[pdf(key="FitWindow")]
+    pub fit_window: bool,
+
+    #[pdf(key="CenterWindow")]
+    pub center_window: bool,
+
+    /// Show the title of the document instead of the file name
+    #[pdf(key="DisplayDocTitle")]
+    pub display_doc_title: bool,
+
+    /// The page mode when leaving full screen mode
+    #[pdf(key="NonFullScreenPageMode")]
+    pub non_full_screen_page_mode: Option<PageMode>,
+
+    #[pdf(key="Direction")]
+    pub direction: Option<Direction>,
+
+    #[pdf(key="PrintScaling")]
+    pub print_scaling: Option<PrintScaling>,
+
+    #[pdf(key="Duplex")]
+    pub duplex: Option<Duplex>,
+
+    /// Choose the paper tray by the page size
+    #[pdf(key="PickTrayByPDFSize")]
+    pub pick_tray_by_pdf_size: Option<bool>,
+
+    /// First and last pages (counting from 1) of the ranges to print
+    #[pdf(key="PrintPageRange")]
+    pub print_page_range: Option<Vec<i32>>,
+
+    #[pdf(key="NumCopies")]
+    pub num_copies: Option<i32>,
+
+    #[pdf(other)]
+    pub other: Dictionary,
+}
+impl ViewerPreferences {
+    /// The ranges of `print_page_range` as pairs of page numbers (counting from 0)
+    pub fn print_ranges(&self) -> Vec<(u32, u32)> {
+        match self.print_page_range {
+            Some(ref range) => range.chunks_exact(2)
+                .filter(|r| r[0] >= 1 && r[0] <= r[1])
+                .map(|r| (r[0] as u32 - 1, r[1] as u32 - 1))
+                .collect(),
+            None => vec![]
+        }
+    }
+}
+/// Entries of the wrong type are dropped with a warning, like in `DocInfo`.
+impl Object for ViewerPreferences {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let take_flag = |dict: &mut Dictionary, key: &str| take(dict, key, "viewer preferences", resolve).unwrap_or(false);
+        let mut dict = t!(p.into_dictionary(resolve));
+        Ok(ViewerPreferences {
+            hide_toolbar: take_flag(&mut dict, "HideToolbar"),
+            hide_menubar: take_flag(&mut dict, "HideMenubar"),
+            hide_window_ui: take_flag(&mut dict, "HideWindowUI"),
+            fit_window: take_flag(&mut dict, "FitWindow"),
+            center_window: take_flag(&mut dict, "CenterWindow"),
+            display_doc_title: take_flag(&mut dict, "DisplayDocTitle"),
+            non_full_screen_page_mode: take(&mut dict, "NonFullScreenPageMode", "viewer preferences", resolve),
+            direction: take(&mut dict, "Direction", "viewer preferences", resolve),
+            print_scaling: take(&mut dict, "PrintScaling", "viewer preferences", resolve),
+            duplex: take(&mut dict, "Duplex", "viewer preferences", resolve),
+            pick_tray_by_pdf_size: take(&mut dict, "PickTrayByPDFSize", "viewer preferences", resolve),
+            print_page_range: take(&mut dict, "PrintPageRange", "viewer preferences", resolve),
+            num_copies: take(&mut dict, "NumCopies", "viewer preferences", resolve),
+            other: dict,
+        })
+    }
+}
+
 #[derive(Object, ObjectWrite, Debug, Default, Clone)]
 #[pdf(Type = "Pages?")]
 pub struct PageTree {
@@ -163,6 +544,9 @@ pub struct PageTree {
     
     #[pdf(key="CropBox")]
     pub crop_box:   Option<Rect>,
+
+    #[pdf(key="Rotate")]
+    pub rotate:     Option<i32>,
 }
 impl PageTree {
     pub fn page(&self, resolve: &impl Resolve, page_nr: u32) -> Result<PageRc> {
@@ -187,6 +571,27 @@ impl PageTree {
         Err(PdfError::PageOutOfBounds {page_nr, max: pos})
     }
 
+    /// The number of the page `page` (counting from 0), `None` if it isn't in the tree
+    pub fn page_nr(&self, resolve: &impl Resolve, page: PlainRef) -> Result<Option<u32>> {
+        let mut pos = 0;
+        for &kid in &self.kids {
+            if kid.get_inner() == page {
+                return Ok(Some(pos));
+            }
+            let node = resolve.get(kid)?;
+            match *node {
+                PagesNode::Tree(ref tree) => {
+                    if let Some(n) = tree.page_nr(resolve, page)? {
+                        return Ok(Some(pos + n));
+                    }
+                    pos += tree.count;
+                }
+                PagesNode::Leaf(_) => pos += 1,
+            }
+        }
+        Ok(None)
+    }
+
     /*
     pub fn update_pages(&mut self, mut offset: u32, page_nr: u32, page: Page) -> Result<()> {
         for kid in &self.kids {
@@ -227,10 +632,13 @@ impl PageTree {
 impl SubType<PagesNode> for PageTree {}
 
 #[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type = "Page?")]
 pub struct Page {
     #[pdf(key="Parent")]
     pub parent: PagesRc,
 
+    /// Set from the parents when the page is loaded from the page tree, like `media_box`,
+    /// `crop_box` and `rotate`, see `resolve_inherited`
     #[pdf(key="Resources")]
     pub resources: Option<MaybeRef<Resources>>,
     
@@ -240,12 +648,137 @@ pub struct Page {
     #[pdf(key="CropBox")]
     pub crop_box:   Option<Rect>,
     
+    #[pdf(key="BleedBox")]
+    pub bleed_box:  Option<Rect>,
+
     #[pdf(key="TrimBox")]
     pub trim_box:   Option<Rect>,
+
+    #[pdf(key="ArtBox")]
+    pub art_box:    Option<Rect>,
+
+    /// Clockwise rotation in degrees when displayed, a multiple of 90
+    #[pdf(key="Rotate")]
+    pub rotate:     Option<i32>,
     
     #[pdf(key="Contents")]
-    pub contents:   Option<Content>
+    pub contents:   Option<Content>,
+
+    /// Annotation dictionaries (`Annots`)
+    #[pdf(key="Annots")]
+    pub annotations: Option<Vec<Primitive>>,
+
+    /// Key of the page in the parent tree of the structure tree (`StructParents`)
+    #[pdf(key="StructParents")]
+    pub struct_parents: Option<i32>,
+
+    /// Files associated with the page (`AF`)
+    #[pdf(key="AF")]
+    pub associated_files: Option<Vec<FileSpec>>,
+
+    /// How the page is shown when a presentation moves to it (`Trans`)
+    #[pdf(key="Trans")]
+    pub transition: Option<Transition>,
+
+    /// Seconds the page is shown before a presentation moves on (`Dur`)
+    #[pdf(key="Dur")]
+    pub duration: Option<f32>,
 }
+/// The effect of a page transition (`S`)
+#[derive(Object, ObjectWrite, Debug, Clone, PartialEq, Eq)]
+pub enum TransitionStyle {
+    /// Lines sweep across the page, see `dimension` and `motion`
+    Split,
+    Blinds,
+    /// A rectangle sweeps inward from the edges or outward from the center
+    Box,
+    Wipe,
+    Dissolve,
+    Glitter,
+    /// The new page simply replaces the old one
+    #[pdf(name="R")]
+    Replace,
+    Fly,
+    Push,
+    Cover,
+    Uncover,
+    Fade,
+    #[pdf(other)]
+    Other(String),
+}
+
+/// The direction of the lines of `Split` and `Blinds` transitions (`Dm`)
+#[derive(Object, ObjectWrite, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionDimension {
+    #[pdf(name="H")]
+    Horizontal,
+    #[pdf(name="V")]
+    Vertical,
+}
+
+/// Whether `Split`, `Box` and `Fly` transitions move inward or outward (`M`)
+#[derive(Object, ObjectWrite, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionMotion {
+    #[pdf(name="I")]
+    Inward,
+    #[pdf(name="O")]
+    Outward,
+}
+
+/// The direction a transition moves in (`Di`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionDirection {
+    /// Counterclockwise from left to right: 0, 90, 180, 270 or, for `Glitter`, 315
+    Degrees(i32),
+    /// `None`, for `Fly` transitions with a scale other than 1
+    Unspecified,
+}
+impl Object for TransitionDirection {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Name(ref name) if name == "None" => Ok(TransitionDirection::Unspecified),
+            p => Ok(TransitionDirection::Degrees(t!(i32::from_primitive(p, resolve)))),
+        }
+    }
+}
+impl ObjectWrite for TransitionDirection {
+    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+        Ok(match *self {
+            TransitionDirection::Degrees(degrees) => Primitive::Integer(degrees),
+            TransitionDirection::Unspecified => Primitive::name("None"),
+        })
+    }
+}
+
+/// A page transition dictionary (12.4.4.1)
+#[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type="Trans?")]
+pub struct Transition {
+    #[pdf(key="S", default="TransitionStyle::Replace")]
+    pub style: TransitionStyle,
+
+    /// Seconds the transition takes (`D`)
+    #[pdf(key="D", default="1.")]
+    pub duration: f32,
+
+    #[pdf(key="Dm")]
+    pub dimension: Option<TransitionDimension>,
+
+    #[pdf(key="M")]
+    pub motion: Option<TransitionMotion>,
+
+    #[pdf(key="Di")]
+    pub direction: Option<TransitionDirection>,
+
+    /// Starting or ending scale of the changes of `Fly` transitions (`SS`)
+    #[pdf(key="SS")]
+    pub scale: Option<f32>,
+
+    /// Whether the area that flies in is opaque, for `Fly` transitions (`B`)
+    #[pdf(key="B")]
+    pub opaque: Option<bool>,
+}
+
 fn inherit<'a, T: 'a, F>(mut parent: &'a PageTree, f: F) -> Result<Option<T>>
     where F: Fn(&'a PageTree) -> Option<T>
 {
@@ -259,33 +792,173 @@ fn inherit<'a, T: 'a, F>(mut parent: &'a PageTree, f: F) -> Result<Option<T>>
     }
 }
 
+/// `r` with the lower left and the upper right corner in that order
+fn normalized(r: Rect) -> Rect {
+    Rect {
+        left: r.left.min(r.right),
+        bottom: r.bottom.min(r.top),
+        right: r.left.max(r.right),
+        top: r.bottom.max(r.top),
+    }
+}
+/// The part of `a` inside of `b`, empty at the edge of `b` if they don't overlap
+fn intersect(a: Rect, b: Rect) -> Rect {
+    let left = a.left.max(b.left).min(b.right);
+    let bottom = a.bottom.max(b.bottom).min(b.top);
+    Rect {
+        left,
+        bottom,
+        right: a.right.min(b.right).max(left),
+        top: a.top.min(b.top).max(bottom),
+    }
+}
+
 impl Page {
     pub fn new(parent: PagesRc) -> Page {
         Page {
             parent,
             media_box:  None,
             crop_box:   None,
+            bleed_box:  None,
             trim_box:   None,
+            art_box:    None,
+            rotate:     None,
             resources:  None,
-            contents:   None
+            contents:   None,
+            annotations: None,
+            struct_parents: None,
+            associated_files: None,
+            transition: None,
+            duration: None,
         }
     }
+    /// The size of the medium (`MediaBox`), inherited from the page tree
     pub fn media_box(&self) -> Result<Rect> {
         match self.media_box {
-            Some(b) => Ok(b),
+            Some(b) => Ok(normalized(b)),
             None => inherit(&*self.parent, |pt| pt.media_box)?
+                .map(normalized)
                 .ok_or_else(|| PdfError::MissingEntry { typ: "Page", field: "MediaBox".into() })
         }
     }
+    /// The visible region (`CropBox`), inherited from the page tree.
+    /// Defaults to the media box and is clipped to it.
     pub fn crop_box(&self) -> Result<Rect> {
-        match self.crop_box {
-            Some(b) => Ok(b),
-            None => match inherit(&*self.parent, |pt| pt.crop_box)? {
-                Some(b) => Ok(b),
-                None => self.media_box()
-            }
+        let media_box = self.media_box()?;
+        let crop_box = match self.crop_box {
+            Some(b) => Some(b),
+            None => inherit(&self.parent, |pt| pt.crop_box)?
+        };
+        Ok(match crop_box {
+            Some(b) => intersect(normalized(b), media_box),
+            None => media_box
+        })
+    }
+    /// The region to clip to in production (`BleedBox`), the crop box by default
+    pub fn bleed_box(&self) -> Result<Rect> {
+        self.inner_box(self.bleed_box)
+    }
+    /// The size of the finished page (`TrimBox`), the crop box by default
+    pub fn trim_box(&self) -> Result<Rect> {
+        self.inner_box(self.trim_box)
+    }
+    /// The meaningful content of the page (`ArtBox`), the crop box by default
+    pub fn art_box(&self) -> Result<Rect> {
+        self.inner_box(self.art_box)
+    }
+    fn inner_box(&self, b: Option<Rect>) -> Result<Rect> {
+        match b {
+            Some(b) => Ok(intersect(normalized(b), self.media_box()?)),
+            None => self.crop_box()
         }
     }
+    /// The rotation (`Rotate`), normalized to 0, 90, 180 or 270
+    pub fn rotation(&self) -> Result<i32> {
+        let rotate = match self.rotate {
+            Some(r) => r,
+            None => inherit(&self.parent, |pt| pt.rotate)?.unwrap_or(0)
+        };
+        Ok((rotate / 90).rem_euclid(4) * 90)
+    }
+    /// The image XObjects drawn on the page and where, see `graphics::images`
+    pub fn images<'a, B: Backend>(&'a self, file: &'a File<B>) -> Result<Images<'a, B>> {
+        images(file, self)
+    }
+    /// The annotations (`Annots`) of the page
+    pub fn annots(&self, resolve: &impl Resolve) -> Result<Vec<Annot>> {
+        match self.annotations {
+            Some(ref annots) => annots.iter().map(|p| Annot::from_primitive(p.clone(), resolve)).collect(),
+            None => Ok(vec![])
+        }
+    }
+    /// The links of the page: the area of each `Link` annotation and where it goes.
+    ///
+    /// Destinations, also those of `GoTo` actions, are looked up in `catalog` and given as
+    /// page numbers. Links to destinations that aren't in the document are skipped.
+    pub fn links(&self, resolve: &impl Resolve, catalog: &Catalog) -> Result<Vec<Link>> {
+        let mut links = vec![];
+        for annot in t!(self.annots(resolve)) {
+            let (dict, link) = match annot {
+                Annot::Link(dict, link) => (dict, link),
+                _ => continue
+            };
+            let action = match link.dest {
+                Some(ref dest) => Some(Action::GoTo { dest: t!(MaybeNamedDest::from_primitive(dest.clone(), resolve)) }),
+                None => t!(link.actions(resolve)).into_iter().next()
+            };
+            let target = match action {
+                Some(Action::GoTo { dest }) => {
+                    let dest = match t!(catalog.resolve_dest(resolve, &dest)) {
+                        Some(dest) => dest,
+                        None => {
+                            warn!("skipping a link to an undefined destination: {:?}", dest);
+                            continue;
+                        }
+                    };
+                    match t!(catalog.pages.page_nr(resolve, dest.page.get_inner())) {
+                        Some(page) => LinkTarget::Page { page, view: dest.view },
+                        None => {
+                            warn!("skipping a link to {:?}, which isn't a page of the document", dest.page);
+                            continue;
+                        }
+                    }
+                }
+                Some(Action::Uri { uri, .. }) => LinkTarget::Uri(text(&uri)),
+                Some(Action::GoToR { file, dest, .. }) => LinkTarget::Remote { file, dest },
+                Some(action) => LinkTarget::Action(action),
+                None => continue
+            };
+            links.push(Link { rect: normalized(dict.rect), target });
+        }
+        Ok(links)
+    }
+    /// Set the visible region of the page (`CropBox`). The corners of `rect` may be given in any order.
+    pub fn set_crop_box(&mut self, rect: Rect) {
+        self.crop_box = Some(normalized(rect));
+    }
+    /// Set the clockwise rotation (`Rotate`) in degrees, which has to be a multiple of 90.
+    ///
+    /// Negative angles and angles of a full turn or more are normalized.
+    pub fn set_rotation(&mut self, degrees: i32) -> Result<()> {
+        if degrees % 90 != 0 {
+            bail!("page rotation has to be a multiple of 90, not {}", degrees);
+        }
+        self.rotate = Some(degrees.rem_euclid(360));
+        Ok(())
+    }
+    /// Maps user space to the page as displayed: rotated by `rotation()`,
+    /// with the origin at the lower left corner of the crop box.
+    pub fn display_transform(&self) -> Result<Matrix> {
+        let b = self.crop_box()?;
+        let (w, h) = (b.right - b.left, b.top - b.bottom);
+        let rotate = match self.rotation()? {
+            90 => Matrix { a: 0., b: -1., c: 1., d: 0., e: 0., f: w },
+            180 => Matrix { a: -1., b: 0., c: 0., d: -1., e: w, f: h },
+            270 => Matrix { a: 0., b: 1., c: -1., d: 0., e: h, f: 0. },
+            _ => Matrix::default()
+        };
+        Ok(Matrix::translate(-b.left, -b.bottom) * rotate)
+    }
     pub fn resources(&self) -> Result<&MaybeRef<Resources>> {
         match self.resources {
             Some(ref r) => Ok(r),
@@ -293,10 +966,47 @@ impl Page {
                 .ok_or_else(|| PdfError::MissingEntry { typ: "Page", field: "Resources".into() })
         }
     }
+    /// Set the attributes the page inherits from the page tree (`Resources`, `MediaBox`,
+    /// `CropBox` and `Rotate`) on the page itself, so it can be moved to another parent.
+    pub fn resolve_inherited(&mut self) -> Result<()> {
+        if self.resources.is_none() {
+            self.resources = inherit(&self.parent, |pt| pt.resources.clone())?;
+        }
+        if self.media_box.is_none() {
+            self.media_box = inherit(&self.parent, |pt| pt.media_box)?;
+        }
+        if self.crop_box.is_none() {
+            self.crop_box = inherit(&self.parent, |pt| pt.crop_box)?;
+        }
+        if self.rotate.is_none() {
+            self.rotate = inherit(&self.parent, |pt| pt.rotate)?;
+        }
+        Ok(())
+    }
+    /// Draw `ops` below the existing content, see `Content::prepend`
+    pub fn prepend_content(&mut self, ops: Vec<Op>) -> Result<()> {
+        match self.contents {
+            Some(ref mut content) => content.prepend(ops),
+            None => {
+                self.contents = Some(Content::from_ops(ops));
+                Ok(())
+            }
+        }
+    }
+    /// Draw `ops` on top of the existing content, see `Content::append`
+    pub fn append_content(&mut self, ops: Vec<Op>) -> Result<()> {
+        match self.contents {
+            Some(ref mut content) => content.append(ops),
+            None => {
+                self.contents = Some(Content::from_ops(ops));
+                Ok(())
+            }
+        }
+    }
 }
 impl SubType<PagesNode> for Page {}
 
-#[derive(Object)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 pub struct PageLabel {
     #[pdf(key="S")]
     pub style:  Option<Counter>,
@@ -307,8 +1017,56 @@ pub struct PageLabel {
     #[pdf(key="St")]
     pub start:  Option<usize>
 }
+impl PageLabel {
+    /// The label of the page `offset` pages after the first page of the range
+    pub fn label(&self, offset: usize) -> String {
+        let mut label = self.prefix.as_ref().map(text).unwrap_or_default();
+        let n = self.start.unwrap_or(1) + offset;
+        match self.style {
+            Some(Counter::Arabic) => label.push_str(&n.to_string()),
+            Some(Counter::RomanUpper) => label.push_str(&roman(n)),
+            Some(Counter::RomanLower) => label.push_str(&roman(n).to_lowercase()),
+            Some(Counter::AlphaUpper) => label.push_str(&alpha(n)),
+            Some(Counter::AlphaLower) => label.push_str(&alpha(n).to_lowercase()),
+            None => {}
+        }
+        label
+    }
+}
 
-#[derive(Object, ObjectWrite, Debug)]
+/// A text string, with invalid text replaced
+fn text(s: &PdfString) -> String {
+    match s.as_str() {
+        Ok(s) => s.into_owned(),
+        Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+    }
+}
+
+fn roman(mut n: usize) -> String {
+    const NUMERALS: &[(usize, &str)] = &[
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut s = String::new();
+    for &(value, numeral) in NUMERALS {
+        while n >= value {
+            s.push_str(numeral);
+            n -= value;
+        }
+    }
+    s
+}
+
+/// A to Z, then AA to ZZ, AAA to ZZZ and so on
+fn alpha(n: usize) -> String {
+    if n == 0 {
+        return String::new();
+    }
+    let letter = (b'A' + ((n - 1) % 26) as u8) as char;
+    letter.to_string().repeat((n - 1) / 26 + 1)
+}
+
+#[derive(Object, ObjectWrite, Debug, Default, Clone)]
 pub struct Resources {
     #[pdf(key="ExtGState")]
     pub graphics_states: HashMap<String, GraphicsStateParameters>,
@@ -331,23 +1089,98 @@ impl Resources {
     pub fn fonts(&self) -> impl Iterator<Item=(&str, &Ref<Font>)> {
         self.fonts.iter().map(|(k, v)| (k.as_str(), v))
     }
+
+    /// The property list of a `BDC` or `DP` operator.
+    ///
+    /// `properties` is either the dictionary itself, or the name of an entry in `Properties`.
+    pub fn marked_content_properties(&self, properties: &Primitive, resolve: &impl Resolve) -> Result<MarkedContentProperties> {
+        let dict = match *properties {
+            Primitive::Name(ref name) => match self.properties.get(name) {
+                Some(dict) => (**dict).clone(),
+                None => bail!("property list {} not found", name)
+            },
+            ref p => t!(Dictionary::from_primitive(p.clone(), resolve))
+        };
+        MarkedContentProperties::from_dict(dict, resolve)
+    }
 }
 
+/// Properties of marked content (`BDC` / `DP`)
+#[derive(Debug, Clone)]
+pub enum MarkedContentProperties {
+    /// The content belongs to an optional content group (layer), usually tagged `/OC`
+    Group(OptionalContentGroup),
+    /// The visibility of the content depends on several groups
+    Membership(OptionalContentMembership),
+    /// Any other property list, like `<< /MCID 3 >>` of tagged content
+    Other(Dictionary),
+}
+impl MarkedContentProperties {
+    pub fn from_dict(dict: Dictionary, resolve: &impl Resolve) -> Result<MarkedContentProperties> {
+        let typ = dict.get("Type").and_then(|t| t.as_name().ok()).map(|t| t.to_owned());
+        Ok(match typ.as_deref() {
+            Some("OCG") => MarkedContentProperties::Group(t!(OptionalContentGroup::from_primitive(Primitive::Dictionary(dict), resolve))),
+            Some("OCMD") => MarkedContentProperties::Membership(t!(OptionalContentMembership::from_primitive(Primitive::Dictionary(dict), resolve))),
+            _ => MarkedContentProperties::Other(dict)
+        })
+    }
 
-#[derive(Object, ObjectWrite, Debug)]
+    /// Marked content identifier (`MCID`), which links the content to the structure tree
+    pub fn mcid(&self) -> Option<i32> {
+        match *self {
+            MarkedContentProperties::Other(ref dict) => dict.get("MCID").and_then(|p| p.as_integer().ok()),
+            _ => None
+        }
+    }
+}
+
+/// Optional content group (`OCG`), a layer that can be shown or hidden
+#[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type="OCG")]
+pub struct OptionalContentGroup {
+    #[pdf(key="Name")]
+    pub name: PdfString,
+
+    /// `View`, `Design` or both
+    #[pdf(key="Intent")]
+    pub intent: Vec<String>,
+
+    #[pdf(key="Usage")]
+    pub usage: Option<Dictionary>,
+}
+
+/// Optional content membership dictionary (`OCMD`)
+#[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type="OCMD")]
+pub struct OptionalContentMembership {
+    /// A reference to a group, or an array of them
+    #[pdf(key="OCGs")]
+    pub groups: Option<Primitive>,
+
+    /// `AllOn`, `AnyOn` (the default), `AnyOff` or `AllOff`
+    #[pdf(key="P")]
+    pub policy: Option<String>,
+
+    /// Visibility expression, takes precedence over `groups` and `policy`
+    #[pdf(key="VE")]
+    pub expression: Option<Primitive>,
+}
+
+
+#[derive(Object, ObjectWrite, Debug, Copy, Clone)]
 pub enum LineCap {
     Butt = 0,
     Round = 1,
     Square = 2
 }
-#[derive(Object, ObjectWrite, Debug)]
+#[derive(Object, ObjectWrite, Debug, Copy, Clone)]
 pub enum LineJoin {
     Miter = 0,
     Round = 1,
     Bevel = 2
 }
 
-#[derive(Object, ObjectWrite, Debug)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 #[pdf(Type = "ExtGState?")]
 /// `ExtGState`
 pub struct GraphicsStateParameters {
@@ -424,18 +1257,28 @@ pub enum XObject {
     Form (FormXObject),
 }
 
+impl ObjectWrite for XObject {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        match *self {
+            XObject::Postscript(ref stream) => stream.to_primitive(update),
+            XObject::Image(ref stream) => stream.to_primitive(update),
+            XObject::Form(ref form) => form.to_primitive(update),
+        }
+    }
+}
+
 /// A variant of XObject
 pub type PostScriptXObject = Stream<PostScriptDict>;
 /// A variant of XObject
 pub type ImageXObject = Stream<ImageDict>;
 
-#[derive(Object, Debug)]
+#[derive(Object, ObjectWrite, Debug)]
 #[pdf(Type="XObject", Subtype="PS")]
 pub struct PostScriptDict {
     // TODO
 }
 
-#[derive(Object, Debug, Clone)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 #[pdf(Type="XObject?", Subtype="Image")]
 /// A variant of XObject
 pub struct ImageDict {
@@ -487,6 +1330,19 @@ pub struct ImageDict {
     #[pdf(key="SMask")]
     pub smask: Option<Ref<Stream<ImageDict>>>,
 
+    /// If this is a soft mask: the color the image was premultiplied with, in the color space of the image (`Matte`)
+    #[pdf(key="Matte")]
+    pub matte: Option<Vec<f32>>,
+
+    /// How a JPEG 2000 image uses its own alpha channel (`SMaskInData`):
+    /// 0 (or absent) not at all, 1 as soft mask, 2 as soft mask with premultiplied colors
+    #[pdf(key="SMaskInData")]
+    pub smask_in_data: Option<i32>,
+
+    /// Associated files (`AF`)
+    #[pdf(key="AF")]
+    pub associated_files: Option<Vec<FileSpec>>,
+
     // OPI: dict
     // Metadata: stream
     // OC: dict
@@ -496,7 +1352,7 @@ pub struct ImageDict {
 }
 
 
-#[derive(Object, Debug, Copy, Clone)]
+#[derive(Object, ObjectWrite, Debug, Copy, Clone)]
 pub enum RenderingIntent {
     AbsoluteColorimetric,
     RelativeColorimetric,
@@ -524,7 +1380,7 @@ impl RenderingIntent {
 }
 
 
-#[derive(Object, Debug)]
+#[derive(Object, ObjectWrite, Debug)]
 #[pdf(Type="XObject?", Subtype="Form")]
 pub struct FormDict {
     #[pdf(key="FormType", default="1")]
@@ -540,7 +1396,7 @@ pub struct FormDict {
     pub bbox: Rect,
 
     #[pdf(key="Matrix")]
-    pub matrix: Option<Primitive>,
+    pub matrix: Option<Matrix>,
 
     #[pdf(key="Resources")]
     pub resources: Option<MaybeRef<Resources>>,
@@ -566,34 +1422,29 @@ pub struct FormDict {
     #[pdf(key="OPI")]
     pub opi: Option<Dictionary>,
 
+    /// Associated files (`AF`)
+    #[pdf(key="AF")]
+    pub associated_files: Option<Vec<FileSpec>>,
+
     #[pdf(other)]
     pub other: Dictionary,
 }
 
 
+/// Numbering style of page labels
+#[derive(Object, ObjectWrite, Debug, Copy, Clone)]
 pub enum Counter {
+    #[pdf(name="D")]
     Arabic,
+    #[pdf(name="R")]
     RomanUpper,
+    #[pdf(name="r")]
     RomanLower,
+    #[pdf(name="A")]
     AlphaUpper,
+    #[pdf(name="a")]
     AlphaLower
 }
-impl Object for Counter {
-    // fn serialize<W: io::Write>(&self, out: &mut W) -> Result<()> {
-    //     let style_code = match *self {
-    //         Counter::Arabic     => "D",
-    //         Counter::RomanLower => "r",
-    //         Counter::RomanUpper => "R",
-    //         Counter::AlphaLower => "a",
-    //         Counter::AlphaUpper => "A"
-    //     };
-    //     out.write_all(style_code.as_bytes())?;
-    //     Ok(())
-    // }
-    fn from_primitive(_: Primitive, _: &impl Resolve) -> Result<Self> {
-        unimplemented!();
-    }
-}
 
 #[derive(Debug)]
 pub enum NameTreeNode<T> {
@@ -611,7 +1462,14 @@ pub struct NameTree<T> {
     pub node: NameTreeNode<T>,
 }
 impl<T: Object> NameTree<T> {
+    /// Call `callback` for every entry, in the order of the tree
     pub fn walk(&self, r: &impl Resolve, callback: &mut dyn FnMut(&PdfString, &T)) -> Result<(), PdfError> {
+        self.walk_at(r, callback, 0)
+    }
+    fn walk_at(&self, r: &impl Resolve, callback: &mut dyn FnMut(&PdfString, &T), depth: usize) -> Result<(), PdfError> {
+        if depth > 32 {
+            bail!("name tree is nested too deeply");
+        }
         match self.node {
             NameTreeNode::Leaf(ref items) => {
                 for (name, val) in items {
@@ -621,11 +1479,48 @@ impl<T: Object> NameTree<T> {
             NameTreeNode::Intermediate(ref items) => {
                 for &tree_ref in items {
                     let tree = r.get(tree_ref)?;
-                    tree.walk(r, callback)?;
+                    tree.walk_at(r, callback, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Object + Clone> NameTree<T> {
+    /// The value for `name`. Only the subtrees whose `Limits` include `name` are searched.
+    pub fn get(&self, r: &impl Resolve, name: &[u8]) -> Result<Option<T>> {
+        self.find(r, name, 0)
+    }
+    /// All entries, in the order of the tree (sorted by name in valid files)
+    pub fn entries(&self, r: &impl Resolve) -> Result<Vec<(PdfString, T)>> {
+        let mut entries = vec![];
+        self.walk(r, &mut |name, val| entries.push((name.clone(), val.clone())))?;
+        Ok(entries)
+    }
+    fn find(&self, r: &impl Resolve, name: &[u8], depth: usize) -> Result<Option<T>> {
+        if depth > 32 {
+            bail!("name tree is nested too deeply");
+        }
+        match self.node {
+            NameTreeNode::Leaf(ref items) => {
+                Ok(items.iter().find(|(key, _)| key.as_bytes() == name).map(|(_, val)| val.clone()))
+            }
+            NameTreeNode::Intermediate(ref items) => {
+                for &tree_ref in items {
+                    let tree = r.get(tree_ref)?;
+                    if let Some((ref min, ref max)) = tree.limits {
+                        if name < min.as_bytes() || name > max.as_bytes() {
+                            continue;
+                        }
+                    }
+                    if let Some(val) = tree.find(r, name, depth + 1)? {
+                        return Ok(Some(val));
+                    }
                 }
+                Ok(None)
             }
         }
-        Ok(())
     }
 }
 
@@ -664,7 +1559,7 @@ impl<T: Object> Object for NameTree<T> {
             (None, Some(names)) => {
                 let names = names.into_array(resolve)?;
                 let mut new_names = Vec::new();
-                for pair in names.chunks(2) {
+                for pair in names.chunks_exact(2) {
                     let name = pair[0].clone().into_string()?;
                     let value = t!(T::from_primitive(pair[1].clone(), resolve));
                     new_names.push((name, value));
@@ -680,23 +1575,179 @@ impl<T: Object> Object for NameTree<T> {
 }
 
 impl<T: ObjectWrite> ObjectWrite for NameTree<T> {
-    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
-        todo!("impl ObjectWrite for NameTree")
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        let mut dict = Dictionary::new();
+        if let Some((ref min, ref max)) = self.limits {
+            dict.insert("Limits", Primitive::Array(vec![min.clone().into(), max.clone().into()]));
+        }
+        match self.node {
+            NameTreeNode::Intermediate(ref kids) => {
+                dict.insert("Kids", Primitive::array::<Ref<NameTree<T>>, _, _, _>(kids.iter(), update)?);
+            }
+            NameTreeNode::Leaf(ref names) => {
+                let mut list = Vec::with_capacity(2 * names.len());
+                for (name, value) in names {
+                    list.push(name.clone().into());
+                    list.push(value.to_primitive(update)?);
+                }
+                dict.insert("Names", Primitive::Array(list));
+            }
+        }
+        Ok(Primitive::Dictionary(dict))
+    }
+}
+
+#[derive(Debug)]
+pub enum NumberTreeNode<T> {
+    Intermediate (Vec<Ref<NumberTree<T>>>),
+    Leaf (Vec<(i32, T)>)
+}
+/// Like `NameTree`, with integer keys
+#[derive(Debug)]
+pub struct NumberTree<T> {
+    pub limits: Option<(i32, i32)>,
+    pub node: NumberTreeNode<T>,
+}
+impl<T: Object> NumberTree<T> {
+    /// Call `callback` for every entry, in the order of the tree
+    pub fn walk(&self, r: &impl Resolve, callback: &mut dyn FnMut(i32, &T)) -> Result<(), PdfError> {
+        self.walk_at(r, callback, 0)
+    }
+    fn walk_at(&self, r: &impl Resolve, callback: &mut dyn FnMut(i32, &T), depth: usize) -> Result<(), PdfError> {
+        if depth > 32 {
+            bail!("number tree is nested too deeply");
+        }
+        match self.node {
+            NumberTreeNode::Leaf(ref items) => {
+                for &(key, ref val) in items {
+                    callback(key, val);
+                }
+            }
+            NumberTreeNode::Intermediate(ref items) => {
+                for &tree_ref in items {
+                    let tree = r.get(tree_ref)?;
+                    tree.walk_at(r, callback, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+impl<T: Object + Clone> NumberTree<T> {
+    /// The value for `key`. Only the subtrees whose `Limits` include `key` are searched.
+    pub fn get(&self, r: &impl Resolve, key: i32) -> Result<Option<T>> {
+        self.find(r, key, 0)
+    }
+    fn find(&self, r: &impl Resolve, key: i32, depth: usize) -> Result<Option<T>> {
+        if depth > 32 {
+            bail!("number tree is nested too deeply");
+        }
+        match self.node {
+            NumberTreeNode::Leaf(ref items) => {
+                Ok(items.iter().find(|&&(k, _)| k == key).map(|(_, val)| val.clone()))
+            }
+            NumberTreeNode::Intermediate(ref items) => {
+                for &tree_ref in items {
+                    let tree = r.get(tree_ref)?;
+                    if let Some((min, max)) = tree.limits {
+                        if key < min || key > max {
+                            continue;
+                        }
+                    }
+                    if let Some(val) = tree.find(r, key, depth + 1)? {
+                        return Ok(Some(val));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+    /// All entries, in the order of the tree (sorted by key in valid files)
+    pub fn entries(&self, r: &impl Resolve) -> Result<Vec<(i32, T)>> {
+        let mut entries = vec![];
+        self.walk(r, &mut |key, val| entries.push((key, val.clone())))?;
+        Ok(entries)
+    }
+}
+impl<T: Object> Object for NumberTree<T> {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let mut dict = t!(p.into_dictionary(resolve));
+        let limits = match dict.remove("Limits") {
+            Some(limits) => {
+                let limits = limits.into_array(resolve)?;
+                if limits.len() != 2 {
+                    bail!("Error reading NumberTree: 'Limits' is not of length 2");
+                }
+                Some((limits[0].as_integer()?, limits[1].as_integer()?))
+            }
+            None => None
+        };
+        let node = match (dict.remove("Kids"), dict.remove("Nums")) {
+            (Some(kids), _) => {
+                let kids = t!(kids.into_array(resolve)?.iter().map(|kid|
+                    Ref::<NumberTree<T>>::from_primitive(kid.clone(), resolve)
+                ).collect::<Result<Vec<_>>>());
+                NumberTreeNode::Intermediate(kids)
+            }
+            (None, Some(nums)) => {
+                let nums = nums.into_array(resolve)?;
+                let mut items = Vec::with_capacity(nums.len() / 2);
+                for pair in nums.chunks_exact(2) {
+                    let key = pair[0].as_integer()?;
+                    let value = t!(T::from_primitive(pair[1].clone(), resolve));
+                    items.push((key, value));
+                }
+                NumberTreeNode::Leaf(items)
+            }
+            (None, None) => bail!("Neither Kids nor Nums present in NumberTree node.")
+        };
+        Ok(NumberTree { limits, node })
+    }
+}
+impl<T: ObjectWrite> ObjectWrite for NumberTree<T> {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        let mut dict = Dictionary::new();
+        if let Some((min, max)) = self.limits {
+            dict.insert("Limits", Primitive::Array(vec![Primitive::Integer(min), Primitive::Integer(max)]));
+        }
+        match self.node {
+            NumberTreeNode::Intermediate(ref kids) => {
+                dict.insert("Kids", Primitive::array::<Ref<NumberTree<T>>, _, _, _>(kids.iter(), update)?);
+            }
+            NumberTreeNode::Leaf(ref items) => {
+                let mut list = Vec::with_capacity(2 * items.len());
+                for &(key, ref value) in items {
+                    list.push(Primitive::Integer(key));
+                    list.push(value.to_primitive(update)?);
+                }
+                dict.insert("Nums", Primitive::Array(list));
+            }
+        }
+        Ok(Primitive::Dictionary(dict))
     }
 }
 
+/// How a destination shows its page. Coordinates that are `None` keep their current value.
 #[derive(Debug, Clone)]
 pub enum DestView {
-    // left, top, zoom
+    /// Position `left`, `top` at the top left corner of the window, magnified by `zoom`.
+    /// A `zoom` of 0 keeps the current magnification.
     XYZ { left: Option<f32>, top: Option<f32>, zoom: f32 },
+    /// Fit the whole page into the window
     Fit,
-    FitH { top: f32 },
-    FitV { left: f32 },
+    /// Fit the width of the page, with `top` at the top of the window
+    FitH { top: Option<f32> },
+    /// Fit the height of the page, with `left` at the left edge of the window
+    FitV { left: Option<f32> },
+    /// Fit the rectangle into the window
     FitR(Rect),
+    /// Fit the bounding box of the contents into the window
     FitB,
-    FitBH { top: f32 }
+    FitBH { top: Option<f32> },
+    FitBV { left: Option<f32> },
 }
 
+/// An explicit destination: a page and how to show it
 #[derive(Debug, Clone)]
 pub struct Dest {
     pub page: Ref<Page>,
@@ -712,37 +1763,29 @@ impl Object for Dest {
             Primitive::Dictionary(mut dict) => dict.require("Dest", "D")?,
             p => p
         };
+        let p = match p {
+            Primitive::Reference(r) => resolve.resolve(r)?,
+            p => p
+        };
         let array = p.as_array()?;
         let page = Ref::from_primitive(try_opt!(array.get(0)).clone(), resolve)?;
+        // missing numbers at the end are taken as null
+        let number = |i: usize| match array.get(i) {
+            None | Some(&Primitive::Null) => Ok(None),
+            Some(&Primitive::Integer(n)) => Ok(Some(n as f32)),
+            Some(&Primitive::Number(f)) => Ok(Some(f)),
+            Some(p) => Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.get_debug_name() }),
+        };
         let kind = try_opt!(array.get(1));
         let view = match kind.as_name()? {
             "XYZ" => DestView::XYZ {
-                left: match try_opt!(array.get(2)) {
-                    &Primitive::Null => None,
-                    &Primitive::Integer(n) => Some(n as f32),
-                    &Primitive::Number(f) => Some(f),
-                    ref p => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.get_debug_name() }),
-                },
-                top: match try_opt!(array.get(3)) {
-                    &Primitive::Null => None,
-                    &Primitive::Integer(n) => Some(n as f32),
-                    &Primitive::Number(f) => Some(f),
-                    ref p => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.get_debug_name() }),
-                },
-                zoom: match try_opt!(array.get(4)) {
-                    &Primitive::Null => 0.0,
-                    &Primitive::Integer(n) => n as f32,
-                    &Primitive::Number(f) => f,
-                    ref p => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.get_debug_name() }),
-                },
+                left: number(2)?,
+                top: number(3)?,
+                zoom: number(4)?.unwrap_or(0.0),
             },
             "Fit" => DestView::Fit,
-            "FitH" => DestView::FitH {
-                top: try_opt!(array.get(2)).as_number()?
-            },
-            "FitV" => DestView::FitV {
-                left: try_opt!(array.get(2)).as_number()?
-            },
+            "FitH" => DestView::FitH { top: number(2)? },
+            "FitV" => DestView::FitV { left: number(2)? },
             "FitR" => DestView::FitR(Rect {
                 left:   try_opt!(array.get(2)).as_number()?,
                 bottom: try_opt!(array.get(3)).as_number()?,
@@ -750,9 +1793,8 @@ impl Object for Dest {
                 top:    try_opt!(array.get(5)).as_number()?,
             }),
             "FitB" => DestView::FitB,
-            "FitBH" => DestView::FitBH {
-                top: try_opt!(array.get(2)).as_number()?
-            },
+            "FitBH" => DestView::FitBH { top: number(2)? },
+            "FitBV" => DestView::FitBV { left: number(2)? },
             name => return Err(PdfError::UnknownVariant { id: "Dest", name: name.into() })
         };
         Ok(Dest {
@@ -776,11 +1818,11 @@ impl ObjectWrite for Dest {
             }
             DestView::FitH { top } => {
                 arr.push(Primitive::Name("FitH".into()));
-                arr.push(Primitive::Number(top));
+                arr.push(top.to_primitive(update)?);
             }
             DestView::FitV { left } => {
                 arr.push(Primitive::Name("FitV".into()));
-                arr.push(Primitive::Number(left));
+                arr.push(left.to_primitive(update)?);
             }
             DestView::FitR(rect) => {
                 arr.push(Primitive::Name("FitR".into()));
@@ -794,13 +1836,54 @@ impl ObjectWrite for Dest {
             }
             DestView::FitBH { top } => {
                 arr.push(Primitive::Name("FitBH".into()));
-                arr.push(Primitive::Number(top));
+                arr.push(top.to_primitive(update)?);
+            }
+            DestView::FitBV { left } => {
+                arr.push(Primitive::Name("FitBV".into()));
+                arr.push(left.to_primitive(update)?);
             }
         }
         Ok(Primitive::Array(arr))
     }
 }
 
+/// A destination, or the name of one in the `Dests` of the catalog or its `Names`
+#[derive(Debug, Clone)]
+pub enum MaybeNamedDest {
+    Named(PdfString),
+    Direct(Dest),
+}
+impl Object for MaybeNamedDest {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let p = match p {
+            Primitive::Reference(r) => resolve.resolve(r)?,
+            p => p
+        };
+        match p {
+            Primitive::String(name) => Ok(MaybeNamedDest::Named(name)),
+            Primitive::Name(name) => Ok(MaybeNamedDest::Named(PdfString::new(name.into_bytes()))),
+            p => Ok(MaybeNamedDest::Direct(Dest::from_primitive(p, resolve)?))
+        }
+    }
+}
+impl MaybeNamedDest {
+    /// The destination of a `GoTo` action
+    pub fn from_action(action: &Dictionary, resolve: &impl Resolve) -> Result<Option<MaybeNamedDest>> {
+        match (action.get("S"), action.get("D")) {
+            (Some(Primitive::Name(ref s)), Some(dest)) if s == "GoTo" => Ok(Some(t!(MaybeNamedDest::from_primitive(dest.clone(), resolve)))),
+            _ => Ok(None)
+        }
+    }
+}
+impl ObjectWrite for MaybeNamedDest {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        match *self {
+            MaybeNamedDest::Named(ref name) => Ok(Primitive::String(name.clone())),
+            MaybeNamedDest::Direct(ref dest) => dest.to_primitive(update),
+        }
+    }
+}
+
 /// There is one `NameDictionary` associated with each PDF file.
 #[derive(Object, ObjectWrite, Debug)]
 pub struct NameDictionary {
@@ -842,60 +1925,430 @@ pub struct NameDictionary {
  * to embedded file streams through their EF entries.
 */
 
-#[derive(Object, ObjectWrite, Debug, Clone)]
+/// A file specification (7.11): the name of a file, and maybe the file itself (`EF`).
+///
+/// Can be read from a file specification string, which only has `file`.
+#[derive(ObjectWrite, Debug, Clone, Default)]
+#[pdf(Type="Filespec?")]
 pub struct FileSpec {
+    /// File system (`FS`), `URL` if `file` is a URL
+    #[pdf(key="FS")]
+    pub file_system: Option<String>,
+    /// File specification string (`F`), see `path`
+    #[pdf(key="F")]
+    pub file: Option<PdfString>,
+    /// File specification string as a text string (`UF`)
+    #[pdf(key="UF")]
+    pub unicode_file: Option<PdfString>,
+    /// File names of the system specific forms, not to be used in new files
+    #[pdf(key="DOS")]
+    pub dos: Option<PdfString>,
+    #[pdf(key="Mac")]
+    pub mac: Option<PdfString>,
+    #[pdf(key="Unix")]
+    pub unix: Option<PdfString>,
+    #[pdf(key="Desc")]
+    pub description: Option<PdfString>,
     #[pdf(key="EF")]
-    ef: Option<Files<Ref<Stream<EmbeddedFile>>>>,
-    /*
+    pub ef: Option<Files<Ref<Stream<EmbeddedFile>>>>,
+    /// Files that belong to the embedded file (`RF`), see `related_files`
     #[pdf(key="RF")]
-    rf: Option<Files<RelatedFilesArray>>,
-    */
+    pub rf: Option<Files<Vec<Primitive>>>,
+    /// The file changes often and must not be cached (`V`)
+    #[pdf(key="V")]
+    pub volatile: Option<bool>,
+    /// How an associated file relates to its object (`AFRelationship`), `Unspecified` if `None`
+    #[pdf(key="AFRelationship")]
+    pub relationship: Option<AFRelationship>,
+    /// The values of the collection fields of a portfolio member (`CI`), see `portfolio`
+    #[pdf(key="CI")]
+    pub collection_item: Option<Dictionary>,
+    #[pdf(other)]
+    pub other: Dictionary,
+}
+impl FileSpec {
+    /// The name of the file, preferring `UF` over `F` and those over the system specific names
+    pub fn name(&self) -> Option<String> {
+        let name = self.unicode_file.as_ref().or(self.file.as_ref())
+            .or(self.unix.as_ref()).or(self.mac.as_ref()).or(self.dos.as_ref())?;
+        Some(text(name))
+    }
+    pub fn description_text(&self) -> Option<String> {
+        self.description.as_ref().map(text)
+    }
+    /// The file specification string (`UF` or `F`) as a path, `None` for URLs
+    pub fn path(&self) -> Option<FilePath> {
+        if self.file_system.as_deref() == Some("URL") {
+            return None;
+        }
+        let name = self.unicode_file.as_ref().or(self.file.as_ref())?;
+        Some(FilePath::parse(&text(name)))
+    }
+    /// The name of the file without the directories
+    pub fn file_name(&self) -> Option<String> {
+        if let Some(path) = self.path() {
+            return path.components.last().cloned();
+        }
+        let name = self.name()?;
+        Some(name.rsplit(['/', '\\', ':']).next().unwrap_or_default().to_owned())
+    }
+    /// The embedded file stream, if the file is embedded
+    pub fn embedded(&self) -> Option<Ref<Stream<EmbeddedFile>>> {
+        let ef = self.ef.as_ref()?;
+        ef.uf.or(ef.f).or(ef.unix).or(ef.mac).or(ef.dos)
+    }
+    /// The related files of the embedded file, with their names
+    pub fn related_files(&self, resolve: &impl Resolve) -> Result<Vec<(String, Ref<Stream<EmbeddedFile>>)>> {
+        let rf = match self.rf {
+            Some(ref rf) => rf,
+            None => return Ok(vec![])
+        };
+        let list = match rf.uf.as_ref().or(rf.f.as_ref()).or(rf.unix.as_ref()).or(rf.mac.as_ref()).or(rf.dos.as_ref()) {
+            Some(list) => list,
+            None => return Ok(vec![])
+        };
+        let mut files = Vec::with_capacity(list.len() / 2);
+        for pair in list.chunks_exact(2) {
+            let name = t!(pair[0].clone().into_string());
+            files.push((text(&name), t!(Ref::from_primitive(pair[1].clone(), resolve))));
+        }
+        Ok(files)
+    }
+}
+impl Object for FileSpec {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let mut dict = match p {
+            Primitive::Reference(r) => return FileSpec::from_primitive(resolve.resolve(r)?, resolve),
+            Primitive::String(file) => return Ok(FileSpec { file: Some(file), .. FileSpec::default() }),
+            p => t!(p.into_dictionary(resolve))
+        };
+        dict.remove("Type");
+        let string = |dict: &mut Dictionary, key: &str| -> Option<PdfString> { take(dict, key, "file specification", resolve) };
+        Ok(FileSpec {
+            file_system: take_name(&mut dict, "FS"),
+            file: string(&mut dict, "F"),
+            unicode_file: string(&mut dict, "UF"),
+            dos: string(&mut dict, "DOS"),
+            mac: string(&mut dict, "Mac"),
+            unix: string(&mut dict, "Unix"),
+            description: string(&mut dict, "Desc"),
+            ef: match dict.remove("EF") {
+                Some(ef) => Some(t!(Files::from_primitive(ef, resolve))),
+                None => None
+            },
+            rf: match dict.remove("RF") {
+                Some(rf) => Some(t!(Files::from_primitive(rf, resolve))),
+                None => None
+            },
+            volatile: take(&mut dict, "V", "file specification", resolve),
+            relationship: take(&mut dict, "AFRelationship", "file specification", resolve),
+            collection_item: take(&mut dict, "CI", "file specification", resolve),
+            other: dict,
+        })
+    }
+}
+fn take_name(dict: &mut Dictionary, key: &str) -> Option<String> {
+    match dict.remove(key) {
+        Some(Primitive::Name(name)) => Some(name),
+        Some(p) => {
+            warn!("ignoring the {} entry of the file specification: expected a name, found {}", key, p.get_debug_name());
+            None
+        }
+        None => None
+    }
 }
 
-/// Used only as elements in `FileSpec`
+/// How an associated file relates to the part of the document it belongs to (14.13)
+#[derive(Object, ObjectWrite, Debug, Clone, PartialEq, Eq)]
+pub enum AFRelationship {
+    /// The original content the part was created from
+    Source,
+    /// Data the part shows, like the XML of an electronic invoice
+    Data,
+    /// An alternative representation of the content
+    Alternative,
+    /// Additions to the content, like for accessibility
+    Supplement,
+    /// An encrypted payload document, in a wrapper document
+    EncryptedPayload,
+    /// Data of a form
+    FormData,
+    /// A schema of the content
+    Schema,
+    Unspecified,
+    #[pdf(other)]
+    Other(String),
+}
+
+/// The part of the document an associated file belongs to, see `File::associated_files`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssociatedWith {
+    Document,
+    /// The page, counting from 0
+    Page(u32),
+    /// The annotation at `index` in the `Annots` of the page
+    Annotation { page: u32, index: usize },
+    /// The XObject `name` in the resources of the page
+    XObject { page: u32, name: String },
+}
+
+/// A file specification string split into its parts (7.11.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePath {
+    /// The path starts with `/`. The first component is then the volume or drive.
+    pub absolute: bool,
+    pub components: Vec<String>,
+}
+impl FilePath {
+    /// Split `s` at `/`. A `\` makes the next character part of the component.
+    pub fn parse(s: &str) -> FilePath {
+        let absolute = s.starts_with('/');
+        let mut components = vec![];
+        let mut component = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => component.extend(chars.next()),
+                '/' => {
+                    if !component.is_empty() {
+                        components.push(std::mem::take(&mut component));
+                    }
+                }
+                c => component.push(c)
+            }
+        }
+        if !component.is_empty() {
+            components.push(component);
+        }
+        FilePath { absolute, components }
+    }
+}
+
+/// The portfolio dictionary (`Collection` in the catalog)
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+#[pdf(Type="Collection?")]
+pub struct Collection {
+    /// The fields describing the members, see `fields`
+    #[pdf(key="Schema")]
+    pub schema: Option<Dictionary>,
+
+    /// Name of the member in the `EmbeddedFiles` name tree that is shown first (`D`)
+    #[pdf(key="D")]
+    pub initial: Option<PdfString>,
+
+    #[pdf(key="View")]
+    pub view: Option<CollectionView>,
+
+    #[pdf(key="Sort")]
+    pub sort: Option<CollectionSort>,
+
+    #[pdf(other)]
+    pub other: Dictionary,
+}
+impl Collection {
+    /// The fields of the schema with their keys, in the order they are shown (`O`)
+    pub fn fields(&self, resolve: &impl Resolve) -> Result<Vec<(String, CollectionField)>> {
+        let mut fields = vec![];
+        if let Some(ref schema) = self.schema {
+            for (key, p) in schema.iter() {
+                if key == "Type" {
+                    continue;
+                }
+                fields.push((key.clone(), t!(CollectionField::from_primitive(p.clone(), resolve))));
+            }
+        }
+        fields.sort_by_key(|(_, field)| field.order.unwrap_or(i32::MAX));
+        Ok(fields)
+    }
+}
+
+/// How a viewer shows the members of a portfolio
+#[derive(Object, ObjectWrite, Debug, Clone, PartialEq, Eq)]
+pub enum CollectionView {
+    /// A list with the fields as columns
+    #[pdf(name="D")]
+    Details,
+    /// Icons
+    #[pdf(name="T")]
+    Tile,
+    /// Only the initial document
+    #[pdf(name="H")]
+    Hidden,
+    /// A custom presentation (`Navigator`)
+    #[pdf(name="C")]
+    Custom,
+    #[pdf(other)]
+    Other(String),
+}
+
+/// A field of the portfolio schema
+#[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type="CollectionField?")]
+pub struct CollectionField {
+    /// The kind of value: `S`, `D` and `N` for text, date and number values in the
+    /// members (`CI`), or `F`, `Desc`, `ModDate`, `CreationDate`, `Size` and `CompressedSize`
+    /// for values of the file
+    #[pdf(key="Subtype")]
+    pub subtype: String,
+
+    /// The name shown to the user
+    #[pdf(key="N")]
+    pub name: PdfString,
+
+    #[pdf(key="O")]
+    pub order: Option<i32>,
+
+    #[pdf(key="V", default="true")]
+    pub visible: bool,
+
+    #[pdf(key="E", default="false")]
+    pub editable: bool,
+}
+
+/// The order of the members of a portfolio
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
+#[pdf(Type="CollectionSort?")]
+pub struct CollectionSort {
+    /// The keys of the fields to sort by, the first one first (`S`)
+    #[pdf(key="S", default="vec![]")]
+    pub fields: Vec<String>,
+
+    /// Whether each field is sorted in ascending order (`A`), all of them if there is one value
+    #[pdf(key="A", default="vec![]")]
+    pub ascending: Vec<bool>,
+}
+
+/// The kind of an output intent (`S`), the standard it is for
+#[derive(Object, ObjectWrite, Debug, Clone, PartialEq, Eq)]
+pub enum OutputIntentSubtype {
+    #[pdf(name="GTS_PDFX")]
+    PdfX,
+    #[pdf(name="GTS_PDFA1")]
+    PdfA,
+    #[pdf(name="ISO_PDFE1")]
+    PdfE,
+    #[pdf(other)]
+    Other(String),
+}
+
+/// The color characteristics of the device the document is meant for (`OutputIntents` in the catalog)
 #[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type="OutputIntent?")]
+pub struct OutputIntent {
+    #[pdf(key="S")]
+    pub subtype: OutputIntentSubtype,
+
+    /// Description of the printing condition, for people
+    #[pdf(key="OutputCondition")]
+    pub output_condition: Option<PdfString>,
+
+    /// Name of the printing condition in the registry (`RegistryName`), like `FOGRA39`,
+    /// or `Custom` if the condition is only described by the profile
+    #[pdf(key="OutputConditionIdentifier")]
+    pub output_condition_identifier: PdfString,
+
+    /// URL of the registry of printing conditions, usually `http://www.color.org`
+    #[pdf(key="RegistryName")]
+    pub registry_name: Option<PdfString>,
+
+    #[pdf(key="Info")]
+    pub info: Option<PdfString>,
+
+    /// The ICC profile of the device; may be missing if the condition is in the registry
+    #[pdf(key="DestOutputProfile")]
+    pub dest_output_profile: Option<RcRef<Stream<IccInfo>>>,
+
+    #[pdf(other)]
+    pub other: Dictionary,
+}
+impl OutputIntent {
+    pub fn identifier(&self) -> String {
+        text(&self.output_condition_identifier)
+    }
+
+    pub fn condition(&self) -> Option<String> {
+        self.output_condition.as_ref().map(text)
+    }
+
+    /// The number of color components of the profile, like 4 for a CMYK printer
+    pub fn components(&self) -> Option<u32> {
+        self.dest_output_profile.as_ref().map(|profile| profile.info.components)
+    }
+
+    /// The ICC profile data, `None` if the intent doesn't have a profile
+    pub fn profile_data(&self) -> Result<Option<&[u8]>> {
+        match self.dest_output_profile {
+            Some(ref profile) => Ok(Some(t!(profile.data()))),
+            None => Ok(None)
+        }
+    }
+}
+
+/// Used only as elements in `FileSpec`
+#[derive(Object, ObjectWrite, Debug, Clone, Default)]
 pub struct Files<T: Object + ObjectWrite> {
     #[pdf(key="F")]
-    f: Option<T>,
+    pub f: Option<T>,
     #[pdf(key="UF")]
-    uf: Option<T>,
+    pub uf: Option<T>,
     #[pdf(key="DOS")]
-    dos: Option<T>,
+    pub dos: Option<T>,
     #[pdf(key="Mac")]
-    mac: Option<T>,
+    pub mac: Option<T>,
     #[pdf(key="Unix")]
-    unix: Option<T>,
+    pub unix: Option<T>,
 }
 
 /// PDF Embedded File Stream.
-#[derive(Object, Debug, Clone)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 pub struct EmbeddedFile {
-    /*
+    /// MIME type
     #[pdf(key="Subtype")]
-    subtype: Option<String>,
-    */
+    pub subtype: Option<String>,
+
     #[pdf(key="Params")]
-    params: Option<EmbeddedFileParamDict>,
+    pub params: Option<EmbeddedFileParamDict>,
 }
 
-#[derive(Object, Debug, Clone)]
+#[derive(Object, ObjectWrite, Debug, Clone)]
 pub struct EmbeddedFileParamDict {
     #[pdf(key="Size")]
-    size: Option<i32>,
-    /*
-    // TODO need Date type
+    pub size: Option<i32>,
+
     #[pdf(key="CreationDate")]
-    creationdate: T,
+    pub creation_date: Option<DateTime<FixedOffset>>,
+
     #[pdf(key="ModDate")]
-    moddate: T,
+    pub mod_date: Option<DateTime<FixedOffset>>,
+
+    /// MD5 of the file
+    #[pdf(key="CheckSum")]
+    pub checksum: Option<PdfString>,
+    /*
     #[pdf(key="Mac")]
     mac: T,
-    #[pdf(key="CheckSum")]
-    checksum: T,
     */
 }
 
-#[derive(Object, Debug, Clone)]
+/// An embedded file with its decoded contents, see `File::attachments`
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub name: String,
+    pub description: Option<String>,
+    /// MIME type
+    pub mime: Option<String>,
+    pub creation_date: Option<DateTime<FixedOffset>>,
+    pub mod_date: Option<DateTime<FixedOffset>>,
+    /// MD5 of the file, as stored in the document
+    pub checksum: Option<Vec<u8>>,
+    /// The page the file is attached to with a `FileAttachment` annotation,
+    /// `None` if it is attached to the document
+    pub page: Option<u32>,
+    pub spec: FileSpec,
+    pub data: Vec<u8>,
+}
+
+#[derive(Object, ObjectWrite, Debug, Clone)]
 pub struct OutlineItem {
     #[pdf(key="Title")]
     pub title: Option<PdfString>,
@@ -916,7 +2369,7 @@ pub struct OutlineItem {
     pub count:  i32,
 
     #[pdf(key="Dest")]
-    pub dest: Option<PdfString>,
+    pub dest: Option<MaybeNamedDest>,
 
     #[pdf(key="A")]
     pub action: Option<Dictionary>,
@@ -945,6 +2398,41 @@ pub struct Outlines {
 
 }
 
+/// An article thread, the way to read an article that continues across columns and pages
+#[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type="Thread?")]
+pub struct Thread {
+    /// The first bead (`F`)
+    #[pdf(key="F")]
+    pub first: Option<Ref<Bead>>,
+
+    /// Information about the article (`I`), like its title
+    #[pdf(key="I")]
+    pub info: Option<DocInfo>,
+}
+
+/// A part of an article thread on a page. The beads of a thread are a circular list.
+#[derive(Object, ObjectWrite, Debug, Clone)]
+#[pdf(Type="Bead?")]
+pub struct Bead {
+    /// The thread, only in the first bead (`T`)
+    #[pdf(key="T")]
+    pub thread: Option<Ref<Thread>>,
+
+    #[pdf(key="N")]
+    pub next: Ref<Bead>,
+
+    #[pdf(key="V")]
+    pub prev: Ref<Bead>,
+
+    #[pdf(key="P")]
+    pub page: Ref<Page>,
+
+    /// The area of the page (`R`)
+    #[pdf(key="R")]
+    pub rect: Rect,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Rect {
     pub left:   f32,
@@ -968,7 +2456,7 @@ impl Object for Rect {
 }
 impl ObjectWrite for Rect {
     fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
-        Primitive::array::<f32, _, _, _>([self.left, self.top, self.right, self.bottom].iter(), update)
+        Primitive::array::<f32, _, _, _>([self.left, self.bottom, self.right, self.top].iter(), update)
     }
 }
 
@@ -1010,7 +2498,7 @@ pub struct StructElem {
     page: Option<Ref<Page>>,
 }
 
-#[derive(Object, ObjectWrite, Debug)]
+#[derive(Object, ObjectWrite, Debug, Clone, PartialEq, Eq)]
 pub enum StructType {
     Document,
     Part,
@@ -1034,7 +2522,7 @@ pub enum StructType {
     H5,
     H6,
     L,
-    Ll,
+    LI,
     Lbl,
     LBody,
     Table,
@@ -1073,6 +2561,61 @@ mod tests {
         primitive::Primitive,
     };
 
+    #[test]
+    fn marked_content_properties() {
+        use std::collections::HashMap;
+        use std::rc::Rc;
+        use crate::object::{MarkedContentProperties, PlainRef, RcRef, Resources};
+        use crate::primitive::{Dictionary, PdfString};
+
+        let mut layer = Dictionary::new();
+        layer.insert("Type", Primitive::Name("OCG".into()));
+        layer.insert("Name", Primitive::String(PdfString::new(b"Layer 1".to_vec())));
+        let mut properties = HashMap::new();
+        properties.insert("oc1".to_string(), RcRef::new(PlainRef { id: 5, gen: 0 }, Rc::new(layer)));
+        let resources = Resources {
+            graphics_states: HashMap::new(),
+            color_spaces: HashMap::new(),
+            xobjects: HashMap::new(),
+            fonts: HashMap::new(),
+            properties,
+        };
+
+        let named = resources.marked_content_properties(&Primitive::Name("oc1".into()), &NoResolve).unwrap();
+        match named {
+            MarkedContentProperties::Group(group) => {
+                assert_eq!(group.name.as_bytes(), b"Layer 1");
+                assert!(group.intent.is_empty());
+            }
+            p => panic!("expected a group, got {:?}", p)
+        }
+
+        let mut inline = Dictionary::new();
+        inline.insert("MCID", Primitive::Integer(3));
+        let inline = resources.marked_content_properties(&Primitive::Dictionary(inline), &NoResolve).unwrap();
+        assert_eq!(inline.mcid(), Some(3));
+
+        assert!(resources.marked_content_properties(&Primitive::Name("oc2".into()), &NoResolve).is_err());
+    }
+
+    #[test]
+    fn page_label_styles() {
+        use crate::object::{Counter, PageLabel};
+        use crate::primitive::PdfString;
+
+        let label = |style, prefix: Option<&str>, start, offset| PageLabel {
+            style,
+            prefix: prefix.map(|p| PdfString::new(p.as_bytes().to_vec())),
+            start,
+        }.label(offset);
+        assert_eq!(label(Some(Counter::RomanLower), None, None, 3), "iv");
+        assert_eq!(label(Some(Counter::RomanUpper), None, Some(1990), 4), "MCMXCIV");
+        assert_eq!(label(Some(Counter::Arabic), Some("A-"), Some(3), 0), "A-3");
+        assert_eq!(label(Some(Counter::AlphaUpper), None, None, 27), "BB");
+        assert_eq!(label(Some(Counter::AlphaLower), None, None, 0), "a");
+        assert_eq!(label(None, Some("Cover"), None, 0), "Cover");
+    }
+
     #[test]
     fn parse_struct_type() {
         assert!(matches!(
@@ -1088,4 +2631,41 @@ mod tests {
             panic!("Incorrect result of {:?}", &result);
         }
     }
+
+    #[test]
+    fn file_spec_strings() {
+        use crate::object::{AFRelationship, FilePath, FileSpec};
+        use crate::primitive::{Dictionary, PdfString};
+
+        let path = |s: &str| FilePath::parse(s);
+        assert_eq!(path("/C/docs/a.pdf"), FilePath { absolute: true, components: vec!["C".into(), "docs".into(), "a.pdf".into()] });
+        assert_eq!(path("../b.pdf").components, ["..", "b.pdf"]);
+        assert_eq!(path(r"a\/b.pdf").components, ["a/b.pdf"]);
+        assert!(!path("b.pdf").absolute);
+
+        let string = |s: &str| Primitive::String(PdfString::new(s.as_bytes().to_vec()));
+        let spec = FileSpec::from_primitive(string("dir/plain.txt"), &NoResolve).unwrap();
+        assert_eq!(spec.file_name().as_deref(), Some("plain.txt"));
+        assert!(spec.embedded().is_none());
+
+        let mut dict = Dictionary::new();
+        dict.insert("Type", Primitive::name("Filespec"));
+        dict.insert("F", string("a.txt"));
+        dict.insert("UF", string("docs/b.txt"));
+        dict.insert("DOS", string(r"DOCS\C.TXT"));
+        dict.insert("V", Primitive::Boolean(true));
+        dict.insert("AFRelationship", Primitive::name("Source"));
+        let mut spec = FileSpec::from_primitive(Primitive::Dictionary(dict), &NoResolve).unwrap();
+        assert_eq!(spec.name().as_deref(), Some("docs/b.txt"));
+        assert_eq!(spec.file_name().as_deref(), Some("b.txt"));
+        assert_eq!(spec.volatile, Some(true));
+        assert_eq!(spec.relationship, Some(AFRelationship::Source));
+
+        spec.file = None;
+        spec.unicode_file = None;
+        assert_eq!(spec.file_name().as_deref(), Some("C.TXT"));
+        spec.file_system = Some("URL".into());
+        spec.file = Some(PdfString::new(b"https://example.com/c.pdf".to_vec()));
+        assert!(spec.path().is_none());
+    }
 }
@@ -0,0 +1,112 @@
+//! The document outline (bookmarks) as a tree.
+//!
+//! In the file the items of each level are a linked list (`First`, `Next`, `Last`),
+//! `outline` follows it into a tree of `Outline` nodes. Items that were already
+//! visited are skipped, so broken files with cycles in the list can't loop forever.
+use std::collections::HashSet;
+
+use crate::error::*;
+use crate::object::*;
+use crate::primitive::{Dictionary, PdfString};
+
+/// Outline item flags (`F`)
+const ITALIC: i32 = 1 << 0;
+const BOLD: i32 = 1 << 1;
+
+/// An item of the outline with its children, see `outline`
+#[derive(Debug, Clone)]
+pub struct Outline {
+    /// The outline item dictionary
+    pub r: Ref<OutlineItem>,
+    pub title: String,
+    /// Destination to jump to (`Dest`)
+    pub dest: Option<MaybeNamedDest>,
+    /// Action to perform instead of jumping to `dest` (`A`)
+    pub action: Option<Dictionary>,
+    /// Number of visible descendants if the item is open, negative if it is closed
+    pub count: i32,
+    /// Color of the title (`C`) as RGB
+    pub color: Option<Vec<f32>>,
+    /// Style flags of the title (`F`)
+    pub flags: i32,
+    pub kids: Vec<Outline>,
+}
+impl Outline {
+    /// Whether the children are shown
+    pub fn is_open(&self) -> bool {
+        self.count > 0
+    }
+    pub fn is_italic(&self) -> bool {
+        self.flags & ITALIC != 0
+    }
+    pub fn is_bold(&self) -> bool {
+        self.flags & BOLD != 0
+    }
+}
+
+/// The items of `outlines` as a tree, in document order
+pub fn outline(resolve: &impl Resolve, outlines: &Outlines) -> Result<Vec<Outline>> {
+    let mut visited = HashSet::new();
+    siblings(resolve, outlines.first, &mut visited, 0)
+}
+
+fn siblings(resolve: &impl Resolve, first: Option<Ref<OutlineItem>>, visited: &mut HashSet<PlainRef>, depth: usize) -> Result<Vec<Outline>> {
+    if depth > 64 {
+        bail!("outline is nested too deeply");
+    }
+    let mut items = vec![];
+    let mut next = first;
+    while let Some(r) = next {
+        if !visited.insert(r.get_inner()) {
+            warn!("outline item {:?} is linked more than once", r.get_inner());
+            break;
+        }
+        let item = t!(resolve.get(r));
+        items.push(Outline {
+            r,
+            title: item.title.as_ref().map(text).unwrap_or_default(),
+            dest: item.dest.clone(),
+            action: item.action.clone(),
+            count: item.count,
+            color: item.color.clone(),
+            flags: item.flags.unwrap_or(0),
+            kids: siblings(resolve, item.first, visited, depth + 1)?,
+        });
+        next = item.next;
+    }
+    Ok(items)
+}
+
+/// Iterate over `items` and their descendants in document order, with their depth
+pub fn iter(items: &[Outline]) -> Iter<'_> {
+    Iter { stack: vec![items.iter()] }
+}
+
+/// Iterator over an outline, see `iter`
+pub struct Iter<'a> {
+    stack: Vec<std::slice::Iter<'a, Outline>>,
+}
+impl<'a> Iterator for Iter<'a> {
+    type Item = (usize, &'a Outline);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let depth = self.stack.len().checked_sub(1)?;
+            match self.stack[depth].next() {
+                Some(item) => {
+                    self.stack.push(item.kids.iter());
+                    return Some((depth, item));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+fn text(s: &PdfString) -> String {
+    match s.as_str() {
+        Ok(s) => s.into_owned(),
+        Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+    }
+}
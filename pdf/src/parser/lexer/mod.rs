@@ -171,7 +171,7 @@ impl<'a> Lexer<'a> {
         if self.is_delimiter(pos) {
             if self.buf[pos] == b'/' {
                 pos = self.advance_pos(pos)?;
-                while !self.is_whitespace(pos) && !self.is_delimiter(pos) {
+                while pos < self.buf.len() && !self.is_whitespace(pos) && !self.is_delimiter(pos) {
                     pos = self.advance_pos(pos)?;
                 }
                 return Ok((self.new_substr(start_pos..pos), pos));
@@ -186,8 +186,8 @@ impl<'a> Lexer<'a> {
             return Ok((self.new_substr(start_pos..pos), pos));
         }
 
-        // Read to past the end of lexeme
-        while !self.is_whitespace(pos) && !self.is_delimiter(pos) {
+        // Read to past the end of lexeme (or the end of the buffer)
+        while pos < self.buf.len() && !self.is_whitespace(pos) && !self.is_delimiter(pos) {
             pos = self.advance_pos(pos)?;
         }
         let result = self.new_substr(start_pos..pos);
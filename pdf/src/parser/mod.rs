@@ -12,7 +12,7 @@ use crate::error::*;
 use crate::primitive::{Primitive, Dictionary, PdfStream, PdfString};
 use crate::object::{ObjNr, GenNr, PlainRef, Resolve};
 use self::lexer::{HexStringLexer, StringLexer};
-use crate::crypt::Decoder;
+use crate::crypt::{is_signature, Decoder};
 
 pub struct Context<'a> {
     pub decoder: Option<&'a Decoder>,
@@ -20,9 +20,29 @@ pub struct Context<'a> {
     pub gen_nr: u16
 }
 impl<'a> Context<'a> {
+    /// Decrypt a string of the object
     pub fn decrypt<'buf>(&self, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
         if let Some(ref decoder) = self.decoder {
-            decoder.decrypt(self.obj_nr, self.gen_nr, data)
+            decoder.decrypt_string(self.obj_nr, self.gen_nr, data)
+        } else {
+            Ok(data)
+        }
+    }
+    /// Decrypt a string of the object, keeping strings as they are if they can't be decrypted,
+    /// some writers leave single strings unencrypted
+    fn decrypt_string(&self, mut data: Vec<u8>) -> Vec<u8> {
+        match self.decrypt(&mut data) {
+            Ok(decrypted) => decrypted.to_vec(),
+            Err(e) => {
+                warn!("can't decrypt a string of object {} {}: {:?}", self.obj_nr, self.gen_nr, e);
+                data
+            }
+        }
+    }
+    /// Decrypt the data of the stream with the dictionary `info`
+    pub fn decrypt_stream<'buf>(&self, info: &Dictionary, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
+        if let Some(decoder) = self.decoder {
+            decoder.decrypt_stream(self.obj_nr, self.gen_nr, info, data)
         } else {
             Ok(data)
         }
@@ -47,8 +67,12 @@ fn parse_dictionary_object(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Con
         // Expect a Name (and Object) or the '>>' delimiter
         let token = t!(lexer.next());
         if token.starts_with(b"/") {
-            let key = token.reslice(1..).to_string();
-            let obj = t!(parse_with_lexer_ctx(lexer, r, ctx));
+            let key = decode_name(token.reslice(1..).as_slice());
+            // the Contents of signatures are not encrypted, which is only known at the end
+            let obj = match key.as_str() {
+                "Contents" => t!(parse_with_lexer_ctx(lexer, r, None)),
+                _ => t!(parse_with_lexer_ctx(lexer, r, ctx))
+            };
             dict.insert(key, obj);
         } else if token.equals(b">>") {
             break;
@@ -56,6 +80,12 @@ fn parse_dictionary_object(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Con
             err!(PdfError::UnexpectedLexeme{ pos: lexer.get_pos(), lexeme: token.to_string(), expected: "/ or >>"});
         }
     }
+    if let Some(ctx) = ctx {
+        if let (false, Some(Primitive::String(contents))) = (is_signature(&dict), dict.get("Contents")) {
+            let contents = ctx.decrypt_string(contents.as_bytes().to_vec());
+            dict.insert("Contents", Primitive::String(PdfString::new(contents)));
+        }
+    }
     Ok(dict)
 }
 
@@ -77,7 +107,7 @@ fn parse_stream_object(dict: Dictionary, lexer: &mut Lexer, r: &impl Resolve, ct
 
     // decrypt it
     if let Some(ctx) = ctx {
-        data = t!(ctx.decrypt(&mut data)).to_vec();
+        data = t!(ctx.decrypt_stream(&dict, &mut data)).to_vec();
     }
 
     Ok(PdfStream {
@@ -105,32 +135,38 @@ pub fn parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Co
         // First backup position
         let pos_bk = lexer.get_pos();
 
-        let second_lexeme = t!(lexer.next());
-        if second_lexeme.is_integer() {
-            let third_lexeme = t!(lexer.next());
-            if third_lexeme.equals(b"R") {
+        // The end of the data means it is a number
+        let gen = match lexer.next() {
+            Ok(second_lexeme) if second_lexeme.is_integer() => match lexer.next() {
+                Ok(third_lexeme) if third_lexeme.equals(b"R") => Some(second_lexeme),
+                Ok(_) => None,
+                Err(e) if e.is_eof() => None,
+                Err(e) => return Err(e)
+            },
+            Ok(_) => None,
+            Err(e) if e.is_eof() => None,
+            Err(e) => return Err(e)
+        };
+        match gen {
+            Some(gen) => {
                 // It is indeed a reference to an indirect object
                 Primitive::Reference (PlainRef {
                     id: t!(first_lexeme.to::<ObjNr>()),
-                    gen: t!(second_lexeme.to::<GenNr>()),
+                    gen: t!(gen.to::<GenNr>()),
                 })
-            } else {
-                // We are probably in an array of numbers - it's not a reference anyway
+            }
+            None => {
+                // It is but a number, or we are in an array of numbers
                 lexer.set_pos(pos_bk as usize); // (roll back the lexer first)
                 Primitive::Integer(t!(first_lexeme.to::<i32>()))
             }
-        } else {
-            // It is but a number
-            lexer.set_pos(pos_bk as usize); // (roll back the lexer first)
-            Primitive::Integer(t!(first_lexeme.to::<i32>()))
         }
     } else if first_lexeme.is_real_number() {
         // Real Number
         Primitive::Number (t!(first_lexeme.to::<f32>()))
     } else if first_lexeme.starts_with(b"/") {
         // Name
-        let s = first_lexeme.reslice(1..).to_string();
-        Primitive::Name(s)
+        Primitive::Name(decode_name(first_lexeme.reslice(1..).as_slice()))
     } else if first_lexeme.equals(b"[") {
         let mut array = Vec::new();
         // Array
@@ -161,7 +197,7 @@ pub fn parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Co
         lexer.offset_pos(bytes_traversed as usize);
         // decrypt it
         if let Some(ctx) = ctx {
-            string = t!(ctx.decrypt(&mut string)).to_vec();
+            string = ctx.decrypt_string(string);
         }
         Primitive::String (PdfString::new(string))
     } else if first_lexeme.equals(b"<") {
@@ -179,7 +215,7 @@ pub fn parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Co
 
         // decrypt it
         if let Some(ctx) = ctx {
-            string = t!(ctx.decrypt(&mut string)).to_vec();
+            string = ctx.decrypt_string(string);
         }
         Primitive::String (PdfString::new(string))
     } else if first_lexeme.equals(b"true") {
@@ -221,6 +257,31 @@ fn parse_stream_with_lexer(lexer: &mut Lexer, r: &impl Resolve, _ctx: Option<&Co
     Ok(obj)
 }
 
+/// Replace `#xx` escapes in a name
+fn decode_name(data: &[u8]) -> String {
+    if !data.contains(&b'#') {
+        return String::from_utf8_lossy(data).into_owned();
+    }
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let hex = data.get(i + 1 .. i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (data[i], hex) {
+            (b'#', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -284,4 +345,47 @@ mod tests {
         let array = primitive.into_array(&NoResolve).unwrap();
         assert!(array.is_empty());
     }
+
+    #[test]
+    fn escaped_name() {
+        use crate::object::NoResolve;
+        use crate::primitive::Primitive;
+
+        let name = "A#B (c)/d\u{e9}";
+        let mut data = vec![];
+        Primitive::name(name).serialize(&mut data, 0).unwrap();
+        assert_eq!(data, b"/A#23B#20#28c#29#2Fd#C3#A9");
+        let primitive = super::parse(&data, &NoResolve).unwrap();
+        assert_eq!(primitive.as_name().unwrap(), name);
+    }
+
+    #[test]
+    fn encrypted_strings() {
+        use crate::crypt::{CryptMethod, Decoder};
+        use crate::object::NoResolve;
+
+        let decoder = Decoder::new([1; 32], 16, CryptMethod::AESV2, true);
+        let ctx = super::Context { decoder: Some(&decoder), obj_nr: 7, gen_nr: 0 };
+        let hex = |s: &[u8]| -> String {
+            decoder.encrypt(7, 0, s).unwrap().iter().map(|b| format!("{:02x}", b)).collect()
+        };
+        let parse = |data: String| {
+            let mut lexer = super::Lexer::new(data.as_bytes());
+            super::parse_with_lexer_ctx(&mut lexer, &NoResolve, Some(&ctx)).unwrap().into_dictionary(&NoResolve).unwrap()
+        };
+        let string = |dict: &super::Dictionary, key| dict.get(key).unwrap().as_string().unwrap().as_bytes().to_vec();
+
+        let annot = parse(format!("<</Type/Annot/Contents<{}>/T[<{}>]>>", hex(b"a comment"), hex(b"author")));
+        assert_eq!(string(&annot, "Contents"), b"a comment");
+        assert_eq!(annot.get("T").unwrap().clone().into_array(&NoResolve).unwrap()[0].as_string().unwrap().as_bytes(), b"author");
+
+        // the Contents of signatures are not encrypted, other strings are
+        let sig = parse(format!("<</ByteRange[0 1 2 3]/Contents<30820102>/Reason<{}>>>", hex(b"approved")));
+        assert_eq!(string(&sig, "Contents"), [0x30, 0x82, 0x01, 0x02]);
+        assert_eq!(string(&sig, "Reason"), b"approved");
+
+        // strings that can't be decrypted are kept
+        let plain = parse("<</Title(not encrypted)>>".into());
+        assert_eq!(string(&plain, "Title"), b"not encrypted");
+    }
 }
@@ -0,0 +1,192 @@
+//! Portfolios: documents whose contents are their embedded files (`Collection`, 12.3.5).
+//!
+//! The collection describes the members with fields (the schema), like a name, a date or
+//! a number for every file, says how they are sorted and which one is shown first.
+//! `portfolio` reads the members from the `EmbeddedFiles` name tree in that order.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::error::*;
+use crate::object::*;
+use crate::primitive::{Dictionary, PdfString, Primitive};
+
+/// The value of a collection field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Date(DateTime<FixedOffset>),
+    Number(f32),
+}
+impl Value {
+    fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Text(a), Value::Text(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+            (Value::Date(a), Value::Date(b)) => a.cmp(b),
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            _ => Ordering::Equal
+        }
+    }
+}
+
+/// A file of a portfolio, see `portfolio`
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// The name in the `EmbeddedFiles` name tree
+    pub key: PdfString,
+    pub spec: FileSpec,
+    /// The values of the fields of the schema, by the key of the field
+    pub values: HashMap<String, Value>,
+    /// Text shown before a value, which isn't used for sorting (`P`)
+    pub prefixes: HashMap<String, String>,
+}
+impl Member {
+    /// The value of the field `key`
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+    /// The contents of the file, `None` if it isn't embedded
+    pub fn data(&self, resolve: &impl Resolve) -> Result<Option<Vec<u8>>> {
+        match self.spec.embedded() {
+            Some(r) => Ok(Some(t!(t!(resolve.get(r)).data()).to_vec())),
+            None => Ok(None)
+        }
+    }
+}
+
+/// The members of a portfolio and how to show them, see `portfolio`
+#[derive(Debug, Clone)]
+pub struct Portfolio {
+    pub view: Option<CollectionView>,
+    /// The fields of the schema with their keys, in the order they are shown
+    pub fields: Vec<(String, CollectionField)>,
+    /// In the order of `Sort`, or of the name tree if there is none
+    pub members: Vec<Member>,
+    /// Index of the member that is shown first (`D`)
+    pub initial: Option<usize>,
+}
+impl Portfolio {
+    pub fn initial(&self) -> Option<&Member> {
+        self.members.get(self.initial?)
+    }
+}
+
+/// The portfolio of the document with `catalog`, `None` if it doesn't have a `Collection`
+pub fn portfolio(resolve: &impl Resolve, catalog: &Catalog) -> Result<Option<Portfolio>> {
+    let collection = match catalog.collection {
+        Some(ref collection) => collection,
+        None => return Ok(None)
+    };
+    let fields = t!(collection.fields(resolve));
+    let entries = match catalog.names.as_ref().and_then(|names| names.embedded_files.as_ref()) {
+        Some(tree) => t!(tree.entries(resolve)),
+        None => vec![]
+    };
+    let mut members = Vec::with_capacity(entries.len());
+    for (key, spec) in entries {
+        let mut member = Member { key, spec, values: HashMap::new(), prefixes: HashMap::new() };
+        for (field_key, field) in &fields {
+            if let Some(value) = t!(file_value(resolve, &member.spec, &field.subtype)) {
+                member.values.insert(field_key.clone(), value);
+            }
+        }
+        if let Some(item) = member.spec.collection_item.clone() {
+            read_item(resolve, &item, &fields, &mut member);
+        }
+        members.push(member);
+    }
+
+    if let Some(ref sort) = collection.sort {
+        members.sort_by(|a, b| {
+            for (i, key) in sort.fields.iter().enumerate() {
+                let ascending = sort.ascending.get(i).or_else(|| sort.ascending.first()).copied().unwrap_or(true);
+                // members without a value at the end
+                let order = match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) if ascending => a.compare(b),
+                    (Some(a), Some(b)) => b.compare(a),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal
+                };
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            Ordering::Equal
+        });
+    }
+    let initial = collection.initial.as_ref()
+        .and_then(|key| members.iter().position(|m| m.key.as_bytes() == key.as_bytes()));
+
+    Ok(Some(Portfolio {
+        view: collection.view.clone(),
+        fields,
+        members,
+        initial,
+    }))
+}
+
+/// The values of fields that are taken from the file, not from the `CI` dictionary
+fn file_value(resolve: &impl Resolve, spec: &FileSpec, subtype: &str) -> Result<Option<Value>> {
+    let params = || -> Result<Option<EmbeddedFileParamDict>> {
+        match spec.embedded() {
+            Some(r) => Ok(t!(resolve.get(r)).params.clone()),
+            None => Ok(None)
+        }
+    };
+    Ok(match subtype {
+        "F" => spec.file_name().map(Value::Text),
+        "Desc" => spec.description_text().map(Value::Text),
+        "ModDate" => params()?.and_then(|p| p.mod_date).map(Value::Date),
+        "CreationDate" => params()?.and_then(|p| p.creation_date).map(Value::Date),
+        "Size" => params()?.and_then(|p| p.size).map(|size| Value::Number(size as f32)),
+        _ => None
+    })
+}
+
+/// Read the values of the text, date and number fields from the `CI` dictionary
+fn read_item(resolve: &impl Resolve, item: &Dictionary, fields: &[(String, CollectionField)], member: &mut Member) {
+    for (key, field) in fields {
+        let mut p = match item.get(key) {
+            Some(&Primitive::Reference(r)) => match resolve.resolve(r) {
+                Ok(p) => p,
+                Err(e) => {
+                    warn!("ignoring the value of the collection field {}: {}", key, e);
+                    continue;
+                }
+            },
+            Some(p) => p.clone(),
+            None => continue
+        };
+        // a collection subitem, with a prefix
+        if let Ok(mut sub) = p.clone().into_dictionary(resolve) {
+            if let Some(Primitive::String(prefix)) = sub.remove("P") {
+                member.prefixes.insert(key.clone(), text(&prefix));
+            }
+            match sub.remove("D") {
+                Some(d) => p = d,
+                None => continue
+            }
+        }
+        let value = match field.subtype.as_str() {
+            "S" => p.into_string().map(|s| Value::Text(text(&s))),
+            "D" => DateTime::from_primitive(p, resolve).map(Value::Date),
+            "N" => p.as_number().map(Value::Number),
+            _ => continue
+        };
+        match value {
+            Ok(value) => {
+                member.values.insert(key.clone(), value);
+            }
+            Err(e) => warn!("ignoring the value of the collection field {}: {}", key, e)
+        }
+    }
+}
+
+fn text(s: &PdfString) -> String {
+    match s.as_str() {
+        Ok(s) => s.into_owned(),
+        Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+    }
+}
@@ -3,7 +3,7 @@ use crate::object::{PlainRef, Resolve, Object, NoResolve, ObjectWrite, Updater};
 
 use std::collections::{btree_map, BTreeMap};
 use std::{str, fmt, io};
-use std::ops::{Index, Range};
+use std::ops::Index;
 use chrono::{DateTime, FixedOffset};
 use std::ops::Deref;
 use std::convert::TryInto;
@@ -83,13 +83,12 @@ fn serialize_list(arr: &[Primitive], out: &mut impl io::Write, level: usize) ->
 
 pub fn serialize_name(s: &str, out: &mut impl io::Write) -> Result<()> {
     write!(out, "/")?;
-    for b in s.chars() {
+    for &b in s.as_bytes() {
         match b {
-            '\\' | '(' | ')' => write!(out, r"\")?,
-            c if c > '~' => panic!("only ASCII"),
-            _ => ()
+            b'#' | b'/' | b'%' | b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' => write!(out, "#{:02X}", b)?,
+            0x21 ..= 0x7E => out.write_all(&[b])?,
+            _ => write!(out, "#{:02X}", b)?
         }
-        write!(out, "{}", b)?;
     }
     Ok(())
 }
@@ -132,6 +131,9 @@ impl Dictionary {
     pub fn iter(&self) -> btree_map::Iter<String, Primitive> {
         self.dict.iter()
     }
+    pub fn iter_mut(&mut self) -> btree_map::IterMut<'_, String, Primitive> {
+        self.dict.iter_mut()
+    }
     pub fn remove(&mut self, key: &str) -> Option<Primitive> {
         self.dict.remove(key)
     }
@@ -180,7 +182,9 @@ impl Dictionary {
     fn serialize(&self, out: &mut impl io::Write, level: usize) -> Result<()> {
         write!(out, "<<\n")?;
         for (key, val) in self.iter() {
-            write!(out, "{:w$}/{} ", "", key, w=2*level+2)?;
+            write!(out, "{:w$}", "", w=2*level+2)?;
+            serialize_name(key, out)?;
+            write!(out, " ")?;
             val.serialize(out, level+2)?;
             out.write_all(b"\n")?;
         }
@@ -238,6 +242,11 @@ impl Object for PdfStream {
         }
     }
 }
+impl ObjectWrite for PdfStream {
+    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+        Ok(Primitive::Stream(self.clone()))
+    }
+}
 impl PdfStream {
     pub fn serialize(&self, out: &mut impl io::Write) -> Result<()> {
         self.info.serialize(out, 0)?;
@@ -306,6 +315,10 @@ impl PdfString {
             for &b in &self.data {
                 match b {
                     b'\\' | b'(' | b')' => write!(out, r"\")?,
+                    b'\r' => {
+                        write!(out, r"\r")?;
+                        continue;
+                    }
                     _ => ()
                 }
                 out.write_all(&[b])?;
@@ -581,48 +594,113 @@ impl<'a> TryInto<String> for &'a Primitive {
     }
 }
 
-fn parse_or<T: str::FromStr + Clone>(buffer: &str, range: Range<usize>, default: T) -> T {
-    buffer.get(range)
-        .map(|s| str::parse::<T>(s).unwrap_or_else(|_| default.clone()))
-        .unwrap_or(default)
-}
+/// Parse a date (`D:YYYYMMDDHHmmSSOHH'mm'`).
+///
+/// Everything after the year is optional. Real-world sloppiness is accepted: a missing `D:`,
+/// `+HHmm` or `+HH:mm` offsets, a missing or extra `'`, zero months and days, and the years
+/// after 1999 that some old writers print as `19100`, `19101`, ….
+pub fn parse_date(s: &str) -> Result<DateTime<FixedOffset>> {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 
-impl Object for DateTime<FixedOffset> {
-    fn from_primitive(p: Primitive, _: &impl Resolve) -> Result<Self> {
-        use chrono::{NaiveDateTime, NaiveDate, NaiveTime};
-        match p {
-            Primitive::String (PdfString {data}) => {
-                let s = str::from_utf8(&data)?;
-                let len = s.len();
-                if len > 2 && &s[0..2] == "D:" {
+    let date = s.trim().trim_end_matches('\0');
+    let date = date.strip_prefix("D:").unwrap_or(date).trim_start();
+    let digits = date.find(|c: char| !c.is_ascii_digit()).unwrap_or(date.len());
+    let (mut fields, rest) = date.split_at(digits);
 
-                    let year = match s.get(2..6) {
-                        Some(year) => {
-                            str::parse::<i32>(year)?
-                        }
-                        None => bail!("Missing obligatory year in date")
-                    };
-                    let month = parse_or(s, 6..8, 1);
-                    let day = parse_or(s, 8..10, 1);
-                    let hour = parse_or(s, 10..12, 0);
-                    let minute = parse_or(s, 12..14, 0);
-                    let second = parse_or(s, 14..16, 0);
-                    let tz_hour = parse_or(s, 16..18, 0);
-                    let tz_minute = parse_or(s, 19..21, 0);
-                    let tz = FixedOffset::east(tz_hour * 60 + tz_minute);
+    let year = match fields.len() {
+        0 ..= 3 => bail!("invalid date {:?}: no year", s),
+        // the year 2000 as 19100
+        n if n % 2 == 1 && fields.starts_with("191") => {
+            let year = 1900 + fields[2..5].parse::<i32>()?;
+            fields = &fields[5..];
+            year
+        }
+        _ => {
+            let year = fields[..4].parse::<i32>()?;
+            fields = &fields[4..];
+            year
+        }
+    };
+    let mut next = |default: u32| match fields.get(..2) {
+        Some(field) => {
+            fields = &fields[2..];
+            field.parse::<u32>().unwrap_or(default)
+        }
+        None => default
+    };
+    let month = next(1).max(1);
+    let day = next(1).max(1);
+    let hour = next(0);
+    let minute = next(0);
+    let second = next(0).min(59);
 
-                    Ok(DateTime::from_utc(
-                            NaiveDateTime::new(NaiveDate::from_ymd(year, month, day),
-                                               NaiveTime::from_hms(hour, minute, second)),
-                          tz
-                      ))
+    // the time is local, `+HH'mm'` or `-HH'mm'` after it gives the offset to UT
+    let rest = rest.trim();
+    let sign = match rest.chars().next() {
+        Some('-') => -1,
+        _ => 1
+    };
+    let offset_digits: String = rest.trim_start_matches(['+', '-'])
+        .chars()
+        .filter(|&c| c != '\'' && c != ':')
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    let tz_hour = offset_digits.get(..2).and_then(|h| h.parse::<i32>().ok()).unwrap_or(0);
+    let tz_minute = offset_digits.get(2..4).and_then(|m| m.parse::<i32>().ok()).unwrap_or(0);
+    let tz = FixedOffset::east_opt(sign * (tz_hour * 3600 + tz_minute * 60));
 
-                } else {
-                    bail!("Failed parsing date");
-                }
-            }
+    let date = NaiveDate::from_ymd_opt(year, month, day);
+    let time = NaiveTime::from_hms_opt(hour, minute, second);
+    match (date, time, tz) {
+        (Some(date), Some(time), Some(tz)) => {
+            let local = NaiveDateTime::new(date, time);
+            Ok(DateTime::from_naive_utc_and_offset(local - tz, tz))
+        }
+        _ => bail!("invalid date {:?}", s)
+    }
+}
+
+impl Object for DateTime<FixedOffset> {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Reference(r) => DateTime::from_primitive(resolve.resolve(r)?, resolve),
+            Primitive::String(ref s) => match s.as_str() {
+                Ok(date) => parse_date(&date),
+                Err(_) => parse_date(&String::from_utf8_lossy(s.as_bytes())),
+            },
             _ => unexpected_primitive!(String, p.get_debug_name()),
         }
     }
 }
+impl ObjectWrite for DateTime<FixedOffset> {
+    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+        let offset = self.offset().local_minus_utc() / 60;
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.abs();
+        let s = format!("D:{}{}{:02}'{:02}'", self.format("%Y%m%d%H%M%S"), sign, offset / 60, offset % 60);
+        Ok(Primitive::String(PdfString::new(s.into_bytes())))
+    }
+}
+
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sloppy_dates() {
+        let date = |s: &str| parse_date(s).unwrap().to_rfc3339();
+        assert_eq!(date("D:20210304050607+02'00'"), "2021-03-04T05:06:07+02:00");
+        assert_eq!(date("D:20210304050607-05'30"), "2021-03-04T05:06:07-05:30");
+        assert_eq!(date("D:20210304050607+0530"), "2021-03-04T05:06:07+05:30");
+        assert_eq!(date("D:20210304050607+05:30"), "2021-03-04T05:06:07+05:30");
+        assert_eq!(date("D:20210304050607Z00'00'"), "2021-03-04T05:06:07+00:00");
+        assert_eq!(date("20210304050607Z"), "2021-03-04T05:06:07+00:00");
+        assert_eq!(date("D:2021"), "2021-01-01T00:00:00+00:00");
+        assert_eq!(date("D:202100001230 "), "2021-01-01T12:30:00+00:00");
+        assert_eq!(date("D:191100102030405"), "2010-01-02T03:04:05+00:00");
+        assert!(parse_date("D:20").is_err());
+        assert!(parse_date("yesterday").is_err());
+        assert!(parse_date("D:20211304").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Applying redaction annotations (`Redact`, 12.5.6.23).
+//!
+//! A redaction annotation only marks a region. Applying it removes what is drawn there from
+//! the content stream of the page, draws the overlay (`RO`) or fills the region with the
+//! interior color, and removes the annotation. `redact_content` does the first part.
+//!
+//! Glyphs are removed when the center of their box is in a region; the rest of the string is
+//! moved with `TJ` adjustments, so it stays where it was. Text whose glyphs can't be placed,
+//! because its font is missing or can't be loaded, is removed as a whole if there is any
+//! region. Images and form XObjects that touch a region are removed as a whole. Paths are kept.
+use crate::backend::Backend;
+use crate::build::stamp;
+use crate::content::{Cmyk, Color, Content, Matrix, Op, Rect as ViewRect, Rgb, TextDrawAdjusted, Winding};
+use crate::error::*;
+use crate::file::File;
+use crate::graphics::{bounding_box, Event, GraphicsStateMachine};
+use crate::object::*;
+use crate::primitive::{PdfString, Primitive};
+use crate::text::{text_box, TextItem};
+
+/// Apply the redaction annotations of page `page_nr` (counting from 0), and return how many there were.
+pub fn redact_page<B: Backend>(file: &mut File<B>, page_nr: u32) -> Result<usize> {
+    let page = t!(file.get_page(page_nr));
+    let mut redactions = vec![];
+    let mut annotations = vec![];
+    for p in page.annotations.iter().flatten() {
+        match Annot::from_primitive(p.clone(), file) {
+            Ok(Annot::Redact(dict, redact)) => redactions.push((dict, redact)),
+            Ok(_) => annotations.push(p.clone()),
+            Err(e) => {
+                warn!("keeping an annotation that can't be read: {}", e);
+                annotations.push(p.clone());
+            }
+        }
+    }
+    if redactions.is_empty() {
+        return Ok(0);
+    }
+    let areas: Vec<Rect> = redactions.iter().flat_map(|(dict, redact)| redact.areas(dict.rect)).collect();
+
+    let mut redacted = (*page).clone();
+    if let Some(ref contents) = page.contents {
+        redacted.contents = Some(t!(redact_content(file, contents, t!(page.resources()), &areas)));
+    }
+    for (dict, redact) in &redactions {
+        if let Some(overlay) = t!(overlay(file, redact)) {
+            let transform = match *t!(file.get(overlay)) {
+                XObject::Form(ref form) => dict.appearance_matrix(form),
+                _ => continue
+            };
+            t!(stamp(&mut redacted, overlay, transform));
+            continue;
+        }
+        let color = match redact.interior_color.as_deref() {
+            None => Color::Gray(0.),
+            Some(&[gray]) => Color::Gray(gray),
+            Some(&[red, green, blue]) => Color::Rgb(Rgb { red, green, blue }),
+            Some(&[cyan, magenta, yellow, key]) => Color::Cmyk(Cmyk { cyan, magenta, yellow, key }),
+            // transparent
+            Some(_) => continue
+        };
+        let mut ops = vec![Op::FillColor { color }];
+        for area in redact.areas(dict.rect) {
+            ops.push(Op::Rect { rect: ViewRect {
+                x: area.left,
+                y: area.bottom,
+                width: area.right - area.left,
+                height: area.top - area.bottom,
+            } });
+        }
+        ops.push(Op::Fill { winding: Winding::NonZero });
+        t!(redacted.append_content(ops));
+    }
+    redacted.annotations = if annotations.is_empty() { None } else { Some(annotations) };
+    t!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(redacted)));
+    Ok(redactions.len())
+}
+
+/// Apply the redaction annotations of all pages, and return how many there were.
+pub fn apply_redactions<B: Backend>(file: &mut File<B>) -> Result<usize> {
+    let mut count = 0;
+    for page_nr in 0 .. file.num_pages() {
+        count += redact_page(file, page_nr)?;
+    }
+    Ok(count)
+}
+
+/// `content` (drawn with `resources`) without what it draws in `areas`, in default user space.
+pub fn redact_content<B: Backend>(file: &File<B>, content: &Content, resources: &Resources, areas: &[Rect]) -> Result<Content> {
+    let mut machine = GraphicsStateMachine::new(Matrix::default());
+    content.rewrite(|op| {
+        let events = machine.apply(&op, file, resources);
+        let items: Vec<TextItem> = events.iter().filter_map(|event| match event {
+            Event::Text(item) => Some(item.clone()),
+            _ => None
+        }).collect();
+        match op {
+            // without glyph positions any of the text may be in an area, so all of it goes
+            Op::TextDraw { .. } | Op::TextDrawAdjusted { .. } if !areas.is_empty() && items.len() < strings(&op) => None,
+            Op::TextDraw { ref text } => Some(match redact_text(&[TextDrawAdjusted::Text(text.clone())], &items, areas) {
+                Some(array) => Op::TextDrawAdjusted { array },
+                None => op
+            }),
+            Op::TextDrawAdjusted { ref array } => Some(match redact_text(array, &items, areas) {
+                Some(array) => Op::TextDrawAdjusted { array },
+                None => op
+            }),
+            Op::XObject { .. } | Op::InlineImage { .. } => {
+                let covered = events.iter().any(|event| match *event {
+                    Event::Image { transform, .. } | Event::InlineImage { transform, .. } =>
+                        touches(bounding_box(transform, Rect { left: 0., bottom: 0., right: 1., top: 1. }), areas),
+                    Event::Form { ref name, transform } => match resources.xobjects.get(name).map(|&r| file.get(r)) {
+                        Some(Ok(xobject)) => match *xobject {
+                            XObject::Form(ref form) => touches(bounding_box(transform, form.dict().bbox), areas),
+                            _ => false
+                        },
+                        _ => false
+                    },
+                    _ => false
+                });
+                if covered { None } else { Some(op) }
+            }
+            op => Some(op)
+        }
+    })
+}
+
+/// The number of strings `op` shows
+fn strings(op: &Op) -> usize {
+    match *op {
+        Op::TextDraw { .. } => 1,
+        Op::TextDrawAdjusted { ref array } => array.iter().filter(|part| matches!(part, TextDrawAdjusted::Text(_))).count(),
+        _ => 0
+    }
+}
+
+/// The parts of a `TJ` array without the glyphs in `areas`, `None` if none are.
+///
+/// `items` are the strings of `parts` as shown by the text state, one for each.
+fn redact_text(parts: &[TextDrawAdjusted], items: &[TextItem], areas: &[Rect]) -> Option<Vec<TextDrawAdjusted>> {
+    let mut items = items.iter();
+    let mut out = vec![];
+    let mut changed = false;
+    for part in parts {
+        let text = match *part {
+            TextDrawAdjusted::Text(ref text) => text,
+            TextDrawAdjusted::Spacing(n) => {
+                push_spacing(&mut out, n);
+                continue;
+            }
+        };
+        let item = match items.next() {
+            Some(item) => item,
+            None => break
+        };
+        let mut kept = vec![];
+        for (code, glyph) in item.font.font.split_codes(text.as_bytes()).zip(&item.glyphs) {
+            let b = text_box(&item.transform, &item.font, glyph.offset, glyph.offset + glyph.width);
+            let (x, y) = ((b.left + b.right) / 2., (b.bottom + b.top) / 2.);
+            if areas.iter().any(|a| a.left <= x && x <= a.right && a.bottom <= y && y <= a.top) {
+                changed = true;
+                if !kept.is_empty() {
+                    out.push(TextDrawAdjusted::Text(PdfString::new(std::mem::take(&mut kept))));
+                }
+                // move by the advance of the glyph instead
+                push_spacing(&mut out, -glyph.width * 1000.);
+            } else {
+                kept.extend_from_slice(code);
+            }
+        }
+        if !kept.is_empty() {
+            out.push(TextDrawAdjusted::Text(PdfString::new(kept)));
+        }
+    }
+    if changed { Some(out) } else { None }
+}
+
+fn push_spacing(parts: &mut Vec<TextDrawAdjusted>, n: f32) {
+    match parts.last_mut() {
+        Some(TextDrawAdjusted::Spacing(ref mut m)) => *m += n,
+        _ => parts.push(TextDrawAdjusted::Spacing(n))
+    }
+}
+
+/// The overlay XObject (`RO`) of `redact`
+fn overlay(resolve: &impl Resolve, redact: &RedactAnnot) -> Result<Option<Ref<XObject>>> {
+    match redact.overlay {
+        Some(Primitive::Reference(r)) => match *t!(resolve.get(Ref::<XObject>::new(r))) {
+            XObject::Form(_) => Ok(Some(Ref::new(r))),
+            _ => Ok(None)
+        },
+        Some(_) => {
+            warn!("ignoring an overlay of a redaction that isn't a reference");
+            Ok(None)
+        }
+        None => Ok(None)
+    }
+}
+
+/// Whether `rect` overlaps one of `areas`
+fn touches(rect: Rect, areas: &[Rect]) -> bool {
+    areas.iter().any(|a| rect.left < a.right && a.left < rect.right && rect.bottom < a.top && a.bottom < rect.top)
+}
@@ -0,0 +1,378 @@
+//! Signing documents and reading their signatures.
+//!
+//! `sign` adds a signature field whose signature dictionary has a `ByteRange` placeholder
+//! and a gap for `Contents`, writes the file, and passes the bytes outside the gap to a callback.
+//! The callback creates the signature (usually a detached PKCS#7 / CMS signature,
+//! from a key that may live in an HSM), and it is patched into the gap.
+//!
+//! `signatures` goes the other way: it reads the signature dictionaries, and
+//! `Signature::digest` hashes the bytes they cover. Checking the CMS signature against
+//! the digest and the certificates is left to the caller.
+use chrono::{DateTime, FixedOffset};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::backend::Backend;
+use crate::build::text_string;
+use crate::error::*;
+use crate::file::{File, SaveOptions};
+use crate::form::{self, FieldType};
+use crate::object::*;
+use crate::primitive::{Dictionary, PdfString, Primitive};
+
+/// `ByteRange` as written before the ranges are known,
+/// wide enough for any offset with padding
+const BYTE_RANGE_PLACEHOLDER: &[u8] = b"[0 2147483647 2147483647 2147483647]";
+
+/// Where and how the signature is added, see `sign`.
+#[derive(Debug, Clone)]
+pub struct SignatureOptions {
+    /// Partial name (`T`) of the new signature field
+    pub field_name: String,
+    /// Page of the widget annotation
+    pub page: u32,
+    /// Position of the widget on the page, `None` for an invisible signature
+    pub rect: Option<Rect>,
+    /// `SubFilter` of the signature dictionary, like `adbe.pkcs7.detached` or `ETSI.CAdES.detached`
+    pub sub_filter: String,
+    /// Name of the signer (`Name`)
+    pub name: Option<String>,
+    /// `Reason`
+    pub reason: Option<String>,
+    /// `Location`
+    pub location: Option<String>,
+    /// `ContactInfo`
+    pub contact_info: Option<String>,
+    /// Time of signing (`M`)
+    pub date: Option<DateTime<FixedOffset>>,
+    /// Bytes reserved for the signature
+    pub contents_size: usize,
+    /// Append the signature as an incremental update, so earlier signatures stay valid
+    pub incremental: bool,
+}
+impl SignatureOptions {
+    /// An invisible `adbe.pkcs7.detached` signature on the first page,
+    /// with 8 KiB reserved for the signature, added as an incremental update
+    pub fn new(field_name: &str) -> SignatureOptions {
+        SignatureOptions {
+            field_name: field_name.into(),
+            page: 0,
+            rect: None,
+            sub_filter: "adbe.pkcs7.detached".into(),
+            name: None,
+            reason: None,
+            location: None,
+            contact_info: None,
+            date: None,
+            contents_size: 8192,
+            incremental: true,
+        }
+    }
+}
+
+/// The bytes covered by a signature: the whole file except the `Contents` gap
+pub struct SignedData<'a> {
+    parts: [&'a [u8]; 2],
+}
+impl<'a> SignedData<'a> {
+    /// The bytes before and after the gap
+    pub fn parts(&self) -> [&'a [u8]; 2] {
+        self.parts
+    }
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.parts.concat()
+    }
+    /// SHA-256 digest of the covered bytes
+    pub fn sha256(&self) -> [u8; 32] {
+        let mut hash = Sha256::new();
+        hash.update(self.parts[0]);
+        hash.update(self.parts[1]);
+        hash.finalize().into()
+    }
+}
+
+/// Hash functions for `Signature::digest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+/// A signature of the document, see `signatures`
+#[derive(Debug, Clone)]
+pub struct Signature {
+    /// Fully qualified name of the signature field
+    pub field_name: String,
+    /// The signature dictionary, for the entries that aren't read
+    pub dict: Dictionary,
+    /// The handler (`Filter`), like `Adobe.PPKLite`
+    pub filter: Option<String>,
+    /// The format of `contents` (`SubFilter`), like `adbe.pkcs7.detached` or `ETSI.CAdES.detached`
+    pub sub_filter: Option<String>,
+    /// The signed parts of the file as offset and length (`ByteRange`)
+    pub byte_range: Vec<(usize, usize)>,
+    /// The DER encoded signature (`Contents`) without the padding of the gap
+    pub contents: Vec<u8>,
+    /// The certificates of `adbe.x509.rsa_sha1` signatures (`Cert`), DER encoded
+    pub certificates: Vec<Vec<u8>>,
+    pub name: Option<String>,
+    pub reason: Option<String>,
+    pub location: Option<String>,
+    pub contact_info: Option<String>,
+    /// Time of signing (`M`), as claimed by the signer
+    pub date: Option<DateTime<FixedOffset>>,
+}
+impl Signature {
+    /// The parts of `data`, the file as it was read (see `File::raw_data`), that are signed
+    pub fn signed_data<'a>(&self, data: &'a [u8]) -> Result<Vec<&'a [u8]>> {
+        let mut parts = Vec::with_capacity(self.byte_range.len());
+        for &(start, len) in &self.byte_range {
+            match start.checked_add(len) {
+                Some(end) if end <= data.len() => parts.push(&data[start .. end]),
+                _ => bail!("the ByteRange {} + {} is outside of the file of {} bytes", start, len, data.len())
+            }
+        }
+        Ok(parts)
+    }
+
+    /// The digest of the signed parts of `data`, to compare with the one in the signature
+    pub fn digest(&self, data: &[u8], algorithm: DigestAlgorithm) -> Result<Vec<u8>> {
+        fn hash<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+            let mut hash = D::new();
+            for part in parts {
+                hash.update(part);
+            }
+            hash.finalize().to_vec()
+        }
+        let parts = self.signed_data(data)?;
+        Ok(match algorithm {
+            DigestAlgorithm::Sha256 => hash::<Sha256>(&parts),
+            DigestAlgorithm::Sha384 => hash::<Sha384>(&parts),
+            DigestAlgorithm::Sha512 => hash::<Sha512>(&parts),
+        })
+    }
+
+    /// Whether the signature covers all of `data` except the `Contents` string.
+    ///
+    /// This is false for signatures of earlier revisions, when changes were appended
+    /// after signing, and for byte ranges that leave out more than the signature.
+    pub fn covers_whole_file(&self, data: &[u8]) -> bool {
+        match self.byte_range[..] {
+            [(0, first), (second, len)] => {
+                second.checked_add(len) == Some(data.len())
+                    && first < second
+                    && data[first] == b'<'
+                    && data[second - 1] == b'>'
+                    && data[first + 1 .. second - 1].iter().all(u8::is_ascii_hexdigit)
+            }
+            _ => false
+        }
+    }
+}
+
+/// The signatures of the signed signature fields of `file`, in the order of the form
+pub fn signatures<B: Backend>(file: &File<B>) -> Result<Vec<Signature>> {
+    let mut signatures = vec![];
+    for field in form::fields(file)? {
+        for field in field.descendants() {
+            if field.field_type != Some(FieldType::Signature) {
+                continue;
+            }
+            let dict = match field.value {
+                Some(ref v) => t!(v.clone().into_dictionary(file)),
+                None => continue
+            };
+            signatures.push(t!(read_signature(file, field.name.clone(), dict)));
+        }
+    }
+    Ok(signatures)
+}
+
+fn read_signature(resolve: &impl Resolve, field_name: String, dict: Dictionary) -> Result<Signature> {
+    let text = |key: &str| match dict.get(key) {
+        Some(Primitive::String(s)) => Some(match s.as_str() {
+            Ok(s) => s.into_owned(),
+            Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+        }),
+        _ => None
+    };
+    let name = |key: &str| dict.get(key).and_then(|p| p.as_name().ok()).map(String::from);
+
+    let range: Vec<i32> = match dict.get("ByteRange") {
+        Some(range) => t!(Vec::from_primitive(range.clone(), resolve)),
+        None => bail!("the signature has no ByteRange")
+    };
+    let pairs = range.chunks_exact(2);
+    if !pairs.remainder().is_empty() || range.iter().any(|&n| n < 0) {
+        bail!("invalid ByteRange {:?}", range);
+    }
+    let byte_range = pairs.map(|r| (r[0] as usize, r[1] as usize)).collect();
+
+    let contents = match dict.get("Contents") {
+        Some(contents) => t!(contents.clone().into_string()).into_bytes(),
+        None => bail!("the signature has no Contents")
+    };
+    let certificates = match dict.get("Cert") {
+        Some(cert) => t!(Vec::<PdfString>::from_primitive(cert.clone(), resolve)).into_iter().map(|c| c.into_bytes()).collect(),
+        None => vec![]
+    };
+    let date = match dict.get("M") {
+        Some(m) => DateTime::from_primitive(m.clone(), resolve).map_err(|e| warn!("ignoring the date of signature {}: {}", field_name, e)).ok(),
+        None => None
+    };
+    Ok(Signature {
+        filter: name("Filter"),
+        sub_filter: name("SubFilter"),
+        byte_range,
+        contents: unpadded(contents),
+        certificates,
+        name: text("Name"),
+        reason: text("Reason"),
+        location: text("Location"),
+        contact_info: text("ContactInfo"),
+        date,
+        field_name,
+        dict,
+    })
+}
+
+/// `contents` without the zeros filling the rest of the gap: the DER value if it is one
+fn unpadded(mut contents: Vec<u8>) -> Vec<u8> {
+    let der_len = match contents[..] {
+        [0x30, n, ..] if n < 0x80 => Some(2 + n as usize),
+        [0x30, n, ref rest @ ..] if (0x81 ..= 0x84).contains(&n) && rest.len() >= (n - 0x80) as usize => {
+            let bytes = (n - 0x80) as usize;
+            let len = rest[.. bytes].iter().fold(0usize, |len, &b| len << 8 | b as usize);
+            Some(2 + bytes + len)
+        }
+        _ => None
+    };
+    match der_len {
+        Some(len) if len <= contents.len() => contents.truncate(len),
+        _ => {
+            let end = contents.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            contents.truncate(end);
+        }
+    }
+    contents
+}
+
+/// Add a signature field described by `options` and return the signed file.
+///
+/// `signer` gets the signed bytes and returns the DER encoded signature,
+/// which has to fit into `contents_size` bytes.
+/// The document keeps the new field, but saving it again writes an empty signature.
+pub fn sign<B: Backend>(file: &mut File<B>, options: &SignatureOptions, signer: impl FnOnce(&SignedData) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+    let mut sig = Dictionary::new();
+    sig.insert("Type", Primitive::name("Sig"));
+    sig.insert("Filter", Primitive::name("Adobe.PPKLite"));
+    sig.insert("SubFilter", Primitive::name(options.sub_filter.as_str()));
+    sig.insert("ByteRange", Primitive::Array(vec![
+        Primitive::Integer(0), Primitive::Integer(i32::MAX), Primitive::Integer(i32::MAX), Primitive::Integer(i32::MAX)
+    ]));
+    // bytes of 0xff are written in hex, like the signature will be
+    sig.insert("Contents", Primitive::String(PdfString::new(vec![0xff; options.contents_size])));
+    for (key, value) in [("Name", &options.name), ("Reason", &options.reason), ("Location", &options.location), ("ContactInfo", &options.contact_info)] {
+        if let Some(value) = value {
+            sig.insert(key, Primitive::String(text_string(value)));
+        }
+    }
+    if let Some(date) = options.date {
+        sig.insert("M", date.to_primitive(&mut NoUpdate)?);
+    }
+    let sig = file.create(Primitive::Dictionary(sig))?.get_ref().get_inner();
+
+    let page = file.get_page(options.page)?.get_ref().get_inner();
+    let rect = options.rect.unwrap_or(Rect { left: 0., bottom: 0., right: 0., top: 0. });
+    let mut field = Dictionary::new();
+    field.insert("FT", Primitive::name("Sig"));
+    field.insert("T", Primitive::String(text_string(&options.field_name)));
+    field.insert("V", Primitive::Reference(sig));
+    field.insert("Type", Primitive::name("Annot"));
+    field.insert("Subtype", Primitive::name("Widget"));
+    field.insert("Rect", rect.to_primitive(&mut NoUpdate)?);
+    // print, locked
+    field.insert("F", Primitive::Integer(132));
+    field.insert("P", Primitive::Reference(page));
+    let field = file.create(Primitive::Dictionary(field))?.get_ref().get_inner();
+    t!(add_to_form(file, page, field));
+
+    let save = SaveOptions { incremental: options.incremental, ..SaveOptions::default() };
+    let mut data = file.save_to_bytes(&save)?;
+
+    let contents = {
+        let mut placeholder = vec![];
+        PdfString::new(vec![0xff; options.contents_size]).serialize(&mut placeholder)?;
+        match rfind(&data, &placeholder) {
+            Some(pos) => pos .. pos + placeholder.len(),
+            None => bail!("the signature placeholder was not found in the written file")
+        }
+    };
+    let byte_range = match rfind(&data, BYTE_RANGE_PLACEHOLDER) {
+        Some(pos) => pos,
+        None => bail!("the ByteRange placeholder was not found in the written file")
+    };
+    let mut ranges = format!("[0 {} {} {}", contents.start, contents.end, data.len() - contents.end).into_bytes();
+    if ranges.len() >= BYTE_RANGE_PLACEHOLDER.len() {
+        bail!("the file is too large to sign");
+    }
+    ranges.resize(BYTE_RANGE_PLACEHOLDER.len() - 1, b' ');
+    ranges.push(b']');
+    data[byte_range .. byte_range + ranges.len()].copy_from_slice(&ranges);
+
+    let signature = {
+        let signed = SignedData { parts: [&data[.. contents.start], &data[contents.end ..]] };
+        signer(&signed)?
+    };
+    if 2 * signature.len() > contents.len() - 2 {
+        bail!("the signature has {} bytes, only {} are reserved", signature.len(), options.contents_size);
+    }
+    let mut hex: Vec<u8> = signature.iter().flat_map(|b| format!("{:02x}", b).into_bytes()).collect();
+    hex.resize(contents.len() - 2, b'0');
+    data[contents.start + 1 .. contents.end - 1].copy_from_slice(&hex);
+    Ok(data)
+}
+
+/// Add the widget `field` to the annotations of `page` and to the fields of the form,
+/// creating the form if there is none.
+fn add_to_form<B: Backend>(file: &mut File<B>, page: PlainRef, field: PlainRef) -> Result<()> {
+    // dictionaries are updated as they are, the structs don't have all entries
+    let mut page_dict = t!(file.resolve(page)?.into_dictionary(file));
+    let mut annots = match page_dict.remove("Annots") {
+        Some(annots) => t!(annots.into_array(file)),
+        None => vec![]
+    };
+    annots.push(Primitive::Reference(field));
+    page_dict.insert("Annots", Primitive::Array(annots));
+    file.update(page, Primitive::Dictionary(page_dict))?;
+
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = t!(file.resolve(catalog)?.into_dictionary(file));
+    let (form_ref, mut form) = match catalog_dict.get("AcroForm") {
+        Some(&Primitive::Reference(r)) => (Some(r), t!(file.resolve(r)?.into_dictionary(file))),
+        Some(p) => (None, t!(p.clone().into_dictionary(file))),
+        None => (None, Dictionary::new())
+    };
+    let mut fields = match form.remove("Fields") {
+        Some(fields) => t!(fields.into_array(file)),
+        None => vec![]
+    };
+    fields.push(Primitive::Reference(field));
+    form.insert("Fields", Primitive::Array(fields));
+    // signatures exist, append only
+    form.insert("SigFlags", Primitive::Integer(3));
+    match form_ref {
+        Some(r) => {
+            file.update(r, Primitive::Dictionary(form))?;
+        }
+        None => {
+            catalog_dict.insert("AcroForm", Primitive::Dictionary(form));
+            file.update(catalog, Primitive::Dictionary(catalog_dict))?;
+        }
+    }
+    file.reload_root()
+}
+
+fn rfind(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).rposition(|w| w == needle)
+}
@@ -0,0 +1,203 @@
+//! The 14 standard fonts, and where to get font programs for them.
+//!
+//! PDF files may use the standard fonts without embedding them.
+//! A `StandardFontProvider` supplies replacement font programs at runtime.
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use once_cell::unsync::OnceCell;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StandardFont {
+    Helvetica,
+    HelveticaBold,
+    HelveticaOblique,
+    HelveticaBoldOblique,
+    TimesRoman,
+    TimesBold,
+    TimesItalic,
+    TimesBoldItalic,
+    Courier,
+    CourierBold,
+    CourierOblique,
+    CourierBoldOblique,
+    Symbol,
+    ZapfDingbats,
+}
+impl StandardFont {
+    /// Recognizes the standard names and their common aliases (`Arial,Bold`, `TimesNewRomanPSMT`, ...).
+    /// A subset tag (`ABCDEF+`) is ignored.
+    pub fn from_name(name: &str) -> Option<StandardFont> {
+        use StandardFont::*;
+
+        let name = match name.find('+') {
+            Some(6) => &name[7 ..],
+            _ => name
+        };
+        let (family, style) = match name.find(&['-', ','][..]) {
+            Some(i) => (&name[.. i], &name[i + 1 ..]),
+            None => (name, "")
+        };
+        let family = family.trim_end_matches("MT").trim_end_matches("PS");
+        let bold = style.contains("Bold") || family.ends_with("Bold");
+        let italic = style.contains("Italic") || style.contains("Oblique");
+
+        Some(match (family.trim_end_matches("Bold"), bold, italic) {
+            ("Helvetica" | "Arial", false, false) => Helvetica,
+            ("Helvetica" | "Arial", true, false) => HelveticaBold,
+            ("Helvetica" | "Arial", false, true) => HelveticaOblique,
+            ("Helvetica" | "Arial", true, true) => HelveticaBoldOblique,
+            ("Times" | "TimesNewRoman", false, false) => TimesRoman,
+            ("Times" | "TimesNewRoman", true, false) => TimesBold,
+            ("Times" | "TimesNewRoman", false, true) => TimesItalic,
+            ("Times" | "TimesNewRoman", true, true) => TimesBoldItalic,
+            ("Courier" | "CourierNew", false, false) => Courier,
+            ("Courier" | "CourierNew", true, false) => CourierBold,
+            ("Courier" | "CourierNew", false, true) => CourierOblique,
+            ("Courier" | "CourierNew", true, true) => CourierBoldOblique,
+            ("Symbol", ..) => Symbol,
+            ("ZapfDingbats", ..) => ZapfDingbats,
+            _ => return None
+        })
+    }
+
+    /// The PostScript name as used in `BaseFont`
+    pub fn name(self) -> &'static str {
+        use StandardFont::*;
+        match self {
+            Helvetica => "Helvetica",
+            HelveticaBold => "Helvetica-Bold",
+            HelveticaOblique => "Helvetica-Oblique",
+            HelveticaBoldOblique => "Helvetica-BoldOblique",
+            TimesRoman => "Times-Roman",
+            TimesBold => "Times-Bold",
+            TimesItalic => "Times-Italic",
+            TimesBoldItalic => "Times-BoldItalic",
+            Courier => "Courier",
+            CourierBold => "Courier-Bold",
+            CourierOblique => "Courier-Oblique",
+            CourierBoldOblique => "Courier-BoldOblique",
+            Symbol => "Symbol",
+            ZapfDingbats => "ZapfDingbats",
+        }
+    }
+
+    /// File names of metric compatible substitutes (URW base35, Liberation), most preferred first.
+    fn substitutes(self) -> &'static [&'static str] {
+        use StandardFont::*;
+        match self {
+            Helvetica => &["NimbusSans-Regular.otf", "NimbusSans-Regular.t1", "n019003l.pfb", "LiberationSans-Regular.ttf"],
+            HelveticaBold => &["NimbusSans-Bold.otf", "NimbusSans-Bold.t1", "n019004l.pfb", "LiberationSans-Bold.ttf"],
+            HelveticaOblique => &["NimbusSans-Italic.otf", "NimbusSans-Italic.t1", "n019023l.pfb", "LiberationSans-Italic.ttf"],
+            HelveticaBoldOblique => &["NimbusSans-BoldItalic.otf", "NimbusSans-BoldItalic.t1", "n019024l.pfb", "LiberationSans-BoldItalic.ttf"],
+            TimesRoman => &["NimbusRoman-Regular.otf", "NimbusRoman-Regular.t1", "n021003l.pfb", "LiberationSerif-Regular.ttf"],
+            TimesBold => &["NimbusRoman-Bold.otf", "NimbusRoman-Bold.t1", "n021004l.pfb", "LiberationSerif-Bold.ttf"],
+            TimesItalic => &["NimbusRoman-Italic.otf", "NimbusRoman-Italic.t1", "n021023l.pfb", "LiberationSerif-Italic.ttf"],
+            TimesBoldItalic => &["NimbusRoman-BoldItalic.otf", "NimbusRoman-BoldItalic.t1", "n021024l.pfb", "LiberationSerif-BoldItalic.ttf"],
+            Courier => &["NimbusMonoPS-Regular.otf", "NimbusMonoPS-Regular.t1", "n022003l.pfb", "LiberationMono-Regular.ttf"],
+            CourierBold => &["NimbusMonoPS-Bold.otf", "NimbusMonoPS-Bold.t1", "n022004l.pfb", "LiberationMono-Bold.ttf"],
+            CourierOblique => &["NimbusMonoPS-Italic.otf", "NimbusMonoPS-Italic.t1", "n022023l.pfb", "LiberationMono-Italic.ttf"],
+            CourierBoldOblique => &["NimbusMonoPS-BoldItalic.otf", "NimbusMonoPS-BoldItalic.t1", "n022024l.pfb", "LiberationMono-BoldItalic.ttf"],
+            Symbol => &["StandardSymbolsPS.otf", "StandardSymbolsPS.t1", "s050000l.pfb"],
+            ZapfDingbats => &["D050000L.otf", "D050000L.t1", "d050000l.pfb"],
+        }
+    }
+}
+
+/// Supplies font programs for the standard fonts.
+pub trait StandardFontProvider {
+    /// Font program (Type1, TrueType or OpenType) to use for `font`
+    fn font_data(&self, font: StandardFont) -> Option<Vec<u8>>;
+}
+
+/// Looks up freely redistributable, metric compatible substitutes
+/// (URW base35 or Liberation fonts) in a list of directories.
+///
+/// By default this is the directory in the `STANDARD_FONTS` environment variable (if set at runtime)
+/// followed by the usual system font directories.
+pub struct SubstituteFonts {
+    dirs: Vec<PathBuf>,
+    // file name -> path
+    index: OnceCell<HashMap<String, PathBuf>>,
+}
+impl SubstituteFonts {
+    pub fn new() -> SubstituteFonts {
+        let mut dirs = vec![];
+        if let Some(dir) = env::var_os("STANDARD_FONTS") {
+            dirs.push(dir.into());
+        }
+        dirs.extend(["/usr/share/fonts", "/usr/local/share/fonts", "/Library/Fonts", "C:\\Windows\\Fonts"].iter().map(PathBuf::from));
+        if let Some(home) = env::var_os("HOME") {
+            dirs.push(Path::new(&home).join(".fonts"));
+            dirs.push(Path::new(&home).join(".local/share/fonts"));
+        }
+        SubstituteFonts::with_dirs(dirs)
+    }
+    /// Only search `dirs` (and their subdirectories)
+    pub fn with_dirs(dirs: Vec<PathBuf>) -> SubstituteFonts {
+        SubstituteFonts { dirs, index: OnceCell::new() }
+    }
+    /// Path of the substitute for `font`, if one was found
+    pub fn path(&self, font: StandardFont) -> Option<&Path> {
+        let index = self.index.get_or_init(|| {
+            let mut index = HashMap::new();
+            for dir in self.dirs.iter().rev() {
+                add_dir(&mut index, dir, 4);
+            }
+            index
+        });
+        font.substitutes().iter().find_map(|name| index.get(*name)).map(|p| p.as_path())
+    }
+}
+impl Default for SubstituteFonts {
+    fn default() -> Self {
+        SubstituteFonts::new()
+    }
+}
+impl StandardFontProvider for SubstituteFonts {
+    fn font_data(&self, font: StandardFont) -> Option<Vec<u8>> {
+        let path = self.path(font)?;
+        match fs::read(path) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                warn!("can't read {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+}
+
+fn add_dir(index: &mut HashMap<String, PathBuf>, dir: &Path, depth: usize) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 0 {
+                add_dir(index, &path, depth - 1);
+            }
+        } else if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            index.insert(name.to_owned(), path.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(StandardFont::from_name("Helvetica"), Some(StandardFont::Helvetica));
+        assert_eq!(StandardFont::from_name("Times-BoldItalic"), Some(StandardFont::TimesBoldItalic));
+        assert_eq!(StandardFont::from_name("ABCDEF+Arial,Bold"), Some(StandardFont::HelveticaBold));
+        assert_eq!(StandardFont::from_name("TimesNewRomanPSMT"), Some(StandardFont::TimesRoman));
+        assert_eq!(StandardFont::from_name("CourierNewPS-BoldItalicMT"), Some(StandardFont::CourierBoldOblique));
+        assert_eq!(StandardFont::from_name("Arial-BoldMT"), Some(StandardFont::HelveticaBold));
+        assert_eq!(StandardFont::from_name("DejaVuSans"), None);
+    }
+}
@@ -0,0 +1,265 @@
+//! The logical structure of tagged documents (`StructTreeRoot`).
+//!
+//! The structure tree has elements like headings, paragraphs and tables. Their contents are
+//! marked-content sequences on the pages, identified by their `MCID`, and whole objects like
+//! annotations. `struct_tree` reads the tree, `StructTree::element_of` goes the other way,
+//! from marked content to its element, with the parent tree.
+use std::collections::{HashMap, HashSet};
+
+use crate::backend::Backend;
+use crate::error::*;
+use crate::file::File;
+use crate::object::*;
+use crate::primitive::{Dictionary, PdfString, Primitive};
+
+/// An element of the structure tree, see `struct_tree`
+#[derive(Debug, Clone)]
+pub struct StructNode {
+    /// The element dictionary, `None` for direct objects
+    pub r: Option<PlainRef>,
+    /// The type after following the role map
+    pub struct_type: StructType,
+    /// The type as written in the element (`S`)
+    pub role: String,
+    /// Title (`T`)
+    pub title: Option<String>,
+    /// Language (`Lang`)
+    pub lang: Option<String>,
+    /// Alternate description (`Alt`), like the description of a figure
+    pub alt: Option<String>,
+    /// Replacement text (`ActualText`)
+    pub actual_text: Option<String>,
+    /// Expanded form of an abbreviation (`E`)
+    pub expansion: Option<String>,
+    /// Element identifier (`ID`)
+    pub id: Option<PdfString>,
+    /// The page of the contents (`Pg`), inherited from the parent elements
+    pub page: Option<PlainRef>,
+    /// Associated files (`AF`), like the MathML source of a formula
+    pub associated_files: Vec<FileSpec>,
+    pub kids: Vec<StructKid>,
+}
+
+/// A child of a structure element
+#[derive(Debug, Clone)]
+pub enum StructKid {
+    Element(Box<StructNode>),
+    /// A marked-content sequence of the content stream of `page`
+    MarkedContent { page: Option<PlainRef>, mcid: i32 },
+    /// A whole object, like an annotation or an XObject
+    Object { page: Option<PlainRef>, object: PlainRef },
+}
+
+impl StructNode {
+    /// The child elements
+    pub fn elements(&self) -> impl Iterator<Item=&StructNode> {
+        self.kids.iter().filter_map(|kid| match kid {
+            StructKid::Element(e) => Some(&**e),
+            _ => None
+        })
+    }
+
+    /// This element and all its descendants, parents before their kids, with their depth
+    pub fn descendants(&self) -> Vec<(usize, &StructNode)> {
+        let mut nodes = vec![];
+        self.collect(0, &mut nodes);
+        nodes
+    }
+    fn collect<'a>(&'a self, depth: usize, nodes: &mut Vec<(usize, &'a StructNode)>) {
+        nodes.push((depth, self));
+        for e in self.elements() {
+            e.collect(depth + 1, nodes);
+        }
+    }
+
+    /// The marked-content sequences that are direct children of the element, as `(page, MCID)`
+    pub fn marked_content(&self) -> Vec<(Option<PlainRef>, i32)> {
+        self.kids.iter().filter_map(|kid| match *kid {
+            StructKid::MarkedContent { page, mcid } => Some((page, mcid)),
+            _ => None
+        }).collect()
+    }
+
+    pub fn is_heading(&self) -> bool {
+        use StructType::*;
+        matches!(self.struct_type, H | H1 | H2 | H3 | H4 | H5 | H6)
+    }
+}
+
+/// The structure tree of a tagged document
+#[derive(Debug, Clone)]
+pub struct StructTree {
+    pub children: Vec<StructNode>,
+    /// Custom structure types and the types they stand for (`RoleMap`)
+    pub role_map: HashMap<String, String>,
+    parent_tree: Option<Primitive>,
+}
+
+impl StructTree {
+    /// All elements in document order, with their depth
+    pub fn elements(&self) -> Vec<(usize, &StructNode)> {
+        self.children.iter().flat_map(|e| e.descendants()).collect()
+    }
+
+    /// The element with the dictionary `r`
+    pub fn find(&self, r: PlainRef) -> Option<&StructNode> {
+        self.elements().into_iter().map(|(_, e)| e).find(|e| e.r == Some(r))
+    }
+
+    /// The element that contains the marked content `mcid` of `page`.
+    ///
+    /// Looked up in the parent tree (`ParentTree`) with the `StructParents` key of the page,
+    /// or found by searching the tree if the document doesn't have either.
+    pub fn element_of(&self, resolve: &impl Resolve, page: &PageRc, mcid: i32) -> Result<Option<&StructNode>> {
+        let page_ref = page.get_ref().get_inner();
+        if let (Some(ref tree), Some(key)) = (&self.parent_tree, page.struct_parents) {
+            let tree = t!(NumberTree::<Primitive>::from_primitive(tree.clone(), resolve));
+            if let Some(entry) = t!(tree.get(resolve, key)) {
+                let parents = t!(entry.into_array(resolve));
+                if let Some(&Primitive::Reference(r)) = parents.get(mcid as usize) {
+                    return Ok(self.find(r));
+                }
+                return Ok(None);
+            }
+        }
+        Ok(self.elements().into_iter().map(|(_, e)| e).find(|e| e.kids.iter().any(|kid| matches!(*kid,
+            StructKid::MarkedContent { page: Some(p), mcid: m } if p == page_ref && m == mcid
+        ))))
+    }
+}
+
+/// The structure tree of `file`, `None` if the document isn't tagged
+pub fn struct_tree<B: Backend>(file: &File<B>) -> Result<Option<StructTree>> {
+    let catalog = t!(file.resolve(file.trailer.root.get_ref().get_inner())?.into_dictionary(file));
+    let root = match catalog.get("StructTreeRoot") {
+        Some(root) => t!(root.clone().into_dictionary(file)),
+        None => return Ok(None)
+    };
+    let mut role_map = HashMap::new();
+    if let Some(map) = root.get("RoleMap") {
+        for (role, p) in t!(map.clone().into_dictionary(file)) {
+            if let Ok(name) = p.as_name() {
+                role_map.insert(role, name.to_owned());
+            }
+        }
+    }
+    let mut reader = Reader { resolve: file, role_map: &role_map, visited: HashSet::new() };
+    let mut children = vec![];
+    for kid in kids(&root, file)? {
+        if let Some(StructKid::Element(e)) = reader.kid(kid, None, 0)? {
+            children.push(*e);
+        }
+    }
+    Ok(Some(StructTree {
+        children,
+        parent_tree: root.get("ParentTree").cloned(),
+        role_map,
+    }))
+}
+
+fn kids(dict: &Dictionary, resolve: &impl Resolve) -> Result<Vec<Primitive>> {
+    match dict.get("K") {
+        Some(Primitive::Array(kids)) => Ok(kids.clone()),
+        Some(&Primitive::Reference(r)) => match resolve.resolve(r)? {
+            Primitive::Array(kids) => Ok(kids),
+            _ => Ok(vec![Primitive::Reference(r)])
+        },
+        Some(kid) => Ok(vec![kid.clone()]),
+        None => Ok(vec![])
+    }
+}
+
+struct Reader<'a, R> {
+    resolve: &'a R,
+    role_map: &'a HashMap<String, String>,
+    visited: HashSet<PlainRef>,
+}
+impl<'a, R: Resolve> Reader<'a, R> {
+    fn kid(&mut self, p: Primitive, page: Option<PlainRef>, depth: usize) -> Result<Option<StructKid>> {
+        if depth > 64 {
+            bail!("structure tree is nested too deeply");
+        }
+        let (r, mut dict) = match p {
+            Primitive::Integer(mcid) => return Ok(Some(StructKid::MarkedContent { page, mcid })),
+            Primitive::Reference(r) => {
+                if !self.visited.insert(r) {
+                    warn!("structure element {:?} is linked more than once", r);
+                    return Ok(None);
+                }
+                (Some(r), t!(self.resolve.resolve(r)?.into_dictionary(self.resolve)))
+            }
+            Primitive::Dictionary(dict) => (None, dict),
+            _ => return Ok(None)
+        };
+        let page = match dict.get("Pg") {
+            Some(&Primitive::Reference(pg)) => Some(pg),
+            _ => page
+        };
+        match dict.get("Type").and_then(|t| t.as_name().ok()) {
+            Some("MCR") => {
+                let mcid = t!(dict.require("MCR", "MCID")?.as_integer());
+                return Ok(Some(StructKid::MarkedContent { page, mcid }));
+            }
+            Some("OBJR") => {
+                let object = t!(dict.require("OBJR", "Obj")?.into_reference());
+                return Ok(Some(StructKid::Object { page, object }));
+            }
+            _ => {}
+        }
+        let role = match dict.get("S") {
+            Some(s) => t!(s.as_name()).to_owned(),
+            None => return Ok(None)
+        };
+        let mut node = StructNode {
+            r,
+            struct_type: self.struct_type(&role)?,
+            title: text(&dict, "T"),
+            lang: text(&dict, "Lang"),
+            alt: text(&dict, "Alt"),
+            actual_text: text(&dict, "ActualText"),
+            expansion: text(&dict, "E"),
+            id: dict.get("ID").and_then(|p| p.as_string().ok()).cloned(),
+            page,
+            associated_files: match dict.remove("AF") {
+                Some(af) => Vec::from_primitive(af, self.resolve).unwrap_or_else(|e| {
+                    warn!("ignoring the associated files of structure element {:?}: {}", r, e);
+                    vec![]
+                }),
+                None => vec![]
+            },
+            kids: vec![],
+            role,
+        };
+        for kid in kids(&dict, self.resolve)? {
+            if let Some(kid) = self.kid(kid, page, depth + 1)? {
+                node.kids.push(kid);
+            }
+        }
+        Ok(Some(StructKid::Element(Box::new(node))))
+    }
+
+    /// Follow the role map from `role` to a standard type
+    fn struct_type(&self, role: &str) -> Result<StructType> {
+        let mut name = role;
+        for _ in 0 .. 8 {
+            match StructType::from_primitive(Primitive::name(name), &NoResolve)? {
+                StructType::Other(_) => match self.role_map.get(name) {
+                    Some(mapped) if mapped != name => name = mapped,
+                    _ => break
+                },
+                known => return Ok(known)
+            }
+        }
+        Ok(StructType::Other(role.to_owned()))
+    }
+}
+
+fn text(dict: &Dictionary, key: &str) -> Option<String> {
+    match dict.get(key) {
+        Some(Primitive::String(s)) => Some(match s.as_str() {
+            Ok(s) => s.into_owned(),
+            Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+        }),
+        _ => None
+    }
+}
@@ -0,0 +1,53 @@
+//! Logical order for right-to-left text
+use unicode_bidi::BidiInfo;
+
+/// Convert a line of text from visual order (the order of the glyphs on the page) to logical order.
+///
+/// Right-to-left runs are reversed and their brackets mirrored.
+/// Left-to-right runs, like numbers in Arabic text, keep their order.
+pub fn visual_to_logical(line: &str) -> String {
+    let info = BidiInfo::new(line, None);
+    let mut out = String::with_capacity(line.len());
+    for para in &info.paragraphs {
+        let (levels, runs) = info.visual_runs(para, para.range.clone());
+        for run in runs {
+            let text = &line[run.clone()];
+            if levels[run.start].is_rtl() {
+                out.extend(text.chars().rev().map(mirror));
+            } else {
+                out.push_str(text);
+            }
+        }
+    }
+    out
+}
+
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        c => c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hebrew() {
+        // "שלום (עולם) 123" as drawn on the page
+        assert_eq!(visual_to_logical("123 (םלוע) םולש"), "שלום (עולם) 123");
+        assert_eq!(visual_to_logical("Hello World"), "Hello World");
+    }
+}
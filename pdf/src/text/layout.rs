@@ -0,0 +1,247 @@
+//! Grouping glyphs into words, lines and blocks
+use crate::content::Point;
+use crate::object::Rect;
+use super::{TextItem, is_word_gap, text_box, union};
+
+/// Distance between baselines (in units of the font size) up to which lines belong to the same block
+const LINE_GAP: f32 = 1.6;
+
+/// A run of glyphs without a space or gap
+#[derive(Debug, Clone)]
+pub struct Word {
+    pub text: String,
+    pub bbox: Rect,
+    /// Font size in page space
+    pub size: f32,
+    /// width of a space in units of the font size
+    space: f32,
+    // baseline, in page space
+    start: Point,
+    end: Point,
+    dir: Point,
+}
+
+/// Words on a common baseline, left to right
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub words: Vec<Word>,
+    pub bbox: Rect,
+}
+impl Line {
+    /// The words separated by spaces
+    pub fn text(&self) -> String {
+        let words: Vec<&str> = self.words.iter().map(|w| w.text.as_str()).collect();
+        words.join(" ")
+    }
+    fn size(&self) -> f32 {
+        self.words.iter().map(|w| w.size).fold(0., f32::max)
+    }
+}
+
+/// Consecutive lines with the same spacing, like a paragraph
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub lines: Vec<Line>,
+    pub bbox: Rect,
+}
+impl Block {
+    /// The lines separated by newlines
+    pub fn text(&self) -> String {
+        let lines: Vec<String> = self.lines.iter().map(|l| l.text()).collect();
+        lines.join("\n")
+    }
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+fn sub(a: Point, b: Point) -> Point {
+    Point { x: a.x - b.x, y: a.y - b.y }
+}
+/// `p` relative to `origin`: distance along `dir`, and across it (positive to the left)
+fn frame(p: Point, origin: Point, dir: Point) -> (f32, f32) {
+    let d = sub(p, origin);
+    (dot(d, dir), d.y * dir.x - d.x * dir.y)
+}
+
+/// Split the items into words, in content stream order.
+pub fn words(items: &[TextItem]) -> Vec<Word> {
+    let mut words: Vec<Word> = vec![];
+    let mut current: Option<Word> = None;
+    for item in items {
+        let size = item.user_size();
+        let dir = item.dir();
+        for glyph in &item.glyphs {
+            let text = &item.text[glyph.text.clone()];
+            let start = item.transform.transform(Point { x: glyph.offset, y: 0. });
+            let end = item.transform.transform(Point { x: glyph.offset + glyph.width, y: 0. });
+            if text.trim().is_empty() {
+                words.extend(current.take());
+                continue;
+            }
+            let bbox = text_box(&item.transform, &item.font, glyph.offset, glyph.offset + glyph.width);
+
+            if let Some(ref mut word) = current {
+                let (along, across) = frame(start, word.end, word.dir);
+                let s = word.size.max(size);
+                if across.abs() <= 0.5 * s && !is_word_gap(along / s, word.space) && along >= -0.5 * s {
+                    word.text.push_str(text);
+                    word.bbox = union(word.bbox, bbox);
+                    word.end = end;
+                    continue;
+                }
+            }
+            let space = item.space();
+            words.extend(current.replace(Word { text: text.into(), bbox, size, space, start, end, dir }));
+        }
+    }
+    words.extend(current);
+    words
+}
+
+/// Group words into lines, in the order the lines are first drawn.
+pub fn lines(words: Vec<Word>) -> Vec<Line> {
+    // first word of the line, all words
+    let mut lines: Vec<(Point, Point, Vec<Word>)> = vec![];
+    for word in words {
+        if let Some((origin, dir, line)) = lines.last_mut() {
+            let (along, across) = frame(word.start, *origin, *dir);
+            let last = line.last().unwrap();
+            let (end, _) = frame(last.end, *origin, *dir);
+            let s = last.size.max(word.size);
+            if dot(word.dir, *dir) > 0.99 && across.abs() <= 0.5 * s && along > -s && along - end < 3. * s {
+                line.push(word);
+                continue;
+            }
+        }
+        lines.push((word.start, word.dir, vec![word]));
+    }
+
+    lines.into_iter().map(|(origin, dir, mut words)| {
+        words.sort_by(|a, b| {
+            let a = frame(a.start, origin, dir).0;
+            let b = frame(b.start, origin, dir).0;
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let bbox = words.iter().map(|w| w.bbox).reduce(union).unwrap();
+        Line { words, bbox }
+    }).collect()
+}
+
+/// Group lines into blocks of consecutive, evenly spaced lines.
+pub fn blocks(lines: Vec<Line>) -> Vec<Block> {
+    let mut blocks: Vec<Block> = vec![];
+    for line in lines {
+        if let Some(block) = blocks.last_mut() {
+            let prev = block.lines.last().unwrap();
+            let (a, b) = (&prev.words[0], &line.words[0]);
+            let (_, across) = frame(b.start, a.start, a.dir);
+            let (s0, s1) = (prev.size(), line.size());
+            let similar = s0.max(s1) <= 1.3 * s0.min(s1);
+            let overlap = line.bbox.left < prev.bbox.right && prev.bbox.left < line.bbox.right;
+            let spacing = -across;
+            let even = match block.lines.len() {
+                1 => true,
+                n => {
+                    let p = &block.lines[n - 2].words[0];
+                    let (_, last) = frame(a.start, p.start, p.dir);
+                    (spacing + last).abs() <= 0.2 * s0
+                }
+            };
+            if dot(a.dir, b.dir) > 0.99 && similar && overlap && spacing > 0. && spacing <= LINE_GAP * s0 && even {
+                block.bbox = union(block.bbox, line.bbox);
+                block.lines.push(line);
+                continue;
+            }
+        }
+        blocks.push(Block { bbox: line.bbox, lines: vec![line] });
+    }
+    blocks
+}
+
+/// Sort blocks into reading order by recursive XY-cut.
+///
+/// The blocks are split at the widest horizontal or vertical gap not crossed by any block,
+/// so full width headers come before columns, and columns are read left to right.
+pub fn reading_order(blocks: Vec<Block>) -> Vec<Block> {
+    let mut out = Vec::with_capacity(blocks.len());
+    xy_cut(blocks, &mut out);
+    out
+}
+
+/// Groups separated by gaps in the projection onto one axis,
+/// and the widest gap. `range` gives the extent of a block, increasing in reading order.
+fn split(mut blocks: Vec<Block>, range: impl Fn(&Rect) -> (f32, f32)) -> (Vec<Vec<Block>>, f32) {
+    blocks.sort_by(|a, b| range(&a.bbox).0.partial_cmp(&range(&b.bbox).0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut groups: Vec<Vec<Block>> = vec![];
+    let mut widest = 0f32;
+    let mut end = f32::NEG_INFINITY;
+    for block in blocks {
+        let (lo, hi) = range(&block.bbox);
+        match groups.last_mut() {
+            Some(group) if lo <= end => group.push(block),
+            _ => {
+                if end.is_finite() {
+                    widest = widest.max(lo - end);
+                }
+                groups.push(vec![block]);
+            }
+        }
+        end = end.max(hi);
+    }
+    (groups, widest)
+}
+
+fn xy_cut(blocks: Vec<Block>, out: &mut Vec<Block>) {
+    if blocks.len() <= 1 {
+        out.extend(blocks);
+        return;
+    }
+    // top to bottom, left to right
+    let (rows, row_gap) = split(blocks.clone(), |r| (-r.top, -r.bottom));
+    let (columns, column_gap) = split(blocks, |r| (r.left, r.right));
+    let groups = match (rows.len() > 1, columns.len() > 1) {
+        (true, true) if column_gap > row_gap => columns,
+        (true, _) => rows,
+        (false, true) => columns,
+        (false, false) => {
+            // overlapping blocks
+            out.extend(rows.into_iter().flatten());
+            return;
+        }
+    };
+    for group in groups {
+        xy_cut(group, out);
+    }
+}
+
+/// Words, lines and blocks of `items`.
+pub fn layout(items: &[TextItem]) -> Vec<Block> {
+    blocks(lines(words(items)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(text: &str, left: f32, bottom: f32, right: f32, top: f32) -> Block {
+        let bbox = Rect { left, bottom, right, top };
+        let p = Point { x: left, y: bottom };
+        let word = Word { text: text.into(), bbox, size: 10., space: 0.25, start: p, end: p, dir: Point { x: 1., y: 0. } };
+        Block { lines: vec![Line { words: vec![word], bbox }], bbox }
+    }
+
+    #[test]
+    fn columns() {
+        let blocks = vec![
+            block("right 2", 310., 100., 500., 300.),
+            block("left 1", 100., 400., 290., 600.),
+            block("footer", 100., 50., 500., 60.),
+            block("right 1", 310., 350., 500., 600.),
+            block("title", 100., 650., 500., 700.),
+            block("left 2", 100., 120., 290., 380.),
+        ];
+        let order: Vec<String> = reading_order(blocks).iter().map(|b| b.text()).collect();
+        assert_eq!(order, ["title", "left 1", "left 2", "right 1", "right 2", "footer"]);
+    }
+}
@@ -0,0 +1,394 @@
+//! Text extraction
+//!
+//! Positions are given in page space: the coordinates of the page as it is displayed,
+//! rotated by `/Rotate` and with the origin at the lower left corner of the crop box
+//! (see `Page::display_transform`).
+use std::ops::{Range, RangeBounds};
+use std::rc::Rc;
+
+use crate::backend::Backend;
+use crate::content::{Matrix, Op, Point, TextMode};
+use crate::error::*;
+use crate::file::{page_range, File};
+use crate::font::FontInfo;
+use crate::object::*;
+use crate::primitive::Primitive;
+use crate::content::FormXObject;
+
+mod layout;
+pub use self::layout::*;
+mod state;
+pub use self::state::TextState;
+#[cfg(feature = "bidi")]
+mod bidi;
+#[cfg(feature = "bidi")]
+pub use self::bidi::*;
+
+/// Options for `extract_with`
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// Group the text into blocks (see `layout`) and output them in visual reading order.
+    /// Blocks are separated by an empty line.
+    ///
+    /// Otherwise text is output in the order of the content stream.
+    pub reading_order: bool,
+
+    /// Convert each line from visual to logical order, for right-to-left scripts.
+    /// See `visual_to_logical`. Only available with the `bidi` feature, ignored otherwise.
+    pub bidi: bool,
+
+    /// Include the text of annotation appearances, see `annotation_items`.
+    pub annotations: bool,
+}
+
+/// Extract the text of the pages in `pages` (counting from 0).
+///
+/// Glyphs are decoded using `ToUnicode` or the font encoding.
+/// Spaces and newlines are inserted where the glyph positions leave a gap or start a new line.
+/// Pages are separated by a form feed (`\x0c`), like `pdftotext` does.
+pub fn extract<B: Backend>(file: &File<B>, pages: impl RangeBounds<u32>) -> Result<String> {
+    extract_with(file, pages, &ExtractOptions::default())
+}
+
+/// `extract` with options
+pub fn extract_with<B: Backend>(file: &File<B>, pages: impl RangeBounds<u32>, options: &ExtractOptions) -> Result<String> {
+    let pages = page_range(pages, file.num_pages());
+    let mut out = String::new();
+    for n in pages.clone() {
+        if n > pages.start {
+            out.push('\x0c');
+        }
+        let page = t!(file.get_page(n));
+        let mut items: Vec<_> = t!(items(file, &page)).collect();
+        if options.annotations {
+            items.extend(t!(annotation_items(file, &page)));
+        }
+        let mut text = String::new();
+        if options.reading_order {
+            let blocks: Vec<String> = reading_order(layout(&items)).iter().map(|b| b.text()).collect();
+            text = blocks.join("\n\n");
+        } else {
+            join(&items, &mut text);
+        }
+        #[cfg(feature = "bidi")]
+        if options.bidi {
+            let lines: Vec<String> = text.split('\n').map(visual_to_logical).collect();
+            text = lines.join("\n");
+        }
+        out.push_str(&text);
+    }
+    Ok(out)
+}
+
+/// A string shown by one text operation (`Tj`, or one string of a `TJ` array)
+#[derive(Debug, Clone)]
+pub struct TextItem {
+    pub text: String,
+    pub font: Rc<FontInfo>,
+    /// Font size set by `Tf`
+    pub size: f32,
+    /// Bounding box in page space, from the advance widths and the ascent and descent of the font
+    pub bbox: Rect,
+    /// Text rendering matrix at the first glyph.
+    /// Maps text space, in units of the font size, to page space.
+    pub transform: Matrix,
+    /// Advance width of the whole string in units of the font size
+    pub width: f32,
+    pub render_mode: TextMode,
+    pub glyphs: Vec<Glyph>,
+}
+
+/// A glyph of a `TextItem`
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub code: u32,
+    pub cid: u32,
+    /// The part of `TextItem::text` this glyph decodes to (may be empty)
+    pub text: Range<usize>,
+    /// Position along the baseline in units of the font size, from the start of the item
+    pub offset: f32,
+    /// Advance width (including character and word spacing) in units of the font size
+    pub width: f32,
+}
+impl TextItem {
+    /// Origin of the first glyph in page space
+    pub fn start(&self) -> Point {
+        self.transform.transform(Point { x: 0., y: 0. })
+    }
+    /// Where the next glyph would be drawn, in page space
+    pub fn end(&self) -> Point {
+        self.transform.transform(Point { x: self.width, y: 0. })
+    }
+    /// Font size in page space
+    pub fn user_size(&self) -> f32 {
+        self.transform.c.hypot(self.transform.d)
+    }
+    /// Width of a space in units of the font size
+    fn space(&self) -> f32 {
+        self.font.space_width.unwrap_or(250.) * 0.001
+    }
+    /// Unit vector along the baseline in page space
+    fn dir(&self) -> Point {
+        let Matrix { a, b, .. } = self.transform;
+        let len = a.hypot(b);
+        if len > 0. {
+            Point { x: a / len, y: b / len }
+        } else {
+            Point { x: 1., y: 0. }
+        }
+    }
+}
+
+/// Whether a gap between two glyphs separates words.
+///
+/// `gap` is the distance along the baseline in units of the font size (this is where `TJ` adjustments go),
+/// and `space` the width of the space glyph in the same units.
+/// Half a space or more is a word break, less is kerning.
+fn is_word_gap(gap: f32, space: f32) -> bool {
+    gap > (0.5 * space).clamp(0.1, 0.3)
+}
+
+/// Bounding box in page space of the text between `x0` and `x1` (in units of the font size)
+pub(crate) fn text_box(transform: &Matrix, font: &FontInfo, x0: f32, x1: f32) -> Rect {
+    let descriptor = font.font.descriptor();
+    let ascent = descriptor.and_then(|d| d.ascent).unwrap_or(800.) * 0.001;
+    let descent = descriptor.and_then(|d| d.descent).unwrap_or(-200.) * 0.001;
+    let corners = [(x0, descent), (x1, descent), (x0, ascent), (x1, ascent)]
+        .map(|(x, y)| transform.transform(Point { x, y }));
+    Rect {
+        left: corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min),
+        bottom: corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min),
+        right: corners.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max),
+        top: corners.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max),
+    }
+}
+
+/// Smallest rectangle containing `a` and `b`
+fn union(a: Rect, b: Rect) -> Rect {
+    Rect {
+        left: a.left.min(b.left),
+        bottom: a.bottom.min(b.bottom),
+        right: a.right.max(b.right),
+        top: a.top.max(b.top),
+    }
+}
+
+/// The text shown on `page`, in content stream order.
+pub fn items<'a, B: Backend>(file: &'a File<B>, page: &'a Page) -> Result<TextItems<'a, B>> {
+    let ops: &[Op] = match page.contents {
+        Some(ref contents) => &contents.operations,
+        None => &[]
+    };
+    Ok(TextItems {
+        file,
+        resources: t!(page.resources()),
+        ops: ops.iter(),
+        pending: vec![].into_iter(),
+        state: TextState::with_ctm(page.display_transform().unwrap_or_default()),
+    })
+}
+
+/// Iterator over the `TextItem`s of a page, see `items`
+pub struct TextItems<'a, B: Backend> {
+    file: &'a File<B>,
+    resources: &'a Resources,
+    ops: std::slice::Iter<'a, Op>,
+    /// rest of the items of the last operation
+    pending: std::vec::IntoIter<TextItem>,
+    state: TextState,
+}
+impl<'a, B: Backend> Iterator for TextItems<'a, B> {
+    type Item = TextItem;
+    fn next(&mut self) -> Option<TextItem> {
+        loop {
+            if let Some(item) = self.pending.next() {
+                return Some(item);
+            }
+            self.pending = match *self.ops.next()? {
+                Op::XObject { ref name } => xobject_items(self.file, self.resources, name, &self.state, 0),
+                ref op => self.state.apply(op, self.file, self.resources)
+            }.into_iter();
+        }
+    }
+}
+
+/// Form XObjects nested deeper than this are ignored (and probably recursive)
+pub(crate) const MAX_FORM_DEPTH: usize = 16;
+
+/// The text drawn by the XObject `name`, if it is a form.
+fn xobject_items<B: Backend>(file: &File<B>, resources: &Resources, name: &str, state: &TextState, depth: usize) -> Vec<TextItem> {
+    let xobject = match resources.xobjects.get(name).map(|&r| file.get(r)) {
+        Some(Ok(xobject)) => xobject,
+        Some(Err(e)) => {
+            warn!("can't load XObject {}: {:?}", name, e);
+            return vec![];
+        }
+        None => {
+            warn!("XObject {} not found", name);
+            return vec![];
+        }
+    };
+    match *xobject {
+        XObject::Form(ref form) if depth < MAX_FORM_DEPTH => form_items(file, form, resources, state.clone(), depth + 1),
+        XObject::Form(_) => {
+            warn!("Form XObjects nested too deep");
+            vec![]
+        }
+        _ => vec![]
+    }
+}
+
+/// The text drawn by `form`. `resources` are used if the form has none.
+fn form_items<B: Backend>(file: &File<B>, form: &FormXObject, resources: &Resources, mut state: TextState, depth: usize) -> Vec<TextItem> {
+    let resources = form.dict().resources.as_deref().unwrap_or(resources);
+    if let Some(matrix) = form.dict().matrix {
+        state.ctm = matrix * state.ctm;
+    }
+    let mut items = vec![];
+    for op in &form.operations {
+        match *op {
+            Op::XObject { ref name } => items.extend(xobject_items(file, resources, name, &state, depth)),
+            ref op => items.extend(state.apply(op, file, resources))
+        }
+    }
+    items
+}
+
+/// The text in the normal appearance streams of the annotations of `page`
+/// (like filled in form fields), in page space.
+///
+/// Hidden annotations are skipped.
+pub fn annotation_items<B: Backend>(file: &File<B>, page: &Page) -> Result<Vec<TextItem>> {
+    let annotations = match page.annotations {
+        Some(ref annotations) => annotations,
+        None => return Ok(vec![])
+    };
+    let resources = t!(page.resources());
+    let display = page.display_transform().unwrap_or_default();
+    let mut items = vec![];
+    for annot in annotations {
+        let annot = t!(annot.clone().into_dictionary(file));
+        if annot.get("Rect").is_none() {
+            continue;
+        }
+        let annot = t!(AnnotDict::from_primitive(Primitive::Dictionary(annot), file));
+        if annot.flags.contains(AnnotFlags::HIDDEN) || annot.flags.contains(AnnotFlags::NO_VIEW) {
+            continue;
+        }
+        let form = match t!(annot.appearance_stream(file, AppearanceKind::Normal)) {
+            Some(form) => form,
+            None => continue
+        };
+        // `form_items` applies `Matrix` itself
+        let state = TextState::with_ctm(annot.appearance_matrix(&form) * display);
+        items.extend(form_items(file, &form, resources, state, 0));
+    }
+    Ok(items)
+}
+
+/// Append the text of `items` to `out`, separating them by a space or newline where needed.
+fn join(items: &[TextItem], out: &mut String) {
+    join_glyphs(items, out, None)
+}
+
+/// Like `join`, and also records the byte range in `out` and the bounding box of every glyph.
+fn join_glyphs(items: &[TextItem], out: &mut String, mut glyphs: Option<&mut Vec<(Range<usize>, Rect)>>) {
+    let mut last: Option<&TextItem> = None;
+    for item in items.iter().filter(|s| !s.text.is_empty()) {
+        if let Some(prev) = last {
+            let size = prev.user_size().max(item.user_size());
+            let (start, end, dir) = (item.start(), prev.end(), prev.dir());
+            let (dx, dy) = (start.x - end.x, start.y - end.y);
+            // distance along and across the baseline of the previous item
+            let along = dx * dir.x + dy * dir.y;
+            let across = dy * dir.x - dx * dir.y;
+
+            let ws = out.ends_with(char::is_whitespace) || item.text.starts_with(char::is_whitespace);
+            if across.abs() > 0.5 * size {
+                let trimmed = out.trim_end_matches(' ').len();
+                out.truncate(trimmed);
+                if let Some(ref mut glyphs) = glyphs {
+                    glyphs.retain(|(r, _)| r.end <= trimmed);
+                }
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+            } else if !ws && size > 0. && (is_word_gap(along / size, prev.space()) || along < -size) {
+                out.push(' ');
+            }
+        }
+        if let Some(ref mut glyphs) = glyphs {
+            let base = out.len();
+            glyphs.extend(item.glyphs.iter().filter(|g| !g.text.is_empty()).map(|g| (
+                base + g.text.start .. base + g.text.end,
+                text_box(&item.transform, &item.font, g.offset, g.offset + g.width)
+            )));
+        }
+        out.push_str(&item.text);
+        last = Some(item);
+    }
+}
+
+/// Where `find` found the text
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// Page number, counting from 0
+    pub page_nr: u32,
+    /// Bounding boxes in page space, one per line the match spans
+    pub rects: Vec<Rect>,
+}
+
+/// Find all occurrences of `needle` in the text of `file`.
+///
+/// The text is the one returned by `extract`, so words are separated by a single space
+/// and lines by a newline.
+pub fn find<B: Backend>(file: &File<B>, needle: &str) -> Result<Vec<Match>> {
+    let mut matches = vec![];
+    if needle.is_empty() {
+        return Ok(matches);
+    }
+    for page_nr in 0 .. file.num_pages() {
+        let page = t!(file.get_page(page_nr));
+        let items: Vec<_> = t!(items(file, &page)).collect();
+        let mut text = String::new();
+        let mut glyphs = vec![];
+        join_glyphs(&items, &mut text, Some(&mut glyphs));
+
+        for (start, s) in text.match_indices(needle) {
+            let end = start + s.len();
+            let mut rects: Vec<Rect> = vec![];
+            for (_, bbox) in glyphs.iter().filter(|(r, _)| r.start < end && start < r.end) {
+                match rects.last_mut() {
+                    Some(last) if same_line(last, bbox) => *last = union(*last, *bbox),
+                    _ => rects.push(*bbox)
+                }
+            }
+            matches.push(Match { page_nr, rects });
+        }
+    }
+    Ok(matches)
+}
+
+/// Whether the boxes overlap vertically by at least half the smaller height
+fn same_line(a: &Rect, b: &Rect) -> bool {
+    let overlap = a.top.min(b.top) - a.bottom.max(b.bottom);
+    overlap >= 0.5 * (a.top - a.bottom).min(b.top - b.bottom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_gaps() {
+        // Helvetica
+        assert!(is_word_gap(0.25, 0.278));
+        assert!(!is_word_gap(0.05, 0.278));
+        assert!(!is_word_gap(-0.3, 0.278));
+        // narrow space
+        assert!(is_word_gap(0.12, 0.2));
+        // bogus widths
+        assert!(!is_word_gap(0.05, 0.));
+        assert!(is_word_gap(0.35, 2.));
+    }
+}
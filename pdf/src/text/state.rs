@@ -0,0 +1,197 @@
+//! The text state of a content stream
+use std::rc::Rc;
+
+use crate::backend::Backend;
+use crate::content::{Matrix, Op, TextDrawAdjusted, TextMode};
+use crate::file::File;
+use crate::font::{FontInfo, FontType};
+use crate::object::Resources;
+use super::{Glyph, TextItem, text_box};
+
+/// Text related parameters of the graphics state, and the text matrices.
+///
+/// Feed it the operations of a content stream with `apply`, and it returns
+/// the strings shown along with their position and advance.
+/// `q` / `Q` save and restore everything but the text matrices.
+#[derive(Debug, Clone)]
+pub struct TextState {
+    /// current transformation matrix
+    pub ctm: Matrix,
+    /// text matrix (`Tm`)
+    pub tm: Matrix,
+    /// text line matrix
+    pub tlm: Matrix,
+    /// `Tc`
+    pub char_space: f32,
+    /// `Tw`
+    pub word_space: f32,
+    /// `Tz / 100`
+    pub horiz_scale: f32,
+    /// `TL`
+    pub leading: f32,
+    /// `Ts`
+    pub rise: f32,
+    /// `Tr`
+    pub render_mode: TextMode,
+    pub font: Option<Rc<FontInfo>>,
+    pub font_size: f32,
+    stack: Vec<TextState>,
+}
+impl Default for TextState {
+    fn default() -> Self {
+        TextState::with_ctm(Matrix::default())
+    }
+}
+impl TextState {
+    pub fn new() -> TextState {
+        TextState::default()
+    }
+    /// Start with `ctm` as the current transformation matrix,
+    /// for example `Page::display_transform` or the transform to device space.
+    pub fn with_ctm(ctm: Matrix) -> TextState {
+        TextState {
+            ctm,
+            tm: Matrix::default(),
+            tlm: Matrix::default(),
+            char_space: 0.,
+            word_space: 0.,
+            horiz_scale: 1.,
+            leading: 0.,
+            rise: 0.,
+            render_mode: TextMode::Fill,
+            font: None,
+            font_size: 0.,
+            stack: vec![],
+        }
+    }
+
+    /// Update the state for `op`, and return the strings it shows.
+    ///
+    /// `resources` are used to look up fonts (`Tf`) and graphics states (`gs`).
+    pub fn apply<B: Backend>(&mut self, op: &Op, file: &File<B>, resources: &Resources) -> Vec<TextItem> {
+        match *op {
+            Op::Save => {
+                let mut stack = std::mem::take(&mut self.stack);
+                stack.push(self.clone());
+                self.stack = stack;
+            }
+            Op::Restore => if let Some(saved) = self.stack.pop() {
+                let stack = std::mem::take(&mut self.stack);
+                *self = TextState { tm: self.tm, tlm: self.tlm, stack, ..saved };
+            },
+            Op::Transform { matrix } => self.ctm = matrix * self.ctm,
+            Op::GraphicsState { ref name } => {
+                if let Some((font, size)) = resources.graphics_states.get(name).and_then(|gs| gs.font) {
+                    self.font = file.get_font(font).ok();
+                    self.font_size = size;
+                }
+            }
+            Op::BeginText => {
+                self.tm = Matrix::default();
+                self.tlm = Matrix::default();
+            }
+            Op::CharSpacing { char_space } => self.char_space = char_space,
+            Op::WordSpacing { word_space } => self.word_space = word_space,
+            Op::TextScaling { horiz_scale } => self.horiz_scale = horiz_scale * 0.01,
+            Op::Leading { leading } => self.leading = leading,
+            Op::TextRise { rise } => self.rise = rise,
+            Op::TextRenderMode { mode } => self.render_mode = mode,
+            Op::TextFont { ref name, size } => {
+                self.font = font(file, resources, name);
+                self.font_size = size;
+            }
+            Op::MoveTextPosition { translation } => self.next_line(translation.x, translation.y),
+            Op::SetTextMatrix { matrix } => {
+                self.tm = matrix;
+                self.tlm = matrix;
+            }
+            Op::TextNewline => self.next_line(0., -self.leading),
+            Op::TextDraw { ref text } => return self.show(&text.data).into_iter().collect(),
+            Op::TextDrawAdjusted { ref array } => return self.show_adjusted(array),
+            _ => {}
+        }
+        vec![]
+    }
+
+    /// Text rendering matrix: maps text space (in units of the font size) to the space of `ctm`
+    pub fn trm(&self) -> Matrix {
+        Matrix { a: self.font_size * self.horiz_scale, d: self.font_size, f: self.rise, ..Matrix::default() }
+            * self.tm * self.ctm
+    }
+
+    /// Move to the start of the next line, offset by (`x`, `y`)  (`Td`)
+    pub fn next_line(&mut self, x: f32, y: f32) {
+        self.tlm = Matrix::translate(x, y) * self.tlm;
+        self.tm = self.tlm;
+    }
+
+    /// Move along the baseline by `tx` in text space
+    pub fn advance(&mut self, tx: f32) {
+        self.tm = Matrix::translate(tx, 0.) * self.tm;
+    }
+
+    /// Show a string (`Tj`) and advance past it.
+    ///
+    /// Returns `None` if no font is set.
+    pub fn show(&mut self, data: &[u8]) -> Option<TextItem> {
+        let font = self.font.clone()?;
+        let transform = self.trm();
+
+        let size = self.font_size;
+        // text space units to units of the font size
+        let scale = if size != 0. { 1. / size } else { 0. };
+
+        let mut text = String::new();
+        let mut glyphs = vec![];
+        let mut width = 0.;
+        let simple = !matches!(font.font.subtype, FontType::Type0);
+        for (code, cid) in font.font.codes(data) {
+            let start = text.len();
+            if let Some(s) = font.unicode(code) {
+                text.push_str(&s);
+            }
+            let mut tx = font.width(cid) * 0.001 * size + self.char_space;
+            if code == 32 && simple {
+                tx += self.word_space;
+            }
+            glyphs.push(Glyph { code, cid, text: start .. text.len(), offset: width * scale, width: tx * scale });
+            width += tx;
+            self.advance(tx * self.horiz_scale);
+        }
+        let width = width * scale;
+        let bbox = text_box(&transform, &font, 0., width);
+
+        Some(TextItem { text, font, size, bbox, transform, width, render_mode: self.render_mode, glyphs })
+    }
+
+    /// Show the strings of a `TJ` array, moving by the adjustments in between.
+    pub fn show_adjusted(&mut self, array: &[TextDrawAdjusted]) -> Vec<TextItem> {
+        let mut items = vec![];
+        for part in array {
+            match *part {
+                TextDrawAdjusted::Text(ref text) => items.extend(self.show(&text.data)),
+                TextDrawAdjusted::Spacing(n) => {
+                    let tx = -n * 0.001 * self.font_size * self.horiz_scale;
+                    self.advance(tx);
+                }
+            }
+        }
+        items
+    }
+}
+
+fn font<B: Backend>(file: &File<B>, resources: &Resources, name: &str) -> Option<Rc<FontInfo>> {
+    match resources.fonts.get(name) {
+        Some(&font) => match file.get_font(font) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                warn!("can't load font {}: {:?}", name, e);
+                None
+            }
+        },
+        None => {
+            warn!("font {} not found", name);
+            None
+        }
+    }
+}
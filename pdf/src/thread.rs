@@ -0,0 +1,76 @@
+//! Article threads (`Threads` in the catalog).
+//!
+//! A thread is the order to read an article in, as areas (beads) on the pages, like the
+//! columns of a magazine article. In the file the beads are a circular list (`N`, `V`),
+//! `threads` follows it from the first bead until it is back there. Beads that were
+//! already visited end the list, so broken files can't loop forever.
+use std::collections::HashSet;
+
+use crate::error::*;
+use crate::object::*;
+use crate::primitive::PdfString;
+
+/// An article thread with its beads, see `threads`
+#[derive(Debug, Clone)]
+pub struct Article {
+    /// The thread dictionary
+    pub r: Ref<Thread>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// The whole `I` dictionary
+    pub info: Option<DocInfo>,
+    /// In reading order
+    pub beads: Vec<ArticleBead>,
+}
+
+/// An area of an article on a page
+#[derive(Debug, Clone)]
+pub struct ArticleBead {
+    /// The bead dictionary
+    pub r: Ref<Bead>,
+    pub page: PlainRef,
+    /// The number of `page`, counting from 0, `None` if it isn't in the page tree
+    pub page_nr: Option<u32>,
+    pub rect: Rect,
+}
+
+/// The article threads of the document with `catalog`, in the order of `Threads`
+pub fn threads(resolve: &impl Resolve, catalog: &Catalog) -> Result<Vec<Article>> {
+    let mut articles = vec![];
+    for &r in catalog.threads.iter().flatten() {
+        let thread = t!(resolve.get(r));
+        let info = thread.info.clone();
+        let mut beads = vec![];
+        let mut visited = HashSet::new();
+        let mut next = thread.first;
+        while let Some(bead_ref) = next {
+            if !visited.insert(bead_ref.get_inner()) {
+                break;
+            }
+            let bead = t!(resolve.get(bead_ref));
+            let page = bead.page.get_inner();
+            beads.push(ArticleBead {
+                r: bead_ref,
+                page,
+                page_nr: t!(catalog.pages.page_nr(resolve, page)),
+                rect: bead.rect,
+            });
+            next = Some(bead.next);
+        }
+        articles.push(Article {
+            r,
+            title: info.as_ref().and_then(|i| i.title.as_ref()).map(text),
+            author: info.as_ref().and_then(|i| i.author.as_ref()).map(text),
+            info,
+            beads,
+        });
+    }
+    Ok(articles)
+}
+
+fn text(s: &PdfString) -> String {
+    match s.as_str() {
+        Ok(s) => s.into_owned(),
+        Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+    }
+}
@@ -0,0 +1,473 @@
+//! XMP metadata packets, as stored in the `Metadata` stream of the catalog
+use chrono::{DateTime, FixedOffset};
+
+use crate::object::{NoResolve, Object};
+use crate::primitive::{Dictionary, PdfStream, Primitive};
+
+/// Text entries of the Info dictionary and the XMP properties they correspond to
+const TEXT_PROPERTIES: &[(&str, &str)] = &[
+    ("Keywords", "pdf:Keywords"),
+    ("Producer", "pdf:Producer"),
+    ("Creator", "xmp:CreatorTool"),
+];
+/// Date entries of the Info dictionary and the XMP properties they correspond to
+const DATE_PROPERTIES: &[(&str, &str)] = &[
+    ("CreationDate", "xmp:CreateDate"),
+    ("ModDate", "xmp:ModifyDate"),
+    ("ModDate", "xmp:MetadataDate"),
+];
+
+fn text(info: &Dictionary, key: &str) -> Option<String> {
+    match info.get(key) {
+        Some(Primitive::String(s)) => Some(match s.as_str() {
+            Ok(s) => s.into_owned(),
+            Err(_) => String::from_utf8_lossy(s.as_bytes()).into_owned()
+        }),
+        _ => None
+    }
+}
+
+fn date(info: &Dictionary, key: &str) -> Option<DateTime<FixedOffset>> {
+    let p = info.get(key)?.clone();
+    DateTime::<FixedOffset>::from_primitive(p, &NoResolve).ok()
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c)
+        }
+    }
+    out
+}
+
+/// An XMP packet with the same metadata as the Info dictionary `info`.
+///
+/// `Title`, `Author` and `Subject` become `dc:title`, `dc:creator` and `dc:description`,
+/// `Keywords` and `Producer` are written to the `pdf` namespace,
+/// `Creator`, `CreationDate` and `ModDate` to the `xmp` namespace.
+pub fn from_info(info: &Dictionary) -> String {
+    let mut props = String::new();
+    props.push_str("   <dc:format>application/pdf</dc:format>\n");
+    if let Some(title) = text(info, "Title") {
+        props.push_str(&format!("   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n", escape(&title)));
+    }
+    if let Some(author) = text(info, "Author") {
+        props.push_str(&format!("   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n", escape(&author)));
+    }
+    if let Some(subject) = text(info, "Subject") {
+        props.push_str(&format!("   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n", escape(&subject)));
+    }
+    for &(key, property) in TEXT_PROPERTIES {
+        if let Some(value) = text(info, key) {
+            props.push_str(&format!("   <{0}>{1}</{0}>\n", property, escape(&value)));
+        }
+    }
+    for &(key, property) in DATE_PROPERTIES {
+        if let Some(value) = date(info, key) {
+            props.push_str(&format!("   <{0}>{1}</{0}>\n", property, value.to_rfc3339()));
+        }
+    }
+
+    format!(concat!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+        " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+        "  <rdf:Description rdf:about=\"\"\n",
+        "    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n",
+        "    xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n",
+        "    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n",
+        "{}",
+        "  </rdf:Description>\n",
+        " </rdf:RDF>\n",
+        "</x:xmpmeta>\n",
+        "<?xpacket end=\"w\"?>"
+    ), props)
+}
+
+/// The `Metadata` stream with the XMP `packet`
+pub fn metadata_stream(packet: String) -> Primitive {
+    let mut info = Dictionary::new();
+    info.insert("Type", Primitive::name("Metadata"));
+    info.insert("Subtype", Primitive::name("XML"));
+    Primitive::Stream(PdfStream { info, data: packet.into_bytes() })
+}
+
+/// Namespaces of the properties in `Metadata`, with the prefixes they are known by
+#[cfg(feature = "xmp")]
+const NAMESPACES: &[(&str, &str)] = &[
+    ("http://purl.org/dc/elements/1.1/", "dc"),
+    ("http://ns.adobe.com/pdf/1.3/", "pdf"),
+    ("http://ns.adobe.com/xap/1.0/", "xmp"),
+    ("http://ns.adobe.com/xap/1.0/mm/", "xmpMM"),
+    ("http://www.aiim.org/pdfa/ns/id/", "pdfaid"),
+];
+#[cfg(feature = "xmp")]
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+#[cfg(feature = "xmp")]
+const XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// The properties of an XMP packet, see `parse`.
+///
+/// Names are qualified with the usual prefix of their namespace (`dc:title`, `xmpMM:DocumentID`),
+/// whatever prefix the packet uses. Properties of unknown namespaces keep the prefix of the packet.
+#[cfg(feature = "xmp")]
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// Simple properties with their text. Arrays (`rdf:Seq`, `rdf:Bag` and `rdf:Alt`)
+    /// have one entry per item, the `x-default` language of an `rdf:Alt` first.
+    pub properties: Vec<(String, Vec<String>)>,
+}
+#[cfg(feature = "xmp")]
+impl Metadata {
+    /// The items of the property `name`
+    pub fn get_all(&self, name: &str) -> &[String] {
+        match self.properties.iter().find(|(n, _)| n == name) {
+            Some((_, values)) => values,
+            None => &[]
+        }
+    }
+    /// The (first) value of the property `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).first().map(|s| s.as_str())
+    }
+    pub fn title(&self) -> Option<&str> {
+        self.get("dc:title")
+    }
+    /// Authors (`dc:creator`)
+    pub fn creators(&self) -> &[String] {
+        self.get_all("dc:creator")
+    }
+    pub fn description(&self) -> Option<&str> {
+        self.get("dc:description")
+    }
+    pub fn producer(&self) -> Option<&str> {
+        self.get("pdf:Producer")
+    }
+    pub fn keywords(&self) -> Option<&str> {
+        self.get("pdf:Keywords")
+    }
+    /// The application that created the document (`xmp:CreatorTool`)
+    pub fn creator_tool(&self) -> Option<&str> {
+        self.get("xmp:CreatorTool")
+    }
+    pub fn create_date(&self) -> Option<DateTime<FixedOffset>> {
+        self.date("xmp:CreateDate")
+    }
+    pub fn modify_date(&self) -> Option<DateTime<FixedOffset>> {
+        self.date("xmp:ModifyDate")
+    }
+    /// `xmpMM:DocumentID`, the same for all versions of the document
+    pub fn document_id(&self) -> Option<&str> {
+        self.get("xmpMM:DocumentID")
+    }
+    /// `xmpMM:InstanceID`, changes with every saved version of the document
+    pub fn instance_id(&self) -> Option<&str> {
+        self.get("xmpMM:InstanceID")
+    }
+    fn date(&self, name: &str) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(self.get(name)?).ok()
+    }
+}
+
+/// Read the properties of the `rdf:Description`s of the XMP `packet`
+#[cfg(feature = "xmp")]
+pub fn parse(packet: &[u8]) -> crate::error::Result<Metadata> {
+    let packet = String::from_utf8_lossy(packet);
+    let root = parse_xml(&packet)?;
+    let mut metadata = Metadata::default();
+    let mut descriptions = vec![];
+    root.find(RDF, "Description", &mut descriptions);
+    for description in descriptions {
+        for (name, value) in &description.attrs {
+            if name.0 != RDF && !name.0.is_empty() && name.0 != XML {
+                metadata.properties.push((qualified(name), vec![value.clone()]));
+            }
+        }
+        for property in description.elements() {
+            let mut items = vec![];
+            for array in property.elements().filter(|e| e.name.0 == RDF && matches!(&*e.name.1, "Seq" | "Bag" | "Alt")) {
+                for li in array.elements().filter(|e| e.name.0 == RDF && e.name.1 == "li") {
+                    let default = li.attrs.iter().any(|(n, v)| n.0 == XML && n.1 == "lang" && v == "x-default");
+                    if default {
+                        items.insert(0, li.text());
+                    } else {
+                        items.push(li.text());
+                    }
+                }
+            }
+            if items.is_empty() {
+                items.push(property.text());
+            }
+            metadata.properties.push((qualified(&property.name), items));
+        }
+    }
+    Ok(metadata)
+}
+
+#[cfg(feature = "xmp")]
+fn qualified(name: &(String, String, String)) -> String {
+    let prefix = match NAMESPACES.iter().find(|&&(ns, _)| ns == name.0) {
+        Some(&(_, prefix)) => prefix,
+        None => &name.2
+    };
+    format!("{}:{}", prefix, name.1)
+}
+
+/// An XML element with its name and the names of its attributes as (namespace, local name, prefix)
+#[cfg(feature = "xmp")]
+#[derive(Debug)]
+struct Element {
+    name: (String, String, String),
+    attrs: Vec<((String, String, String), String)>,
+    children: Vec<Node>,
+}
+#[cfg(feature = "xmp")]
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+#[cfg(feature = "xmp")]
+impl Element {
+    fn elements(&self) -> impl Iterator<Item=&Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None
+        })
+    }
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            match node {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(e) => text.push_str(&e.text()),
+            }
+        }
+        text.trim().to_owned()
+    }
+    fn find<'a>(&'a self, ns: &str, local: &str, found: &mut Vec<&'a Element>) {
+        if self.name.0 == ns && self.name.1 == local {
+            found.push(self);
+            return;
+        }
+        for e in self.elements() {
+            e.find(ns, local, found);
+        }
+    }
+}
+
+/// A minimal XML reader for XMP packets: elements, attributes, text and namespaces.
+/// Processing instructions, comments and doctypes are skipped.
+#[cfg(feature = "xmp")]
+fn parse_xml(xml: &str) -> crate::error::Result<Element> {
+    use std::collections::HashMap;
+
+    struct Open {
+        raw_name: String,
+        raw_attrs: Vec<(String, String)>,
+        children: Vec<Node>,
+        scope: HashMap<String, String>,
+    }
+    fn resolve(raw: &str, scope: &HashMap<String, String>, attribute: bool) -> (String, String, String) {
+        let (prefix, local) = match raw.find(':') {
+            Some(i) => (&raw[..i], &raw[i + 1..]),
+            None => ("", raw)
+        };
+        let ns = match prefix {
+            "xml" => XML.to_owned(),
+            // unprefixed attributes have no namespace
+            "" if attribute => String::new(),
+            _ => scope.get(prefix).cloned().unwrap_or_default()
+        };
+        (ns, local.to_owned(), prefix.to_owned())
+    }
+    fn close(open: Open) -> Element {
+        let attrs = open.raw_attrs.iter()
+            .filter(|(name, _)| name != "xmlns" && !name.starts_with("xmlns:"))
+            .map(|(name, value)| (resolve(name, &open.scope, true), value.clone()))
+            .collect();
+        Element { name: resolve(&open.raw_name, &open.scope, false), attrs, children: open.children }
+    }
+
+    let mut stack: Vec<Open> = vec![Open {
+        raw_name: String::new(),
+        raw_attrs: vec![],
+        children: vec![],
+        scope: HashMap::new(),
+    }];
+    let mut rest = xml;
+    while !rest.is_empty() {
+        if let Some(tail) = rest.strip_prefix("<![CDATA[") {
+            let end = match tail.find("]]>") {
+                Some(end) => end,
+                None => bail!("unterminated CDATA section in XMP")
+            };
+            stack.last_mut().unwrap().children.push(Node::Text(tail[..end].to_owned()));
+            rest = &tail[end + 3..];
+        } else if let Some(tail) = rest.strip_prefix("<?").or_else(|| rest.strip_prefix("<!")) {
+            let end = if rest.starts_with("<!--") { tail.find("-->").map(|i| i + 3) } else { tail.find('>').map(|i| i + 1) };
+            match end {
+                Some(end) => rest = &tail[end..],
+                None => bail!("unterminated markup in XMP")
+            }
+        } else if let Some(tail) = rest.strip_prefix("</") {
+            let end = match tail.find('>') {
+                Some(end) => end,
+                None => bail!("unterminated end tag in XMP")
+            };
+            let name = tail[..end].trim();
+            if stack.len() < 2 || stack.last().unwrap().raw_name != name {
+                bail!("unexpected end tag </{}> in XMP", name);
+            }
+            let element = close(stack.pop().unwrap());
+            stack.last_mut().unwrap().children.push(Node::Element(element));
+            rest = &tail[end + 1..];
+        } else if let Some(tail) = rest.strip_prefix('<') {
+            let (open, self_closing, tail) = match start_tag(tail) {
+                Some(tag) => tag,
+                None => bail!("malformed tag in XMP")
+            };
+            let mut scope = stack.last().unwrap().scope.clone();
+            for (name, value) in &open.1 {
+                if name == "xmlns" {
+                    scope.insert(String::new(), value.clone());
+                } else if let Some(prefix) = name.strip_prefix("xmlns:") {
+                    scope.insert(prefix.to_owned(), value.clone());
+                }
+            }
+            let open = Open { raw_name: open.0, raw_attrs: open.1, children: vec![], scope };
+            if self_closing {
+                let element = close(open);
+                stack.last_mut().unwrap().children.push(Node::Element(element));
+            } else {
+                stack.push(open);
+            }
+            rest = tail;
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            stack.last_mut().unwrap().children.push(Node::Text(unescape(&rest[..end])));
+            rest = &rest[end..];
+        }
+    }
+    if stack.len() != 1 {
+        bail!("unterminated element <{}> in XMP", stack.last().unwrap().raw_name);
+    }
+    let document = stack.pop().unwrap();
+    match document.children.into_iter().find_map(|node| match node {
+        Node::Element(e) => Some(e),
+        Node::Text(_) => None
+    }) {
+        Some(root) => Ok(root),
+        None => bail!("XMP packet without elements")
+    }
+}
+
+/// The name and attributes of the tag at the start of `s` (after the `<`),
+/// whether it closes itself and the text after it
+#[cfg(feature = "xmp")]
+#[allow(clippy::type_complexity)]
+fn start_tag(s: &str) -> Option<((String, Vec<(String, String)>), bool, &str)> {
+    let is_name = |c: char| !c.is_whitespace() && !matches!(c, '>' | '/' | '=');
+    let name_end = s.find(|c: char| !is_name(c))?;
+    let name = s[..name_end].to_owned();
+    let mut attrs = vec![];
+    let mut rest = &s[name_end..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(tail) = rest.strip_prefix("/>") {
+            return Some(((name, attrs), true, tail));
+        }
+        if let Some(tail) = rest.strip_prefix('>') {
+            return Some(((name, attrs), false, tail));
+        }
+        let attr_end = rest.find(|c: char| !is_name(c))?;
+        if attr_end == 0 {
+            return None;
+        }
+        let attr = rest[..attr_end].to_owned();
+        rest = rest[attr_end..].trim_start().strip_prefix('=')?.trim_start();
+        let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let value_end = rest[1..].find(quote)? + 1;
+        attrs.push((attr, unescape(&rest[1..value_end])));
+        rest = &rest[value_end + 1..];
+    }
+}
+
+#[cfg(feature = "xmp")]
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break
+        };
+        let c = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32),
+            entity if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+            _ => None
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(all(test, feature = "xmp"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_packet() {
+        let mut info = Dictionary::new();
+        info.insert("Title", Primitive::String(crate::primitive::PdfString::new(b"A & B".to_vec())));
+        info.insert("Producer", Primitive::String(crate::primitive::PdfString::new(b"pdf-rs".to_vec())));
+        let metadata = parse(from_info(&info).as_bytes()).unwrap();
+        assert_eq!(metadata.title(), Some("A & B"));
+        assert_eq!(metadata.producer(), Some("pdf-rs"));
+        assert_eq!(metadata.get("dc:format"), Some("application/pdf"));
+
+        // other prefixes, properties as attributes, several languages
+        let packet = br#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+            <x:xmpmeta xmlns:x="adobe:ns:meta/"><r:RDF xmlns:r="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <r:Description r:about="" xmlns:mm="http://ns.adobe.com/xap/1.0/mm/" xmlns:a="http://ns.adobe.com/pdf/1.3/"
+                mm:DocumentID="uuid:1" a:Producer="Tool &#x26; Co">
+              <!-- a comment -->
+              <e:title xmlns:e="http://purl.org/dc/elements/1.1/"><r:Alt>
+                <r:li xml:lang="de">Titel</r:li><r:li xml:lang="x-default">Title</r:li>
+              </r:Alt></e:title>
+              <mm:InstanceID><![CDATA[uuid:2]]></mm:InstanceID>
+              <x:empty/>
+            </r:Description></r:RDF></x:xmpmeta><?xpacket end="w"?>"#;
+        let metadata = parse(packet).unwrap();
+        assert_eq!(metadata.document_id(), Some("uuid:1"));
+        assert_eq!(metadata.instance_id(), Some("uuid:2"));
+        assert_eq!(metadata.producer(), Some("Tool & Co"));
+        assert_eq!(metadata.get_all("dc:title"), ["Title", "Titel"]);
+        assert_eq!(metadata.get("x:empty"), Some(""));
+
+        assert!(parse(b"<x:xmpmeta><rdf:RDF></x:xmpmeta>").is_err());
+    }
+}
@@ -106,11 +106,14 @@ impl XRefTable {
 
     pub fn add_entries_from(&mut self, section: XRefSection) {
         for (i, entry) in section.entries() {
-            // Early return if the entry we have has larger or equal generation number
+            // Early return if the entry we have has larger or equal generation number.
+            // Sections are added newest first, so an entry pointing into an object stream stays.
             let should_be_updated = match self.entries[i] {
                 XRef::Raw { gen_nr: gen, .. } | XRef::Free { gen_nr: gen, .. }
                     => entry.get_gen_nr() > gen,
-                XRef::Stream { .. } | XRef::Invalid
+                XRef::Stream { .. }
+                    => false,
+                XRef::Invalid
                     => true,
                 x => panic!("found {:?}", x)
             };
@@ -148,7 +151,7 @@ impl XRefTable {
     }
 }
 
-fn byte_len(n: u64) -> usize {
+pub(crate) fn byte_len(n: u64) -> usize {
     (64 + 8 - 1 - n.leading_zeros()) as usize / 8 + (n == 0) as usize
 }
 
@@ -93,6 +93,65 @@ fn owner_password() {
     }
 }
 
+/// `path` is encrypted with an empty user password, `protected` with "userpassword" and "ownerpassword"
+fn check_decryption(path: &str, protected: &str) {
+    let file = run!(File::<Vec<u8>>::open(path));
+    assert_eq!(run!(pdf::text::extract(&file, ..)), "Hello World!");
+    for password in [&b"userpassword"[..], b"ownerpassword"] {
+        let file = run!(File::<Vec<u8>>::open_password(protected, password));
+        assert_eq!(run!(pdf::text::extract(&file, ..)), "Hello World!");
+    }
+    match File::<Vec<u8>>::open_password(protected, b"wrong") {
+        Err(e) => assert!(e.is_invalid_password(), "{:?}", e),
+        Ok(_) => panic!("opened {} with a wrong password", protected)
+    }
+}
+
+#[test]
+fn decrypt_rc4() {
+    check_decryption(file_path!("encrypted_rc4_rev2.pdf"), file_path!("password_protected/passwords_rc4_rev2.pdf"));
+    check_decryption(file_path!("encrypted_rc4_rev3.pdf"), file_path!("password_protected/passwords_rc4_rev3.pdf"));
+}
+
+#[test]
+fn decrypt_aes_128() {
+    check_decryption(file_path!("encrypted_aes_128.pdf"), file_path!("password_protected/passwords_aes_128.pdf"));
+}
+
+#[test]
+fn decrypt_aes_256() {
+    check_decryption(file_path!("encrypted_aes_256.pdf"), file_path!("password_protected/passwords_aes_256.pdf"));
+    check_decryption(file_path!("encrypted_aes_256_hardened.pdf"), file_path!("password_protected/passwords_aes_256_hardened.pdf"));
+}
+
+#[test]
+fn password_provider() {
+    use pdf::file::FileOptions;
+
+    let mut attempts = vec![];
+    let file = run!(FileOptions::new()
+        .password(b"wrong")
+        .password_provider(|attempt| {
+            attempts.push(attempt);
+            Some(if attempt == 0 { "still wrong" } else { "userpassword" }.into())
+        })
+        .open(file_path!("password_protected/passwords_aes_256.pdf")));
+    assert_eq!(run!(pdf::text::extract(&file, ..)), "Hello World!");
+    assert_eq!(attempts, [0, 1]);
+
+    let mut asked = 0;
+    let result = FileOptions::new()
+        .password_provider(|_| { asked += 1; None })
+        .open(file_path!("password_protected/passwords_rc4_rev3.pdf"));
+    assert!(result.err().unwrap().is_invalid_password());
+    assert_eq!(asked, 1);
+
+    // the empty user password works without asking
+    run!(FileOptions::new()
+        .password_provider(|_| panic!("no password needed"))
+        .open(file_path!("encrypted_aes_128.pdf")));
+}
+
 #[test]
 fn parse_objects_from_stream() {
     use pdf::object::NoResolve;
@@ -107,3 +166,3383 @@ fn parse_objects_from_stream() {
 }
 
 // TODO test decoding
+
+#[test]
+fn embed_truetype_font() {
+    use pdf::build::FontBuilder;
+    use pdf::font::{Font, FontType};
+
+    let mut file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let resources = run!(page.resources());
+    let data = resources.fonts.values()
+        .filter_map(|&r| file.get(r).ok())
+        .find_map(|font| font.embedded_data().and_then(|d| d.ok()).map(|d| d.to_vec()))
+        .expect("no embedded font");
+
+    let builder = run!(FontBuilder::from_ttf(data));
+    let font = run!(builder.build(&mut file));
+    let font = run!(file.create(font));
+
+    let font: RcRef<Font> = run!(file.get(font.get_ref()));
+    assert!(matches!(font.subtype, FontType::Type0));
+    let widths = run!(font.widths(&file)).expect("no widths");
+    assert!(widths.get(1) > 0.);
+    assert!(font.embedded_data().is_some());
+    assert!(run!(font.to_unicode().expect("no ToUnicode")).iter().all(|(gid, _)| gid > 0));
+}
+
+#[test]
+fn symbolic_truetype_glyphs() {
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let resources = run!(page.resources());
+    for &font in resources.fonts.values() {
+        let font = run!(file.get(font));
+        let info = font.info().expect("not a simple font");
+        // code 0 is .notdef
+        let first = (info.first_char.unwrap() as u32).max(1);
+        let last = info.last_char.unwrap() as u32;
+        let font_info = run!(pdf::font::FontInfo::new(font.clone(), &file));
+        for code in first ..= last {
+            assert!(font.glyph_id(code).unwrap_or(0) > 0, "no glyph for code {}", code);
+            // looked up in the table built once
+            assert_eq!(font_info.glyph_id(code), font.glyph_id(code));
+        }
+    }
+}
+
+#[test]
+fn font_cache() {
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let resources = run!(page.resources());
+    for &font in resources.fonts.values() {
+        let a = run!(file.get_font(font));
+        let b = run!(file.get_font(font));
+        assert!(Rc::ptr_eq(&a, &b));
+    }
+}
+
+#[test]
+fn cmap_cycle() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::cmap::CMap;
+    use pdf::primitive::{Dictionary, PdfStream, Primitive};
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let program = b"begincmap 1 begincodespacerange <00> <ff> endcodespacerange endcmap".to_vec();
+    let stream = |use_cmap: Option<Primitive>| {
+        let mut info = Dictionary::new();
+        info.insert("Length", Primitive::Integer(program.len() as i32));
+        if let Some(p) = use_cmap {
+            info.insert("UseCMap", p);
+        }
+        Primitive::Stream(PdfStream { info, data: program.clone() })
+    };
+    let r = run!(file.create(stream(None))).get_ref().get_inner();
+    // uses itself
+    run!(file.update(r, stream(Some(Primitive::Reference(r)))));
+    assert!(CMap::from_primitive(Primitive::Reference(r), &file).is_err());
+}
+
+#[test]
+fn font_cache_update() {
+    use pdf::primitive::Primitive;
+
+    let mut file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let resources = run!(page.resources());
+    let font = *resources.fonts.values().next().expect("no fonts");
+    let old = run!(file.get_font(font));
+
+    let mut dict = run!(run!(file.resolve(font.get_inner())).into_dictionary(&file));
+    dict.insert("BaseFont", Primitive::Name("Renamed".into()));
+    run!(file.update(font.get_inner(), Primitive::Dictionary(dict)));
+
+    let new = run!(file.get_font(font));
+    assert!(!Rc::ptr_eq(&old, &new));
+    assert_eq!(new.font.name, "Renamed");
+}
+
+#[test]
+fn extract_text() {
+    use std::ops::Bound;
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let text = run!(pdf::text::extract(&file, ..));
+    assert_eq!(text, "Contents of page 1\x0cContents of page 2\x0cContents of page 3");
+    let text = run!(pdf::text::extract(&file, 1 ..= 1));
+    assert_eq!(text, "Contents of page 2");
+    // bounds at the end of the numbers
+    let text = run!(pdf::text::extract(&file, 2 ..= u32::MAX));
+    assert_eq!(text, "Contents of page 3");
+    assert_eq!(run!(pdf::text::extract(&file, (Bound::Excluded(u32::MAX), Bound::Unbounded))), "");
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("xelatex.pdf")));
+    let text = run!(pdf::text::extract(&file, 0 .. 1));
+    assert!(text.starts_with("Problem Set 2, Part 1\n21. Sept, 2016\n"));
+}
+
+#[test]
+fn text_items() {
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let media_box = page.media_box.unwrap();
+    let items: Vec<_> = run!(pdf::text::items(&file, &page)).filter(|i| !i.text.is_empty()).collect();
+    assert_eq!(items.iter().map(|i| i.text.as_str()).collect::<String>(), "Contents of page 1");
+    for item in &items {
+        assert!(item.bbox.left < item.bbox.right && item.bbox.bottom < item.bbox.top);
+        assert!(media_box.left <= item.bbox.left && item.bbox.right <= media_box.right);
+        assert!(media_box.bottom <= item.bbox.bottom && item.bbox.top <= media_box.top);
+    }
+}
+
+#[test]
+fn text_layout() {
+    let file = run!(File::<Vec<u8>>::open(file_path!("pdf-sample.pdf")));
+    let page = run!(file.get_page(0));
+    let items: Vec<_> = run!(pdf::text::items(&file, &page)).collect();
+    let blocks = pdf::text::layout(&items);
+    assert_eq!(blocks[0].text(), "Adobe Acrobat PDF Files");
+    assert_eq!(blocks[1].lines.len(), 3);
+    assert_eq!(blocks[1].lines[2].text(), "the application and platform used to create it.");
+    assert_eq!(blocks[1].lines[0].words[0].text, "Adobe®");
+}
+
+#[test]
+fn find_text() {
+    let file = run!(File::<Vec<u8>>::open(file_path!("pdf-sample.pdf")));
+    let matches = run!(file.find_text("Portable Document Format"));
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].page_nr, 0);
+    assert_eq!(matches[0].rects.len(), 1);
+    let rect = matches[0].rects[0];
+    assert!(rect.left > 89.0 && rect.right < 506.0 && rect.bottom > 699.0 && rect.top < 741.0);
+
+    let matches = run!(file.find_text("preserves all\nof the"));
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].rects.len(), 2);
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let pages: Vec<u32> = run!(file.find_text("Contents")).iter().map(|m| m.page_nr).collect();
+    assert_eq!(pages, [0, 1, 2]);
+}
+
+#[test]
+fn extract_text_reading_order() {
+    let file = run!(File::<Vec<u8>>::open(file_path!("pdf-sample.pdf")));
+    let options = pdf::text::ExtractOptions { reading_order: true, ..Default::default() };
+    let text = run!(pdf::text::extract_with(&file, .., &options));
+    assert!(text.starts_with("Adobe Acrobat PDF Files\n\nAdobe® Portable Document Format (PDF)"));
+}
+
+#[test]
+fn text_rotated_page() {
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let media_box = run!(page.media_box());
+    let width = media_box.right - media_box.left;
+    let bbox = |page: &Page| {
+        let items: Vec<_> = run!(pdf::text::items(&file, page)).filter(|i| !i.text.is_empty()).collect();
+        items.iter().map(|i| i.bbox).reduce(|a, b| Rect {
+            left: a.left.min(b.left), bottom: a.bottom.min(b.bottom),
+            right: a.right.max(b.right), top: a.top.max(b.top),
+        }).unwrap()
+    };
+    let upright = bbox(&page);
+
+    let mut rotated = (*page).clone();
+    rotated.rotate = Some(90);
+    assert_eq!(run!(rotated.rotation()), 90);
+    let turned = bbox(&rotated);
+    let close = |a: f32, b: f32| (a - b).abs() < 0.01;
+    assert!(close(turned.left, upright.bottom - media_box.bottom));
+    assert!(close(turned.right, upright.top - media_box.bottom));
+    assert!(close(turned.top, width - (upright.left - media_box.left)));
+    assert!(close(turned.bottom, width - (upright.right - media_box.left)));
+}
+
+#[test]
+fn text_state() {
+    use pdf::content::{Op, Matrix, Point};
+    use pdf::primitive::PdfString;
+    use pdf::text::TextState;
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let resources = run!(page.resources());
+    let font = resources.fonts.keys().next().unwrap().clone();
+
+    let mut state = TextState::new();
+    let ops = [
+        Op::Save,
+        Op::CharSpacing { char_space: 2. },
+        Op::Transform { matrix: Matrix::translate(10., 0.) },
+        Op::Restore,
+        Op::BeginText,
+        Op::TextFont { name: font, size: 10. },
+        Op::Leading { leading: 12. },
+        Op::SetTextMatrix { matrix: Matrix::translate(100., 700.) },
+        Op::TextNewline,
+    ];
+    for op in &ops {
+        assert!(state.apply(op, &file, resources).is_empty());
+    }
+    assert_eq!(state.char_space, 0.);
+    assert_eq!(state.ctm, Matrix::default());
+    assert_eq!(state.trm().transform(Point { x: 0., y: 0. }), Point { x: 100., y: 688. });
+
+    let items = state.apply(&Op::TextDraw { text: PdfString::new(vec![1, 2, 3]) }, &file, resources);
+    assert_eq!(items.len(), 1);
+    let item = &items[0];
+    assert_eq!(item.glyphs.len(), 3);
+    assert_eq!(item.start(), Point { x: 100., y: 688. });
+    // the text matrix moved past the string
+    let end = state.trm().transform(Point { x: 0., y: 0. });
+    assert!((end.x - item.end().x).abs() < 1e-3 && end.x > 100.);
+}
+
+#[test]
+fn text_form_xobject() {
+    // the page only draws a form XObject, which shows the text
+    let file = run!(File::<Vec<u8>>::open(file_path!("formxobject.pdf")));
+    assert_eq!(run!(pdf::text::extract(&file, ..)), "Hello World!");
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("xelatex-drawboard.pdf")));
+    let page = run!(file.get_page(0));
+    run!(pdf::text::annotation_items(&file, &page));
+}
+
+#[test]
+fn content_round_trip() {
+    use pdf::content::Content;
+    use pdf::primitive::{Dictionary, PdfStream, Primitive};
+
+    for entry in glob(file_path!("*.pdf")).expect("Failed to read glob pattern") {
+        let path = entry.unwrap();
+        let file = match File::<Vec<u8>>::open(&path) {
+            Ok(file) => file,
+            Err(_) => continue
+        };
+        for page in file.pages().filter_map(|p| p.ok()) {
+            let content = match page.contents {
+                Some(ref content) => content,
+                None => continue
+            };
+            let data = run!(content.to_bytes());
+            let mut info = Dictionary::new();
+            info.insert("Length", Primitive::Integer(data.len() as i32));
+            let stream = Primitive::Stream(PdfStream { info, data });
+            let parsed = run!(Content::from_primitive(stream, &NoResolve));
+            assert_eq!(format!("{:?}", parsed.operations), format!("{:?}", content.operations), "{:?}", path);
+        }
+    }
+}
+
+#[test]
+fn content_lazy() {
+    for entry in glob(file_path!("*.pdf")).expect("Failed to read glob pattern") {
+        let path = entry.unwrap();
+        let file = match File::<Vec<u8>>::open(&path) {
+            Ok(file) => file,
+            Err(_) => continue
+        };
+        for page in file.pages().filter_map(|p| p.ok()) {
+            let content = match page.contents {
+                Some(ref content) => content,
+                None => continue
+            };
+            let ops: Vec<_> = run!(content.operations_lazy().collect::<Result<Vec<_>, _>>());
+            assert_eq!(format!("{:?}", ops), format!("{:?}", content.operations), "{:?}", path);
+        }
+    }
+}
+
+#[test]
+fn content_builder() {
+    use pdf::build::ContentBuilder;
+    use pdf::content::Color;
+
+    let content = ContentBuilder::new()
+        .save()
+        .fill_color(Color::Gray(0.5))
+        .font("F1", 12.)
+        .text_position(72., 720.)
+        .show("Grüße")
+        .rect(72., 700., 100., 0.25)
+        .fill()
+        .build();
+    let data = run!(content.to_bytes());
+    assert_eq!(str::from_utf8(&data).unwrap().replace('\n', " "),
+        "q 0.5 g /F1 12 Tf BT 72 720 Td <4772fcdf65> Tj ET 72 700 100 0.25 re f Q ");
+}
+
+#[test]
+fn graphics_state_machine() {
+    use pdf::content::{Color, Matrix, Op, Point, Rect, Rgb, Winding};
+    use pdf::graphics::{Event, GraphicsStateMachine, PathSegment};
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(0));
+    let resources = run!(page.resources());
+
+    let mut machine = GraphicsStateMachine::new(Matrix::translate(0., 100.));
+    let red = Color::Rgb(Rgb { red: 1., green: 0., blue: 0. });
+    let ops = [
+        Op::Save,
+        Op::Transform { matrix: Matrix { a: 2., d: 2., ..Matrix::default() } },
+        Op::LineWidth { width: 3. },
+        Op::FillColor { color: red },
+        Op::Rect { rect: Rect { x: 1., y: 1., width: 2., height: 3. } },
+    ];
+    for op in &ops {
+        assert!(machine.apply(op, &file, resources).is_empty());
+    }
+    assert_eq!(machine.depth(), 1);
+    assert_eq!(machine.state().fill_color_space, "DeviceRGB");
+
+    let events = machine.apply(&Op::Fill { winding: Winding::NonZero }, &file, resources);
+    match events[..] {
+        [Event::Path(ref path)] => {
+            assert_eq!(path.fill, Some(Winding::NonZero));
+            assert!(!path.stroke);
+            assert_eq!(path.segments[0], PathSegment::MoveTo(Point { x: 2., y: 102. }));
+            assert_eq!(path.segments[2], PathSegment::LineTo(Point { x: 6., y: 108. }));
+        }
+        ref e => panic!("unexpected events {:?}", e)
+    }
+    assert_eq!(machine.state().line_width, 3.);
+
+    machine.apply(&Op::Restore, &file, resources);
+    assert_eq!(machine.depth(), 0);
+    assert_eq!(machine.state().line_width, 1.);
+    assert_eq!(machine.state().ctm, Matrix::translate(0., 100.));
+
+    // the text on a page
+    let contents = page.contents.as_ref().unwrap();
+    let mut machine = GraphicsStateMachine::new(Matrix::default());
+    let text: usize = contents.operations.iter()
+        .flat_map(|op| machine.apply(op, &file, resources))
+        .filter(|e| matches!(e, Event::Text(_)))
+        .count();
+    assert_eq!(text, run!(pdf::text::items(&file, &page)).count());
+}
+#[test]
+fn paths() {
+    use pdf::content::{Color, Rgb, Winding};
+    use pdf::graphics::PathSegment;
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("ep2.pdf")));
+    let page = run!(file.get_page(2));
+    let items: Vec<_> = run!(pdf::graphics::paths(&file, &page)).collect();
+    assert_eq!(items.len(), 1);
+    let rule = &items[0];
+    assert_eq!(rule.path.fill, Some(Winding::NonZero));
+    assert!(!rule.path.stroke);
+    assert!(matches!(rule.fill_color, Some(Color::Rgb(Rgb { red, green, blue })) if red == 0. && green == 0. && blue == 0.));
+    assert!(rule.stroke_color.is_none());
+    assert_eq!(rule.path.segments.len(), 5);
+    assert!(matches!(rule.path.segments[4], PathSegment::Close));
+
+    let resources = run!(page.resources());
+    let red = pdf::graphics::resolve_color(&Color::Components(vec![1., 0., 0.]), "DeviceRGB", resources);
+    assert!(matches!(red, Color::Rgb(Rgb { red, .. }) if red == 1.));
+}
+#[test]
+fn flatten_forms() {
+    use pdf::content::Op;
+    use pdf::graphics::flatten;
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("formxobject.pdf")));
+    let page = run!(file.get_page(0));
+    let ops: Vec<_> = run!(flatten(&file, &page)).collect();
+    let page_ops = page.contents.as_ref().unwrap().operations.len();
+    assert!(ops.len() > page_ops);
+    assert!(ops.iter().any(|op| op.depth == 1));
+    assert!(!ops.iter().any(|op| matches!(op.op, Op::XObject { .. })));
+
+    // every form is wrapped in q / Q of the enclosing depth
+    let mut depth = 0;
+    for (i, op) in ops.iter().enumerate() {
+        if op.depth > depth {
+            assert!(matches!(ops[i - 1].op, Op::Save | Op::Transform { .. }));
+        } else if op.depth < depth {
+            assert!(matches!(op.op, Op::Restore));
+        }
+        depth = op.depth;
+    }
+    assert_eq!(depth, 0);
+}
+
+#[test]
+fn save_round_trip() {
+    let dir = std::env::temp_dir();
+    for entry in glob(file_path!("*.pdf")).expect("Failed to read glob pattern") {
+        let path = entry.unwrap();
+        let mut file = match File::<Vec<u8>>::open(&path) {
+            Ok(file) => file,
+            Err(_) => continue
+        };
+        let out = dir.join(format!("pdf-rs-save-{}", path.file_name().unwrap().to_str().unwrap()));
+        run!(file.save_to(&out));
+        let copy = run!(File::<Vec<u8>>::open(&out));
+        assert_eq!(file.num_pages(), copy.num_pages(), "{:?}", path);
+        if let Ok(text) = pdf::text::extract(&file, ..) {
+            assert_eq!(text, run!(pdf::text::extract(&copy, ..)), "{:?}", path);
+        }
+        let _ = std::fs::remove_file(&out);
+    }
+
+    // the copy of an encrypted file is not encrypted
+    let mut file = run!(File::<Vec<u8>>::open_password(file_path!("password_protected/passwords_rc4_rev3.pdf"), b"userpassword"));
+    let out = dir.join("pdf-rs-save-rc4_rev3.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::<Vec<u8>>::open(&out));
+    assert!(copy.trailer.encrypt_dict.is_none());
+    assert_eq!(run!(pdf::text::extract(&file, ..)), run!(pdf::text::extract(&copy, ..)));
+    let _ = std::fs::remove_file(&out);
+}
+
+#[test]
+fn save_incremental() {
+    let original = std::fs::read(file_path!("libreoffice.pdf")).unwrap();
+    let mut file = run!(File::from_data(original.clone()));
+    let page = run!(file.get_page(1));
+    let mut rotated = (*page).clone();
+    rotated.rotate = Some(90);
+    run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(rotated)));
+
+    let out = std::env::temp_dir().join("pdf-rs-save-incremental.pdf");
+    run!(file.save_incremental(&out));
+    let data = std::fs::read(&out).unwrap();
+    let _ = std::fs::remove_file(&out);
+    assert!(data.starts_with(&original));
+
+    let copy = run!(File::from_data(data));
+    assert_eq!(copy.num_pages(), 3);
+    assert_eq!(run!(copy.get_page(0)).rotate, None);
+    assert_eq!(run!(copy.get_page(1)).rotate, Some(90));
+    assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1\x0cContents of page 2\x0cContents of page 3");
+}
+
+#[test]
+fn xref_stream() {
+    use pdf::file::SaveOptions;
+
+    let original = std::fs::read(file_path!("libreoffice.pdf")).unwrap();
+    for &incremental in &[false, true] {
+        let mut file = run!(File::from_data(original.clone()));
+        let page = run!(file.get_page(2));
+        let mut rotated = (*page).clone();
+        rotated.rotate = Some(180);
+        run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(rotated)));
+
+        let out = std::env::temp_dir().join(format!("pdf-rs-xref-stream-{}.pdf", incremental));
+        run!(file.save_with(&out, &SaveOptions { incremental, xref_stream: true, ..SaveOptions::default() }));
+        let data = std::fs::read(&out).unwrap();
+        let _ = std::fs::remove_file(&out);
+        let tail = if incremental { &data[original.len() ..] } else { &data[..] };
+        let tail = String::from_utf8_lossy(tail);
+        assert!(tail.contains("/Type /XRef"));
+        assert!(!tail.contains("trailer"));
+
+        let copy = run!(File::from_data(data));
+        assert_eq!(copy.num_pages(), 3);
+        assert_eq!(run!(copy.get_page(2)).rotate, Some(180));
+        assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1\x0cContents of page 2\x0cContents of page 3");
+    }
+}
+
+#[test]
+fn object_streams() {
+    use pdf::file::SaveOptions;
+
+    let original = std::fs::read(file_path!("libreoffice.pdf")).unwrap();
+    for &incremental in &[false, true] {
+        let mut file = run!(File::from_data(original.clone()));
+        let page = run!(file.get_page(0));
+        let mut rotated = (*page).clone();
+        rotated.rotate = Some(270);
+        run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(rotated)));
+
+        let out = std::env::temp_dir().join(format!("pdf-rs-object-streams-{}.pdf", incremental));
+        run!(file.save_with(&out, &SaveOptions { incremental, object_streams: true, ..SaveOptions::default() }));
+        let data = std::fs::read(&out).unwrap();
+        let _ = std::fs::remove_file(&out);
+        let tail = if incremental { &data[original.len() ..] } else { &data[..] };
+        let tail = String::from_utf8_lossy(tail);
+        assert!(tail.contains("/Type /ObjStm"));
+        assert!(tail.contains("/Type /XRef"));
+
+        let copy = run!(File::from_data(data));
+        assert_eq!(copy.num_pages(), 3);
+        assert_eq!(run!(copy.get_page(0)).rotate, Some(270));
+        assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1\x0cContents of page 2\x0cContents of page 3");
+    }
+}
+
+#[test]
+fn stamp_pages() {
+    use pdf::build::{stamp, ContentBuilder, DocumentBuilder};
+    use pdf::content::{Matrix, Op};
+    use pdf::standard_fonts::StandardFont;
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    let font = run!(file.create(DocumentBuilder::standard_font(StandardFont::HelveticaBold)));
+    let mut resources = Resources::default();
+    resources.fonts.insert("F1".into(), font.get_ref());
+    let form = run!(ContentBuilder::new()
+        .font("F1", 72.)
+        .text_position(0., 0.)
+        .show("DRAFT")
+        .build_form(Rect { left: 0., bottom: 0., right: 250., top: 72. }, Some(resources)));
+    let draft = run!(file.create(XObject::Form(form))).get_ref();
+    for i in 0 .. file.num_pages() {
+        let page = run!(file.get_page(i));
+        let mut stamped = (*page).clone();
+        assert_eq!(run!(stamp(&mut stamped, draft, Matrix::translate(100., 400.))), "Stamp1");
+        run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(stamped)));
+    }
+
+    let out = std::env::temp_dir().join("pdf-rs-stamp.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+    for i in 0 .. copy.num_pages() {
+        let page = run!(copy.get_page(i));
+        let resources = run!(page.resources());
+        assert_eq!(resources.xobjects.get("Stamp1").map(|r| r.get_inner()), Some(draft.get_inner()));
+        assert!(!resources.fonts.is_empty());
+        let ops = &page.contents.as_ref().unwrap().operations;
+        assert!(matches!(ops[0], Op::Save));
+        assert!(matches!(ops[ops.len() - 2], Op::XObject { ref name } if name == "Stamp1"));
+    }
+    assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1\nDRAFT\x0cContents of page 2\nDRAFT\x0cContents of page 3\nDRAFT");
+}
+
+#[test]
+fn rearrange_pages() {
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    run!(file.reorder_pages(&[2, 0, 1]));
+    assert_eq!(run!(pdf::text::extract(&file, ..)), "Contents of page 3\x0cContents of page 1\x0cContents of page 2");
+    assert!(file.reorder_pages(&[0, 0, 1]).is_err());
+    assert!(file.reorder_pages(&[0, 1]).is_err());
+
+    run!(file.remove_page(1));
+    assert_eq!(file.num_pages(), 2);
+    assert!(file.remove_page(2).is_err());
+
+    let other = run!(File::open(file_path!("libreoffice.pdf")));
+    let imported = run!(file.import_pages(&other, 1 ..));
+    assert_eq!(imported.len(), 2);
+    let none = (std::ops::Bound::Excluded(u32::MAX), std::ops::Bound::Unbounded);
+    assert!(run!(file.import_pages(&other, none)).is_empty());
+    assert!(!run!(imported[0].resources()).fonts.is_empty());
+
+    let out = std::env::temp_dir().join("pdf-rs-rearrange-pages.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+    assert_eq!(copy.num_pages(), 4);
+    assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 3\x0cContents of page 2\x0cContents of page 2\x0cContents of page 3");
+    for page in copy.pages() {
+        let page = run!(page);
+        assert!(run!(page.media_box()).right > 0.);
+    }
+}
+
+#[test]
+fn crop_and_rotate_pages() {
+    use pdf::file::SaveOptions;
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    let page = run!(file.get_page(1));
+    let mut edited = (*page).clone();
+    let crop = Rect { left: 50., bottom: 100., right: 300., top: 400. };
+    edited.set_crop_box(Rect { left: 300., bottom: 400., right: 50., top: 100. });
+    run!(edited.set_rotation(-90));
+    assert!(edited.set_rotation(45).is_err());
+    run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(edited)));
+
+    let data = run!(file.save_to_bytes(&SaveOptions::default()));
+    let copy = run!(File::from_data(data));
+    let page = run!(copy.get_page(1));
+    let crop_box = run!(page.crop_box());
+    assert_eq!((crop_box.left, crop_box.bottom, crop_box.right, crop_box.top), (crop.left, crop.bottom, crop.right, crop.top));
+    assert_eq!(run!(page.rotation()), 270);
+    assert_eq!(run!(copy.get_page(0)).rotate, None);
+    assert_eq!(run!(pdf::text::extract(&copy, 1 ..= 1)), "Contents of page 2");
+}
+
+#[test]
+fn impose_pages() {
+    use pdf::content::Matrix;
+    use pdf::impose::{booklet, booklet_order, n_up, overlay};
+
+    let file = run!(File::open(file_path!("libreoffice.pdf")));
+    let size = run!(run!(file.get_page(0)).media_box());
+    let sheet = Rect { left: 0., bottom: 0., right: size.top, top: size.right };
+
+    let mut two_up = run!(n_up(&file, 2, 1, sheet));
+    assert_eq!(two_up.num_pages(), 2);
+    let data = run!(two_up.save_to_bytes(&Default::default()));
+    let copy = run!(File::from_data(data));
+    assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1 Contents of page 2\x0cContents of page 3");
+    let page = run!(copy.get_page(0));
+    assert_eq!(run!(page.resources()).xobjects.len(), 2);
+
+    assert_eq!(booklet_order(3), vec![[None, Some(0)], [Some(1), Some(2)]]);
+    assert_eq!(booklet_order(8)[2], [Some(5), Some(2)]);
+    let booklet = run!(booklet(&file, sheet));
+    assert_eq!(booklet.num_pages(), 2);
+    assert_eq!(run!(pdf::text::extract(&booklet, 1 ..= 1)), "Contents of page 2 Contents of page 3");
+
+    let mut target = run!(File::open(file_path!("libreoffice.pdf")));
+    run!(overlay(&mut target, 2, &file, 0, Matrix::translate(0., -100.)));
+    assert!(overlay(&mut target, 2, &file, 5, Matrix::default()).is_err());
+    let text = run!(pdf::text::extract(&target, 2 ..= 2));
+    assert!(text.contains("Contents of page 1") && text.contains("Contents of page 3"), "{:?}", text);
+}
+
+#[test]
+fn extract_pages() {
+    let original = std::fs::read(file_path!("libreoffice.pdf")).unwrap();
+    let file = run!(File::from_data(original.clone()));
+    for n in 0 .. file.num_pages() {
+        let mut single = run!(file.extract_pages(n ..= n));
+        assert_eq!(single.num_pages(), 1);
+
+        let out = std::env::temp_dir().join(format!("pdf-rs-extract-pages-{}.pdf", n));
+        run!(single.save_to(&out));
+        let data = std::fs::read(&out).unwrap();
+        let _ = std::fs::remove_file(&out);
+        assert!(data.len() < original.len());
+
+        let copy = run!(File::from_data(data));
+        assert_eq!(copy.num_pages(), 1);
+        assert_eq!(run!(pdf::text::extract(&copy, ..)), format!("Contents of page {}", n + 1));
+    }
+    assert_eq!(run!(file.extract_pages(1 ..)).num_pages(), 2);
+}
+
+#[test]
+fn info_and_xmp() {
+    use chrono::{FixedOffset, TimeZone};
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    assert!(!file.is_dirty());
+    file.set_info("Title", "Grüße & <Co>");
+    file.set_info("Producer", "pdf-rs");
+    let date = FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap();
+    run!(file.set_info_date("ModDate", date));
+    file.set_info("CreationDate", "yesterday");
+    file.set_info("Department", "Docs");
+    file.trailer.info_dict.as_mut().unwrap().insert("Trapped", pdf::primitive::Primitive::Boolean(false));
+    assert!(file.is_dirty());
+
+    let out = std::env::temp_dir().join("pdf-rs-info-and-xmp.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let info = copy.trailer.info_dict.as_ref().unwrap();
+    assert_eq!(run!(info["Title"].as_string().unwrap().as_str()), "Grüße & <Co>");
+    assert_eq!(run!(info["Producer"].as_string().unwrap().as_str()), "pdf-rs");
+
+    let info = run!(copy.info()).unwrap();
+    assert_eq!(run!(info.title.unwrap().as_str()), "Grüße & <Co>");
+    assert_eq!(info.mod_date, Some(date));
+    // not a date
+    assert!(info.creation_date.is_none());
+    assert_eq!(info.trapped, Some(Trapped::False));
+    assert_eq!(run!(info.other["Department"].as_string().unwrap().as_str()), "Docs");
+
+    let metadata = run!(copy.get(copy.get_root().metadata.unwrap()));
+    let xmp = str::from_utf8(run!(metadata.data())).unwrap();
+    assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">Grüße &amp; &lt;Co&gt;</rdf:li>"));
+    assert!(xmp.contains("<pdf:Producer>pdf-rs</pdf:Producer>"));
+    assert!(xmp.contains("<xmp:ModifyDate>2021-03-04T05:06:07+02:00</xmp:ModifyDate>"));
+    assert_eq!(run!(copy.xmp_packet()).unwrap(), xmp.as_bytes());
+    assert_eq!(copy.num_pages(), 3);
+}
+
+#[test]
+fn read_xmp() {
+    let file = run!(File::open(file_path!("pdf-sample.pdf")));
+    let packet = run!(file.xmp_packet()).unwrap();
+    assert!(str::from_utf8(&packet).unwrap().contains("<x:xmpmeta"));
+    assert!(run!(File::open(file_path!("libreoffice.pdf"))).xmp_packet().unwrap().is_none());
+
+    #[cfg(feature = "xmp")]
+    {
+        let metadata = run!(file.xmp_metadata()).unwrap();
+        assert_eq!(metadata.title(), Some("This is a test PDF file"));
+        assert_eq!(metadata.creators(), ["cdaily"]);
+        assert_eq!(metadata.producer(), Some("Acrobat Distiller 4.0 for Windows"));
+        assert_eq!(metadata.creator_tool(), Some("Microsoft Word 8.0"));
+        assert_eq!(metadata.document_id(), Some("uuid:0805e221-80a8-459e-a522-635ed5c1e2e6"));
+        assert_eq!(metadata.instance_id(), Some("uuid:62d6ae6d-43c4-472d-9b28-7c4add8f9e46"));
+        assert_eq!(metadata.create_date().unwrap().to_rfc3339(), "2000-06-29T10:21:08+11:00");
+    }
+}
+
+#[test]
+fn encrypt_output() {
+    use pdf::crypt::{CryptMethod, EncryptOptions, Permissions};
+    use pdf::file::SaveOptions;
+
+    for (n, &method) in [CryptMethod::V2, CryptMethod::AESV2, CryptMethod::AESV3].iter().enumerate() {
+        let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+        file.set_info("Title", "Secret title");
+        let encrypt = EncryptOptions {
+            permissions: Permissions::PRINT,
+            method,
+            ..EncryptOptions::aes256("user", "owner")
+        };
+        let options = SaveOptions { object_streams: n == 1, encrypt: Some(encrypt), ..SaveOptions::default() };
+        let out = std::env::temp_dir().join(format!("pdf-rs-encrypt-output-{}.pdf", n));
+        run!(file.save_with(&out, &options));
+        let data = std::fs::read(&out).unwrap();
+        let _ = std::fs::remove_file(&out);
+        assert!(!String::from_utf8_lossy(&data).contains("Contents of page"));
+        assert!(!String::from_utf8_lossy(&data).contains("Secret title"));
+
+        assert!(File::from_data(data.clone()).is_err());
+        assert!(File::from_data_password(data.clone(), b"wrong").is_err());
+        for password in [&b"user"[..], b"owner"] {
+            let copy = run!(File::from_data_password(data.clone(), password));
+            assert_eq!(copy.num_pages(), 3);
+            assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1\x0cContents of page 2\x0cContents of page 3");
+            let title = copy.trailer.info_dict.as_ref().and_then(|info| info.get("Title")).unwrap();
+            assert_eq!(run!(run!(title.as_string()).as_str()), "Secret title");
+            let permissions = copy.permissions();
+            if password == b"user" {
+                assert_eq!(permissions, Permissions::PRINT);
+                assert!(!permissions.contains(Permissions::COPY));
+            } else {
+                assert_eq!(permissions, Permissions::all());
+            }
+        }
+    }
+    assert_eq!(run!(File::open(file_path!("libreoffice.pdf"))).permissions(), Permissions::all());
+}
+
+#[test]
+fn unencrypted_metadata() {
+    use pdf::crypt::{CryptMethod, EncryptOptions};
+    use pdf::file::SaveOptions;
+
+    for &method in [CryptMethod::AESV2, CryptMethod::AESV3].iter() {
+        let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+        file.set_info("Title", "Searchable title");
+        let encrypt = EncryptOptions { method, encrypt_metadata: false, ..EncryptOptions::aes256("user", "owner") };
+        let data = run!(file.save_to_bytes(&SaveOptions { encrypt: Some(encrypt), ..SaveOptions::default() }));
+        // the XMP packet can be read without the password, the Info dictionary and the pages can't
+        let text = String::from_utf8_lossy(&data);
+        assert_eq!(text.matches("Searchable title").count(), 1);
+        assert!(!text.contains("Contents of page"));
+
+        let copy = run!(File::from_data_password(data, b"user"));
+        let metadata = run!(copy.get(copy.get_root().metadata.unwrap()));
+        assert!(str::from_utf8(run!(metadata.data())).unwrap().contains("Searchable title"));
+        assert_eq!(run!(pdf::text::extract(&copy, 0 ..= 0)), "Contents of page 1");
+    }
+    let encrypt = EncryptOptions { method: CryptMethod::V2, encrypt_metadata: false, ..EncryptOptions::aes256("user", "owner") };
+    assert!(encrypt.security_handler(b"id").is_err());
+}
+
+#[test]
+fn attach_files() {
+    use pdf::primitive::Dictionary;
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    run!(file.attach("b.txt", b"second", "text/plain", ""));
+    run!(file.attach("a.csv", b"1,2,3", "text/csv", "Some numbers"));
+    run!(file.attach("b.txt", b"replaced", "text/plain", ""));
+
+    let out = std::env::temp_dir().join("pdf-rs-attach-files.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+    assert_eq!(copy.num_pages(), 3);
+
+    let names = copy.get_root().names.as_ref().unwrap();
+    let mut found = vec![];
+    run!(names.embedded_files.as_ref().unwrap().walk(&copy, &mut |name, _| found.push(name.as_str().unwrap().into_owned())));
+    assert_eq!(found, ["a.csv", "b.txt"]);
+
+    // the embedded file stream of "b.txt"
+    let catalog = run!(copy.resolve(copy.trailer.root.get_ref().get_inner()).unwrap().into_dictionary(&copy));
+    let names = run!(catalog["Names"].clone().into_dictionary(&copy));
+    let tree = run!(names["EmbeddedFiles"].clone().into_dictionary(&copy));
+    let list = run!(tree["Names"].clone().into_array(&copy));
+    let spec = run!(list[3].clone().into_dictionary(&copy));
+    assert_eq!(run!(spec["Type"].as_name()), "Filespec");
+    let ef = run!(spec["EF"].clone().into_dictionary(&copy));
+    let stream = run!(Stream::<Dictionary>::from_primitive(ef["F"].clone(), &copy));
+    assert_eq!(run!(stream.data()), b"replaced");
+    assert_eq!(run!(stream.info.info["Subtype"].as_name()), "text/plain");
+}
+
+#[test]
+fn fill_form() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::form::{FieldType, FieldValue};
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+    use pdf::standard_fonts::StandardFont;
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn rect(values: [i32; 4]) -> Primitive {
+        Primitive::Array(values.iter().map(|&v| Primitive::Integer(v)).collect())
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+    fn widget(file: &mut File<Vec<u8>>, entries: Vec<(&str, Primitive)>) -> PlainRef {
+        let mut entries = entries;
+        entries.push(("Type", Primitive::name("Annot")));
+        entries.push(("Subtype", Primitive::name("Widget")));
+        run!(file.create(dict(entries))).get_ref().get_inner()
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let helv = run!(file.create(DocumentBuilder::standard_font(StandardFont::Helvetica))).get_ref().get_inner();
+    let empty = run!(file.create(Primitive::Stream(PdfStream { info: Dictionary::new(), data: vec![] }))).get_ref().get_inner();
+
+    let name = widget(&mut file, vec![
+        ("FT", Primitive::name("Tx")), ("T", string("name")), ("Q", Primitive::Integer(1)),
+        ("DA", string("/Helv 0 Tf 0 0 1 rg")), ("Rect", rect([50, 700, 250, 720])),
+    ]);
+    let city = widget(&mut file, vec![("T", string("city")), ("Rect", rect([50, 650, 250, 670]))]);
+    let address = run!(file.create(dict(vec![
+        ("FT", Primitive::name("Tx")), ("T", string("address")), ("Kids", Primitive::Array(vec![Primitive::Reference(city)])),
+    ]))).get_ref().get_inner();
+    let agree = widget(&mut file, vec![("FT", Primitive::name("Btn")), ("T", string("agree")), ("Rect", rect([50, 600, 62, 612]))]);
+    let mut radios = vec![];
+    for state in &["red", "blue"] {
+        let states = dict(vec![(*state, Primitive::Reference(empty)), ("Off", Primitive::Reference(empty))]);
+        radios.push(widget(&mut file, vec![
+            ("Rect", rect([50, 550, 62, 562])), ("AS", Primitive::name("Off")), ("AP", dict(vec![("N", states)])),
+        ]));
+    }
+    let color = run!(file.create(dict(vec![
+        ("FT", Primitive::name("Btn")), ("T", string("color")), ("Ff", Primitive::Integer(1 << 15)),
+        ("Kids", Primitive::Array(radios.iter().map(|&r| Primitive::Reference(r)).collect())),
+    ]))).get_ref().get_inner();
+
+    let fields = [name, address, agree, color].iter().map(|&r| Primitive::Reference(r)).collect();
+    let form = dict(vec![
+        ("Fields", Primitive::Array(fields)),
+        ("DA", string("/Helv 10 Tf 0 g")),
+        ("DR", dict(vec![("Font", dict(vec![("Helv", Primitive::Reference(helv))]))])),
+    ]);
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("AcroForm", form);
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    run!(file.set_field("name", FieldValue::Text("Jane Doe".into())));
+    run!(file.set_field("address.city", FieldValue::Text("Berlin".into())));
+    run!(file.set_field("agree", FieldValue::Check(true)));
+    run!(file.set_field("color", FieldValue::Radio("blue".into())));
+    assert!(file.set_field("color", FieldValue::Radio("green".into())).is_err());
+    assert!(file.set_field("agree", FieldValue::Text("yes".into())).is_err());
+    assert!(file.set_field("address", FieldValue::Text("Berlin".into())).is_err());
+    assert!(file.set_field("missing", FieldValue::Check(true)).is_err());
+
+    let out = std::env::temp_dir().join("pdf-rs-fill-form.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let get = |r: PlainRef| run!(run!(copy.resolve(r)).into_dictionary(&copy));
+    let appearance = |widget: &Dictionary| {
+        let ap = run!(widget["AP"].clone().into_dictionary(&copy));
+        let stream = run!(Stream::<Dictionary>::from_primitive(ap["N"].clone(), &copy));
+        String::from_utf8_lossy(run!(stream.data())).into_owned()
+    };
+    let name = get(name);
+    assert_eq!(run!(name["V"].as_string().unwrap().as_str()), "Jane Doe");
+    let content = appearance(&name);
+    assert!(content.contains("/Tx BMC"));
+    assert!(content.contains("0 0 1 rg"));
+    assert!(content.contains("(Jane Doe) Tj"));
+
+    let city = get(city);
+    assert_eq!(run!(city["V"].as_string().unwrap().as_str()), "Berlin");
+    assert!(appearance(&city).contains("/Helv 10 Tf"));
+    assert!(get(address).get("V").is_none());
+
+    let agree = get(agree);
+    assert_eq!(run!(agree["V"].as_name()), "Yes");
+    assert_eq!(run!(agree["AS"].as_name()), "Yes");
+    assert!(run!(agree["AP"].clone().into_dictionary(&copy)).get("N").is_some());
+
+    assert_eq!(run!(get(color)["V"].as_name()), "blue");
+    assert_eq!(run!(get(radios[0])["AS"].as_name()), "Off");
+    assert_eq!(run!(get(radios[1])["AS"].as_name()), "blue");
+
+    // the field tree, with inherited types and values
+    let fields = run!(copy.form_fields());
+    let names: Vec<_> = fields.iter().flat_map(|f| f.descendants()).map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["name", "address", "address.city", "agree", "color"]);
+    assert_eq!(fields[0].field_type, Some(FieldType::Text));
+    assert_eq!(fields[0].value_text().as_deref(), Some("Jane Doe"));
+    assert_eq!(fields[0].widgets, [fields[0].r]);
+    let city = &fields[1].kids[0];
+    assert_eq!(city.field_type, Some(FieldType::Text));
+    assert_eq!(city.value_text().as_deref(), Some("Berlin"));
+    assert!(city.is_terminal() && !fields[1].is_terminal());
+    assert_eq!(fields[2].field_type, Some(FieldType::Button));
+    assert_eq!(fields[2].value_text().as_deref(), Some("Yes"));
+    assert_eq!(fields[3].value_text().as_deref(), Some("blue"));
+    assert_eq!(fields[3].widgets, radios);
+    assert!(!fields[3].is_read_only());
+}
+
+#[test]
+fn typed_annotations() {
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    let file = run!(File::open(file_path!("xelatex-drawboard.pdf")));
+    let annots: Vec<Annot> = file.pages().flat_map(|page| run!(run!(page).annots(&file))).collect();
+    assert_eq!(annots.len(), 2);
+    for annot in &annots {
+        assert!(annot.flags().contains(AnnotFlags::PRINT));
+        assert_eq!(run!(annot.dict().author.as_ref().unwrap().as_str()), "Erlend Langseth");
+    }
+    match annots[0] {
+        Annot::Underline(ref dict, ref markup) => {
+            assert_eq!(markup.quad_points.len(), 8);
+            assert_eq!(dict.rect.left, 133.768);
+            assert!(dict.other().get("CreationDate").is_some());
+        }
+        ref a => panic!("expected an underline, found {:?}", a)
+    }
+    assert!(matches!(annots[1], Annot::Highlight(..)));
+
+    // annotations are written back with the entries of their subtype
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    let rect = Rect { left: 10., bottom: 10., right: 100., top: 30. };
+    let mut action = Dictionary::new();
+    action.insert("S", Primitive::name("URI"));
+    action.insert("URI", Primitive::String(PdfString::new(b"https://example.com".to_vec())));
+    let mut link = LinkAnnot::default();
+    link.action = Some(action);
+    let link = Annot::Link(AnnotDict::new("Link", rect), link);
+    let mut note = AnnotDict::new("Text", rect);
+    note.contents = Some(PdfString::new(b"a note".to_vec()));
+    let mut text = TextAnnot::default();
+    text.open = true;
+    let note = Annot::Text(note, text);
+
+    let page = run!(file.get_page(0));
+    let mut page_copy = (*page).clone();
+    page_copy.annotations = Some(vec![
+        Primitive::Reference(run!(file.create(link)).get_ref().get_inner()),
+        Primitive::Reference(run!(file.create(note)).get_ref().get_inner()),
+    ]);
+    run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(page_copy)));
+    let copy = run!(File::from_data(run!(file.save_to_bytes(&Default::default()))));
+    let annots = run!(run!(copy.get_page(0)).annots(&copy));
+    match (&annots[0], &annots[1]) {
+        (Annot::Link(_, link), Annot::Text(dict, note)) => {
+            assert_eq!(link.uri().unwrap().as_bytes(), b"https://example.com");
+            assert_eq!(dict.contents.as_ref().unwrap().as_bytes(), b"a note");
+            assert!(note.open);
+        }
+        a => panic!("expected a link and a note, found {:?}", a)
+    }
+}
+
+#[test]
+fn sign_document() {
+    use pdf::sign::SignatureOptions;
+
+    let original = std::fs::read(file_path!("libreoffice.pdf")).unwrap();
+    let mut file = run!(File::from_data(original.clone()));
+    let mut options = SignatureOptions::new("Signature1");
+    options.reason = Some("Approved".into());
+    options.contents_size = 64;
+
+    let mut covered = vec![];
+    let mut digest = [0; 32];
+    let data = run!(file.sign(&options, |signed| {
+        covered = signed.to_vec();
+        digest = signed.sha256();
+        Ok(b"not really a signature".to_vec())
+    }));
+    assert!(data.starts_with(&original));
+
+    let copy = run!(File::from_data(data.clone()));
+    assert_eq!(copy.num_pages(), 3);
+    let page = run!(copy.get_page(0));
+    let annots = page.annotations.as_ref().unwrap();
+    let field = run!(annots.last().unwrap().clone().into_dictionary(&copy));
+    assert_eq!(run!(field["FT"].as_name()), "Sig");
+    let sig = run!(field["V"].clone().into_dictionary(&copy));
+    assert_eq!(run!(sig["Reason"].as_string().unwrap().as_str()), "Approved");
+
+    let range: Vec<usize> = run!(sig["ByteRange"].clone().into_array(&copy)).iter().map(|p| p.as_integer().unwrap() as usize).collect();
+    assert_eq!(range[0], 0);
+    assert_eq!(range[2] + range[3], data.len());
+    assert_eq!(&data[range[1] .. range[1] + 1], b"<");
+    assert_eq!(&data[range[2] - 1 .. range[2]], b">");
+    let mut expected = data[.. range[1]].to_vec();
+    expected.extend_from_slice(&data[range[2] ..]);
+    assert_eq!(covered, expected);
+
+    let contents = sig["Contents"].as_string().unwrap().as_bytes();
+    assert_eq!(contents.len(), 64);
+    assert!(contents.starts_with(b"not really a signature"));
+
+    let catalog = run!(run!(copy.resolve(copy.trailer.root.get_ref().get_inner())).into_dictionary(&copy));
+    let form = run!(catalog["AcroForm"].clone().into_dictionary(&copy));
+    assert_eq!(form["SigFlags"].as_integer().unwrap(), 3);
+
+    // signatures that don't fit are rejected
+    let mut file = run!(File::from_data(original));
+    assert!(file.sign(&options, |_| Ok(vec![0; 65])).is_err());
+}
+
+#[test]
+fn drop_unreachable_objects() {
+    use pdf::primitive::{PdfString, Primitive};
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    let orphan = run!(file.create(Primitive::String(PdfString::new(b"orphaned object".to_vec())))).get_ref().get_inner();
+    let removed = run!(file.get_page(1)).get_ref().get_inner();
+    run!(file.remove_page(1));
+
+    let out = std::env::temp_dir().join("pdf-rs-drop-unreachable.pdf");
+    run!(file.save_to(&out));
+    let data = std::fs::read(&out).unwrap();
+    let _ = std::fs::remove_file(&out);
+    assert!(!String::from_utf8_lossy(&data).contains("orphaned object"));
+
+    let copy = run!(File::from_data(data));
+    assert_eq!(copy.num_pages(), 2);
+    assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1\x0cContents of page 3");
+    for r in [orphan, removed] {
+        assert!(!matches!(copy.resolve(r), Ok(Primitive::String(_)) | Ok(Primitive::Dictionary(_))));
+    }
+}
+
+#[test]
+fn optimize() {
+    use pdf::file::SaveOptions;
+    use pdf::primitive::{Dictionary, Primitive};
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    let other = run!(File::open(file_path!("libreoffice.pdf")));
+    // copies of the fonts and streams of the document
+    run!(file.import_pages(&other, ..));
+    let page = run!(file.get_page(0)).get_ref().get_inner();
+    let mut dict = run!(run!(file.resolve(page)).into_dictionary(&file));
+    dict.insert("PieceInfo", Primitive::Dictionary(Dictionary::new()));
+    run!(file.update(page, Primitive::Dictionary(dict)));
+
+    let plain = std::env::temp_dir().join("pdf-rs-optimize-plain.pdf");
+    let optimized = std::env::temp_dir().join("pdf-rs-optimize.pdf");
+    run!(file.save_with(&plain, &SaveOptions { object_streams: true, ..SaveOptions::default() }));
+    run!(file.save_with(&optimized, &SaveOptions::optimize()));
+    let plain_len = std::fs::metadata(&plain).unwrap().len();
+    let data = std::fs::read(&optimized).unwrap();
+    let _ = std::fs::remove_file(&plain);
+    let _ = std::fs::remove_file(&optimized);
+    assert!((data.len() as u64) < plain_len);
+
+    let copy = run!(File::from_data(data));
+    assert_eq!(copy.num_pages(), 6);
+    let text = "Contents of page 1\x0cContents of page 2\x0cContents of page 3";
+    assert_eq!(run!(pdf::text::extract(&copy, ..)), format!("{}\x0c{}", text, text));
+    let first = run!(copy.get_page(0));
+    let fourth = run!(copy.get_page(3));
+    let fonts = |page: &Page| run!(page.resources()).fonts.values().map(|r| r.get_inner()).collect::<Vec<_>>();
+    assert_eq!(fonts(&first), fonts(&fourth));
+    let dict = run!(run!(copy.resolve(first.get_ref().get_inner())).into_dictionary(&copy));
+    assert!(dict.get("PieceInfo").is_none());
+}
+
+#[test]
+fn copy_untouched_objects() {
+    let original = std::fs::read(file_path!("libreoffice.pdf")).unwrap();
+    let mut file = run!(File::from_data(original.clone()));
+    let page = run!(file.get_page(0));
+    let mut rotated = (*page).clone();
+    rotated.rotate = Some(90);
+    run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(rotated)));
+
+    let out = std::env::temp_dir().join("pdf-rs-copy-untouched.pdf");
+    run!(file.save_to(&out));
+    let data = std::fs::read(&out).unwrap();
+    let _ = std::fs::remove_file(&out);
+
+    // the font dictionary as it is written in the original, not as it would be serialized
+    let font = b"<</Type/Font/Subtype/TrueType/BaseFont/BAAAAA+OpenSans\n";
+    let contains = |data: &[u8], part: &[u8]| data.windows(part.len()).any(|w| w == part);
+    assert!(contains(&original, font));
+    assert!(contains(&data, font));
+    assert!(contains(&data, b"/Rotate 90"));
+
+    let copy = run!(File::from_data(data));
+    assert_eq!(copy.num_pages(), 3);
+    assert_eq!(run!(copy.get_page(0)).rotate, Some(90));
+    assert_eq!(run!(pdf::text::extract(&copy, ..)), "Contents of page 1\x0cContents of page 2\x0cContents of page 3");
+}
+
+#[test]
+fn document_builder() {
+    use pdf::build::{ContentBuilder, DocumentBuilder, PageBuilder};
+    use pdf::standard_fonts::StandardFont;
+
+    let mut doc = DocumentBuilder::new();
+    let font = run!(doc.add_font(DocumentBuilder::standard_font(StandardFont::Helvetica)));
+    for text in &["first page", "second page"] {
+        let content = ContentBuilder::new()
+            .font(font.as_str(), 12.)
+            .text_position(72., 720.)
+            .show(text)
+            .build();
+        doc.add_page(PageBuilder::from_content(content));
+    }
+    doc.info("Title", "Grüße");
+    let data = run!(doc.to_bytes());
+
+    let file = run!(File::from_data(data));
+    assert_eq!(file.num_pages(), 2);
+    assert_eq!(run!(pdf::text::extract(&file, ..)), "first page\x0csecond page");
+    let title = file.trailer.info_dict.as_ref().unwrap().get("Title").unwrap();
+    assert_eq!(title.as_string().unwrap().as_str().unwrap(), "Grüße");
+}
+
+#[test]
+fn add_page() {
+    use pdf::build::{ContentBuilder, PageBuilder};
+
+    let mut file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    let mut resources = Resources::default();
+    resources.fonts = run!(run!(file.get_page(0)).resources()).fonts.clone();
+    let font = resources.fonts.keys().next().unwrap().clone();
+
+    let mut page = PageBuilder::from_content(ContentBuilder::new()
+        .font(font, 12.)
+        .text_position(72., 720.)
+        .show("page 4")
+        .build());
+    page.size(300., 400.);
+    page.rotate(90);
+    page.resources(resources);
+    run!(file.add_page(page));
+    assert_eq!(file.num_pages(), 4);
+
+    let out = std::env::temp_dir().join("pdf-rs-add-page.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::<Vec<u8>>::open(&out));
+    let _ = std::fs::remove_file(&out);
+    assert_eq!(copy.num_pages(), 4);
+    let page = run!(copy.get_page(3));
+    assert_eq!(page.rotate, Some(90));
+    assert_eq!(page.media_box.map(|r| (r.right, r.top)), Some((300., 400.)));
+    assert_eq!(page.parent.count, 4);
+    assert_eq!(run!(pdf::text::extract(&copy, 3 ..= 3)), "page 4");
+}
+
+#[test]
+fn create_and_update() {
+    use pdf::primitive::{Dictionary, Primitive};
+
+    let mut file = run!(File::<Vec<u8>>::open(file_path!("libreoffice.pdf")));
+    assert!(!file.is_dirty());
+
+    let mut dict = Dictionary::new();
+    dict.insert("Answer", Primitive::Integer(42));
+    let obj = run!(file.create(dict));
+    assert!(file.is_dirty());
+    let read = run!(file.get(obj.get_ref()));
+    assert_eq!(read.get("Answer").unwrap().as_integer().unwrap(), 42);
+
+    let page = run!(file.get_page(0));
+    let mut rotated = (*page).clone();
+    rotated.rotate = Some(180);
+    let updated = run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(rotated)));
+    assert_eq!(updated.get_ref().get_inner(), page.get_ref().get_inner());
+    assert_eq!(run!(file.get_page(0)).rotate, Some(180));
+
+    let out = std::env::temp_dir().join("pdf-rs-create-update.pdf");
+    run!(file.save_to(&out));
+    let _ = std::fs::remove_file(&out);
+    assert!(!file.is_dirty());
+}
+
+#[test]
+fn stream_filters() {
+    use pdf::enc::StreamFilter;
+    use pdf::primitive::Primitive;
+
+    let data = b"0 0 m 100 100 l S\n".repeat(20);
+    let stream = run!(Stream::new((), data.clone())
+        .with_filter(StreamFilter::FlateDecode(Default::default()))
+        .and_then(|s| s.with_filter(StreamFilter::ASCII85Decode)));
+    let written = match run!(stream.to_primitive(&mut NoUpdate)) {
+        Primitive::Stream(s) => s,
+        p => panic!("{:?}", p)
+    };
+    assert_eq!(format!("{}", written.info["Filter"]), "[/ASCII85Decode, /FlateDecode]");
+    assert!(written.info.get("DecodeParms").is_none());
+
+    let read = run!(Stream::<()>::from_stream(written, &NoResolve));
+    assert_eq!(run!(read.data()), &data[..]);
+
+    // these can be decoded (or passed on), but not encoded
+    for kind in ["DCTDecode", "JPXDecode", "CCITTFaxDecode", "Crypt"] {
+        let filter = run!(StreamFilter::from_kind_and_params(kind, Default::default(), &NoResolve));
+        assert!(Stream::new((), data.clone()).with_filter(filter).is_err(), "{}", kind);
+    }
+    assert!(StreamFilter::from_kind_and_params("JBIG2Decode", Default::default(), &NoResolve).is_err());
+}
+
+#[test]
+fn outline_tree() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::outline;
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let page = run!(file.get_page(1)).get_ref().get_inner();
+
+    let mut refs = vec![];
+    for _ in 0 .. 4 {
+        refs.push(run!(file.create(Primitive::Null)).get_ref().get_inner());
+    }
+    let (root, intro, details, end) = (refs[0], refs[1], refs[2], refs[3]);
+    let items = vec![
+        (root, dict(vec![
+            ("Type", Primitive::name("Outlines")),
+            ("First", Primitive::Reference(intro)), ("Last", Primitive::Reference(end)),
+            ("Count", Primitive::Integer(3)),
+        ])),
+        (intro, dict(vec![
+            ("Title", string("Intro")), ("Parent", Primitive::Reference(root)),
+            ("Next", Primitive::Reference(end)), ("Dest", string("intro")),
+            ("First", Primitive::Reference(details)), ("Last", Primitive::Reference(details)),
+            ("Count", Primitive::Integer(1)), ("F", Primitive::Integer(2)),
+            ("C", Primitive::Array(vec![Primitive::Integer(1), Primitive::Integer(0), Primitive::Integer(0)])),
+        ])),
+        (details, dict(vec![
+            ("Title", string("Details")), ("Parent", Primitive::Reference(intro)),
+            ("Dest", Primitive::Array(vec![Primitive::Reference(page), Primitive::name("Fit")])),
+        ])),
+        // the last item links back to the first one
+        (end, dict(vec![
+            ("Title", string("End")), ("Parent", Primitive::Reference(root)),
+            ("Prev", Primitive::Reference(intro)), ("Next", Primitive::Reference(intro)),
+            ("Count", Primitive::Integer(-2)),
+            ("A", dict(vec![("S", Primitive::name("URI")), ("URI", string("https://example.com"))])),
+        ])),
+    ];
+    for (r, item) in items {
+        run!(file.update(r, item));
+    }
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Outlines", Primitive::Reference(root));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-outline-tree.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let tree = run!(copy.outline());
+    assert_eq!(tree.len(), 2);
+    let (intro, end) = (&tree[0], &tree[1]);
+    assert_eq!(intro.title, "Intro");
+    assert!(intro.is_open() && intro.is_bold() && !intro.is_italic());
+    assert_eq!(intro.color, Some(vec![1., 0., 0.]));
+    assert!(matches!(intro.dest, Some(MaybeNamedDest::Named(ref name)) if name.as_bytes() == b"intro"));
+    match intro.kids[0].dest {
+        Some(MaybeNamedDest::Direct(ref dest)) => {
+            assert_eq!(dest.page.get_inner(), copy.get_page(1).unwrap().get_ref().get_inner());
+            assert!(matches!(dest.view, DestView::Fit));
+        }
+        ref dest => panic!("unexpected destination {:?}", dest)
+    }
+    assert!(!end.is_open());
+    assert_eq!(end.action.as_ref().unwrap()["S"].as_name().unwrap(), "URI");
+
+    let order: Vec<_> = outline::iter(&tree).map(|(depth, item)| (depth, item.title.as_str())).collect();
+    assert_eq!(order, [(0, "Intro"), (1, "Details"), (0, "End")]);
+}
+
+#[test]
+fn named_destinations() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+    let fit = |page: PlainRef| Primitive::Array(vec![Primitive::Reference(page), Primitive::name("Fit")]);
+
+    let mut doc = DocumentBuilder::new();
+    for _ in 0 .. 3 {
+        doc.add_page(PageBuilder::new());
+    }
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let pages: Vec<_> = (0 .. 3).map(|n| run!(file.get_page(n)).get_ref().get_inner()).collect();
+
+    let mut leaves = vec![];
+    for &(min, max, page) in &[("a", "b", 0), ("m", "n", 1)] {
+        leaves.push(Primitive::Reference(run!(file.create(dict(vec![
+            ("Limits", Primitive::Array(vec![string(min), string(max)])),
+            ("Names", Primitive::Array(vec![
+                string(min), fit(pages[page]),
+                string(max), dict(vec![("D", fit(pages[page]))]),
+            ])),
+        ]))).get_ref().get_inner()));
+    }
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Names", dict(vec![("Dests", dict(vec![("Kids", Primitive::Array(leaves))]))]));
+    catalog_dict.insert("Dests", dict(vec![("old", fit(pages[2]))]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-named-destinations.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+    let pages: Vec<_> = (0 .. 3).map(|n| run!(copy.get_page(n)).get_ref().get_inner()).collect();
+
+    let page_of = |name: &str| run!(copy.named_dest(name.as_bytes())).map(|dest| dest.page.get_inner());
+    assert_eq!(page_of("a"), Some(pages[0]));
+    assert_eq!(page_of("n"), Some(pages[1]));
+    assert_eq!(page_of("old"), Some(pages[2]));
+    assert_eq!(page_of("c"), None);
+
+    let named = MaybeNamedDest::Named(PdfString::new(b"m".to_vec()));
+    assert_eq!(run!(copy.resolve_dest(&named)).unwrap().page.get_inner(), pages[1]);
+
+    let mut link = LinkAnnot::default();
+    let mut action = Dictionary::new();
+    action.insert("S", Primitive::name("GoTo"));
+    action.insert("D", string("b"));
+    link.action = Some(action);
+    let dest = run!(link.destination(&copy)).unwrap();
+    assert!(matches!(dest, MaybeNamedDest::Named(ref name) if name.as_bytes() == b"b"));
+    assert_eq!(run!(copy.resolve_dest(&dest)).unwrap().page.get_inner(), pages[0]);
+}
+
+#[test]
+fn page_labels() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+
+    let mut doc = DocumentBuilder::new();
+    for _ in 0 .. 8 {
+        doc.add_page(PageBuilder::new());
+    }
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    assert_eq!(run!(file.page_label(0)), None);
+
+    let roman = run!(file.create(dict(vec![
+        ("Limits", Primitive::Array(vec![Primitive::Integer(0), Primitive::Integer(0)])),
+        ("Nums", Primitive::Array(vec![Primitive::Integer(0), dict(vec![("S", Primitive::name("r"))])])),
+    ]))).get_ref().get_inner();
+    let rest = run!(file.create(dict(vec![
+        ("Limits", Primitive::Array(vec![Primitive::Integer(4), Primitive::Integer(6)])),
+        ("Nums", Primitive::Array(vec![
+            Primitive::Integer(4), dict(vec![("S", Primitive::name("D")), ("St", Primitive::Integer(215))]),
+            Primitive::Integer(6), dict(vec![
+                ("S", Primitive::name("D")), ("St", Primitive::Integer(3)),
+                ("P", Primitive::String(PdfString::new(b"A-".to_vec()))),
+            ]),
+        ])),
+    ]))).get_ref().get_inner();
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("PageLabels", dict(vec![
+        ("Kids", Primitive::Array(vec![Primitive::Reference(roman), Primitive::Reference(rest)])),
+    ]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-page-labels.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let labels: Vec<_> = (0 .. 8).map(|n| run!(copy.page_label(n)).unwrap()).collect();
+    assert_eq!(labels, ["i", "ii", "iii", "iv", "215", "216", "A-3", "A-4"]);
+}
+
+#[test]
+fn extract_attachments() {
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    run!(file.attach("a.csv", b"1,2,3", "text/csv", "Some numbers"));
+
+    // a file attached to the second page, with an uncompressed stream
+    let params = dict(vec![("CreationDate", string("D:20200102030405Z"))]);
+    let stream = run!(file.create(Primitive::Stream(PdfStream {
+        info: match dict(vec![("Type", Primitive::name("EmbeddedFile")), ("Params", params)]) {
+            Primitive::Dictionary(info) => info,
+            _ => unreachable!()
+        },
+        data: b"note".to_vec(),
+    }))).get_ref().get_inner();
+    let spec = dict(vec![
+        ("Type", Primitive::name("Filespec")), ("F", string("note.txt")),
+        ("EF", dict(vec![("F", Primitive::Reference(stream))])),
+    ]);
+    let rect = Primitive::Array(vec![Primitive::Integer(0), Primitive::Integer(0), Primitive::Integer(10), Primitive::Integer(10)]);
+    let annot = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("FileAttachment")),
+        ("Rect", rect.clone()), ("FS", spec.clone()),
+    ]))).get_ref().get_inner();
+    // the same file again, and one that is only referenced by name
+    let again = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("FileAttachment")),
+        ("Rect", rect.clone()), ("FS", spec),
+    ]))).get_ref().get_inner();
+    let external = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("FileAttachment")),
+        ("Rect", rect), ("FS", string("elsewhere.txt")),
+    ]))).get_ref().get_inner();
+    let page = run!(file.get_page(1));
+    let mut copy = (*page).clone();
+    copy.annotations = Some(vec![annot, again, external].into_iter().map(Primitive::Reference).collect());
+    run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(copy)));
+
+    let out = std::env::temp_dir().join("pdf-rs-extract-attachments.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let attachments = run!(copy.attachments());
+    assert_eq!(attachments.len(), 2);
+    let csv = &attachments[0];
+    assert_eq!(csv.name, "a.csv");
+    assert_eq!(csv.description.as_deref(), Some("Some numbers"));
+    assert_eq!(csv.mime.as_deref(), Some("text/csv"));
+    assert_eq!(csv.data, b"1,2,3");
+    assert_eq!(csv.checksum.as_deref(), Some(&md5::compute(b"1,2,3").0[..]));
+    assert_eq!(csv.page, None);
+
+    let note = &attachments[1];
+    assert_eq!(note.name, "note.txt");
+    assert_eq!(note.data, b"note");
+    assert_eq!(note.page, Some(1));
+    assert_eq!(note.creation_date.unwrap().to_rfc3339(), "2020-01-02T03:04:05+00:00");
+    assert!(note.mime.is_none() && note.description.is_none());
+}
+
+#[test]
+fn structure_tree() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+    use pdf::structure::StructKid;
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let page = run!(file.get_page(0));
+    let mut page_copy = (*page).clone();
+    page_copy.struct_parents = Some(0);
+    let page_ref = page.get_ref().get_inner();
+    run!(file.update(page_ref, PagesNode::Leaf(page_copy)));
+
+    let mut refs = vec![];
+    for _ in 0 .. 5 {
+        refs.push(run!(file.create(Primitive::Null)).get_ref().get_inner());
+    }
+    let (root, document, heading, para, figure) = (refs[0], refs[1], refs[2], refs[3], refs[4]);
+    let annot = run!(file.create(dict(vec![("Subtype", Primitive::name("Link"))]))).get_ref().get_inner();
+    let elements = vec![
+        (root, dict(vec![
+            ("Type", Primitive::name("StructTreeRoot")),
+            ("K", Primitive::Reference(document)),
+            ("RoleMap", dict(vec![("Heading", Primitive::name("H1")), ("Chapter", Primitive::name("Heading"))])),
+            ("ParentTree", dict(vec![("Nums", Primitive::Array(vec![
+                Primitive::Integer(0), Primitive::Array(vec![Primitive::Reference(para), Primitive::Reference(heading)]),
+            ]))])),
+        ])),
+        (document, dict(vec![
+            ("S", Primitive::name("Document")), ("P", Primitive::Reference(root)), ("Lang", string("en")),
+            ("Pg", Primitive::Reference(page_ref)),
+            ("K", Primitive::Array(vec![Primitive::Reference(heading), Primitive::Reference(para), Primitive::Reference(figure), Primitive::Reference(heading)])),
+        ])),
+        (heading, dict(vec![
+            ("S", Primitive::name("Chapter")), ("P", Primitive::Reference(document)), ("T", string("Intro")),
+            ("K", Primitive::Integer(1)),
+        ])),
+        (para, dict(vec![
+            ("S", Primitive::name("P")), ("P", Primitive::Reference(document)),
+            ("K", dict(vec![("Type", Primitive::name("MCR")), ("MCID", Primitive::Integer(0))])),
+        ])),
+        (figure, dict(vec![
+            ("S", Primitive::name("Figure")), ("P", Primitive::Reference(document)), ("Alt", string("A chart")),
+            ("K", dict(vec![("Type", Primitive::name("OBJR")), ("Obj", Primitive::Reference(annot))])),
+        ])),
+    ];
+    for (r, element) in elements {
+        run!(file.update(r, element));
+    }
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("StructTreeRoot", Primitive::Reference(root));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-structure-tree.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+    let page = run!(copy.get_page(0));
+    assert_eq!(page.struct_parents, Some(0));
+
+    let tree = run!(copy.struct_tree()).unwrap();
+    assert_eq!(tree.role_map["Heading"], "H1");
+    let elements: Vec<_> = tree.elements().into_iter().map(|(depth, e)| (depth, e.struct_type.clone())).collect();
+    // the heading is linked twice, but read once
+    assert_eq!(elements, [(0, StructType::Document), (1, StructType::H1), (1, StructType::P), (1, StructType::Figure)]);
+
+    let document = &tree.children[0];
+    assert_eq!(document.lang.as_deref(), Some("en"));
+    let heading = document.elements().next().unwrap();
+    assert!(heading.is_heading());
+    assert_eq!(heading.role, "Chapter");
+    assert_eq!(heading.title.as_deref(), Some("Intro"));
+    assert_eq!(heading.marked_content(), [(Some(page.get_ref().get_inner()), 1)]);
+    let figure = document.elements().nth(2).unwrap();
+    assert_eq!(figure.alt.as_deref(), Some("A chart"));
+    assert!(matches!(figure.kids[0], StructKid::Object { .. }));
+
+    let para = run!(tree.element_of(&copy, &page, 0)).unwrap();
+    assert_eq!(para.struct_type, StructType::P);
+    assert_eq!(run!(tree.element_of(&copy, &page, 1)).unwrap().title.as_deref(), Some("Intro"));
+    assert!(run!(tree.element_of(&copy, &page, 2)).is_none());
+}
+
+#[test]
+fn action_chains() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let page = run!(file.get_page(0));
+    let page_ref = page.get_ref().get_inner();
+
+    let script = run!(file.create(Primitive::Stream(PdfStream { info: Dictionary::new(), data: b"app.alert('hi');".to_vec() }))).get_ref().get_inner();
+    let js = run!(file.create(Primitive::Null)).get_ref().get_inner();
+    let named = run!(file.create(dict(vec![
+        ("S", Primitive::name("Named")), ("N", Primitive::name("NextPage")),
+        // back to the first action of the chain
+        ("Next", Primitive::Reference(js)),
+    ]))).get_ref().get_inner();
+    run!(file.update(js, dict(vec![
+        ("S", Primitive::name("JavaScript")), ("JS", Primitive::Reference(script)),
+        ("Next", Primitive::Array(vec![
+            Primitive::Reference(named),
+            dict(vec![("S", Primitive::name("GoTo")), ("D", Primitive::Array(vec![Primitive::Reference(page_ref), Primitive::name("Fit")]))]),
+        ])),
+    ])));
+    let widget = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Widget")),
+        ("Rect", Primitive::Array(vec![Primitive::Integer(0), Primitive::Integer(0), Primitive::Integer(10), Primitive::Integer(10)])),
+        ("A", dict(vec![
+            ("S", Primitive::name("SubmitForm")), ("F", string("https://example.com/submit")),
+            ("Fields", Primitive::Array(vec![string("name")])), ("Flags", Primitive::Integer(4)),
+            ("Next", Primitive::Reference(js)),
+        ])),
+        ("AA", dict(vec![
+            ("E", dict(vec![("S", Primitive::name("Hide")), ("T", string("help")), ("H", Primitive::Boolean(false))])),
+            ("X", dict(vec![("S", Primitive::name("Thread")), ("D", Primitive::Integer(0))])),
+        ])),
+    ]))).get_ref().get_inner();
+    let mut page_copy = (*page).clone();
+    page_copy.annotations = Some(vec![Primitive::Reference(widget)]);
+    run!(file.update(page_ref, PagesNode::Leaf(page_copy)));
+
+    let out = std::env::temp_dir().join("pdf-rs-action-chains.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let annots = run!(run!(copy.get_page(0)).annots(&copy));
+    let (dict, widget) = match annots[0] {
+        Annot::Widget(ref dict, ref widget) => (dict, widget),
+        ref a => panic!("expected a widget, found {:?}", a)
+    };
+    let actions = run!(widget.actions(&copy));
+    let subtypes: Vec<_> = actions.iter().map(|a| a.subtype()).collect();
+    assert_eq!(subtypes, ["SubmitForm", "JavaScript", "Named", "GoTo"]);
+    match actions[0] {
+        Action::SubmitForm { ref url, ref fields, flags } => {
+            assert_eq!(url.as_ref().unwrap().as_string().unwrap().as_bytes(), b"https://example.com/submit");
+            assert_eq!(fields.len(), 1);
+            assert_eq!(flags, 4);
+        }
+        ref a => panic!("expected SubmitForm, found {:?}", a)
+    }
+    assert!(matches!(actions[1], Action::JavaScript(ref js) if js == "app.alert('hi');"));
+    assert!(matches!(actions[2], Action::Named(ref name) if name == "NextPage"));
+    assert!(matches!(actions[3], Action::GoTo { dest: MaybeNamedDest::Direct(_) }));
+
+    let aa = run!(dict.additional_actions(&copy)).unwrap();
+    assert!(matches!(aa.get("E"), [Action::Hide { hide: false, ref targets }] if targets.len() == 1));
+    assert!(matches!(aa.get("X"), [Action::Other { ref subtype, .. }] if subtype == "Thread"));
+    assert!(aa.get("D").is_empty());
+
+    // written back as it was read
+    let uri = Action::Uri { uri: PdfString::new(b"https://example.com".to_vec()), is_map: false };
+    let written = run!(uri.to_primitive(&mut NoUpdate));
+    assert!(matches!(run!(Action::from_primitive(written, &NoResolve)), Action::Uri { ref uri, is_map: false } if uri.as_bytes() == b"https://example.com"));
+}
+
+#[test]
+fn typed_destinations() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, Primitive};
+
+    let mut doc = DocumentBuilder::new();
+    for _ in 0 .. 3 {
+        doc.add_page(PageBuilder::new());
+    }
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let pages: Vec<_> = (0 .. 3).map(|n| run!(file.get_page(n)).get_ref().get_inner()).collect();
+
+    let dest = |page: usize, args: Vec<Primitive>| {
+        let mut arr = vec![Primitive::Reference(pages[page])];
+        arr.extend(args);
+        Primitive::Array(arr)
+    };
+    let xyz = dest(1, vec![Primitive::name("XYZ"), Primitive::Null, Primitive::Integer(700)]);
+    let fit_bv = dest(2, vec![Primitive::name("FitBV"), Primitive::Number(36.)]);
+    let fit_h = dest(0, vec![Primitive::name("FitH"), Primitive::Null]);
+
+    let parse = |p: Primitive| run!(Dest::from_primitive(p, &file));
+    match parse(xyz.clone()).view {
+        DestView::XYZ { left: None, top: Some(top), zoom } => {
+            assert_eq!(top, 700.);
+            assert_eq!(zoom, 0.);
+        }
+        view => panic!("unexpected view {:?}", view)
+    }
+    assert!(matches!(parse(fit_bv.clone()).view, DestView::FitBV { left: Some(left) } if left == 36.));
+    assert!(matches!(parse(fit_h).view, DestView::FitH { top: None }));
+    assert!(Dest::from_primitive(dest(0, vec![Primitive::name("FitQ")]), &file).is_err());
+
+    let written = run!(parse(fit_bv).to_primitive(&mut NoUpdate));
+    assert!(matches!(parse(written).view, DestView::FitBV { left: Some(left) } if left == 36.));
+
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("OpenAction", xyz);
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-typed-destinations.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let actions = run!(copy.open_action()).unwrap();
+    assert_eq!(actions.len(), 1);
+    match actions[0] {
+        Action::GoTo { dest: MaybeNamedDest::Direct(ref dest) } => {
+            assert_eq!(run!(copy.page_nr(dest.page.get_inner())), Some(1));
+            assert!(matches!(dest.view, DestView::XYZ { top: Some(_), .. }));
+        }
+        ref action => panic!("unexpected action {:?}", action)
+    }
+    for n in 0 .. 3 {
+        let page = run!(copy.get_page(n)).get_ref().get_inner();
+        assert_eq!(run!(copy.page_nr(page)), Some(n));
+    }
+    let mut action = Dictionary::new();
+    action.insert("S", Primitive::name("Named"));
+    action.insert("N", Primitive::name("LastPage"));
+    let mut copy = copy;
+    let catalog = copy.trailer.root.get_ref().get_inner();
+    let mut root = run!(run!(copy.resolve(catalog)).into_dictionary(&copy));
+    root.insert("OpenAction", Primitive::Dictionary(action));
+    run!(copy.update(catalog, Primitive::Dictionary(root)));
+    let out = std::env::temp_dir().join("pdf-rs-typed-destinations-named.pdf");
+    run!(copy.save_to(&out));
+    let named = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+    assert!(matches!(run!(named.open_action()).unwrap()[..], [Action::Named(ref n)] if n == "LastPage"));
+}
+
+#[test]
+fn page_boxes() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::Primitive;
+
+    let mut doc = DocumentBuilder::new();
+    let mut page = PageBuilder::new();
+    page.size(600., 800.);
+    // corners in the wrong order and partly outside of the media box
+    page.trim_box(Rect { left: 590., bottom: 700., right: 10., top: 900. });
+    doc.add_page(page);
+    let mut second = PageBuilder::new();
+    second.size(600., 800.);
+    second.crop_box(Rect { left: 50., bottom: 50., right: 550., top: 750. });
+    second.art_box(Rect { left: 100., bottom: 100., right: 200., top: 200. });
+    doc.add_page(second);
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+
+    let tree = file.trailer.root.pages.get_ref().get_inner();
+    let mut tree_dict = run!(run!(file.resolve(tree)).into_dictionary(&file));
+    tree_dict.insert("CropBox", Primitive::Array([20, 30, 580, 770].iter().map(|&n| Primitive::Integer(n)).collect()));
+    run!(file.update(tree, Primitive::Dictionary(tree_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-page-boxes.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let corners = |r: Rect| (r.left, r.bottom, r.right, r.top);
+    let first = run!(copy.get_page(0));
+    assert_eq!(corners(run!(first.media_box())), (0., 0., 600., 800.));
+    // inherited from the page tree
+    assert_eq!(corners(run!(first.crop_box())), (20., 30., 580., 770.));
+    assert_eq!(corners(run!(first.bleed_box())), (20., 30., 580., 770.));
+    assert_eq!(corners(run!(first.art_box())), (20., 30., 580., 770.));
+    assert_eq!(corners(run!(first.trim_box())), (10., 700., 590., 800.));
+
+    let second = run!(copy.get_page(1));
+    assert_eq!(corners(run!(second.crop_box())), (50., 50., 550., 750.));
+    assert_eq!(corners(run!(second.trim_box())), (50., 50., 550., 750.));
+    assert_eq!(corners(run!(second.art_box())), (100., 100., 200., 200.));
+}
+
+#[test]
+fn inherited_page_attributes() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::Primitive;
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut own = PageBuilder::new();
+    own.size(100., 100.);
+    own.rotate(180);
+    doc.add_page(own);
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+
+    let tree = file.trailer.root.pages.get_ref().get_inner();
+    let mut tree_dict = run!(run!(file.resolve(tree)).into_dictionary(&file));
+    tree_dict.insert("MediaBox", Primitive::Array([0, 0, 300, 400].iter().map(|&n| Primitive::Integer(n)).collect()));
+    tree_dict.insert("Rotate", Primitive::Integer(90));
+    run!(file.update(tree, Primitive::Dictionary(tree_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-inherited-page-attributes.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    // the resources of the document builder are on the page tree
+    let first = run!(copy.get_page(0));
+    assert!(first.resources.is_some());
+    assert_eq!(first.media_box.map(|b| (b.right, b.top)), Some((300., 400.)));
+    assert_eq!(first.rotate, Some(90));
+
+    let second = run!(copy.get_page(1));
+    assert!(second.resources.is_some());
+    assert_eq!(second.media_box.map(|b| (b.right, b.top)), Some((100., 100.)));
+    assert_eq!(second.rotate, Some(180));
+}
+
+#[test]
+fn viewer_preferences() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, Primitive};
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    assert!(file.get_root().viewer_preferences.is_none());
+
+    let mut prefs = Dictionary::new();
+    prefs.insert("HideToolbar", Primitive::Boolean(true));
+    prefs.insert("FitWindow", Primitive::Boolean(true));
+    prefs.insert("Direction", Primitive::name("R2L"));
+    prefs.insert("PrintScaling", Primitive::name("None"));
+    prefs.insert("Duplex", Primitive::name("DuplexFlipLongEdge"));
+    prefs.insert("PrintPageRange", Primitive::Array([1, 2, 5, 5].iter().map(|&n| Primitive::Integer(n)).collect()));
+    // dropped with a warning
+    prefs.insert("CenterWindow", Primitive::Integer(1));
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("ViewerPreferences", Primitive::Dictionary(prefs));
+    catalog_dict.insert("PageLayout", Primitive::name("TwoPageRight"));
+    catalog_dict.insert("PageMode", Primitive::name("UseSomething"));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-viewer-preferences.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let root = copy.get_root();
+    assert_eq!(root.page_layout, Some(PageLayout::TwoPageRight));
+    assert_eq!(root.page_mode, Some(PageMode::Other("UseSomething".into())));
+    let prefs = root.viewer_preferences.as_ref().unwrap();
+    assert!(prefs.hide_toolbar && prefs.fit_window);
+    assert!(!prefs.hide_menubar && !prefs.center_window);
+    assert_eq!(prefs.direction, Some(Direction::R2L));
+    assert_eq!(prefs.print_scaling, Some(PrintScaling::NoScaling));
+    assert_eq!(prefs.duplex, Some(Duplex::DuplexFlipLongEdge));
+    assert_eq!(prefs.print_ranges(), [(0, 1), (4, 4)]);
+
+    let written = run!(run!(prefs.to_primitive(&mut NoUpdate)).into_dictionary(&copy));
+    assert_eq!(run!(written["PrintScaling"].as_name()), "None");
+    assert!(run!(written["HideToolbar"].as_bool()));
+}
+
+#[test]
+fn name_and_number_trees() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+    fn script(js: &str) -> Primitive {
+        dict(vec![("S", Primitive::name("JavaScript")), ("JS", string(js))])
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+
+    let mut script_leaves = vec![];
+    for &(min, max) in &[("init", "setup"), ("zoom", "zoom")] {
+        let names = if min == max {
+            vec![string(min), script(&format!("{}();", min))]
+        } else {
+            vec![string(min), script(&format!("{}();", min)), string(max), script(&format!("{}();", max))]
+        };
+        script_leaves.push(Primitive::Reference(run!(file.create(dict(vec![
+            ("Limits", Primitive::Array(vec![string(min), string(max)])),
+            ("Names", Primitive::Array(names)),
+        ]))).get_ref().get_inner()));
+    }
+    let mut number_leaves = vec![];
+    for &(min, max) in &[(0, 9), (10, 19)] {
+        number_leaves.push(Primitive::Reference(run!(file.create(dict(vec![
+            ("Limits", Primitive::Array(vec![Primitive::Integer(min), Primitive::Integer(max)])),
+            ("Nums", Primitive::Array(vec![
+                Primitive::Integer(min), string(&format!("{}", min)),
+                Primitive::Integer(max), string(&format!("{}", max)),
+            ])),
+        ]))).get_ref().get_inner()));
+    }
+    let numbers = run!(file.create(dict(vec![("Kids", Primitive::Array(number_leaves))])));
+
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Names", dict(vec![("JavaScript", dict(vec![("Kids", Primitive::Array(script_leaves))]))]));
+    // keeps the number tree in the saved file
+    catalog_dict.insert("Numbers", Primitive::Reference(numbers.get_ref().get_inner()));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-name-and-number-trees.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let scripts: Vec<_> = run!(copy.scripts()).into_iter()
+        .map(|(name, js)| (String::from_utf8(name.as_bytes().to_vec()).unwrap(), js))
+        .collect();
+    assert_eq!(scripts, [
+        ("init".to_string(), "init();".to_string()),
+        ("setup".to_string(), "setup();".to_string()),
+        ("zoom".to_string(), "zoom();".to_string()),
+    ]);
+
+    let tree = run!(copy.get_root().names.as_ref().unwrap().javascript.as_ref().unwrap().get(&copy, b"setup")).unwrap();
+    assert!(matches!(run!(Action::from_primitive(tree, &copy)), Action::JavaScript(ref js) if js == "setup();"));
+
+    let catalog = run!(run!(copy.resolve(copy.trailer.root.get_ref().get_inner())).into_dictionary(&copy));
+    let numbers = run!(NumberTree::<PdfString>::from_primitive(catalog["Numbers"].clone(), &copy));
+    let value = |key| run!(numbers.get(&copy, key)).map(|s| s.as_bytes().to_vec());
+    assert_eq!(value(10), Some(b"10".to_vec()));
+    assert_eq!(value(9), Some(b"9".to_vec()));
+    assert_eq!(value(5), None);
+    assert_eq!(value(20), None);
+    let keys: Vec<_> = run!(numbers.entries(&copy)).into_iter().map(|(key, _)| key).collect();
+    assert_eq!(keys, [0, 9, 10, 19]);
+}
+
+#[test]
+fn associated_files() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let mut embed = |data: &[u8]| run!(file.create(Primitive::Stream(PdfStream {
+        info: Dictionary::new(),
+        data: data.to_vec(),
+    }))).get_ref().get_inner();
+    let source = embed(b"source");
+    let part = embed(b"part 2");
+
+    let related = Primitive::Array(vec![string("data.002"), Primitive::Reference(part)]);
+    let spec = dict(vec![
+        ("Type", Primitive::name("Filespec")),
+        ("F", string("data.001")), ("UF", string("data.001")),
+        ("AFRelationship", Primitive::name("Source")),
+        ("EF", dict(vec![("F", Primitive::Reference(source))])),
+        ("RF", dict(vec![("F", related)])),
+    ]);
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("AF", Primitive::Array(vec![spec]));
+    // a link to another document, with a file specification string
+    catalog_dict.insert("OpenAction", dict(vec![
+        ("S", Primitive::name("GoToR")), ("F", string("/C/other/doc.pdf")),
+        ("D", Primitive::Array(vec![Primitive::Integer(0), Primitive::name("Fit")])),
+    ]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-associated-files.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let af = copy.get_root().associated_files.as_ref().unwrap();
+    assert_eq!(af.len(), 1);
+    assert_eq!(af[0].relationship, Some(AFRelationship::Source));
+    let related = run!(af[0].related_files(&copy));
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].0, "data.002");
+    assert_eq!(run!(run!(copy.get(related[0].1)).data()), b"part 2");
+
+    let attachments = run!(copy.attachments());
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].name, "data.001");
+    assert_eq!(attachments[0].data, b"source");
+    assert_eq!(attachments[0].page, None);
+
+    match run!(copy.open_action()).unwrap()[..] {
+        [Action::GoToR { file: Some(ref spec), .. }] => {
+            let path = spec.path().unwrap();
+            assert!(path.absolute);
+            assert_eq!(path.components, ["C", "other", "doc.pdf"]);
+            assert_eq!(spec.file_name().as_deref(), Some("doc.pdf"));
+        }
+        ref actions => panic!("unexpected actions {:?}", actions)
+    }
+}
+
+#[test]
+fn read_portfolio() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::portfolio::Value;
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+
+    let mut names = vec![];
+    for &(name, pages, author) in &[("a.pdf", 12, "Zoe"), ("b.pdf", 3, "Adam"), ("c.txt", 3, "Bea")] {
+        let stream = run!(file.create(Primitive::Stream(PdfStream {
+            info: match dict(vec![("Params", dict(vec![("Size", Primitive::Integer(name.len() as i32))]))]) {
+                Primitive::Dictionary(info) => info,
+                _ => unreachable!()
+            },
+            data: name.as_bytes().to_vec(),
+        }))).get_ref().get_inner();
+        names.push(string(name));
+        names.push(dict(vec![
+            ("Type", Primitive::name("Filespec")), ("F", string(name)),
+            ("EF", dict(vec![("F", Primitive::Reference(stream))])),
+            ("CI", dict(vec![
+                ("pages", Primitive::Integer(pages)),
+                ("author", dict(vec![("D", string(author)), ("P", string("by "))])),
+            ])),
+        ]));
+    }
+    let schema = dict(vec![
+        ("file", dict(vec![("Subtype", Primitive::name("F")), ("N", string("Name")), ("O", Primitive::Integer(0))])),
+        ("pages", dict(vec![("Subtype", Primitive::name("N")), ("N", string("Pages")), ("O", Primitive::Integer(2))])),
+        ("author", dict(vec![("Subtype", Primitive::name("S")), ("N", string("Author")), ("O", Primitive::Integer(1)), ("V", Primitive::Boolean(false))])),
+    ]);
+    let collection = dict(vec![
+        ("Type", Primitive::name("Collection")),
+        ("Schema", schema),
+        ("D", string("c.txt")),
+        ("View", Primitive::name("D")),
+        ("Sort", dict(vec![
+            ("S", Primitive::Array(vec![Primitive::name("pages"), Primitive::name("author")])),
+            ("A", Primitive::Array(vec![Primitive::Boolean(false), Primitive::Boolean(true)])),
+        ])),
+    ]);
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Names", dict(vec![("EmbeddedFiles", dict(vec![("Names", Primitive::Array(names))]))]));
+    catalog_dict.insert("Collection", collection);
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-portfolio.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let portfolio = run!(copy.portfolio()).unwrap();
+    assert_eq!(portfolio.view, Some(CollectionView::Details));
+    let fields: Vec<_> = portfolio.fields.iter().map(|(key, field)| (key.as_str(), field.visible)).collect();
+    assert_eq!(fields, [("file", true), ("author", false), ("pages", true)]);
+
+    // by pages, descending, then by author
+    let order: Vec<_> = portfolio.members.iter().map(|m| m.key.as_bytes().to_vec()).collect();
+    assert_eq!(order, [b"a.pdf".to_vec(), b"b.pdf".to_vec(), b"c.txt".to_vec()]);
+    let first = &portfolio.members[0];
+    assert_eq!(first.get("file"), Some(&Value::Text("a.pdf".into())));
+    assert_eq!(first.get("pages"), Some(&Value::Number(12.)));
+    assert_eq!(first.get("author"), Some(&Value::Text("Zoe".into())));
+    assert_eq!(first.prefixes.get("author").map(|p| p.as_str()), Some("by "));
+
+    let initial = portfolio.initial().unwrap();
+    assert_eq!(initial.key.as_bytes(), b"c.txt");
+    assert_eq!(run!(initial.data(&copy)).unwrap(), b"c.txt");
+    assert!(run!(File::open(file_path!("libreoffice.pdf"))).portfolio().unwrap().is_none());
+}
+
+#[test]
+fn associated_invoice() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let xml = b"<rsm:CrossIndustryInvoice/>";
+    run!(file.attach("factur-x.xml", xml, "text/xml", "Factur-X invoice"));
+
+    // like Factur-X: the file of the name tree is also associated with the document
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    let names = run!(run!(catalog_dict["Names"].clone().into_dictionary(&file))["EmbeddedFiles"].clone().into_dictionary(&file));
+    let spec = run!(names["Names"].clone().into_array(&file))[1].clone();
+    let spec_ref = run!(spec.clone().into_reference());
+    let mut spec_dict = run!(spec.into_dictionary(&file));
+    spec_dict.insert("AFRelationship", Primitive::name("Alternative"));
+    run!(file.update(spec_ref, Primitive::Dictionary(spec_dict)));
+    catalog_dict.insert("AF", Primitive::Array(vec![Primitive::Reference(spec_ref)]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let rect = Primitive::Array([0, 0, 10, 10].iter().map(|&n| Primitive::Integer(n)).collect());
+    let annot = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Square")), ("Rect", rect),
+        ("AF", Primitive::Array(vec![dict(vec![("F", string("review.txt")), ("AFRelationship", Primitive::name("Supplement"))])])),
+    ]))).get_ref().get_inner();
+    let page = run!(file.get_page(0));
+    let mut copy = (*page).clone();
+    copy.annotations = Some(vec![Primitive::Reference(annot)]);
+    run!(file.update(page.get_ref().get_inner(), PagesNode::Leaf(copy)));
+
+    let out = std::env::temp_dir().join("pdf-rs-associated-invoice.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let files = run!(copy.associated_files());
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].0, AssociatedWith::Document);
+    assert_eq!(files[0].1.relationship, Some(AFRelationship::Alternative));
+    assert_eq!(files[1].0, AssociatedWith::Annotation { page: 0, index: 0 });
+    assert_eq!(files[1].1.relationship, Some(AFRelationship::Supplement));
+    assert_eq!(files[1].1.name().as_deref(), Some("review.txt"));
+
+    let invoice = run!(copy.embedded_invoice()).unwrap();
+    assert_eq!(invoice.data, xml);
+    assert_eq!(invoice.mime.as_deref(), Some("text/xml"));
+    assert_eq!(run!(copy.attachments()).len(), 1);
+}
+
+#[test]
+fn read_signatures() {
+    use pdf::file::SaveOptions;
+    use pdf::sign::{DigestAlgorithm, SignatureOptions};
+
+    let mut file = run!(File::open(file_path!("libreoffice.pdf")));
+    assert!(run!(file.signatures()).is_empty());
+    let mut options = SignatureOptions::new("Approval");
+    options.reason = Some("Reviewed".into());
+    options.date = Some(run!(pdf::primitive::parse_date("D:20210304050607+01'00'")));
+    options.contents_size = 64;
+
+    // a DER sequence, the rest of the gap is filled with zeros
+    let der = vec![0x30, 0x03, 0x02, 0x01, 0x00];
+    let mut digest = [0; 32];
+    let data = run!(file.sign(&options, |signed| {
+        digest = signed.sha256();
+        Ok(der.clone())
+    }));
+
+    let mut copy = run!(File::from_data(data.clone()));
+    let signatures = run!(copy.signatures());
+    assert_eq!(signatures.len(), 1);
+    let sig = &signatures[0];
+    assert_eq!(sig.field_name, "Approval");
+    assert_eq!(sig.filter.as_deref(), Some("Adobe.PPKLite"));
+    assert_eq!(sig.sub_filter.as_deref(), Some("adbe.pkcs7.detached"));
+    assert_eq!(sig.reason.as_deref(), Some("Reviewed"));
+    assert_eq!(sig.date.unwrap().to_rfc3339(), "2021-03-04T05:06:07+01:00");
+    assert_eq!(sig.contents, der);
+    assert_eq!(sig.byte_range.len(), 2);
+
+    let raw = run!(copy.raw_data()).to_vec();
+    assert_eq!(raw, data);
+    assert_eq!(run!(sig.digest(&raw, DigestAlgorithm::Sha256)), digest);
+    assert_eq!(run!(sig.digest(&raw, DigestAlgorithm::Sha512)).len(), 64);
+    assert!(sig.covers_whole_file(&raw));
+
+    // changes after signing are outside of the signed bytes
+    copy.set_info("Title", "Changed");
+    let changed = run!(copy.save_to_bytes(&SaveOptions { incremental: true, ..SaveOptions::default() }));
+    assert!(changed.starts_with(&data));
+    let changed_file = run!(File::from_data(changed.clone()));
+    let sig = &run!(changed_file.signatures())[0];
+    assert_eq!(run!(sig.digest(&changed, DigestAlgorithm::Sha256)), digest);
+    assert!(!sig.covers_whole_file(&changed));
+    assert!(sig.signed_data(&changed[.. 100]).is_err());
+}
+
+#[test]
+fn xfa_form() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+    fn stream(file: &mut File<Vec<u8>>, data: &str) -> Primitive {
+        let mut info = Dictionary::new();
+        info.insert("Length", Primitive::Integer(data.len() as i32));
+        let stream = Primitive::Stream(PdfStream { info, data: data.as_bytes().to_vec() });
+        Primitive::Reference(run!(file.create(stream)).get_ref().get_inner())
+    }
+    fn set_form(file: &mut File<Vec<u8>>, xfa: Primitive, needs_rendering: bool) {
+        let catalog = file.trailer.root.get_ref().get_inner();
+        let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(file));
+        catalog_dict.insert("AcroForm", dict(vec![("Fields", Primitive::Array(vec![])), ("XFA", xfa)]));
+        catalog_dict.insert("NeedsRendering", Primitive::Boolean(needs_rendering));
+        run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    assert!(run!(file.xfa()).is_none());
+
+    // a single stream
+    let xdp = stream(&mut file, "<xdp:xdp><template/></xdp:xdp>");
+    set_form(&mut file, xdp, false);
+    let xfa = run!(file.xfa()).unwrap();
+    assert!(!xfa.needs_rendering);
+    assert_eq!(xfa.packets.len(), 1);
+    assert_eq!(xfa.xml(), b"<xdp:xdp><template/></xdp:xdp>");
+
+    // packets
+    let packets = vec![
+        string("preamble"), stream(&mut file, "<xdp:xdp>"),
+        string("template"), stream(&mut file, "<template/>"),
+        string("datasets"), stream(&mut file, "<xfa:datasets/>"),
+        string("postamble"), stream(&mut file, "</xdp:xdp>"),
+    ];
+    let packets = Primitive::Reference(run!(file.create(Primitive::Array(packets))).get_ref().get_inner());
+    set_form(&mut file, packets, true);
+
+    let out = std::env::temp_dir().join("pdf-rs-xfa-form.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let xfa = run!(copy.xfa()).unwrap();
+    assert!(xfa.needs_rendering);
+    let names: Vec<_> = xfa.packets.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["preamble", "template", "datasets", "postamble"]);
+    assert_eq!(xfa.packet("datasets"), Some(&b"<xfa:datasets/>"[..]));
+    assert_eq!(xfa.packet("config"), None);
+    assert_eq!(xfa.xml(), b"<xdp:xdp><template/><xfa:datasets/></xdp:xdp>");
+}
+
+#[test]
+fn output_intents() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::object::OutputIntentSubtype;
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    assert!(file.get_root().output_intents.is_none());
+
+    let profile = b"not really an ICC profile".to_vec();
+    let mut info = Dictionary::new();
+    info.insert("N", Primitive::Integer(4));
+    info.insert("Length", Primitive::Integer(profile.len() as i32));
+    let profile_ref = run!(file.create(Primitive::Stream(PdfStream { info, data: profile.clone() }))).get_ref().get_inner();
+    let intents = Primitive::Array(vec![
+        dict(vec![
+            ("Type", Primitive::name("OutputIntent")), ("S", Primitive::name("GTS_PDFA1")),
+            ("OutputConditionIdentifier", string("FOGRA39")), ("OutputCondition", string("Coated FOGRA39")),
+            ("RegistryName", string("http://www.color.org")), ("DestOutputProfile", Primitive::Reference(profile_ref)),
+        ]),
+        dict(vec![("S", Primitive::name("GTS_PDFX")), ("OutputConditionIdentifier", string("Custom"))]),
+        dict(vec![("S", Primitive::name("ACME_Print")), ("OutputConditionIdentifier", string("Custom"))]),
+    ]);
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("OutputIntents", intents);
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-output-intents.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let root = copy.get_root();
+    let intents = root.output_intents.as_ref().unwrap();
+    assert_eq!(intents.len(), 3);
+    assert_eq!(intents[2].subtype, OutputIntentSubtype::Other("ACME_Print".into()));
+
+    let pdfa = root.output_intent(&OutputIntentSubtype::PdfA).unwrap();
+    assert_eq!(pdfa.identifier(), "FOGRA39");
+    assert_eq!(pdfa.condition().as_deref(), Some("Coated FOGRA39"));
+    assert_eq!(pdfa.components(), Some(4));
+    assert_eq!(run!(pdfa.profile_data()), Some(&profile[..]));
+
+    let pdfx = root.output_intent(&OutputIntentSubtype::PdfX).unwrap();
+    assert_eq!(pdfx.identifier(), "Custom");
+    assert_eq!(pdfx.components(), None);
+    assert_eq!(run!(pdfx.profile_data()), None);
+    assert!(root.output_intent(&OutputIntentSubtype::PdfE).is_none());
+}
+
+#[test]
+fn enumerate_javascript() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::javascript::ScriptLocation;
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+    fn js(source: &str) -> Primitive {
+        dict(vec![("S", Primitive::name("JavaScript")), ("JS", string(source))])
+    }
+    fn rect() -> Primitive {
+        Primitive::Array(vec![Primitive::Integer(0), Primitive::Integer(0), Primitive::Integer(10), Primitive::Integer(10)])
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    assert!(run!(file.javascript()).is_empty());
+    let page_ref = run!(file.get_page(0)).get_ref().get_inner();
+
+    let link = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Link")), ("Rect", rect()),
+        ("A", dict(vec![
+            ("S", Primitive::name("URI")), ("URI", string("https://example.com")),
+            ("Next", js("link()")),
+        ])),
+    ]))).get_ref().get_inner();
+    // a field merged with its widget
+    let merged = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Widget")), ("Rect", rect()),
+        ("FT", Primitive::name("Tx")), ("T", string("merged")),
+        ("AA", dict(vec![("K", js("keystroke()"))])),
+    ]))).get_ref().get_inner();
+    let widget = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Widget")), ("Rect", rect()),
+    ]))).get_ref().get_inner();
+    let field = run!(file.create(dict(vec![
+        ("FT", Primitive::name("Tx")), ("T", string("total")),
+        ("Kids", Primitive::Array(vec![Primitive::Reference(widget)])),
+        ("AA", dict(vec![("C", js("calculate()")), ("F", dict(vec![("S", Primitive::name("Named")), ("N", Primitive::name("NextPage"))]))])),
+    ]))).get_ref().get_inner();
+
+    let mut page_dict = run!(run!(file.resolve(page_ref)).into_dictionary(&file));
+    page_dict.insert("Annots", Primitive::Array(vec![Primitive::Reference(link), Primitive::Reference(merged), Primitive::Reference(widget)]));
+    page_dict.insert("AA", dict(vec![("O", js("opened()")), ("C", Primitive::Integer(1))]));
+    run!(file.update(page_ref, Primitive::Dictionary(page_dict)));
+
+    let item = run!(file.create(Primitive::Null)).get_ref().get_inner();
+    let outlines = run!(file.create(dict(vec![
+        ("First", Primitive::Reference(item)), ("Last", Primitive::Reference(item)), ("Count", Primitive::Integer(1)),
+    ]))).get_ref().get_inner();
+    run!(file.update(item, dict(vec![
+        ("Title", string("Run")), ("Parent", Primitive::Reference(outlines)), ("A", js("bookmark()")),
+    ])));
+
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Names", dict(vec![
+        ("JavaScript", dict(vec![("Names", Primitive::Array(vec![string("init"), js("init()")]))])),
+    ]));
+    catalog_dict.insert("OpenAction", js("open()"));
+    catalog_dict.insert("AA", dict(vec![("WC", js("close()"))]));
+    catalog_dict.insert("Outlines", Primitive::Reference(outlines));
+    catalog_dict.insert("AcroForm", dict(vec![
+        ("Fields", Primitive::Array(vec![Primitive::Reference(merged), Primitive::Reference(field)])),
+    ]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-enumerate-javascript.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let scripts = run!(copy.javascript());
+    let found: Vec<_> = scripts.iter().map(|s| (s.location.clone(), s.source.as_str())).collect();
+    assert_eq!(found, [
+        (ScriptLocation::Names("init".into()), "init()"),
+        (ScriptLocation::OpenAction, "open()"),
+        (ScriptLocation::Document { event: "WC".into() }, "close()"),
+        (ScriptLocation::Page { page: 0, event: "O".into() }, "opened()"),
+        (ScriptLocation::Annotation { page: 0, index: 0, event: None }, "link()"),
+        (ScriptLocation::Annotation { page: 0, index: 1, event: Some("K".into()) }, "keystroke()"),
+        (ScriptLocation::Field { name: "total".into(), event: "C".into() }, "calculate()"),
+        (ScriptLocation::Outline { title: "Run".into() }, "bookmark()"),
+    ]);
+}
+
+#[test]
+fn open_destination() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn set_open_action(file: &mut File<Vec<u8>>, action: Primitive) -> File<Vec<u8>> {
+        let catalog = file.trailer.root.get_ref().get_inner();
+        let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(file));
+        catalog_dict.insert("OpenAction", action);
+        run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+        run!(File::from_data(run!(file.save_to_bytes(&Default::default()))))
+    }
+
+    let mut doc = DocumentBuilder::new();
+    for _ in 0 .. 3 {
+        doc.add_page(PageBuilder::new());
+    }
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    assert!(run!(file.open_destination()).is_none());
+    let pages: Vec<_> = (0 .. 3).map(|n| run!(file.get_page(n)).get_ref().get_inner()).collect();
+
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Dests", dict(vec![
+        ("start", Primitive::Array(vec![Primitive::Reference(pages[2]), Primitive::name("FitH"), Primitive::Integer(500)])),
+    ]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    // a script first, then a named destination
+    let copy = set_open_action(&mut file, dict(vec![
+        ("S", Primitive::name("JavaScript")), ("JS", Primitive::String(PdfString::new(b"init()".to_vec()))),
+        ("Next", dict(vec![("S", Primitive::name("GoTo")), ("D", Primitive::name("start"))])),
+    ]));
+    assert!(matches!(run!(copy.open_destination()), Some((2, DestView::FitH { top: Some(top) })) if top == 500.));
+
+    // a destination array
+    let copy = set_open_action(&mut file, Primitive::Array(vec![Primitive::Reference(pages[1]), Primitive::name("Fit")]));
+    assert!(matches!(run!(copy.open_destination()), Some((1, DestView::Fit))));
+
+    // names that aren't defined and actions that don't go anywhere
+    let copy = set_open_action(&mut file, Primitive::name("missing"));
+    assert!(run!(copy.open_destination()).is_none());
+    let copy = set_open_action(&mut file, dict(vec![("S", Primitive::name("Named")), ("N", Primitive::name("LastPage"))]));
+    assert!(run!(copy.open_destination()).is_none());
+}
+
+#[test]
+fn article_threads() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn rect(values: [i32; 4]) -> Primitive {
+        Primitive::Array(values.iter().map(|&v| Primitive::Integer(v)).collect())
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    assert!(run!(file.threads()).is_empty());
+    let pages: Vec<_> = (0 .. 2).map(|n| run!(file.get_page(n)).get_ref().get_inner()).collect();
+
+    let mut refs = vec![];
+    for _ in 0 .. 6 {
+        refs.push(run!(file.create(Primitive::Null)).get_ref().get_inner());
+    }
+    let (story, beads, broken, loose) = (refs[0], &refs[1 .. 4], refs[4], refs[5]);
+    run!(file.update(story, dict(vec![
+        ("Type", Primitive::name("Thread")), ("F", Primitive::Reference(beads[0])),
+        ("I", dict(vec![
+            ("Title", Primitive::String(PdfString::new(b"Cover story".to_vec()))),
+            ("Author", Primitive::String(PdfString::new(b"A. Writer".to_vec()))),
+        ])),
+    ])));
+    let areas = [(pages[0], [36, 400, 290, 756]), (pages[0], [310, 400, 576, 756]), (pages[1], [36, 36, 576, 756])];
+    for (i, &(page, area)) in areas.iter().enumerate() {
+        let mut entries = vec![
+            ("Type", Primitive::name("Bead")),
+            ("N", Primitive::Reference(beads[(i + 1) % 3])),
+            ("V", Primitive::Reference(beads[(i + 2) % 3])),
+            ("P", Primitive::Reference(page)),
+            ("R", rect(area)),
+        ];
+        if i == 0 {
+            entries.push(("T", Primitive::Reference(story)));
+        }
+        run!(file.update(beads[i], dict(entries)));
+    }
+    // a thread whose only bead links to itself
+    run!(file.update(broken, dict(vec![("F", Primitive::Reference(loose))])));
+    run!(file.update(loose, dict(vec![
+        ("T", Primitive::Reference(broken)), ("N", Primitive::Reference(loose)), ("V", Primitive::Reference(loose)),
+        ("P", Primitive::Reference(pages[1])), ("R", rect([0, 0, 10, 10])),
+    ])));
+
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Threads", Primitive::Array(vec![Primitive::Reference(story), Primitive::Reference(broken)]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-article-threads.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let threads = run!(copy.threads());
+    assert_eq!(threads.len(), 2);
+    let story = &threads[0];
+    assert_eq!(story.title.as_deref(), Some("Cover story"));
+    assert_eq!(story.author.as_deref(), Some("A. Writer"));
+    let pages: Vec<_> = story.beads.iter().map(|b| b.page_nr).collect();
+    assert_eq!(pages, [Some(0), Some(0), Some(1)]);
+    let lefts: Vec<_> = story.beads.iter().map(|b| b.rect.left).collect();
+    assert_eq!(lefts, [36., 310., 36.]);
+
+    assert_eq!(threads[1].title, None);
+    assert_eq!(threads[1].beads.len(), 1);
+    assert_eq!(threads[1].beads[0].page_nr, Some(1));
+}
+
+#[test]
+fn page_transitions() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+
+    let mut doc = DocumentBuilder::new();
+    for _ in 0 .. 3 {
+        doc.add_page(PageBuilder::new());
+    }
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let transitions = vec![
+        dict(vec![
+            ("Type", Primitive::name("Trans")), ("S", Primitive::name("Split")), ("D", Primitive::Number(0.5)),
+            ("Dm", Primitive::name("V")), ("M", Primitive::name("O")),
+        ]),
+        dict(vec![("S", Primitive::name("Fly")), ("Di", Primitive::name("None")), ("SS", Primitive::Number(0.25)), ("B", Primitive::Boolean(true))]),
+        dict(vec![("Di", Primitive::Integer(270))]),
+    ];
+    for (n, trans) in transitions.into_iter().enumerate() {
+        let page = run!(file.get_page(n as u32)).get_ref().get_inner();
+        let mut page_dict = run!(run!(file.resolve(page)).into_dictionary(&file));
+        page_dict.insert("Trans", trans);
+        if n == 0 {
+            page_dict.insert("Dur", Primitive::Integer(5));
+        }
+        run!(file.update(page, Primitive::Dictionary(page_dict)));
+    }
+
+    let out = std::env::temp_dir().join("pdf-rs-page-transitions.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let page = run!(copy.get_page(0));
+    assert_eq!(page.duration, Some(5.));
+    let split = page.transition.as_ref().unwrap();
+    assert_eq!(split.style, TransitionStyle::Split);
+    assert_eq!(split.duration, 0.5);
+    assert_eq!(split.dimension, Some(TransitionDimension::Vertical));
+    assert_eq!(split.motion, Some(TransitionMotion::Outward));
+    assert_eq!(split.direction, None);
+
+    let page = run!(copy.get_page(1));
+    assert_eq!(page.duration, None);
+    let fly = page.transition.as_ref().unwrap();
+    assert_eq!(fly.style, TransitionStyle::Fly);
+    assert_eq!(fly.direction, Some(TransitionDirection::Unspecified));
+    assert_eq!(fly.scale, Some(0.25));
+    assert_eq!(fly.opaque, Some(true));
+
+    // the defaults
+    let page = run!(copy.get_page(2));
+    let replace = page.transition.as_ref().unwrap();
+    assert_eq!(replace.style, TransitionStyle::Replace);
+    assert_eq!(replace.duration, 1.);
+    assert_eq!(replace.direction, Some(TransitionDirection::Degrees(270)));
+
+    let written = run!(fly.to_primitive(&mut NoUpdate));
+    let read = run!(Transition::from_primitive(written, &NoResolve));
+    assert_eq!(read.direction, Some(TransitionDirection::Unspecified));
+    assert_eq!(read.style, TransitionStyle::Fly);
+}
+
+#[test]
+fn annotation_appearances() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::content::Point;
+    use pdf::primitive::{Dictionary, PdfStream, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn numbers(values: &[i32]) -> Primitive {
+        Primitive::Array(values.iter().map(|&v| Primitive::Integer(v)).collect())
+    }
+    fn form(file: &mut File<Vec<u8>>, ops: &str, bbox: [i32; 4], matrix: Option<[i32; 6]>) -> Primitive {
+        let mut info = Dictionary::new();
+        info.insert("Type", Primitive::name("XObject"));
+        info.insert("Subtype", Primitive::name("Form"));
+        info.insert("BBox", numbers(&bbox));
+        if let Some(matrix) = matrix {
+            info.insert("Matrix", numbers(&matrix));
+        }
+        info.insert("Length", Primitive::Integer(ops.len() as i32));
+        let stream = Primitive::Stream(PdfStream { info, data: ops.as_bytes().to_vec() });
+        Primitive::Reference(run!(file.create(stream)).get_ref().get_inner())
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let page_ref = run!(file.get_page(0)).get_ref().get_inner();
+
+    let yes = form(&mut file, "0 0 1 rg 0 0 10 10 re f", [0, 0, 10, 10], None);
+    let off = form(&mut file, "", [0, 0, 10, 10], None);
+    let down = form(&mut file, "0 0 10 10 re f", [0, 0, 10, 10], None);
+    let check_box = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Widget")),
+        ("Rect", numbers(&[100, 100, 120, 120])), ("AS", Primitive::name("Yes")),
+        ("AP", dict(vec![
+            ("N", dict(vec![("Yes", yes), ("Off", off)])),
+            ("D", dict(vec![("Yes", down)])),
+        ])),
+    ]))).get_ref().get_inner();
+    // a rotated appearance with a single state
+    let rotated = form(&mut file, "0 0 m 50 20 l S", [0, 0, 50, 20], Some([0, 1, -1, 0, 0, 0]));
+    let stamp = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Stamp")),
+        ("Rect", numbers(&[200, 200, 240, 300])),
+        ("AP", dict(vec![("N", rotated)])),
+    ]))).get_ref().get_inner();
+    let bare = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Text")),
+        ("Rect", numbers(&[0, 0, 10, 10])),
+    ]))).get_ref().get_inner();
+
+    let mut page_dict = run!(run!(file.resolve(page_ref)).into_dictionary(&file));
+    page_dict.insert("Annots", Primitive::Array(vec![Primitive::Reference(check_box), Primitive::Reference(stamp), Primitive::Reference(bare)]));
+    run!(file.update(page_ref, Primitive::Dictionary(page_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-annotation-appearances.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let annots = run!(run!(copy.get_page(0)).annots(&copy));
+    let check_box = annots[0].dict();
+    assert_eq!(run!(check_box.appearance_states(&copy, AppearanceKind::Normal)), ["Off", "Yes"]);
+    let normal = run!(check_box.appearance_stream(&copy, AppearanceKind::Normal)).unwrap();
+    assert_eq!(normal.operations.len(), 3);
+    // no rollover appearance, the normal one is used
+    let rollover = run!(check_box.appearance_stream(&copy, AppearanceKind::Rollover)).unwrap();
+    assert_eq!(rollover.operations.len(), 3);
+    let down = run!(check_box.appearance_stream(&copy, AppearanceKind::Down)).unwrap();
+    assert_eq!(down.operations.len(), 2);
+    let m = check_box.appearance_matrix(&normal);
+    let corner = m.transform(Point { x: 10., y: 10. });
+    assert_eq!((corner.x, corner.y), (120., 120.));
+
+    let stamp = annots[1].dict();
+    assert!(run!(stamp.appearance_states(&copy, AppearanceKind::Normal)).is_empty());
+    let form = run!(stamp.appearance_stream(&copy, AppearanceKind::Down)).unwrap();
+    assert_eq!(form.dict().bbox.right, 50.);
+    let form_matrix = form.dict().matrix.unwrap();
+    let m = form_matrix * stamp.appearance_matrix(&form);
+    let start = m.transform(Point { x: 0., y: 0. });
+    let end = m.transform(Point { x: 50., y: 20. });
+    assert_eq!((start.x, start.y), (240., 200.));
+    assert_eq!((end.x, end.y), (200., 300.));
+
+    assert!(run!(annots[2].dict().appearance_stream(&copy, AppearanceKind::Normal)).is_none());
+}
+
+#[test]
+fn page_links() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+    fn link(file: &mut File<Vec<u8>>, y: i32, entry: (&str, Primitive)) -> Primitive {
+        let rect = Primitive::Array([100, y + 10, 0, y].iter().map(|&v| Primitive::Integer(v)).collect());
+        let annot = dict(vec![("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Link")), ("Rect", rect), entry]);
+        Primitive::Reference(run!(file.create(annot)).get_ref().get_inner())
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let pages: Vec<_> = (0 .. 2).map(|n| run!(file.get_page(n)).get_ref().get_inner()).collect();
+
+    let annots = vec![
+        link(&mut file, 0, ("Dest", Primitive::Array(vec![Primitive::Reference(pages[1]), Primitive::name("Fit")]))),
+        link(&mut file, 20, ("A", dict(vec![("S", Primitive::name("GoTo")), ("D", string("chapter"))]))),
+        link(&mut file, 40, ("A", dict(vec![("S", Primitive::name("URI")), ("URI", string("https://example.com/"))]))),
+        link(&mut file, 60, ("A", dict(vec![("S", Primitive::name("GoToR")), ("F", string("other.pdf")), ("D", string("intro"))]))),
+        link(&mut file, 80, ("A", dict(vec![("S", Primitive::name("Named")), ("N", Primitive::name("NextPage"))]))),
+        link(&mut file, 100, ("Dest", Primitive::name("missing"))),
+        Primitive::Reference(run!(file.create(dict(vec![
+            ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Text")),
+            ("Rect", Primitive::Array(vec![Primitive::Integer(0); 4])),
+        ]))).get_ref().get_inner()),
+    ];
+    let mut page_dict = run!(run!(file.resolve(pages[0])).into_dictionary(&file));
+    page_dict.insert("Annots", Primitive::Array(annots));
+    run!(file.update(pages[0], Primitive::Dictionary(page_dict)));
+
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("Dests", dict(vec![
+        ("chapter", Primitive::Array(vec![Primitive::Reference(pages[1]), Primitive::name("FitH"), Primitive::Integer(700)])),
+    ]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-page-links.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let links = run!(run!(copy.get_page(0)).links(&copy, copy.get_root()));
+    assert_eq!(links.len(), 5);
+    let r = links[0].rect;
+    assert_eq!((r.left, r.bottom, r.right, r.top), (0., 0., 100., 10.));
+    assert!(matches!(links[0].target, LinkTarget::Page { page: 1, view: DestView::Fit }));
+    assert!(matches!(links[1].target, LinkTarget::Page { page: 1, view: DestView::FitH { top: Some(top) } } if top == 700.));
+    assert!(matches!(links[2].target, LinkTarget::Uri(ref uri) if uri == "https://example.com/"));
+    match links[3].target {
+        LinkTarget::Remote { ref file, ref dest } => {
+            assert_eq!(file.as_ref().unwrap().name().as_deref(), Some("other.pdf"));
+            assert!(matches!(dest, Some(Primitive::String(ref s)) if s.as_bytes() == b"intro"));
+        }
+        ref target => panic!("unexpected target {:?}", target)
+    }
+    assert!(matches!(links[4].target, LinkTarget::Action(Action::Named(ref name)) if name == "NextPage"));
+    assert!(run!(run!(copy.get_page(1)).links(&copy, copy.get_root())).is_empty());
+}
+
+#[test]
+fn widget_fields() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn rect(values: [i32; 4]) -> Primitive {
+        Primitive::Array(values.iter().map(|&v| Primitive::Integer(v)).collect())
+    }
+    fn string(s: &str) -> Primitive {
+        Primitive::String(PdfString::new(s.as_bytes().to_vec()))
+    }
+    fn widget(file: &mut File<Vec<u8>>, entries: Vec<(&str, Primitive)>) -> PlainRef {
+        let mut entries = entries;
+        entries.push(("Type", Primitive::name("Annot")));
+        entries.push(("Subtype", Primitive::name("Widget")));
+        run!(file.create(dict(entries))).get_ref().get_inner()
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let pages: Vec<_> = (0 .. 2).map(|n| run!(file.get_page(n)).get_ref().get_inner()).collect();
+
+    // merged with its widget
+    let name = widget(&mut file, vec![("FT", Primitive::name("Tx")), ("T", string("name")), ("Rect", rect([50, 700, 250, 720]))]);
+    // one field with a widget on each page, the second one only with `P`
+    let initials: Vec<_> = [(pages[0], [520, 20, 500, 40]), (pages[1], [500, 20, 520, 40])].iter()
+        .map(|&(page, area)| widget(&mut file, vec![("Rect", rect(area)), ("P", Primitive::Reference(page))]))
+        .collect();
+    let field = run!(file.create(dict(vec![
+        ("FT", Primitive::name("Tx")), ("T", string("initials")),
+        ("Kids", Primitive::Array(initials.iter().map(|&r| Primitive::Reference(r)).collect())),
+    ]))).get_ref().get_inner();
+    let note = run!(file.create(dict(vec![
+        ("Type", Primitive::name("Annot")), ("Subtype", Primitive::name("Text")), ("Rect", rect([0, 0, 10, 10])),
+    ]))).get_ref().get_inner();
+
+    let mut page_dict = run!(run!(file.resolve(pages[0])).into_dictionary(&file));
+    page_dict.insert("Annots", Primitive::Array([name, initials[0], note].iter().map(|&r| Primitive::Reference(r)).collect()));
+    run!(file.update(pages[0], Primitive::Dictionary(page_dict)));
+    let catalog = file.trailer.root.get_ref().get_inner();
+    let mut catalog_dict = run!(run!(file.resolve(catalog)).into_dictionary(&file));
+    catalog_dict.insert("AcroForm", dict(vec![("Fields", Primitive::Array(vec![Primitive::Reference(name), Primitive::Reference(field)]))]));
+    run!(file.update(catalog, Primitive::Dictionary(catalog_dict)));
+
+    let out = std::env::temp_dir().join("pdf-rs-widget-fields.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let owner = |widget: PlainRef| run!(copy.field_of_widget(widget)).map(|f| f.name);
+    assert_eq!(owner(name).as_deref(), Some("name"));
+    assert_eq!(owner(initials[0]).as_deref(), Some("initials"));
+    assert_eq!(owner(initials[1]).as_deref(), Some("initials"));
+    assert_eq!(owner(note), None);
+
+    let fields = run!(copy.form_fields());
+    let widgets = run!(copy.field_widgets(&fields[0]));
+    assert_eq!(widgets.len(), 1);
+    assert_eq!((widgets[0].r, widgets[0].page), (name, Some(0)));
+
+    let widgets = run!(copy.field_widgets(&fields[1]));
+    let found: Vec<_> = widgets.iter().map(|w| (w.r, w.page, w.rect.left, w.rect.right)).collect();
+    assert_eq!(found, [(initials[0], Some(0), 500., 520.), (initials[1], Some(1), 500., 520.)]);
+}
+
+#[test]
+fn apply_redactions() {
+    use pdf::build::{ContentBuilder, DocumentBuilder, PageBuilder};
+    use pdf::content::{Color, Op, Rgb};
+    use pdf::primitive::{Dictionary, Primitive};
+    use pdf::standard_fonts::StandardFont;
+
+    fn dict(entries: Vec<(&str, Primitive)>) -> Primitive {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        Primitive::Dictionary(dict)
+    }
+    fn numbers(values: &[f32]) -> Primitive {
+        Primitive::Array(values.iter().map(|&v| Primitive::Number(v)).collect())
+    }
+
+    let mut doc = DocumentBuilder::new();
+    let font = run!(doc.add_font(DocumentBuilder::standard_font(StandardFont::Helvetica)));
+    doc.add_page(PageBuilder::from_content(ContentBuilder::new()
+        .font(font.as_str(), 12.)
+        .text_position(72., 720.)
+        .show("keep secret keep")
+        .build()));
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let page = run!(file.get_page(0));
+    let page_ref = page.get_ref().get_inner();
+
+    // the box of "secret", from the glyphs
+    let item = run!(pdf::text::items(&file, &page)).next().unwrap();
+    let x = |i: usize| item.transform.transform(pdf::content::Point { x: item.glyphs[i].offset, y: 0. }).x;
+    let (left, right) = (x(5), x(11));
+    let end = item.end();
+
+    let annot = |file: &mut File<Vec<u8>>, entries: Vec<(&str, Primitive)>| {
+        let mut all = vec![("Type", Primitive::name("Annot"))];
+        all.extend(entries);
+        Primitive::Reference(run!(file.create(dict(all))).get_ref().get_inner())
+    };
+    let annots = vec![
+        annot(&mut file, vec![
+            ("Subtype", Primitive::name("Redact")),
+            ("Rect", numbers(&[left, 700., right, 740.])),
+            ("QuadPoints", numbers(&[left, 730., right, 730., left, 715., right, 715.])),
+        ]),
+        annot(&mut file, vec![
+            ("Subtype", Primitive::name("Redact")),
+            ("Rect", numbers(&[0., 0., 20., 10.])),
+            ("IC", numbers(&[1., 0., 0.])),
+        ]),
+        annot(&mut file, vec![("Subtype", Primitive::name("Text")), ("Rect", numbers(&[0., 0., 10., 10.]))]),
+    ];
+    let mut page_dict = run!(run!(file.resolve(page_ref)).into_dictionary(&file));
+    page_dict.insert("Annots", Primitive::Array(annots));
+    run!(file.update(page_ref, Primitive::Dictionary(page_dict)));
+
+    let page = run!(file.get_page(0));
+    match run!(page.annots(&file))[0] {
+        Annot::Redact(ref d, ref redact) => {
+            let areas = redact.areas(d.rect);
+            assert_eq!(areas.len(), 1);
+            assert_eq!((areas[0].bottom, areas[0].top), (715., 730.));
+        }
+        ref annot => panic!("expected a redaction, got {:?}", annot)
+    }
+
+    assert_eq!(run!(file.apply_redactions()), 2);
+    let out = std::env::temp_dir().join("pdf-rs-apply-redactions.pdf");
+    run!(file.save_to(&out));
+    let copy = run!(File::open(&out));
+    let _ = std::fs::remove_file(&out);
+
+    let page = run!(copy.get_page(0));
+    let annots = run!(page.annots(&copy));
+    assert_eq!(annots.len(), 1);
+    assert_eq!(annots[0].subtype(), "Text");
+
+    let text = run!(pdf::text::extract(&copy, ..));
+    assert!(!text.contains("secret") && text.contains("keep"), "{:?}", text);
+    // the rest of the string doesn't move
+    let items: Vec<_> = run!(pdf::text::items(&copy, &page)).collect();
+    let last = items.last().unwrap();
+    assert!((last.end().x - end.x).abs() < 0.01 && (last.end().y - end.y).abs() < 0.01);
+
+    let ops = &page.contents.as_ref().unwrap().operations;
+    assert!(ops.iter().any(|op| matches!(op, Op::FillColor { color: Color::Gray(g) } if *g == 0.)));
+    assert!(ops.iter().any(|op| matches!(op, Op::FillColor { color: Color::Rgb(Rgb { red, .. }) } if *red == 1.)));
+}
+
+#[test]
+fn redact_without_font() {
+    use pdf::build::{ContentBuilder, DocumentBuilder, PageBuilder};
+    use pdf::content::Op;
+    use pdf::primitive::{Dictionary, Primitive};
+
+    // "F1" is not in the resources of the page, so the glyphs can't be placed
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::from_content(ContentBuilder::new()
+        .font("F1", 12.)
+        .text_position(72., 720.)
+        .show("secret")
+        .build()));
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let page_ref = run!(file.get_page(0)).get_ref().get_inner();
+
+    let mut annot = Dictionary::new();
+    annot.insert("Type", Primitive::name("Annot"));
+    annot.insert("Subtype", Primitive::name("Redact"));
+    annot.insert("Rect", Primitive::Array([0., 0., 10., 10.].iter().map(|&v| Primitive::Number(v)).collect()));
+    let annot = run!(file.create(Primitive::Dictionary(annot))).get_ref().get_inner();
+    let mut page_dict = run!(run!(file.resolve(page_ref)).into_dictionary(&file));
+    page_dict.insert("Annots", Primitive::Array(vec![Primitive::Reference(annot)]));
+    run!(file.update(page_ref, Primitive::Dictionary(page_dict)));
+
+    assert_eq!(run!(file.apply_redactions()), 1);
+    let page = run!(file.get_page(0));
+    let ops = &page.contents.as_ref().unwrap().operations;
+    assert!(!ops.iter().any(|op| matches!(op, Op::TextDraw { .. } | Op::TextDrawAdjusted { .. })), "{:?}", ops);
+}
+
+#[test]
+fn image_data() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+
+    let file = run!(File::<Vec<u8>>::open(file_path!("jpeg.pdf")));
+    let page = run!(file.get_page(0));
+    let image = run!(page.resources()).xobjects.values()
+        .map(|&r| run!(file.get(r)))
+        .find(|o| matches!(**o, XObject::Image(_)))
+        .expect("no image");
+    let image = match *image {
+        XObject::Image(ref image) => image,
+        _ => unreachable!()
+    };
+    let pixels = run!(image.image_data(&file));
+    assert_eq!((pixels.width, pixels.height), (image.width as u32, image.height as u32));
+    assert_eq!(pixels.data.len(), pixels.width as usize * pixels.height as usize * 4);
+    assert!(pixels.data.chunks(4).all(|p| p[3] == 255));
+
+    fn stream(entries: Vec<(&str, Primitive)>, data: &[u8]) -> Primitive {
+        let mut info = Dictionary::new();
+        for (key, value) in entries {
+            info.insert(key, value);
+        }
+        info.insert("Length", Primitive::Integer(data.len() as i32));
+        Primitive::Stream(PdfStream { info, data: data.to_vec() })
+    }
+    let image = |entries: Vec<(&str, Primitive)>, data: &[u8], resolve: &File<Vec<u8>>| {
+        let mut all = vec![("Type", Primitive::name("XObject")), ("Subtype", Primitive::name("Image"))];
+        all.extend(entries);
+        run!(run!(ImageXObject::from_primitive(stream(all, data), resolve)).image_data(resolve))
+    };
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let smask = run!(file.create(stream(vec![
+        ("Type", Primitive::name("XObject")), ("Subtype", Primitive::name("Image")),
+        ("Width", Primitive::Integer(1)), ("Height", Primitive::Integer(1)),
+        ("ColorSpace", Primitive::name("DeviceGray")), ("BitsPerComponent", Primitive::Integer(8)),
+    ], &[128]))).get_ref().get_inner();
+
+    // 2 bit indexed colors with a soft mask
+    let palette = Primitive::String(PdfString::new(vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]));
+    let pixels = image(vec![
+        ("Width", Primitive::Integer(2)), ("Height", Primitive::Integer(2)), ("BitsPerComponent", Primitive::Integer(2)),
+        ("ColorSpace", Primitive::Array(vec![Primitive::name("Indexed"), Primitive::name("DeviceRGB"), Primitive::Integer(3), palette])),
+        ("SMask", Primitive::Reference(smask)),
+    ], &[0b0001_0000, 0b1011_0000], &file);
+    assert_eq!(pixels.pixel(0, 0), Some([255, 0, 0, 128]));
+    assert_eq!(pixels.pixel(1, 0), Some([0, 255, 0, 128]));
+    assert_eq!(pixels.pixel(0, 1), Some([0, 0, 255, 128]));
+    assert_eq!(pixels.pixel(1, 1), Some([255, 255, 255, 128]));
+    assert_eq!(pixels.pixel(2, 0), None);
+
+    // the palette expanded to the base color space, with indices out of range clamped
+    let palette = Primitive::String(PdfString::new(vec![0, 0, 0, 255, 255, 0, 0, 0]));
+    let indexed = run!(ImageXObject::from_primitive(stream(vec![
+        ("Type", Primitive::name("XObject")), ("Subtype", Primitive::name("Image")),
+        ("Width", Primitive::Integer(3)), ("Height", Primitive::Integer(1)), ("BitsPerComponent", Primitive::Integer(8)),
+        ("ColorSpace", Primitive::Array(vec![Primitive::name("Indexed"), Primitive::name("DeviceCMYK"), Primitive::Integer(1), palette])),
+    ], &[1, 0, 7]), &file));
+    let samples = run!(indexed.color_samples(&file));
+    assert!(matches!(samples.color_space, ColorSpace::DeviceCMYK));
+    assert_eq!((samples.width, samples.height), (3, 1));
+    assert_eq!(samples.data, [1., 0., 0., 0., 0., 0., 0., 1., 1., 0., 0., 0.]);
+    assert_eq!(run!(indexed.image_data(&file)).pixel(0, 0), Some([0, 255, 255, 255]));
+
+    // CMYK with a color key mask, and an inverted Decode
+    let pixels = image(vec![
+        ("Width", Primitive::Integer(2)), ("Height", Primitive::Integer(1)), ("BitsPerComponent", Primitive::Integer(8)),
+        ("ColorSpace", Primitive::name("DeviceCMYK")),
+        ("Mask", Primitive::Array([0, 10, 0, 10, 0, 10, 0, 10].iter().map(|&i| Primitive::Integer(i)).collect())),
+    ], &[0, 255, 255, 0, 0, 0, 0, 0], &file);
+    assert_eq!(pixels.pixel(0, 0), Some([255, 0, 0, 255]));
+    assert_eq!(pixels.pixel(1, 0), Some([255, 255, 255, 0]));
+    let pixels = image(vec![
+        ("Width", Primitive::Integer(1)), ("Height", Primitive::Integer(1)), ("BitsPerComponent", Primitive::Integer(16)),
+        ("ColorSpace", Primitive::name("DeviceGray")),
+        ("Decode", Primitive::Array(vec![Primitive::Integer(1), Primitive::Integer(0)])),
+    ], &[0xff, 0xff], &file);
+    assert_eq!(pixels.pixel(0, 0), Some([0, 0, 0, 255]));
+
+    // image masks paint where the sample is 0
+    let pixels = image(vec![
+        ("Width", Primitive::Integer(8)), ("Height", Primitive::Integer(1)), ("BitsPerComponent", Primitive::Integer(1)),
+        ("ImageMask", Primitive::Boolean(true)),
+    ], &[0xf0], &file);
+    let alpha: Vec<u8> = pixels.data.chunks(4).map(|p| p[3]).collect();
+    assert_eq!(alpha, [0, 0, 0, 0, 255, 255, 255, 255]);
+
+    // a huge size with hardly any data fails instead of filling in the missing pixels
+    let huge = run!(ImageXObject::from_primitive(stream(vec![
+        ("Type", Primitive::name("XObject")), ("Subtype", Primitive::name("Image")),
+        ("Width", Primitive::Integer(i32::MAX)), ("Height", Primitive::Integer(i32::MAX)), ("BitsPerComponent", Primitive::Integer(8)),
+        ("ColorSpace", Primitive::name("DeviceRGB")),
+    ], &[0, 0, 0]), &file));
+    assert!(huge.image_data(&file).is_err());
+    assert!(huge.color_samples(&file).is_err());
+    // within the pixel limit, but the missing pixels would still need gigabytes
+    let huge = run!(ImageXObject::from_primitive(stream(vec![
+        ("Type", Primitive::name("XObject")), ("Subtype", Primitive::name("Image")),
+        ("Width", Primitive::Integer(32768)), ("Height", Primitive::Integer(32768)), ("BitsPerComponent", Primitive::Integer(8)),
+        ("ColorSpace", Primitive::name("DeviceRGB")),
+    ], &[0, 0, 0]), &file));
+    assert!(huge.image_data(&file).is_err());
+    assert!(huge.color_samples(&file).is_err());
+    // a row missing at the end is still filled in
+    let pixels = image(vec![
+        ("Width", Primitive::Integer(1)), ("Height", Primitive::Integer(2)), ("BitsPerComponent", Primitive::Integer(8)),
+        ("ColorSpace", Primitive::name("DeviceGray")),
+    ], &[255], &file);
+    assert_eq!(pixels.pixel(0, 1), Some([0, 0, 0, 255]));
+}
+
+#[test]
+fn page_images() {
+    use pdf::build::{ContentBuilder, DocumentBuilder, PageBuilder};
+    use pdf::content::Matrix;
+    use pdf::primitive::{Dictionary, PdfStream, Primitive};
+
+    let mut info = Dictionary::new();
+    for (key, value) in [("Type", "XObject"), ("Subtype", "Image"), ("ColorSpace", "DeviceGray")] {
+        info.insert(key, Primitive::name(value));
+    }
+    info.insert("Width", Primitive::Integer(300));
+    info.insert("Height", Primitive::Integer(150));
+    info.insert("BitsPerComponent", Primitive::Integer(8));
+    info.insert("Length", Primitive::Integer(0));
+    let image = run!(ImageXObject::from_primitive(Primitive::Stream(PdfStream { info, data: vec![] }), &NoResolve));
+
+    let mut doc = DocumentBuilder::new();
+    let name = run!(doc.add_image(image));
+    let mut page = PageBuilder::from_content(ContentBuilder::new()
+        .save()
+        .transform(Matrix { a: 144., b: 0., c: 0., d: 72., e: 10., f: 20. })
+        .xobject(name.as_str())
+        .restore()
+        // rotated by 90°
+        .transform(Matrix { a: 0., b: 300., c: -150., d: 0., e: 400., f: 0. })
+        .xobject(name.as_str())
+        .build());
+    page.media_box(Rect { left: 0., bottom: 0., right: 500., top: 400. });
+    doc.add_page(page);
+    let file = run!(File::from_data(run!(doc.to_bytes())));
+    let page = run!(file.get_page(0));
+    let images: Vec<_> = run!(page.images(&file)).collect();
+    assert_eq!(images.len(), 2);
+    assert!(images.iter().all(|i| i.name == name && i.depth == 0 && (i.width, i.height) == (300, 150)));
+    assert!(matches!(*run!(file.get(images[0].xobject)), XObject::Image(_)));
+
+    let bbox = images[0].bbox();
+    assert_eq!((bbox.left, bbox.bottom, bbox.right, bbox.top), (10., 20., 154., 92.));
+    assert_eq!(images[0].dpi(), (150., 150.));
+    let bbox = images[1].bbox();
+    assert_eq!((bbox.left, bbox.bottom, bbox.right, bbox.top), (250., 0., 400., 300.));
+    assert_eq!(images[1].dpi(), (72., 72.));
+
+    // in page space, like the paths and the text: turned with the page
+    let mut rotated = (*page).clone();
+    rotated.rotate = Some(90);
+    let images: Vec<_> = run!(rotated.images(&file)).collect();
+    let bbox = images[0].bbox();
+    assert_eq!((bbox.left, bbox.bottom, bbox.right, bbox.top), (20., 346., 92., 490.));
+    assert_eq!(images[0].dpi(), (150., 150.));
+}
+
+#[test]
+fn color_management() {
+    use pdf::content::{Cmyk, Color};
+    use pdf::icc::ColorManagement;
+
+    let mut resources = Resources::default();
+    let palette = ColorSpace::Indexed(Box::new(ColorSpace::DeviceCMYK), vec![0, 0, 0, 255, 255, 0, 0, 0]);
+    resources.color_spaces.insert("Cs0".into(), palette);
+    let cm = ColorManagement::default();
+    let rgb = |color: Color, cs: &str| cm.color_to_rgb(&color, cs, &resources).map(|c| [c.red, c.green, c.blue]);
+    assert_eq!(rgb(Color::Cmyk(Cmyk { cyan: 0., magenta: 1., yellow: 1., key: 0. }), "DeviceCMYK"), Some([1., 0., 0.]));
+    assert_eq!(rgb(Color::Components(vec![0.5]), "DeviceGray"), Some([0.5; 3]));
+    assert_eq!(rgb(Color::Components(vec![1.]), "Cs0"), Some([0., 1., 1.]));
+    assert_eq!(rgb(Color::Components(vec![1.]), "Cs1"), None);
+}
+
+#[cfg(feature = "icc")]
+#[test]
+fn icc_srgb_profile() {
+    use pdf::icc::{ColorManagement, Profile};
+
+    // the output intent has the "sRGB built-in" profile of lcms (matrix and curves)
+    let file = run!(File::<Vec<u8>>::open(file_path!("jpeg.pdf")));
+    let intent = &file.trailer.root.output_intents.as_ref().expect("no output intents")[0];
+    let profile = run!(Profile::parse(run!(intent.profile_data()).expect("no profile")));
+    assert_eq!(profile.components(), 3);
+
+    // from sRGB to sRGB: the values lcms gives for this profile
+    let reference: &[([f32; 3], [f32; 3])] = &[
+        ([1., 1., 1.], [1., 1., 1.]),
+        ([0., 0., 0.], [0., 0., 0.]),
+        ([1., 0., 0.], [1., 0., 0.]),
+        ([0., 1., 0.], [0., 1., 0.]),
+        ([0., 0., 1.], [0., 0., 1.]),
+        ([0.5, 0.5, 0.5], [0.5, 0.5, 0.5]),
+        ([0.2, 0.4, 0.6], [0.2, 0.4, 0.6]),
+        ([0.9, 0.1, 0.3], [0.9, 0.1, 0.3]),
+    ];
+    for &(color, expected) in reference {
+        let rgb = profile.to_rgb(&color).unwrap();
+        assert!(rgb.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 0.01), "{:?} gives {:?}", color, rgb);
+    }
+
+    // so the DeviceRGB pixels of the image stay as they are
+    let page = run!(file.get_page(0));
+    let image = run!(page.resources()).xobjects.values()
+        .map(|&r| run!(file.get(r)))
+        .find(|o| matches!(**o, XObject::Image(_)))
+        .expect("no image");
+    let image = match *image {
+        XObject::Image(ref image) => image,
+        _ => unreachable!()
+    };
+    let cm = run!(ColorManagement::with_output_intent(intent));
+    let managed = run!(image.image_data_with(&file, &cm));
+    let plain = run!(image.image_data(&file));
+    assert!(managed.data.iter().zip(&plain.data).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 2));
+}
+
+#[test]
+fn image_soft_mask() {
+    use pdf::build::{DocumentBuilder, PageBuilder};
+    use pdf::primitive::{Dictionary, PdfStream, Primitive};
+
+    fn stream(entries: Vec<(&str, Primitive)>, data: &[u8]) -> Primitive {
+        let mut info = Dictionary::new();
+        info.insert("Type", Primitive::name("XObject"));
+        info.insert("Subtype", Primitive::name("Image"));
+        for (key, value) in entries {
+            info.insert(key, value);
+        }
+        info.insert("Length", Primitive::Integer(data.len() as i32));
+        Primitive::Stream(PdfStream { info, data: data.to_vec() })
+    }
+    fn gray(width: i32, bits: i32) -> Vec<(&'static str, Primitive)> {
+        vec![
+            ("Width", Primitive::Integer(width)), ("Height", Primitive::Integer(1)),
+            ("ColorSpace", Primitive::name("DeviceGray")), ("BitsPerComponent", Primitive::Integer(bits)),
+        ]
+    }
+
+    let mut doc = DocumentBuilder::new();
+    doc.add_page(PageBuilder::new());
+    let mut file = run!(File::from_data(run!(doc.to_bytes())));
+    let mut create = |entries, data: &[u8]| Primitive::Reference(run!(file.create(stream(entries, data))).get_ref().get_inner());
+    let smask = create(gray(2, 8), &[0, 255]);
+    let mut premultiplied = gray(1, 8);
+    premultiplied.push(("Matte", Primitive::Array(vec![Primitive::Number(0.5)])));
+    let matte = create(premultiplied, &[128]);
+    let mut stencil = vec![("Width", Primitive::Integer(2)), ("Height", Primitive::Integer(1)), ("BitsPerComponent", Primitive::Integer(1))];
+    stencil.push(("ImageMask", Primitive::Boolean(true)));
+    let mask = create(stencil, &[0b0100_0000]);
+
+    let image = |mut entries: Vec<(&'static str, Primitive)>, data: &[u8], mask: (&'static str, &Primitive)| {
+        entries.push((mask.0, mask.1.clone()));
+        run!(ImageXObject::from_primitive(stream(entries, data), &file))
+    };
+
+    // the soft mask is interpolated to the size of the image
+    let soft = image(gray(4, 8), &[0; 4], ("SMask", &smask));
+    assert_eq!(run!(soft.alpha(&file)), Some(vec![0, 64, 191, 255]));
+    let pixels = run!(soft.image_data(&file));
+    assert_eq!(pixels.data.chunks(4).map(|p| p[3]).collect::<Vec<_>>(), [0, 64, 191, 255]);
+
+    // stencil masks are not
+    let stenciled = image(gray(4, 8), &[0; 4], ("Mask", &mask));
+    assert_eq!(run!(stenciled.alpha(&file)), Some(vec![255, 255, 0, 0]));
+
+    // colors premultiplied with the matte color
+    let pixels = run!(image(gray(1, 8), &[191], ("SMask", &matte)).image_data(&file));
+    assert_eq!(pixels.pixel(0, 0), Some([254, 254, 254, 128]));
+
+    assert_eq!(run!(image(gray(1, 8), &[0], ("Interpolate", &Primitive::Boolean(true))).alpha(&file)), None);
+}
+
+#[test]
+fn stencil_masks() {
+    use pdf::content::{Content, Op};
+    use pdf::primitive::{Dictionary, PdfStream, Primitive};
+
+    fn parse(data: &[u8]) -> Content {
+        let mut info = Dictionary::new();
+        info.insert("Length", Primitive::Integer(data.len() as i32));
+        run!(Content::from_primitive(Primitive::Stream(PdfStream { info, data: data.to_vec() }), &NoResolve))
+    }
+
+    // inline image masks are parsed like image XObjects
+    let content = parse(b"1 0 0 rg BI /IM true /W 4 /H 2 /D [1 0] ID \xa0\x50 EI");
+    let image = match content.operations[1] {
+        Op::InlineImage { ref image } => image,
+        ref op => panic!("expected an inline image, got {:?}", op)
+    };
+    let mask = run!(image.stencil_mask()).unwrap();
+    assert_eq!((mask.width, mask.height), (4, 2));
+    // inverted by the Decode array
+    assert_eq!(mask.painted, [true, false, true, false, false, true, false, true]);
+
+    let red = mask.paint([255, 0, 0]);
+    assert!(!red.needs_fill_color);
+    assert_eq!(red.pixel(0, 0), Some([255, 0, 0, 255]));
+    assert_eq!(red.pixel(1, 0), Some([255, 0, 0, 0]));
+
+    let pixels = run!(image.image_data(&NoResolve));
+    assert!(pixels.needs_fill_color);
+    assert_eq!(pixels.pixel(0, 0), Some([0, 0, 0, 255]));
+    assert_eq!(run!(image.alpha(&NoResolve)), None);
+
+    let gray = parse(b"BI /W 1 /H 1 /BPC 8 /CS /G ID \x80 EI");
+    let image = match gray.operations[0] {
+        Op::InlineImage { ref image } => image,
+        ref op => panic!("expected an inline image, got {:?}", op)
+    };
+    assert_eq!(run!(image.stencil_mask()), None);
+    let pixels = run!(image.image_data(&NoResolve));
+    assert!(!pixels.needs_fill_color);
+    assert_eq!(pixels.pixel(0, 0), Some([128, 128, 128, 255]));
+}
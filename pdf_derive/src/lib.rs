@@ -382,7 +382,7 @@ fn impl_objectwrite_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
             if let Some((_, ref expr)) = var.discriminant {
                 let var_ident = &var.ident;
                 quote! {
-                    #id::#var_ident => Ok(Primitive::Integer(#expr))
+                    #id::#var_ident => Ok(pdf::primitive::Primitive::Integer(#expr))
                 }
             } else {
                 panic!()
@@ -391,7 +391,7 @@ fn impl_objectwrite_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
 
         quote! {
             impl #impl_generics pdf::object::ObjectWrite for #id #ty_generics #where_clause {
-                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> Result<Primitive> {
+                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> Result<pdf::primitive::Primitive> {
                     match *self {
                         #( #parts, )*
                     }
@@ -418,12 +418,12 @@ fn impl_objectwrite_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
 
         quote! {
             impl #impl_generics pdf::object::ObjectWrite for #id #ty_generics #where_clause {
-                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> Result<Primitive> {
+                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> Result<pdf::primitive::Primitive> {
                     let name = match *self {
                         #( #ser_code, )*
                     };
                     
-                    Ok(Primitive::Name(name.into()))
+                    Ok(pdf::primitive::Primitive::Name(name.into()))
                 }
             }
         }
@@ -631,6 +631,16 @@ fn impl_objectwrite_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream
             }
         }
     );
+    // the unknown entries first, so the known fields take precedence
+    let other_ser = parts.iter()
+    .filter(|(_, attrs, _)| attrs.other)
+    .map(|(field, _, _)|
+        quote! {
+            for (key, val) in self.#field.iter() {
+                dict.insert(key.clone(), val.clone());
+            }
+        }
+    );
     let checks_code = attrs.checks.iter().map(|&(ref key, ref val)|
         quote! {
             dict.insert(#key, pdf::primitive::Primitive::Name(#val.into()));
@@ -652,6 +662,7 @@ fn impl_objectwrite_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream
         impl #impl_generics pdf::object::ToDict for #id #ty_generics #where_clause {
             fn to_dict(&self, updater: &mut impl pdf::object::Updater) -> Result<pdf::primitive::Dictionary> {
                 let mut dict = pdf::primitive::Dictionary::new();
+                #( #other_ser )*
                 #pdf_type
                 #( #checks_code )*
                 #(#fields_ser)*